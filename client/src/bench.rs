@@ -0,0 +1,152 @@
+use anyhow::Result;
+use clap::Args;
+use libcrate::dither::Dither;
+use libcrate::image_processing::{
+    MatchStrategy, PaletteMatcher, apply_palette_with, palette_indices_with,
+};
+use libcrate::traversal::Traversal;
+use libcrate::{Palette, ProcessedImage, ScaleFilter};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use std::{env, fs, process};
+
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    input: PathBuf,
+    #[arg(long = "width", default_value_t = 128)]
+    width: u32,
+    #[arg(long = "colors", default_value_t = 16)]
+    colors: usize,
+    #[arg(long = "sample-factor", default_value_t = 10)]
+    sample_factor: i32,
+    #[arg(long = "runs", default_value_t = 3)]
+    runs: u32,
+}
+
+struct Timing {
+    stage: String,
+    min: Duration,
+    mean: Duration,
+}
+
+fn measure<T, F>(stage: String, runs: u32, mut op: F) -> Result<(Timing, T)>
+where
+    F: FnMut() -> Result<T>,
+{
+    let mut durations = vec![];
+    let mut result = None;
+    for _ in 0..runs.max(1) {
+        let start = Instant::now();
+        result = Some(op()?);
+        durations.push(start.elapsed());
+    }
+    let timing = Timing {
+        stage,
+        min: durations.iter().min().copied().unwrap_or_default(),
+        mean: durations.iter().sum::<Duration>() / durations.len() as u32,
+    };
+    Ok((timing, result.expect("at least one run")))
+}
+
+pub fn run(args: BenchArgs) -> Result<()> {
+    println!(
+        "Benchmarking {:?} ({} runs per stage)...",
+        args.input, args.runs
+    );
+    let mut timings = vec![];
+
+    let (timing, image) = measure("decode".to_string(), args.runs, || {
+        ProcessedImage::new(&args.input)
+    })?;
+    timings.push(timing);
+    let (width, height) = image.uniform_size_for_width(args.width);
+
+    let mut scaled = image.clone();
+    for filter in ScaleFilter::ALL {
+        let (timing, result) = measure(format!("scale {:?}", filter), args.runs, || {
+            let mut scaled = image.clone();
+            scaled.scale(width, height, filter);
+            Ok(scaled)
+        })?;
+        timings.push(timing);
+        scaled = result;
+    }
+    let (timing, _) = measure("scale linear".to_string(), args.runs, || {
+        let mut scaled = image.clone();
+        scaled.scale_linear(width, height, ScaleFilter::default());
+        Ok(scaled)
+    })?;
+    timings.push(timing);
+
+    let (timing, palette) = measure("palette neuquant".to_string(), args.runs, || {
+        Ok(scaled.generate_image_palette(args.sample_factor, args.colors))
+    })?;
+    timings.push(timing);
+    let (timing, _) = measure("palette contrast".to_string(), args.runs, || {
+        Ok(scaled.generate_contrast_palette(args.sample_factor, args.colors, 1.0))
+    })?;
+    timings.push(timing);
+    let (timing, _) = measure("palette reduce".to_string(), args.runs, || {
+        let palette = scaled.generate_image_palette(args.sample_factor, args.colors * 4);
+        scaled.reduce_palette(&palette, args.colors)
+    })?;
+    timings.push(timing);
+
+    // guards LINEAR_SCAN_MAX_COLORS: linear should win up to it and the tree above it
+    for colors in [4, 8, 16, 32, 64] {
+        let palette = scaled.generate_image_palette(args.sample_factor, colors);
+        for strategy in [MatchStrategy::Linear, MatchStrategy::Tree] {
+            let stage = format!("match {:?} {}", strategy, colors);
+            let (timing, _) = measure(stage, args.runs, || {
+                let matcher = PaletteMatcher::with_strategy(&palette, strategy);
+                Ok(palette_indices_with(&scaled.data, &matcher))
+            })?;
+            timings.push(timing);
+        }
+    }
+
+    let palette_full = image.generate_image_palette(args.sample_factor, args.colors);
+    for traversal in [
+        Traversal::Rows,
+        Traversal::Tiles,
+        Traversal::ZOrder,
+        Traversal::Hilbert,
+    ] {
+        let stage = format!("apply full {}", traversal);
+        let (timing, _) = measure(stage, args.runs, || {
+            Ok(apply_palette_with(&image.data, &palette_full, traversal))
+        })?;
+        timings.push(timing);
+    }
+
+    let mut result = scaled.clone();
+    for method in Dither::ALL {
+        let (timing, dithered) = measure(format!("dither {:?}", method), args.runs, || {
+            Ok(dither(&scaled, &palette, method))
+        })?;
+        timings.push(timing);
+        result = dithered;
+    }
+
+    let path = env::temp_dir().join(format!("pixelize_bench_{}.png", process::id()));
+    let (timing, _) = measure("encode png".to_string(), args.runs, || result.save(&path))?;
+    timings.push(timing);
+    let _ = fs::remove_file(&path);
+
+    println!("  {:<24} {:>10} {:>10}", "stage", "min ms", "mean ms");
+    for timing in timings {
+        println!(
+            "  {:<24} {:>10.2} {:>10.2}",
+            timing.stage,
+            timing.min.as_secs_f64() * 1000.0,
+            timing.mean.as_secs_f64() * 1000.0
+        );
+    }
+    Ok(())
+}
+
+fn dither(image: &ProcessedImage, palette: &Palette, method: Dither) -> ProcessedImage {
+    let mut dithered = image.clone();
+    dithered.dither(palette, method);
+    dithered
+}
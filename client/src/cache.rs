@@ -0,0 +1,164 @@
+use crate::config::{ColorCount, Config};
+use crate::sidecar::to_hex;
+use anyhow::{Context, Result};
+use libcrate::hue_lightness::HueLightness;
+use libcrate::palette::{HexColor, HexPalette, HueRange};
+use libcrate::{Palette, ProcessedImage};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// every setting the palette generators read, anything else does not invalidate the cache
+#[derive(Serialize)]
+struct QuantizerSettings {
+    number_of_colors: Option<ColorCount>,
+    sample_factor: Option<i32>,
+    palette_proxy_pixels: Option<u32>,
+    ignore_color: Option<HexColor>,
+    ignore_color_tolerance: Option<u8>,
+    ignore_color_weight: Option<f32>,
+    contrast_weight: Option<f32>,
+    hue_ranges: Option<Vec<HueRange>>,
+    hue_lightness: Option<HueLightness>,
+    auto_max_colors: Option<usize>,
+    auto_max_delta_e: Option<f64>,
+}
+
+impl From<&Config> for QuantizerSettings {
+    fn from(config: &Config) -> Self {
+        QuantizerSettings {
+            number_of_colors: config.number_of_colors,
+            sample_factor: config.sample_factor,
+            palette_proxy_pixels: config.palette_proxy_pixels,
+            ignore_color: config.ignore_color,
+            ignore_color_tolerance: config.ignore_color_tolerance,
+            ignore_color_weight: config.ignore_color_weight,
+            contrast_weight: config.contrast_weight,
+            hue_ranges: config.hue_ranges.clone(),
+            hue_lightness: config.hue_lightness,
+            auto_max_colors: config.auto_max_colors,
+            auto_max_delta_e: config.auto_max_delta_e,
+        }
+    }
+}
+
+pub struct PaletteCache {
+    dir: PathBuf,
+}
+
+impl PaletteCache {
+    pub fn open<P>(dir: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        fs::create_dir_all(dir.as_ref())
+            .with_context(|| format!("Failed to create: {:?}", dir.as_ref()))?;
+        Ok(PaletteCache {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    pub fn key(image: &ProcessedImage, config: &Config) -> Result<String> {
+        let mut hasher = Sha256::new();
+        hasher.update(image.width().to_le_bytes());
+        hasher.update(image.height().to_le_bytes());
+        hasher.update(image.data.as_raw());
+        if let Some(alpha) = &image.alpha {
+            hasher.update(alpha.as_raw());
+        }
+        hasher.update(serde_json::to_vec(&QuantizerSettings::from(config))?);
+        Ok(to_hex(&hasher.finalize()))
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(key).with_extension("hex")
+    }
+
+    pub fn get(&self, key: &str) -> Option<Palette> {
+        let content = fs::read_to_string(self.path(key)).ok()?;
+        content.parse::<HexPalette>().ok().map(|p| p.0)
+    }
+
+    pub fn put(&self, key: &str, palette: &Palette) -> Result<()> {
+        let path = self.path(key);
+        fs::write(&path, HexPalette(palette.clone()).to_string())
+            .with_context(|| format!("Failed to save: {:?}", path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::PaletteCache;
+    use crate::config::{ColorCount, Config};
+    use crate::process::build_palette;
+    use libcrate::ProcessedImage;
+    use libcrate::dither::Dither;
+    use libcrate::image_processing::rgb_from_tuple;
+    use std::env;
+    use std::fs;
+
+    fn test_image() -> ProcessedImage {
+        let colors = [(200, 30, 30), (20, 40, 220), (240, 240, 240), (10, 10, 10)];
+        let buffer = (0..64)
+            .map(|i| rgb_from_tuple(colors[i % 4]))
+            .collect::<Vec<_>>();
+        ProcessedImage::from_buffer(8, 8, &buffer)
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let image = test_image();
+        let config = Config::default();
+        let key = PaletteCache::key(&image, &config).unwrap();
+        assert_eq!(key, PaletteCache::key(&image, &config).unwrap());
+        let unrelated = Config {
+            desired_width: Some(64),
+            dither: Some(Dither::Bayer4),
+            trim: Some(true),
+            ..Config::default()
+        };
+        assert_eq!(key, PaletteCache::key(&image, &unrelated).unwrap());
+        for changed in [
+            Config {
+                number_of_colors: Some(ColorCount::Fixed(4)),
+                ..Config::default()
+            },
+            Config {
+                sample_factor: Some(1),
+                ..Config::default()
+            },
+            Config {
+                ignore_color: Some(rgb_from_tuple((240, 240, 240)).into()),
+                ..Config::default()
+            },
+        ] {
+            assert_ne!(key, PaletteCache::key(&image, &changed).unwrap());
+        }
+        let mut other = test_image();
+        other.data.put_pixel(0, 0, rgb_from_tuple((0, 255, 0)));
+        assert_ne!(key, PaletteCache::key(&other, &config).unwrap());
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let dir = env::temp_dir().join("pixelize_test_palette_cache");
+        let _ = fs::remove_dir_all(&dir);
+        let cache = PaletteCache::open(&dir).unwrap();
+        let palette = vec![rgb_from_tuple((1, 2, 3)), rgb_from_tuple((250, 128, 0))];
+        assert_eq!(cache.get("abc"), None);
+        cache.put("abc", &palette).unwrap();
+        assert_eq!(cache.get("abc"), Some(palette));
+
+        // a cache entry that cannot be written does not fail the palette
+        let image = test_image();
+        let config = Config {
+            palette_cache: Some(dir.clone()),
+            ..Config::default()
+        };
+        let key = PaletteCache::key(&image, &config).unwrap();
+        fs::create_dir_all(dir.join(&key).with_extension("hex")).unwrap();
+        assert!(!build_palette(&image, &config).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,30 @@
+use anyhow::{Context, Result};
+use arboard::{Clipboard, ImageData};
+use libcrate::ProcessedImage;
+use std::borrow::Cow;
+
+pub fn copy_image(image: &ProcessedImage) -> Result<()> {
+    let rgba = image.to_rgba();
+    let data = ImageData {
+        width: rgba.width() as usize,
+        height: rgba.height() as usize,
+        bytes: Cow::Owned(rgba.into_raw()),
+    };
+    let mut clipboard = Clipboard::new().with_context(|| "Failed to open the clipboard")?;
+    #[cfg(target_os = "linux")]
+    {
+        use arboard::SetExtLinux;
+        println!(
+            "Keeping the image on the clipboard until something else is copied (Ctrl+C to stop)..."
+        );
+        clipboard
+            .set()
+            .wait()
+            .image(data)
+            .with_context(|| "Failed to copy the image to the clipboard")
+    }
+    #[cfg(not(target_os = "linux"))]
+    clipboard
+        .set_image(data)
+        .with_context(|| "Failed to copy the image to the clipboard")
+}
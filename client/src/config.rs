@@ -1,33 +1,183 @@
-use anyhow::Result;
+use crate::output::Overwrite;
+use anyhow::{Result, anyhow, ensure};
+use libcrate::adjust::{AutoBalance, Curve, Levels};
+use libcrate::bitdepth::BitDepth;
+use libcrate::dither::Dither;
+use libcrate::hue_lightness::HueLightness;
+use libcrate::noise::NoiseKind;
+use libcrate::outline::OutlineColor;
+use libcrate::palette::{HexColor, HexPalette, HueRange};
+use libcrate::pipeline::StageSpec;
+use libcrate::preset::Preset;
+use libcrate::threshold::Threshold;
+use libcrate::transfer::ColorTransfer;
+use libcrate::traversal::Traversal;
+use libcrate::{Anchor, PowerOfTwo, ScaleFilter, ScaleMode};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::path::PathBuf;
 use std::{env, fs};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
-    pub number_of_colors: Option<usize>,
+    pub number_of_colors: Option<ColorCount>,
     pub sample_factor: Option<i32>,
     pub desired_width: Option<u32>,
     pub desired_height: Option<u32>,
     pub uniform_scale_by_width: bool,
     pub uniform_scale_by_height: bool,
+    pub max_dimension: Option<u32>,
+    pub scale_percent: Option<f64>,
+    pub snap_to_multiple: Option<u32>,
+    pub linear_scaling: Option<bool>,
+    pub scale_filter: Option<ScaleFilter>,
+    pub scale_mode: Option<ScaleMode>,
+    pub letterbox_color: Option<HexColor>,
+    pub dither: Option<Dither>,
+    pub dither_strength: Option<f32>,
+    pub dither_error_clamp: Option<f32>,
+    pub dither_serpentine: Option<bool>,
+    pub traversal: Option<Traversal>,
+    pub palette_cache: Option<PathBuf>,
+    pub bit_depth: Option<BitDepth>,
+    pub threshold: Option<Threshold>,
+    pub temporal_hysteresis: Option<f32>,
+    pub threads: Option<usize>,
+    pub palette_proxy_pixels: Option<u32>,
+    pub auto_balance: Option<AutoBalance>,
+    pub levels: Option<Levels>,
+    pub curves: Option<Vec<Curve>>,
+    pub noise: Option<NoiseKind>,
+    pub noise_amplitude: Option<f32>,
+    pub noise_seed: Option<u64>,
+    pub stages: Option<Vec<StageSpec>>,
+    pub color_reference: Option<PathBuf>,
+    pub color_transfer: Option<ColorTransfer>,
+    pub preset: Option<Preset>,
+    pub auto_max_colors: Option<usize>,
+    pub auto_max_delta_e: Option<f64>,
+    pub contrast_weight: Option<f32>,
+    pub hue_ranges: Option<Vec<HueRange>>,
+    pub hue_lightness: Option<HueLightness>,
     pub use_custom_palette: bool,
     pub dump_palette: bool,
-    pub custom_palette: Vec<(u8, u8, u8)>,
+    pub custom_palette: HexPalette,
+    pub ignore_color: Option<HexColor>,
+    pub ignore_color_tolerance: Option<u8>,
+    pub ignore_color_weight: Option<f32>,
+    pub flatten: Option<HexColor>,
+    pub chroma_key: Option<HexColor>,
+    pub chroma_key_tolerance: Option<u8>,
+    pub trim: Option<bool>,
+    pub trim_color: Option<HexColor>,
+    pub trim_tolerance: Option<u8>,
+    pub trim_padding: Option<u32>,
+    pub pad_to: Option<(u32, u32)>,
+    pub pad_anchor: Option<Anchor>,
+    pub pad_color: Option<HexColor>,
+    pub power_of_two: Option<PowerOfTwo>,
+    pub outline: Option<OutlineColor>,
+    pub overwrite: Option<Overwrite>,
+    pub output_template: Option<String>,
+    pub jobs: Option<usize>,
+    pub sidecar: Option<bool>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
-            number_of_colors: Some(8),
+            number_of_colors: Some(ColorCount::Fixed(8)),
             sample_factor: Some(10),
             desired_width: Some(32),
             desired_height: Some(32),
             uniform_scale_by_width: false,
             uniform_scale_by_height: false,
+            max_dimension: None,
+            scale_percent: None,
+            snap_to_multiple: None,
+            linear_scaling: None,
+            scale_filter: None,
+            scale_mode: None,
+            letterbox_color: None,
+            dither: None,
+            dither_strength: None,
+            dither_error_clamp: None,
+            dither_serpentine: None,
+            traversal: None,
+            palette_cache: None,
+            bit_depth: None,
+            threshold: None,
+            temporal_hysteresis: None,
+            threads: None,
+            palette_proxy_pixels: None,
+            auto_balance: None,
+            levels: None,
+            curves: None,
+            noise: None,
+            noise_amplitude: None,
+            noise_seed: None,
+            stages: None,
+            color_reference: None,
+            color_transfer: None,
+            preset: None,
+            auto_max_colors: None,
+            auto_max_delta_e: None,
+            contrast_weight: None,
+            hue_ranges: None,
+            hue_lightness: None,
             use_custom_palette: false,
             dump_palette: false,
-            custom_palette: vec![],
+            custom_palette: HexPalette::default(),
+            ignore_color: None,
+            ignore_color_tolerance: None,
+            ignore_color_weight: None,
+            flatten: None,
+            chroma_key: None,
+            chroma_key_tolerance: None,
+            trim: None,
+            trim_color: None,
+            trim_tolerance: None,
+            trim_padding: None,
+            pad_to: None,
+            pad_anchor: None,
+            pad_color: None,
+            power_of_two: None,
+            outline: None,
+            overwrite: None,
+            output_template: None,
+            jobs: None,
+            sidecar: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "toml::Value", into = "toml::Value")]
+pub enum ColorCount {
+    Fixed(usize),
+    Auto,
+}
+
+impl TryFrom<toml::Value> for ColorCount {
+    type Error = anyhow::Error;
+
+    fn try_from(value: toml::Value) -> Result<Self> {
+        match value {
+            toml::Value::Integer(n) if n > 0 => Ok(ColorCount::Fixed(n as usize)),
+            toml::Value::String(s) if s == "auto" => Ok(ColorCount::Auto),
+            _ => Err(anyhow!(
+                "number_of_colors must be a positive number or \"auto\", got {}",
+                value
+            )),
+        }
+    }
+}
+
+impl From<ColorCount> for toml::Value {
+    fn from(count: ColorCount) -> Self {
+        match count {
+            ColorCount::Fixed(n) => toml::Value::Integer(n as i64),
+            ColorCount::Auto => toml::Value::String("auto".to_string()),
         }
     }
 }
@@ -47,6 +197,18 @@ impl Config {
         Ok(cfg)
     }
 
+    // overrides by config names, e.g. {"number_of_colors": 16, "dither": "bayer4"}
+    pub fn with_overrides(&self, overrides: &Map<String, Value>) -> Result<Config> {
+        let mut fields: Map<String, Value> = serde_json::from_value(serde_json::to_value(self)?)?;
+        for (key, value) in overrides {
+            ensure!(fields.contains_key(key), "Unknown config setting {}", key);
+            fields.insert(key.clone(), value.clone());
+        }
+        let config = serde_json::from_value::<Config>(Value::Object(fields))?;
+        ensure!(config.is_valid(), "Config is not valid.");
+        Ok(config)
+    }
+
     fn get_config_path(filename: &str) -> Result<PathBuf> {
         let exe_path = env::current_exe()?;
         let exe_dir = exe_path.parent().unwrap();
@@ -69,6 +231,62 @@ impl Config {
             validation_messages
                 .push("Warning: invalid configuration: custom_palette is empty.".to_string());
         };
+        if let Some(weight) = self.ignore_color_weight
+            && !(0.0..=1.0).contains(&weight)
+        {
+            validation_messages.push(
+                "Warning: invalid configuration: ignore_color_weight must be between 0 and 1."
+                    .to_string(),
+            );
+        }
+        if let Some(strength) = self.dither_strength
+            && !(0.0..=1.0).contains(&strength)
+        {
+            validation_messages.push(
+                "Warning: invalid configuration: dither_strength must be between 0 and 1."
+                    .to_string(),
+            );
+        }
+        if let Some(strength) = self.contrast_weight
+            && strength < 0.0
+        {
+            validation_messages.push(
+                "Warning: invalid configuration: contrast_weight cannot be negative.".to_string(),
+            );
+        }
+        if let Some(hysteresis) = self.temporal_hysteresis
+            && hysteresis < 0.0
+        {
+            validation_messages.push(
+                "Warning: invalid configuration: temporal_hysteresis cannot be negative."
+                    .to_string(),
+            );
+        }
+        if self.threads == Some(0) {
+            validation_messages
+                .push("Warning: invalid configuration: threads must be positive.".to_string());
+        }
+        if self.jobs == Some(0) {
+            validation_messages
+                .push("Warning: invalid configuration: jobs must be positive.".to_string());
+        }
+        if self.max_dimension == Some(0) {
+            validation_messages.push(
+                "Warning: invalid configuration: max_dimension must be positive.".to_string(),
+            );
+        }
+        if let Some(percent) = self.scale_percent
+            && percent <= 0.0
+        {
+            validation_messages.push(
+                "Warning: invalid configuration: scale_percent must be positive.".to_string(),
+            );
+        }
+        if self.snap_to_multiple == Some(0) {
+            validation_messages.push(
+                "Warning: invalid configuration: snap_to_multiple must be positive.".to_string(),
+            );
+        }
         if self.uniform_scale_by_width && self.uniform_scale_by_height {
             validation_messages.push(
                 "Warning: invalid configuration: cannot have both uniform scaling enabled."
@@ -92,3 +310,40 @@ impl Config {
         errors.is_empty()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use libcrate::palette::HexColor;
+    use serde_json::json;
+
+    #[test]
+    fn test_config_colors() {
+        let overrides = json!({
+            "use_custom_palette": true,
+            "custom_palette": ["#ff00ff", [0, 0, 0]],
+            "chroma_key": "#ff00ff",
+            "pad_color": [0, 0, 0],
+        });
+        let config = Config::default()
+            .with_overrides(overrides.as_object().unwrap())
+            .unwrap();
+        let magenta = "#ff00ff".parse::<HexColor>().unwrap();
+        assert_eq!(config.chroma_key, Some(magenta));
+        assert_eq!(
+            config.pad_color.map(String::from).as_deref(),
+            Some("#000000")
+        );
+        assert_eq!(config.custom_palette[0], magenta.0);
+        // colors are written back the same way as palette entries
+        let saved = toml::to_string(&config).unwrap();
+        assert!(saved.contains(r##"chroma_key = "#ff00ff""##), "{}", saved);
+        assert!(saved.contains(r##"pad_color = "#000000""##), "{}", saved);
+        let invalid = json!({ "trim_color": "#ff00" });
+        assert!(
+            Config::default()
+                .with_overrides(invalid.as_object().unwrap())
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use crate::output::{Overwrite, resolve_output};
+use anyhow::Result;
+use clap::Args;
+use libcrate::dither::{Dither, DitherOptions};
+use libcrate::gradient::{GradientDirection, render_gradient};
+use libcrate::image_processing::save_image;
+use libcrate::palette::{HexPalette, load_palette_file};
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct GradientArgs {
+    #[arg(required = true)]
+    colors: Vec<String>,
+    #[arg(short, long)]
+    output: String,
+    #[arg(long = "size", value_parser = crate::parse_size, default_value = "64x16")]
+    size: (u32, u32),
+    #[arg(long = "dither", default_value = "bayer4")]
+    dither: Dither,
+    #[arg(long = "direction", default_value = "horizontal")]
+    direction: GradientDirection,
+    #[arg(long = "palette")]
+    palette: Option<PathBuf>,
+    #[arg(long = "force", conflicts_with = "rename")]
+    force: bool,
+    #[arg(long = "rename")]
+    rename: bool,
+}
+
+pub fn run(args: GradientArgs) -> Result<()> {
+    let stops = args.colors.join(",").parse::<HexPalette>()?;
+    let palette = args.palette.as_ref().map(load_palette_file).transpose()?;
+    let (width, height) = args.size;
+    let overwrite = match (args.force, args.rename) {
+        (true, _) => Overwrite::Force,
+        (_, true) => Overwrite::Rename,
+        _ => Overwrite::default(),
+    };
+    let output = resolve_output(&args.output, overwrite)?;
+    println!(
+        "Rendering {}x{} {} gradient of {} colors...",
+        width,
+        height,
+        args.direction,
+        stops.len()
+    );
+    let gradient = render_gradient(
+        width,
+        height,
+        &stops,
+        args.direction,
+        palette.as_ref(),
+        &DitherOptions::new(args.dither),
+    )?;
+    println!("Saving to {}", output);
+    save_image(&output, &gradient)
+}
@@ -0,0 +1,495 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use libcrate::dither::Dither;
+use libcrate::export::palette::{PaletteFormat, save_palette_as};
+use libcrate::font::{draw_text, fit_text};
+use libcrate::image_processing::{rgb_from_tuple, rgb_to_hex, save_palette};
+use libcrate::palette::{ColorChannel, nudge_color};
+use libcrate::preset::Preset;
+use libcrate::{Palette, ProcessedImage, ScaleFilter};
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+use std::path::{Path, PathBuf};
+
+const PANEL_WIDTH: u32 = 176;
+const VIEW_SIZE: u32 = 512;
+const MARGIN: u32 = 8;
+const ROW_HEIGHT: u32 = 40;
+const TEXT_SCALE: u32 = 2;
+const TRACK_WIDTH: u32 = PANEL_WIDTH - 2 * MARGIN;
+const COLOR_RANGE: (u32, u32) = (2, 64);
+const WIDTH_RANGE: (u32, u32) = (8, 256);
+const SWATCH_SIZE: u32 = TRACK_WIDTH / 8;
+const SWATCHES_TOP: u32 = MARGIN + 6 * ROW_HEIGHT + 4;
+
+const BACKGROUND: (u8, u8, u8) = (32, 32, 40);
+const PANEL: (u8, u8, u8) = (48, 48, 60);
+const CONTROL: (u8, u8, u8) = (90, 90, 110);
+const HIGHLIGHT: (u8, u8, u8) = (230, 180, 60);
+const TEXT: (u8, u8, u8) = (235, 235, 235);
+
+#[derive(Args, Debug)]
+pub struct GuiArgs {
+    input: PathBuf,
+    #[arg(short = 'o', long = "output")]
+    output: Option<PathBuf>,
+    #[arg(long = "export-palette")]
+    export_palette: Option<PathBuf>,
+    #[arg(long = "colors", default_value_t = 16)]
+    colors: u32,
+    #[arg(long = "width", default_value_t = 64)]
+    width: u32,
+    #[arg(long = "dither", default_value = "none")]
+    dither: Dither,
+    #[arg(long = "sample-factor", default_value_t = 10)]
+    sample_factor: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Settings {
+    colors: u32,
+    width: u32,
+    dither: usize,
+    preset: Option<Preset>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Control {
+    Colors,
+    Width,
+    Dither,
+    Palette,
+    Save,
+    Export,
+}
+
+impl Control {
+    const ALL: [Control; 6] = [
+        Control::Colors,
+        Control::Width,
+        Control::Dither,
+        Control::Palette,
+        Control::Save,
+        Control::Export,
+    ];
+
+    fn at(x: u32, y: u32) -> Option<Control> {
+        if x >= PANEL_WIDTH || y < MARGIN {
+            return None;
+        }
+        Control::ALL
+            .get(((y - MARGIN) / ROW_HEIGHT) as usize)
+            .copied()
+    }
+
+    fn top(&self) -> u32 {
+        let row = Control::ALL.iter().position(|c| c == self).unwrap_or(0);
+        MARGIN + row as u32 * ROW_HEIGHT
+    }
+}
+
+struct Editor {
+    selected: usize,
+    channel: usize,
+    hsl: bool,
+}
+
+impl Editor {
+    fn channel(&self) -> ColorChannel {
+        let channels = if self.hsl {
+            ColorChannel::HSL
+        } else {
+            ColorChannel::RGB
+        };
+        channels[self.channel]
+    }
+}
+
+struct Session {
+    source: ProcessedImage,
+    sample_factor: i32,
+    settings: Settings,
+    scaled: ProcessedImage,
+    palette: Palette,
+    image: ProcessedImage,
+}
+
+impl Session {
+    fn new(source: ProcessedImage, sample_factor: i32, settings: Settings) -> Self {
+        let mut session = Session {
+            scaled: source.clone(),
+            image: source.clone(),
+            source,
+            sample_factor,
+            settings,
+            palette: vec![],
+        };
+        session.rebuild();
+        session
+    }
+
+    fn rebuild(&mut self) {
+        self.scaled = self.source.clone();
+        self.scaled
+            .uniform_scale_width(self.settings.width, ScaleFilter::default());
+        self.palette = match self.settings.preset {
+            Some(preset) => preset.palette(),
+            None => self
+                .scaled
+                .generate_image_palette(self.sample_factor, self.settings.colors as usize),
+        };
+        self.apply();
+    }
+
+    fn apply(&mut self) {
+        self.image = self.scaled.clone();
+        self.image
+            .dither(&self.palette, Dither::ALL[self.settings.dither]);
+    }
+
+    fn update(&mut self, settings: Settings) {
+        let previous = self.settings;
+        self.settings = settings;
+        if (settings.colors, settings.width, settings.preset)
+            != (previous.colors, previous.width, previous.preset)
+        {
+            self.rebuild();
+        } else if settings.dither != previous.dither {
+            self.apply();
+        }
+    }
+}
+
+pub fn run(args: GuiArgs) -> Result<()> {
+    println!("Loading {:?}...", args.input);
+    let source = ProcessedImage::new(&args.input)?;
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| sibling(&args.input, "pixelized"));
+    let palette_output = args
+        .export_palette
+        .clone()
+        .unwrap_or_else(|| sibling(&args.input, "palette"));
+
+    let (width, height) = (PANEL_WIDTH + VIEW_SIZE, VIEW_SIZE);
+    let mut window = Window::new(
+        &format!("pixelize - {}", args.input.display()),
+        width as usize,
+        height as usize,
+        WindowOptions::default(),
+    )
+    .context("Failed to open the preview window")?;
+    window.set_target_fps(60);
+    println!(
+        "Drag the sliders to adjust, S saves to {:?}, E exports the palette to {:?}, Esc quits",
+        output, palette_output
+    );
+    println!(
+        "Click a swatch or use Left/Right to select a color, 1/2/3 to pick a channel, H to switch RGB/HSL, \
+         Up/Down to nudge (Shift for bigger steps), R to revert"
+    );
+
+    let mut session = Session::new(
+        source,
+        args.sample_factor,
+        Settings {
+            colors: args.colors.clamp(COLOR_RANGE.0, COLOR_RANGE.1),
+            width: args.width.clamp(WIDTH_RANGE.0, WIDTH_RANGE.1),
+            dither: Dither::ALL
+                .iter()
+                .position(|d| *d == args.dither)
+                .unwrap_or(0),
+            preset: None,
+        },
+    );
+    let mut editor = Editor {
+        selected: 0,
+        channel: 0,
+        hsl: false,
+    };
+    let mut active = None;
+    let mut status = String::new();
+
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        let mut settings = session.settings;
+        let mut save = window.is_key_pressed(Key::S, KeyRepeat::No);
+        let mut export = window.is_key_pressed(Key::E, KeyRepeat::No);
+        if window.is_key_pressed(Key::D, KeyRepeat::No) {
+            settings.dither = (settings.dither + 1) % Dither::ALL.len();
+        }
+        if window.is_key_pressed(Key::P, KeyRepeat::No) {
+            settings.preset = next_preset(settings.preset);
+        }
+
+        let mouse = window.get_mouse_pos(MouseMode::Clamp);
+        if !window.get_mouse_down(MouseButton::Left) {
+            active = None;
+        } else if let Some((x, y)) = mouse.map(|(x, y)| (x as u32, y as u32)) {
+            let pressed = active.is_none();
+            if pressed {
+                active = Control::at(x, y);
+                if let Some(idx) = swatch_at(x, y).filter(|idx| *idx < session.palette.len()) {
+                    editor.selected = idx;
+                }
+            }
+            match active {
+                Some(Control::Colors) => settings.colors = slider_value(x, COLOR_RANGE),
+                Some(Control::Width) => settings.width = slider_value(x, WIDTH_RANGE),
+                Some(Control::Dither) if pressed => {
+                    settings.dither = (settings.dither + 1) % Dither::ALL.len()
+                }
+                Some(Control::Palette) if pressed => settings.preset = next_preset(settings.preset),
+                Some(Control::Save) if pressed => save = true,
+                Some(Control::Export) if pressed => export = true,
+                _ => {}
+            }
+        }
+
+        if settings != session.settings {
+            session.update(settings);
+            status.clear();
+        }
+        if window.is_key_pressed(Key::R, KeyRepeat::No) {
+            session.rebuild();
+            status = "REVERTED".to_string();
+        }
+        edit_palette(&window, &mut session, &mut editor);
+
+        if save {
+            status = report(session.image.save(&output), "SAVED", &output);
+        }
+        if export {
+            status = report(
+                export_palette(&palette_output, &session.palette),
+                "EXPORTED",
+                &palette_output,
+            );
+        }
+
+        let mut canvas = ProcessedImage::from_buffer(
+            width,
+            height,
+            &vec![rgb_from_tuple(BACKGROUND); (width * height) as usize],
+        );
+        draw_panel(&mut canvas, &session, &editor, &status);
+        draw_preview(&mut canvas, &session.image);
+        let buffer = canvas
+            .data
+            .pixels()
+            .map(|p| u32::from_be_bytes([0, p[0], p[1], p[2]]))
+            .collect::<Vec<_>>();
+        window
+            .update_with_buffer(&buffer, width as usize, height as usize)
+            .context("Failed to update the preview window")?;
+    }
+    Ok(())
+}
+
+fn edit_palette(window: &Window, session: &mut Session, editor: &mut Editor) {
+    let count = session.palette.len();
+    editor.selected = editor.selected.min(count.saturating_sub(1));
+    if count == 0 {
+        return;
+    }
+    if window.is_key_pressed(Key::Right, KeyRepeat::Yes) {
+        editor.selected = (editor.selected + 1) % count;
+    }
+    if window.is_key_pressed(Key::Left, KeyRepeat::Yes) {
+        editor.selected = (editor.selected + count - 1) % count;
+    }
+    for (idx, key) in [Key::Key1, Key::Key2, Key::Key3].into_iter().enumerate() {
+        if window.is_key_pressed(key, KeyRepeat::No) {
+            editor.channel = idx;
+        }
+    }
+    if window.is_key_pressed(Key::H, KeyRepeat::No) {
+        editor.hsl = !editor.hsl;
+    }
+
+    let direction = if window.is_key_pressed(Key::Up, KeyRepeat::Yes) {
+        1.0
+    } else if window.is_key_pressed(Key::Down, KeyRepeat::Yes) {
+        -1.0
+    } else {
+        return;
+    };
+    let step = match (editor.channel(), window.is_key_down(Key::LeftShift)) {
+        (ColorChannel::Hue, false) => 2.0,
+        (ColorChannel::Hue, true) => 15.0,
+        (_, false) => 1.0,
+        (_, true) => 8.0,
+    };
+    let color = &mut session.palette[editor.selected];
+    *color = nudge_color(color, editor.channel(), direction * step);
+    session.apply();
+}
+
+fn export_palette(path: &Path, palette: &Palette) -> Result<()> {
+    match PaletteFormat::from_extension(path) {
+        Ok(format) => save_palette_as(path, palette, format, "palette"),
+        Err(_) => save_palette(path, palette),
+    }
+}
+
+fn report(result: Result<()>, done: &str, path: &Path) -> String {
+    match result {
+        Ok(()) => {
+            println!("{} {:?}", done.to_lowercase(), path);
+            done.to_string()
+        }
+        Err(e) => {
+            eprintln!("{:#}", e);
+            "FAILED".to_string()
+        }
+    }
+}
+
+fn sibling(input: &Path, suffix: &str) -> PathBuf {
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    input.with_file_name(format!("{}_{}.png", stem, suffix))
+}
+
+fn next_preset(preset: Option<Preset>) -> Option<Preset> {
+    match preset {
+        None => Some(Preset::ALL[0]),
+        Some(preset) => Preset::ALL
+            .iter()
+            .position(|p| *p == preset)
+            .and_then(|idx| Preset::ALL.get(idx + 1))
+            .copied(),
+    }
+}
+
+fn slider_value(x: u32, (min, max): (u32, u32)) -> u32 {
+    let offset = x.saturating_sub(MARGIN).min(TRACK_WIDTH);
+    min + (offset * (max - min) + TRACK_WIDTH / 2) / TRACK_WIDTH
+}
+
+fn swatch_at(x: u32, y: u32) -> Option<usize> {
+    if !(MARGIN..MARGIN + TRACK_WIDTH).contains(&x) || y < SWATCHES_TOP {
+        return None;
+    }
+    let (column, row) = ((x - MARGIN) / SWATCH_SIZE, (y - SWATCHES_TOP) / SWATCH_SIZE);
+    Some((row * 8 + column) as usize)
+}
+
+fn fill_rect(canvas: &mut ProcessedImage, x: u32, y: u32, w: u32, h: u32, color: (u8, u8, u8)) {
+    let color = rgb_from_tuple(color);
+    for py in y..(y + h).min(canvas.height()) {
+        for px in x..(x + w).min(canvas.width()) {
+            canvas.data.put_pixel(px, py, color);
+        }
+    }
+}
+
+fn draw_label(canvas: &mut ProcessedImage, x: u32, y: u32, text: &str) {
+    let text = fit_text(text, PANEL_WIDTH - x - MARGIN, TEXT_SCALE);
+    draw_text(
+        &mut canvas.data,
+        x,
+        y,
+        &text,
+        rgb_from_tuple(TEXT),
+        TEXT_SCALE,
+    );
+}
+
+fn draw_slider(canvas: &mut ProcessedImage, top: u32, value: u32, (min, max): (u32, u32)) {
+    fill_rect(canvas, MARGIN, top + 20, TRACK_WIDTH, 4, CONTROL);
+    let knob = MARGIN + (value - min) * TRACK_WIDTH / (max - min);
+    fill_rect(canvas, knob.saturating_sub(3), top + 14, 6, 16, HIGHLIGHT);
+}
+
+fn draw_button(canvas: &mut ProcessedImage, top: u32, text: &str) {
+    fill_rect(canvas, MARGIN, top + 12, TRACK_WIDTH, 20, CONTROL);
+    draw_label(canvas, MARGIN + 4, top + 17, text);
+}
+
+fn draw_panel(canvas: &mut ProcessedImage, session: &Session, editor: &Editor, status: &str) {
+    let settings = &session.settings;
+    fill_rect(canvas, 0, 0, PANEL_WIDTH, VIEW_SIZE, PANEL);
+    for control in Control::ALL {
+        let top = control.top();
+        match control {
+            Control::Colors => {
+                draw_label(canvas, MARGIN, top, &format!("COLORS: {}", settings.colors));
+                draw_slider(canvas, top, settings.colors, COLOR_RANGE);
+            }
+            Control::Width => {
+                draw_label(canvas, MARGIN, top, &format!("WIDTH: {}", settings.width));
+                draw_slider(canvas, top, settings.width, WIDTH_RANGE);
+            }
+            Control::Dither => {
+                draw_label(canvas, MARGIN, top, "DITHER (D)");
+                draw_button(canvas, top, &format!("{:?}", Dither::ALL[settings.dither]));
+            }
+            Control::Palette => {
+                let name = settings
+                    .preset
+                    .map_or("Generated".to_string(), |p| p.to_string());
+                draw_label(canvas, MARGIN, top, "PALETTE (P)");
+                draw_button(canvas, top, &name);
+            }
+            Control::Save => draw_button(canvas, top, "SAVE (S)"),
+            Control::Export => draw_button(canvas, top, "EXPORT PAL (E)"),
+        }
+    }
+    draw_swatches(canvas, &session.palette, editor);
+
+    let info = format!(
+        "{}X{} {}",
+        session.image.width(),
+        session.image.height(),
+        status
+    );
+    draw_label(canvas, MARGIN, VIEW_SIZE - MARGIN - 10, &info);
+}
+
+fn draw_swatches(canvas: &mut ProcessedImage, palette: &Palette, editor: &Editor) {
+    for (idx, color) in palette.iter().enumerate() {
+        let (column, row) = (idx as u32 % 8, idx as u32 / 8);
+        let (x, y) = (
+            MARGIN + column * SWATCH_SIZE,
+            SWATCHES_TOP + row * SWATCH_SIZE,
+        );
+        if idx == editor.selected {
+            fill_rect(canvas, x, y, SWATCH_SIZE, SWATCH_SIZE, HIGHLIGHT);
+        }
+        let rgb = (color[0], color[1], color[2]);
+        fill_rect(canvas, x + 2, y + 2, SWATCH_SIZE - 4, SWATCH_SIZE - 4, rgb);
+    }
+    let Some(color) = palette.get(editor.selected) else {
+        return;
+    };
+
+    let rows = (palette.len() as u32).div_ceil(8);
+    let top = SWATCHES_TOP + rows * SWATCH_SIZE + 6;
+    draw_label(canvas, MARGIN, top, &rgb_to_hex(color));
+    let channels = if editor.hsl {
+        ColorChannel::HSL
+    } else {
+        ColorChannel::RGB
+    };
+    for (idx, channel) in channels.iter().enumerate() {
+        let x = MARGIN + idx as u32 * 52;
+        if idx == editor.channel {
+            fill_rect(canvas, x, top + 14, 50, 14, CONTROL);
+        }
+        let name = format!("{:?}", channel);
+        let text = format!("{}{}", &name[..1], channel.value(color).round());
+        draw_label(canvas, x + 2, top + 16, &text);
+    }
+}
+
+fn draw_preview(canvas: &mut ProcessedImage, image: &ProcessedImage) {
+    let scale = (VIEW_SIZE / image.width().max(image.height())).max(1);
+    let (w, h) = (image.width() * scale, image.height() * scale);
+    let x = PANEL_WIDTH + VIEW_SIZE.saturating_sub(w) / 2;
+    let y = VIEW_SIZE.saturating_sub(h) / 2;
+    for py in 0..h.min(VIEW_SIZE) {
+        for px in 0..w.min(VIEW_SIZE) {
+            let color = *image.data.get_pixel(px / scale, py / scale);
+            canvas.data.put_pixel(x + px, y + py, color);
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use anyhow::Result;
+use clap::Args;
+use libcrate::ProcessedImage;
+use libcrate::formats::png::{PALETTE_KEY, SETTINGS_KEY, load_text};
+use libcrate::palette::HexPalette;
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct InfoArgs {
+    input: PathBuf,
+}
+
+pub fn run(args: InfoArgs) -> Result<()> {
+    let image = ProcessedImage::new(&args.input)?;
+    println!("Size: {}x{}", image.width(), image.height());
+    println!("Colors: {}", image.get_color_histogram().len());
+    let text = load_text(&args.input)?;
+    if text.is_empty() {
+        println!("No embedded settings found.");
+    }
+    for (key, value) in text {
+        match key.as_str() {
+            PALETTE_KEY => {
+                let palette = value.parse::<HexPalette>()?;
+                println!("Palette ({} colors): {}", palette.len(), palette);
+            }
+            SETTINGS_KEY => {
+                let settings = serde_json::from_str::<serde_json::Value>(&value)?;
+                println!("Settings:\n{}", serde_json::to_string_pretty(&settings)?);
+            }
+            _ => println!("{}: {}", key, value),
+        }
+    }
+    Ok(())
+}
@@ -1,93 +1,1139 @@
+// progress messages, moved to stderr while stdout carries JSON reports
+macro_rules! status {
+    ($($arg:tt)*) => {{
+        let line = crate::report::status_line(format_args!($($arg)*));
+        if crate::report::stdout_reserved() {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    }};
+}
+
+mod bench;
+mod cache;
+#[cfg(feature = "clipboard")]
+mod clipboard;
 mod config;
+mod gradient;
+#[cfg(feature = "gui")]
+mod gui;
+mod info;
+mod output;
+mod pack;
+mod palette;
+mod pipeline;
+mod process;
+mod redo;
+mod report;
+#[cfg(feature = "script")]
+mod script;
+mod sequence;
+mod sidecar;
+mod slice;
+#[cfg(feature = "ffmpeg")]
+mod video;
 
+use crate::bench::BenchArgs;
 use crate::config::Config;
-use anyhow::{Result, anyhow};
-use clap::{ArgGroup, Parser};
-use libcrate::ProcessedImage;
-use libcrate::image_processing::{palette_from_tuples, save_palette};
+use crate::gradient::GradientArgs;
+use crate::info::InfoArgs;
+use crate::output::{
+    DEFAULT_TEMPLATE, Freshness, OutputClaims, Overwrite, TemplateValues, check_template,
+    expand_template, freshness, is_template,
+};
+use crate::pack::PackArgs;
+use crate::palette::PaletteArgs;
+use crate::pipeline::RunArgs;
+use crate::process::{finish_stages, load_color_reference, load_image, prepare_stages};
+use crate::redo::RedoArgs;
+use crate::report::{Report, ReportFormat, Stopwatch, reserve_stdout, set_status_label};
+use crate::sequence::SequenceOptions;
+use crate::sidecar::{Sidecar, png_text, to_hex};
+use crate::slice::SliceArgs;
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use clap::parser::ValueSource;
+use clap::{ArgGroup, CommandFactory, FromArgMatches, Parser, Subcommand};
+use libcrate::adjust::{AutoBalance, Curve, Levels};
+use libcrate::bitdepth::BitDepth;
+use libcrate::cvd::{Deficiency, confusable_pairs};
+use libcrate::dither::Dither;
+use libcrate::export::cycling::{CycleRange, save_cycling_gif};
+use libcrate::export::gameboy::{ShadeMapping, TileOrder, save_2bpp};
+use libcrate::export::godot::save_bundle;
+use libcrate::export::ico::{ICO_SIZES, icon_frames, save_ico};
+use libcrate::export::mipmap::{mipmap_chain, save_mipmaps};
+use libcrate::export::nes;
+use libcrate::export::palette::{
+    PaletteFormat, save_histogram_json, save_palette_as, save_palette_card, save_palette_json,
+};
+use libcrate::export::pico8::save_pico8;
+use libcrate::export::source::{PixelEncoding, SourceLanguage, save_source};
+use libcrate::export::svg::save_svg;
+#[cfg(feature = "textures")]
+use libcrate::export::texture::{TextureFormat, save_texture};
+use libcrate::formats::bmp::save_indexed_bmp;
+use libcrate::formats::load_embedded_palette;
+use libcrate::formats::png::save_with_text;
+use libcrate::hue_lightness::HueLightness;
+use libcrate::image_processing::{rgb_to_hex, save_palette};
+use libcrate::names::ColorNames;
+use libcrate::noise::NoiseKind;
+use libcrate::outline::OutlineColor;
+use libcrate::palette::{
+    HexColor, HexPalette, HueRange, PruneThreshold, RemapStrategy, load_palette_file, score_palette,
+};
+use libcrate::pipeline::{PipelineState, StageSpec};
+use libcrate::preset::Preset;
+use libcrate::preview::{CompareLayout, checkerboard_preview, compare_images};
+use libcrate::sequence::{is_pattern, parse_range};
+use libcrate::spritesheet::SheetLayout;
+use libcrate::threshold::Threshold;
+use libcrate::transfer::{ColorReference, ColorTransfer};
+use libcrate::traversal::Traversal;
+use libcrate::{
+    Anchor, Palette, PowerOfTwo, ProcessedImage, ScaleFilter, ScaleMode, set_thread_count,
+};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 #[command(group(
     ArgGroup::new("input")
-        .args(["input_pos", "input_flag"])
-        .required(true)
-))]
-#[command(group(
-    ArgGroup::new("output")
-        .args(["output_pos", "output_flag"])
+        .args(["paths", "input_flag"])
+        .multiple(true)
         .required(true)
 ))]
 struct Args {
-    #[arg(short = 'i', long = "input", group = "input")]
-    input_flag: Option<String>,
-    #[arg(short = 'o', long = "output", group = "output")]
+    #[arg(short = 'i', long = "input", num_args = 1..)]
+    input_flag: Vec<String>,
+    #[arg(short = 'o', long = "output")]
     output_flag: Option<String>,
-    #[arg(index = 1, group = "input")]
-    input_pos: Option<String>,
-    #[arg(index = 2, group = "output")]
-    output_pos: Option<String>,
+    #[arg(long = "force", conflicts_with = "rename")]
+    force: bool,
+    #[arg(long = "rename")]
+    rename: bool,
+    #[arg(long = "template")]
+    template: Option<String>,
+    #[arg(long = "jobs", short = 'j')]
+    jobs: Option<usize>,
+    #[arg(long = "sidecar")]
+    sidecar: bool,
+    #[arg(long = "no-metadata")]
+    no_metadata: bool,
+    #[arg(value_name = "INPUT... OUTPUT")]
+    paths: Vec<String>,
+    #[arg(long = "export-palette")]
+    export_palette: Option<String>,
+    #[arg(long = "color-names")]
+    color_names: Option<String>,
+    #[arg(long = "export-histogram")]
+    export_histogram: Option<String>,
+    #[arg(long = "export-source")]
+    export_source: Option<String>,
+    #[arg(long = "source-encoding", default_value = "indexed")]
+    source_encoding: PixelEncoding,
+    #[arg(long = "export-pico8")]
+    export_pico8: Option<String>,
+    #[cfg(feature = "clipboard")]
+    #[arg(long = "to-clipboard")]
+    to_clipboard: bool,
+    #[arg(long = "export-svg")]
+    export_svg: Option<String>,
+    #[arg(long = "svg-scale", default_value_t = 10)]
+    svg_scale: u32,
+    #[arg(long = "export-mipmaps")]
+    export_mipmaps: Option<String>,
+    #[arg(long = "mipmap-smooth", requires = "export_mipmaps")]
+    mipmap_smooth: bool,
+    #[arg(long = "export-ico")]
+    export_ico: Option<String>,
+    #[arg(long = "ico-sizes", value_delimiter = ',', default_values_t = ICO_SIZES, value_parser = clap::value_parser!(u32).range(1..=256), requires = "export_ico")]
+    ico_sizes: Vec<u32>,
+    #[cfg(feature = "textures")]
+    #[arg(long = "export-texture")]
+    export_texture: Option<String>,
+    #[cfg(feature = "textures")]
+    #[arg(long = "texture-format", default_value = "rgba8")]
+    texture_format: TextureFormat,
+    #[cfg(feature = "textures")]
+    #[arg(long = "texture-mipmaps", requires = "export_texture")]
+    texture_mipmaps: bool,
+    #[arg(long = "export-gb")]
+    export_gb: Option<String>,
+    #[arg(long = "gb-order", default_value = "row")]
+    gb_order: TileOrder,
+    #[arg(long = "export-nes")]
+    export_nes: Option<String>,
+    #[arg(long = "nes-fix")]
+    nes_fix: bool,
+    #[arg(long = "godot")]
+    godot: bool,
+    #[arg(long = "godot-res-dir", default_value = "")]
+    godot_res_dir: String,
+    #[arg(long = "godot-cells", value_parser = parse_size)]
+    godot_cells: Option<(u32, u32)>,
+    #[arg(long = "frames", value_parser = parse_range)]
+    frames: Option<RangeInclusive<u32>>,
+    #[arg(long = "shared-palette")]
+    shared_palette: bool,
+    #[arg(long = "threads", global = true)]
+    threads: Option<usize>,
+    #[arg(long = "hysteresis")]
+    hysteresis: Option<f32>,
+    #[arg(long = "compare")]
+    compare: Option<String>,
+    #[arg(long = "compare-layout", default_value = "side")]
+    compare_layout: CompareLayout,
+    #[arg(long = "checker-preview")]
+    checker_preview: Option<String>,
+    #[arg(long = "checker-size", default_value_t = 8)]
+    checker_size: u32,
+    #[arg(long = "scale-filter")]
+    scale_filter: Option<ScaleFilter>,
+    #[arg(long = "scale-mode")]
+    scale_mode: Option<ScaleMode>,
+    #[arg(long = "size", value_parser = parse_target_size)]
+    size: Option<TargetSize>,
+    #[arg(long = "max-dim", conflicts_with = "size")]
+    max_dim: Option<u32>,
+    #[arg(long = "snap")]
+    snap: Option<u32>,
+    #[arg(long = "gameboy", group = "preset")]
+    gameboy: bool,
+    #[arg(long = "pico8", group = "preset")]
+    pico8: bool,
+    #[arg(long = "c64", group = "preset")]
+    c64: bool,
+    #[arg(long = "dither")]
+    dither: Option<Dither>,
+    #[arg(long = "dither-strength")]
+    dither_strength: Option<f32>,
+    #[arg(long = "dither-clamp")]
+    dither_clamp: Option<f32>,
+    #[arg(long = "serpentine")]
+    serpentine: bool,
+    #[arg(long = "traversal")]
+    traversal: Option<Traversal>,
+    #[arg(long = "palette-cache")]
+    palette_cache: Option<PathBuf>,
+    #[arg(long = "bit-depth")]
+    bit_depth: Option<BitDepth>,
+    #[arg(long = "threshold", conflicts_with = "bit_depth")]
+    threshold: Option<Threshold>,
+    #[arg(long = "flatten")]
+    flatten: Option<HexColor>,
+    #[arg(long = "trim")]
+    trim: bool,
+    #[arg(long = "trim-padding")]
+    trim_padding: Option<u32>,
+    #[arg(long = "pad-to", value_parser = parse_size)]
+    pad_to: Option<(u32, u32)>,
+    #[arg(long = "pad-anchor")]
+    pad_anchor: Option<Anchor>,
+    #[arg(long = "pot")]
+    pot: Option<PowerOfTwo>,
+    #[arg(long = "outline")]
+    outline: Option<OutlineColor>,
+    #[arg(long = "auto-balance")]
+    auto_balance: Option<AutoBalance>,
+    #[arg(long = "levels")]
+    levels: Option<Levels>,
+    #[arg(long = "curve")]
+    curves: Vec<Curve>,
+    #[arg(long = "hue-lightness")]
+    hue_lightness: Option<HueLightness>,
+    #[arg(long = "hue-range")]
+    hue_ranges: Vec<HueRange>,
+    #[arg(long = "noise")]
+    noise: Option<NoiseKind>,
+    #[arg(long = "noise-amplitude")]
+    noise_amplitude: Option<f32>,
+    #[arg(long = "noise-seed")]
+    noise_seed: Option<u64>,
+    #[arg(long = "stage")]
+    stages: Vec<StageSpec>,
+    #[arg(long = "match-colors")]
+    match_colors: Option<PathBuf>,
+    #[arg(long = "color-transfer")]
+    color_transfer: Option<ColorTransfer>,
+    #[arg(long = "remap")]
+    remap: Option<String>,
+    #[arg(long = "remap-strategy", default_value = "nearest")]
+    remap_strategy: RemapStrategy,
+    #[arg(long = "report")]
+    report: Option<ReportFormat>,
+    #[arg(long = "report-output", requires = "report")]
+    report_output: Option<String>,
+    #[arg(long = "cycle-gif", requires = "cycle")]
+    cycle_gif: Option<String>,
+    #[arg(long = "cycle")]
+    cycle: Vec<CycleRange>,
+    #[arg(long = "cycle-fps", default_value_t = 30)]
+    cycle_fps: u32,
+    #[arg(long = "keep-palette")]
+    keep_palette: bool,
+    #[arg(long = "reduce-to")]
+    reduce_to: Option<usize>,
+    #[arg(long = "prune")]
+    prune: Option<PruneThreshold>,
+    #[arg(long = "palette-usage")]
+    palette_usage: bool,
+    #[arg(long = "check-cvd")]
+    check_cvd: bool,
+    #[arg(long = "cvd-threshold", default_value_t = 24.0)]
+    cvd_threshold: f64,
+    #[arg(long = "montage")]
+    montage: Option<String>,
+    #[arg(long = "montage-columns", default_value_t = 4)]
+    montage_columns: u32,
+    #[arg(long = "palette-card")]
+    palette_card: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(skip)]
+    options: String,
+}
+
+// command line options that change nothing in the outputs
+const UNRELATED_OPTIONS: &[&str] = &[
+    "input_flag",
+    "output_flag",
+    "paths",
+    "force",
+    "rename",
+    "jobs",
+    "threads",
+    "report",
+    "report_output",
+    "palette_usage",
+    "check_cvd",
+    "cvd_threshold",
+];
+
+fn parse_args<I, T>(itr: I) -> Args
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let command = Args::command();
+    let matches = command.clone().get_matches_from(itr);
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // options missing from the config still change the outputs, so their fingerprint is saved
+    // next to the settings and joins the freshness check
+    let mut hasher = Sha256::new();
+    for id in command.get_arguments().map(|arg| arg.get_id().as_str()) {
+        if UNRELATED_OPTIONS.contains(&id)
+            || matches.value_source(id) != Some(ValueSource::CommandLine)
+        {
+            continue;
+        }
+        hasher.update(id);
+        for value in matches.try_get_raw(id).ok().flatten().into_iter().flatten() {
+            hasher.update([0]);
+            hasher.update(value.as_encoded_bytes());
+        }
+        hasher.update([1]);
+    }
+    args.options = to_hex(&hasher.finalize());
+    args
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Times every processing stage on the given image
+    Bench(BenchArgs),
+    /// Opens a preview window with live sliders for colors, width, dithering and palette
+    #[cfg(feature = "gui")]
+    Gui(gui::GuiArgs),
+    /// Renders a dithered gradient between palette colors, e.g. for sky backgrounds and UI bars
+    Gradient(GradientArgs),
+    /// Prints the palette and settings embedded in a PNG produced by pixelize
+    Info(InfoArgs),
+    /// Packs images into a single sprite sheet with a JSON map of frames
+    Pack(PackArgs),
+    /// Palette tools
+    Palette(PaletteArgs),
+    /// Processes an image with the settings embedded in a previous output or its JSON sidecar
+    Redo(RedoArgs),
+    /// Runs the stages of a pipeline file (TOML or YAML) on each input image
+    Run(RunArgs),
+    /// Runs a Rhai script that loads, processes and saves images
+    #[cfg(feature = "script")]
+    Script(script::ScriptArgs),
+    /// Slices a sprite sheet into fixed-size cells saved as individual files
+    Slice(SliceArgs),
+    /// Pixelizes every frame of a video with a shared palette using ffmpeg
+    #[cfg(feature = "ffmpeg")]
+    Video(video::VideoArgs),
 }
 
 fn main() -> Result<()> {
-    println!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
-    let args = Args::parse();
-
-    let input = args
-        .input_flag
-        .or(args.input_pos)
-        .expect("Missing input file");
-    let output = args
-        .output_flag
-        .or(args.output_pos)
-        .expect("Missing output file");
+    let args = parse_args(env::args_os());
+    if args.command.is_none() && args.report.is_some() {
+        match &args.report_output {
+            // reports of this run are appended, so start from an empty file
+            Some(path) => {
+                fs::write(path, "").with_context(|| format!("Failed to save: {:?}", path))?
+            }
+            None => reserve_stdout(),
+        }
+    }
+    status!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
 
     let config = Config::load()?;
-    if !config.is_valid() {
-        return Err(anyhow!("Config is not valid."));
+    if let Some(threads) = args.threads.or(config.threads) {
+        set_thread_count(threads)?;
+    }
+
+    if let Some(command) = args.command {
+        return match command {
+            Command::Bench(args) => bench::run(args),
+            #[cfg(feature = "gui")]
+            Command::Gui(args) => gui::run(args),
+            Command::Gradient(args) => gradient::run(args),
+            Command::Info(args) => info::run(args),
+            Command::Pack(args) => pack::run(args),
+            Command::Palette(args) => palette::run(args),
+            Command::Redo(args) => redo::run(args),
+            Command::Run(args) => pipeline::run(args, config),
+            #[cfg(feature = "script")]
+            Command::Script(args) => script::run(args, config),
+            Command::Slice(args) => slice::run(args),
+            #[cfg(feature = "ffmpeg")]
+            Command::Video(args) => video::run(args, config),
+        };
     }
-    println!("Loading image...");
-    let mut image = ProcessedImage::new(input)?;
-
-    if config.uniform_scale_by_width {
-        println!("Uniform scaling by width...");
-        image.uniform_scale_width(config.desired_width.unwrap(), true);
-    } else if config.uniform_scale_by_height {
-        println!("Uniform scaling by height...");
-        image.uniform_scale_height(config.desired_height.unwrap(), true);
-    } else if config.desired_width.is_none() && config.desired_height.is_none() {
-        println!("Skipping scaling");
+
+    let (inputs, output) = resolve_paths(&args)?;
+    let templates = [("output", &output)]
+        .into_iter()
+        .chain(args.template.as_ref().map(|t| ("--template", t)))
+        .chain(
+            config
+                .output_template
+                .as_ref()
+                .map(|t| ("output_template", t)),
+        )
+        .chain(side_outputs(&args));
+    for (name, template) in templates {
+        check_template(template).with_context(|| format!("Invalid {} {}", name, template))?;
+    }
+    let config = apply_args(&args, config)?;
+    // measured once, every input of a batch is matched to the same reference
+    let reference = load_color_reference(&config)?;
+    // unknown stages and bad parameters fail before the first input is processed
+    process::registry(&config, None, false)?.check(&prepare_stages(&config))?;
+    if inputs.len() == 1 && !is_directory(&output) {
+        let claims = OutputClaims::default();
+        return run_with_config(
+            &args,
+            config,
+            reference.as_ref(),
+            &claims,
+            &inputs[0],
+            &output,
+        );
+    }
+    let target = if is_template(&output) {
+        output.clone()
     } else {
-        println!("Scaling by width and height...");
-        image.scale(
-            config.desired_width.unwrap(),
-            config.desired_height.unwrap(),
-            true,
+        fs::create_dir_all(&output).with_context(|| format!("Failed to create: {:?}", output))?;
+        let template = match &args.template {
+            Some(template) => template.clone(),
+            None => config
+                .output_template
+                .clone()
+                .unwrap_or(DEFAULT_TEMPLATE.to_string()),
+        };
+        Path::new(&output)
+            .join(template)
+            .to_string_lossy()
+            .into_owned()
+    };
+    check_batch_outputs(&args, &config, &inputs, &target)?;
+    let jobs = args.jobs.or(config.jobs).unwrap_or(1).max(1);
+    let claims = OutputClaims::default();
+    let (next, skipped, failed) = (
+        AtomicUsize::new(0),
+        AtomicUsize::new(0),
+        AtomicBool::new(false),
+    );
+    // every worker takes the next input once its current one is saved, so at most `jobs` images are in flight
+    let worker = || -> Result<()> {
+        while !failed.load(Ordering::Relaxed) {
+            let Some(input) = inputs.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                return Ok(());
+            };
+            // parallel workers label their messages, one input at a time needs no label
+            set_status_label((jobs > 1).then_some(input.as_str()));
+            let result =
+                process_batch_input(&args, &config, reference.as_ref(), &claims, input, &target);
+            match result {
+                Ok(true) => {}
+                Ok(false) => _ = skipped.fetch_add(1, Ordering::Relaxed),
+                Err(e) => {
+                    failed.store(true, Ordering::Relaxed);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    };
+    thread::scope(|scope| {
+        let workers = (0..jobs.min(inputs.len()))
+            .map(|_| scope.spawn(worker))
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .map(|w| w.join().map_err(|_| anyhow!("Batch worker panicked"))?)
+            .collect::<Result<Vec<_>>>()
+    })?;
+    let skipped = skipped.into_inner();
+    status!(
+        "Processed {} images into {} ({} up to date)",
+        inputs.len() - skipped,
+        output,
+        skipped
+    );
+    Ok(())
+}
+
+fn resolve_paths(args: &Args) -> Result<(Vec<String>, String)> {
+    let mut inputs = args.input_flag.clone();
+    inputs.extend(args.paths.iter().cloned());
+    let output = match &args.output_flag {
+        Some(output) => output.clone(),
+        None if inputs.len() > 1 => inputs.pop().unwrap(),
+        None => return Err(anyhow!("Missing output file")),
+    };
+    ensure!(!inputs.is_empty(), "Missing input file");
+    ensure!(
+        inputs.len() == 1
+            || is_directory(&output)
+            || is_template(&output)
+            || Path::new(&output).extension().is_none(),
+        "Output {} must be a directory or a template when multiple inputs are given",
+        output
+    );
+    Ok((inputs, output))
+}
+
+fn is_directory(path: &str) -> bool {
+    path.ends_with('/') || path.ends_with('\\') || Path::new(path).is_dir()
+}
+
+fn side_outputs(args: &Args) -> Vec<(&'static str, &String)> {
+    #[cfg(feature = "textures")]
+    let texture = [("--export-texture", &args.export_texture)];
+    #[cfg(not(feature = "textures"))]
+    let texture: [(&str, &Option<String>); 0] = [];
+    let paths = [
+        ("--export-palette", &args.export_palette),
+        ("--export-histogram", &args.export_histogram),
+        ("--export-source", &args.export_source),
+        ("--export-pico8", &args.export_pico8),
+        ("--export-svg", &args.export_svg),
+        ("--export-mipmaps", &args.export_mipmaps),
+        ("--export-ico", &args.export_ico),
+        ("--export-gb", &args.export_gb),
+        ("--export-nes", &args.export_nes),
+        ("--compare", &args.compare),
+        ("--checker-preview", &args.checker_preview),
+        ("--cycle-gif", &args.cycle_gif),
+        ("--palette-card", &args.palette_card),
+    ];
+    paths
+        .into_iter()
+        .chain(texture)
+        .filter_map(|(flag, path)| path.as_ref().map(|path| (flag, path)))
+        .collect()
+}
+
+// every input of a batch needs its own output and side output files
+fn check_batch_outputs(
+    args: &Args,
+    config: &Config,
+    inputs: &[String],
+    target: &str,
+) -> Result<()> {
+    for (flag, path) in side_outputs(args) {
+        ensure!(
+            is_template(path),
+            "{} {} would be overwritten by every input, add a placeholder like {{stem}}",
+            flag,
+            path
         );
     }
+    ensure!(
+        !config.dump_palette,
+        "dump_palette saves ./palette.png for every input, use --export-palette with a template instead"
+    );
+    // outputs named after the result are only known later, the rest can be compared now
+    let mut outputs = HashMap::new();
+    for input in inputs {
+        let Ok(output) = expand_template(target, &TemplateValues::from_input(input)) else {
+            continue;
+        };
+        if let Some(other) = outputs.insert(output.clone(), input) {
+            bail!(
+                "Inputs {} and {} would both be saved to {}, add {{parent}} or {{ext}} to the template",
+                other,
+                input,
+                output
+            );
+        }
+    }
+    Ok(())
+}
 
-    let palette = if config.use_custom_palette {
-        println!("Using custom palette...");
-        palette_from_tuples(&config.custom_palette)
-    } else {
-        println!("Generating palette...");
-        image.generate_image_palette(
-            config.sample_factor.unwrap(),
-            config.number_of_colors.unwrap(),
-        )
+fn process_batch_input(
+    args: &Args,
+    config: &Config,
+    reference: Option<&ColorReference>,
+    claims: &OutputClaims,
+    input: &str,
+    target: &str,
+) -> Result<bool> {
+    let mut config = config.clone();
+    match freshness(input, target, &config, &args.options) {
+        Freshness::UpToDate if !args.force => {
+            status!("Skipping {}, its output is up to date", input);
+            return Ok(false);
+        }
+        // outdated results of an earlier run are replaced like make would
+        Freshness::Stale if !args.rename => {
+            status!("Replacing the outdated output of {}", input);
+            config.overwrite = Some(Overwrite::Force);
+        }
+        _ => {}
+    }
+    status!("Processing {}...", input);
+    run_with_config(args, config, reference, claims, input, target)
+        .with_context(|| format!("Failed to process {}", input))?;
+    Ok(true)
+}
+
+fn apply_args(args: &Args, mut config: Config) -> Result<Config> {
+    if !config.is_valid() {
+        return Err(anyhow!("Config is not valid."));
+    }
+    if args.scale_filter.is_some() {
+        config.scale_filter = args.scale_filter;
+    }
+    match args.size {
+        Some(TargetSize::Percent(percent)) => {
+            config.scale_percent = Some(percent);
+            config.max_dimension = None;
+        }
+        Some(TargetSize::Exact(width, height)) => {
+            config.desired_width = Some(width);
+            config.desired_height = Some(height);
+            config.uniform_scale_by_width = false;
+            config.uniform_scale_by_height = false;
+            config.scale_percent = None;
+            config.max_dimension = None;
+        }
+        None => {}
+    }
+    if args.max_dim.is_some() {
+        config.max_dimension = args.max_dim;
+        config.scale_percent = None;
+    }
+    if args.snap.is_some() {
+        config.snap_to_multiple = args.snap;
+    }
+    if args.scale_mode.is_some() {
+        config.scale_mode = args.scale_mode;
+    }
+    if args.dither.is_some() {
+        config.dither = args.dither;
+    }
+    if args.dither_strength.is_some() {
+        config.dither_strength = args.dither_strength;
+    }
+    if args.dither_clamp.is_some() {
+        config.dither_error_clamp = args.dither_clamp;
+    }
+    if args.serpentine {
+        config.dither_serpentine = Some(true);
+    }
+    if args.traversal.is_some() {
+        config.traversal = args.traversal;
+    }
+    if args.palette_cache.is_some() {
+        config.palette_cache = args.palette_cache.clone();
+    }
+    if args.bit_depth.is_some() {
+        config.bit_depth = args.bit_depth;
+    }
+    if args.threshold.is_some() {
+        config.threshold = args.threshold;
+    }
+    if args.hysteresis.is_some() {
+        config.temporal_hysteresis = args.hysteresis;
+    }
+    if args.flatten.is_some() {
+        config.flatten = args.flatten;
+    }
+    if args.trim {
+        config.trim = Some(true);
+    }
+    if args.trim_padding.is_some() {
+        config.trim_padding = args.trim_padding;
+    }
+    if args.pad_to.is_some() {
+        config.pad_to = args.pad_to;
+    }
+    if args.pad_anchor.is_some() {
+        config.pad_anchor = args.pad_anchor;
+    }
+    if args.pot.is_some() {
+        config.power_of_two = args.pot;
+    }
+    if args.outline.is_some() {
+        config.outline = args.outline;
+    }
+    if args.noise.is_some() {
+        config.noise = args.noise;
+    }
+    if args.noise_amplitude.is_some() {
+        config.noise_amplitude = args.noise_amplitude;
+    }
+    if args.auto_balance.is_some() {
+        config.auto_balance = args.auto_balance;
+    }
+    if args.levels.is_some() {
+        config.levels = args.levels;
+    }
+    if !args.curves.is_empty() {
+        config.curves = Some(args.curves.clone());
+    }
+    if args.hue_lightness.is_some() {
+        config.hue_lightness = args.hue_lightness;
+    }
+    if !args.hue_ranges.is_empty() {
+        config.hue_ranges = Some(args.hue_ranges.clone());
+    }
+    if args.noise_seed.is_some() {
+        config.noise_seed = args.noise_seed;
+    }
+    if !args.stages.is_empty() {
+        config.stages = Some(args.stages.clone());
+    }
+    if args.match_colors.is_some() {
+        config.color_reference = args.match_colors.clone();
+    }
+    if args.color_transfer.is_some() {
+        config.color_transfer = args.color_transfer;
+    }
+    if args.gameboy {
+        config.preset = Some(Preset::GameBoy);
+    } else if args.pico8 {
+        config.preset = Some(Preset::Pico8);
+    } else if args.c64 {
+        config.preset = Some(Preset::C64);
+    }
+    if args.sidecar {
+        config.sidecar = Some(true);
+    }
+    if args.force {
+        config.overwrite = Some(Overwrite::Force);
+    } else if args.rename {
+        config.overwrite = Some(Overwrite::Rename);
+    }
+    Ok(config)
+}
+
+// the config already has the command line overrides applied
+fn run_with_config(
+    args: &Args,
+    config: Config,
+    reference: Option<&ColorReference>,
+    claims: &OutputClaims,
+    input: &str,
+    output: &str,
+) -> Result<()> {
+    let overwrite = config.overwrite.unwrap_or_default();
+    if is_pattern(input) {
+        let options = SequenceOptions {
+            frames: args.frames.clone(),
+            shared_palette: args.shared_palette,
+            montage: args.montage.clone(),
+            montage_columns: args.montage_columns,
+            overwrite,
+            report: args.report,
+            report_output: args.report_output.clone(),
+        };
+        return sequence::run(input, output, &config, reference, options);
+    }
+    let resolved = match is_template(output) {
+        true => None,
+        false => Some(claims.resolve(output, overwrite)?),
+    };
+    let final_output = |values: &TemplateValues| match &resolved {
+        Some(output) => Ok(output.clone()),
+        None => claims.resolve(&expand_template(output, values)?, overwrite),
+    };
+
+    let mut stopwatch = Stopwatch::start();
+    let mut report = Report::new(input, output);
+
+    status!("Loading image...");
+    let image = load_image(input, &config)?;
+    report.source = (&image).into();
+    report.timing.load_ms = stopwatch.lap();
+
+    // the regular pipeline runs as registry stages, the command line only adds steps in between
+    let registry = process::registry(&config, reference.cloned(), true)?;
+    let mut state = PipelineState::new(image);
+    registry.run(&prepare_stages(&config), &mut state)?;
+    report.timing.prepare_ms = stopwatch.lap();
+
+    if args.keep_palette {
+        status!("Using the palette of {}...", input);
+        state.palette = Some(
+            load_embedded_palette(input)?
+                .ok_or_else(|| anyhow!("{} has no embedded palette", input))?,
+        );
+    }
+    registry.apply(&StageSpec::new("palette", Value::Null), &mut state)?;
+    let image = state.image;
+    let palette = state
+        .palette
+        .ok_or_else(|| anyhow!("The pipeline produced no palette"))?;
+    let palette = match args.reduce_to {
+        Some(target) if target < palette.len() => {
+            status!("Reducing palette to {} colors...", target);
+            image.reduce_palette(&palette, target)?
+        }
+        _ => palette,
     };
 
     if config.dump_palette {
-        println!("Saving palette to palette.png");
+        status!("Saving palette to palette.png");
         save_palette("./palette.png", &palette)?;
     }
 
-    println!("Applying palette...");
-    image.apply_palette(&palette);
+    let color_names = match &args.color_names {
+        Some(path) => ColorNames::load(path)?,
+        None => ColorNames::css(),
+    };
+
+    if args.check_cvd {
+        check_cvd(&palette, args.cvd_threshold);
+    }
+
+    if args.report.is_some() {
+        report.quality = Some(score_palette(&image.data, &palette)?);
+    }
+    report.timing.palette_ms = stopwatch.lap();
+
+    let mut state = PipelineState {
+        image,
+        palette: Some(palette.clone()),
+    };
+    registry.apply(&StageSpec::new("apply-palette", Value::Null), &mut state)?;
+    let mut image = state.image;
+    report.timing.apply_ms = stopwatch.lap();
 
-    println!("Saving to {}", output);
-    image.save(&output)?;
+    let palette = match args.prune {
+        Some(threshold) => {
+            let pruned = image.prune_palette(&palette, threshold);
+            status!("Pruned {} rarely used colors", palette.len() - pruned.len());
+            pruned
+        }
+        None => palette,
+    };
+
+    if args.palette_usage {
+        let usage = image.palette_usage(&palette);
+        status!("Palette usage:");
+        for (i, color) in palette.iter().enumerate() {
+            status!(
+                "  {:3} {} {:8} px {:6.2}% {}",
+                i,
+                rgb_to_hex(color),
+                usage.counts[i],
+                usage.percentage(i),
+                color_names.nearest(color)
+            );
+        }
+        if !usage.unused().is_empty() {
+            status!("Unused colors: {:?}", usage.unused());
+        }
+    }
+
+    let mut state = PipelineState {
+        image,
+        palette: Some(palette.clone()),
+    };
+    let remapped = match &args.remap {
+        Some(path) => {
+            status!("Remapping to palette {}...", path);
+            let target = load_palette_file(path)?;
+            let params =
+                json!({ "colors": HexPalette(target.clone()), "strategy": args.remap_strategy });
+            registry.apply(&StageSpec::new("remap", params), &mut state)?;
+            Some(target)
+        }
+        None => None,
+    };
+    // the outline picks its color from the remapped palette
+    registry.run(&finish_stages(&config, &state.image), &mut state)?;
+    let image = state.image;
+    let values = TemplateValues::new(input, &image);
+    // side outputs take the same placeholders as the output, so batch inputs do not share them
+    let side = |path: &Option<String>| {
+        path.as_deref()
+            .map(|path| expand_template(path, &values))
+            .transpose()
+    };
+
+    if let Some(path) = &side(&args.export_palette)? {
+        status!("Exporting palette to {}", path);
+        match PaletteFormat::from_extension(path)? {
+            PaletteFormat::Json => save_palette_json(path, &palette, "palette", &color_names)?,
+            format => save_palette_as(path, &palette, format, "palette")?,
+        }
+    }
+
+    let output = &final_output(&values)?;
+    report.output = output.clone();
+    if args.godot {
+        let frames = match args.godot_cells {
+            Some((width, height)) => SheetLayout::new(width, height)
+                .cells(image.width(), image.height())?
+                .into_iter()
+                .enumerate()
+                .map(|(idx, rect)| (format!("{:03}", idx), rect))
+                .collect(),
+            None => vec![],
+        };
+        status!("Saving Godot bundle to {}", output);
+        save_bundle(output, &image, &args.godot_res_dir, &frames)?;
+    } else {
+        status!("Saving to {}", output);
+        let indexed_bmp = Path::new(&output)
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("bmp"))
+            && image.alpha.is_none()
+            && args.remap.is_none()
+            && palette.len() <= 256;
+        if indexed_bmp {
+            save_indexed_bmp(output, &image.to_indexed(&palette)?)?;
+        } else {
+            let palette = remapped.as_ref().unwrap_or(&palette);
+            save_image(
+                &image,
+                output,
+                Some(palette),
+                &config,
+                Some(&args.options),
+                args.no_metadata,
+            )?;
+        }
+    }
+    if config.sidecar.unwrap_or(false) {
+        status!("Saving metadata to {}", Sidecar::path(output));
+        let palette = remapped.as_ref().unwrap_or(&palette);
+        Sidecar::new(
+            input,
+            report.source,
+            &image,
+            Some(palette),
+            &config,
+            Some(&args.options),
+        )?
+        .save(output)?;
+    }
+    report.timing.save_ms = stopwatch.lap();
+
+    if let Some(path) = &side(&args.cycle_gif)? {
+        status!("Saving palette cycling animation to {}", path);
+        save_cycling_gif(path, &image.data, &palette, &args.cycle, args.cycle_fps)?;
+    }
+
+    if let Some(path) = &side(&args.palette_card)? {
+        status!("Saving palette card to {}", path);
+        save_palette_card(path, &palette, &image.data)?;
+    }
+
+    if let Some(path) = &side(&args.export_histogram)? {
+        status!("Exporting color histogram to {}", path);
+        save_histogram_json(path, &image.get_color_histogram(), &color_names)?;
+    }
 
-    println!("Done.");
+    if let Some(path) = &side(&args.compare)? {
+        status!("Saving comparison to {}", path);
+        let original = load_image(input, &config)?;
+        compare_images(&original, &image, args.compare_layout).save(path)?;
+    }
+
+    if let Some(path) = &side(&args.checker_preview)? {
+        status!("Saving checkerboard preview to {}", path);
+        checkerboard_preview(&image, args.checker_size).save(path)?;
+    }
+
+    if let Some(path) = &side(&args.export_source)? {
+        status!("Exporting source array to {}", path);
+        save_source(
+            path,
+            &image.data,
+            &palette,
+            SourceLanguage::from_extension(path)?,
+            args.source_encoding,
+        )?;
+    }
+
+    if let Some(path) = &side(&args.export_pico8)? {
+        status!("Exporting PICO-8 graphics to {}", path);
+        save_pico8(path, &image.data)?;
+    }
+
+    if let Some(path) = &side(&args.export_svg)? {
+        status!("Exporting SVG to {}", path);
+        save_svg(path, &image, args.svg_scale)?;
+    }
+
+    if let Some(path) = &side(&args.export_mipmaps)? {
+        status!("Exporting mipmaps to {}", path);
+        let levels = mipmap_chain(&image, (!args.mipmap_smooth).then_some(&palette));
+        save_mipmaps(path, &levels)?;
+    }
+
+    if let Some(path) = &side(&args.export_ico)? {
+        status!("Exporting icon to {}", path);
+        save_ico(path, &icon_frames(&image, &palette, &args.ico_sizes)?)?;
+    }
+
+    #[cfg(feature = "textures")]
+    if let Some(path) = &side(&args.export_texture)? {
+        status!("Exporting {:?} texture to {}", args.texture_format, path);
+        let levels = if args.texture_mipmaps {
+            mipmap_chain(&image, Some(&palette))
+        } else {
+            vec![image.clone()]
+        };
+        let levels = levels.iter().map(|l| l.to_rgba()).collect::<Vec<_>>();
+        save_texture(path, &levels, args.texture_format)?;
+    }
+
+    if let Some(path) = &side(&args.export_gb)? {
+        status!("Exporting Game Boy tiles to {}", path);
+        save_2bpp(path, &image.data, &ShadeMapping::Luminance, args.gb_order)?;
+    }
+
+    if let Some(path) = &side(&args.export_nes)? {
+        let background = nes::most_common_color(&image.data);
+        let violations = nes::check_tiles(&image.data, background, nes::ATTRIBUTE_SIZE);
+        violations.iter().for_each(|v| {
+            status!(
+                "Warning: NES area {}x{} uses {} colors besides the background.",
+                v.x,
+                v.y,
+                v.colors
+            )
+        });
+        // fixing also merges sub-palettes beyond the limit of four, which no area reports
+        let data = if args.nes_fix {
+            status!("Fixing {} NES color violations...", violations.len());
+            nes::fix_tiles(&image.data, background, nes::ATTRIBUTE_SIZE)
+        } else {
+            image.data.clone()
+        };
+        let attributes = Path::new(path).with_extension("atr");
+        status!(
+            "Exporting NES CHR data to {} and attributes to {:?}",
+            path,
+            attributes
+        );
+        nes::export(&data, background)?.save(Path::new(path), attributes.as_path())?;
+    }
+
+    #[cfg(feature = "clipboard")]
+    if args.to_clipboard {
+        status!("Copying the result to the clipboard");
+        clipboard::copy_image(&image)?;
+    }
+
+    status!("Done.");
+    if let Some(format) = args.report {
+        report.result = (&image).into();
+        report.set_palette(&palette);
+        report.timing.total_ms = stopwatch.total();
+        report.emit(format, args.report_output.as_deref())?;
+    }
     Ok(())
 }
+
+fn save_image(
+    image: &ProcessedImage,
+    output: &str,
+    palette: Option<&Palette>,
+    config: &Config,
+    options: Option<&str>,
+    no_metadata: bool,
+) -> Result<()> {
+    let is_png = Path::new(output)
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("png"));
+    if is_png && !no_metadata {
+        save_with_text(output, image, &png_text(palette, config, options)?)
+    } else {
+        image.save(output)
+    }
+}
+
+fn check_cvd(palette: &Palette, threshold: f64) {
+    for deficiency in Deficiency::ALL {
+        for pair in confusable_pairs(palette, deficiency, threshold) {
+            status!(
+                "Warning: colors {} and {} are hard to distinguish with {} (distance {:.1}).",
+                rgb_to_hex(&palette[pair.first]),
+                rgb_to_hex(&palette[pair.second]),
+                deficiency,
+                pair.distance
+            );
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TargetSize {
+    Percent(f64),
+    Exact(u32, u32),
+}
+
+fn parse_target_size(s: &str) -> Result<TargetSize> {
+    match s.strip_suffix('%') {
+        Some(percent) => {
+            let percent = percent.trim().parse::<f64>()?;
+            if percent <= 0.0 {
+                return Err(anyhow!("Expected a positive percentage, got {}", s));
+            }
+            Ok(TargetSize::Percent(percent))
+        }
+        None => {
+            let (width, height) = parse_size(s)?;
+            Ok(TargetSize::Exact(width, height))
+        }
+    }
+}
+
+fn parse_size(s: &str) -> Result<(u32, u32)> {
+    let (width, height) = s
+        .split_once('x')
+        .ok_or_else(|| anyhow!("Expected size as WIDTHxHEIGHT, got {}", s))?;
+    Ok((width.parse()?, height.parse()?))
+}
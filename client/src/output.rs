@@ -0,0 +1,268 @@
+use crate::config::Config;
+use crate::sidecar::{load_options, load_settings};
+use anyhow::{Result, anyhow, bail, ensure};
+use libcrate::ProcessedImage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Overwrite {
+    #[default]
+    Error,
+    Force,
+    Rename,
+}
+
+impl FromStr for Overwrite {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "error" => Ok(Overwrite::Error),
+            "force" => Ok(Overwrite::Force),
+            "rename" => Ok(Overwrite::Rename),
+            _ => Err(anyhow!(
+                "Unknown overwrite mode {}, expected error, force or rename",
+                s
+            )),
+        }
+    }
+}
+
+pub const DEFAULT_TEMPLATE: &str = "{stem}.{ext}";
+
+pub struct TemplateValues {
+    pub stem: String,
+    pub parent: String,
+    pub ext: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub colors: Option<usize>,
+}
+
+impl TemplateValues {
+    pub fn new(input: &str, image: &ProcessedImage) -> Self {
+        TemplateValues {
+            width: Some(image.width()),
+            height: Some(image.height()),
+            colors: Some(image.get_color_histogram().len()),
+            ..TemplateValues::from_input(input)
+        }
+    }
+
+    // only the values known before the image is processed
+    pub fn from_input(input: &str) -> Self {
+        let input = Path::new(input);
+        let part = |part: Option<&std::ffi::OsStr>| {
+            part.map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        TemplateValues {
+            stem: part(input.file_stem()),
+            parent: part(input.parent().and_then(Path::file_name)),
+            ext: part(input.extension()),
+            width: None,
+            height: None,
+            colors: None,
+        }
+    }
+}
+
+pub fn is_template(path: &str) -> bool {
+    path.contains('{')
+}
+
+pub fn expand_template(template: &str, values: &TemplateValues) -> Result<String> {
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("Unclosed placeholder in output template {}", template))?;
+        let name = &rest[start + 1..start + end];
+        let value = match name {
+            "stem" => Some(values.stem.clone()),
+            "parent" => Some(values.parent.clone()),
+            "ext" => Some(values.ext.clone()),
+            "width" => values.width.map(|w| w.to_string()),
+            "height" => values.height.map(|h| h.to_string()),
+            "colors" => values.colors.map(|c| c.to_string()),
+            other => bail!(
+                "Unknown placeholder {{{}}} in output template, expected stem, parent, ext, width, height or colors",
+                other
+            ),
+        };
+        let value = value.ok_or_else(|| anyhow!("{{{}}} is only known after processing", name))?;
+        result.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    ensure!(!result.is_empty(), "Output template {} is empty", template);
+    Ok(result)
+}
+
+// unknown placeholders fail before any image is processed, their values only exist afterwards
+pub fn check_template(template: &str) -> Result<()> {
+    let values = TemplateValues {
+        stem: "stem".to_string(),
+        parent: "parent".to_string(),
+        ext: "ext".to_string(),
+        width: Some(1),
+        height: Some(1),
+        colors: Some(1),
+    };
+    expand_template(template, &values).map(|_| ())
+}
+
+pub fn resolve_output(path: &str, overwrite: Overwrite) -> Result<String> {
+    resolve(path, overwrite, &HashSet::new())
+}
+
+// outputs handed out to the workers of one batch, each path goes to a single input
+#[derive(Default)]
+pub struct OutputClaims(Mutex<HashSet<String>>);
+
+impl OutputClaims {
+    pub fn resolve(&self, path: &str, overwrite: Overwrite) -> Result<String> {
+        let mut claimed = self
+            .0
+            .lock()
+            .map_err(|_| anyhow!("Output claims poisoned"))?;
+        let path = resolve(path, overwrite, &claimed)?;
+        claimed.insert(path.clone());
+        Ok(path)
+    }
+}
+
+fn resolve(path: &str, overwrite: Overwrite, claimed: &HashSet<String>) -> Result<String> {
+    // renaming skips claimed names, any other mode would write two results to one file
+    ensure!(
+        overwrite == Overwrite::Rename || !claimed.contains(path),
+        "Output {} is already used by another input of this batch",
+        path
+    );
+    if !Path::new(path).exists() && !claimed.contains(path) {
+        return Ok(path.to_string());
+    }
+    match overwrite {
+        Overwrite::Force => Ok(path.to_string()),
+        Overwrite::Error => bail!(
+            "Output {} already exists, use --force to overwrite it or --rename to keep both",
+            path
+        ),
+        Overwrite::Rename => {
+            let path = Path::new(path);
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let extension = path
+                .extension()
+                .map(|e| format!(".{}", e.to_string_lossy()))
+                .unwrap_or_default();
+            (1..)
+                .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, extension)))
+                .map(|candidate| candidate.to_string_lossy().into_owned())
+                .find(|candidate| !Path::new(candidate).exists() && !claimed.contains(candidate))
+                .ok_or_else(|| anyhow!("No free file name for {:?}", path))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freshness {
+    // missing, not made by pixelize or named after the processed image
+    Unknown,
+    Stale,
+    UpToDate,
+}
+
+// make-style freshness: the output is newer than the input and was made with the same settings
+pub fn freshness(input: &str, output: &str, config: &Config, options: &str) -> Freshness {
+    let output = match is_template(output) {
+        true => match expand_template(output, &TemplateValues::from_input(input)) {
+            Ok(output) => output,
+            Err(_) => return Freshness::Unknown,
+        },
+        false => output.to_string(),
+    };
+    let Ok((settings, _)) = load_settings(&output) else {
+        return Freshness::Unknown;
+    };
+    let modified = |path: &str| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let newer = matches!((modified(input), modified(&output)), (Some(i), Some(o)) if o >= i);
+    let same_options = load_options(&output).is_some_and(|saved| saved == options);
+    match newer && same_options && comparable(&settings).ok() == comparable(config).ok() {
+        true => Freshness::UpToDate,
+        false => Freshness::Stale,
+    }
+}
+
+// settings that do not change the result are left out of the comparison
+fn comparable(config: &Config) -> Result<serde_json::Value> {
+    let mut config = config.clone();
+    config.overwrite = None;
+    config.threads = None;
+    config.palette_cache = None;
+    Ok(serde_json::to_value(&config)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::{
+        OutputClaims, Overwrite, TemplateValues, check_template, expand_template, resolve_output,
+    };
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_expand_template() {
+        let values = TemplateValues {
+            width: Some(32),
+            height: Some(24),
+            colors: Some(16),
+            ..TemplateValues::from_input("sprites/hero.jpg")
+        };
+        let path = expand_template("{parent}/{stem}_{width}x{height}_{colors}c.{ext}", &values);
+        assert_eq!(path.unwrap(), "sprites/hero_32x24_16c.jpg");
+        assert_eq!(expand_template("out.png", &values).unwrap(), "out.png");
+
+        let input = TemplateValues::from_input("hero.png");
+        assert_eq!(expand_template("{stem}.{ext}", &input).unwrap(), "hero.png");
+        assert!(expand_template("{stem}_{colors}c.png", &input).is_err());
+        assert!(expand_template("{name}.png", &values).is_err());
+        assert!(expand_template("{stem.png", &values).is_err());
+        assert!(expand_template("", &values).is_err());
+
+        assert!(check_template("{stem}_{colors}c.png").is_ok());
+        assert!(check_template("{size}.png").is_err());
+    }
+
+    #[test]
+    fn test_resolve_output() {
+        let dir = env::temp_dir().join("pixelize_test_resolve_output");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let free = dir.join("free.png").to_string_lossy().into_owned();
+        let taken = dir.join("taken.png").to_string_lossy().into_owned();
+        fs::write(&taken, "").unwrap();
+
+        assert_eq!(resolve_output(&free, Overwrite::Error).unwrap(), free);
+        assert!(resolve_output(&taken, Overwrite::Error).is_err());
+        assert_eq!(resolve_output(&taken, Overwrite::Force).unwrap(), taken);
+        let renamed = resolve_output(&taken, Overwrite::Rename).unwrap();
+        assert!(renamed.ends_with("taken (1).png"), "{}", renamed);
+
+        let claims = OutputClaims::default();
+        assert_eq!(claims.resolve(&free, Overwrite::Error).unwrap(), free);
+        assert!(claims.resolve(&free, Overwrite::Force).is_err());
+        let renamed = claims.resolve(&free, Overwrite::Rename).unwrap();
+        assert!(renamed.ends_with("free (1).png"), "{}", renamed);
+        let renamed = claims.resolve(&free, Overwrite::Rename).unwrap();
+        assert!(renamed.ends_with("free (2).png"), "{}", renamed);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use libcrate::ProcessedImage;
+use libcrate::export::texturepacker::{AtlasFormat, save_atlas};
+use libcrate::spritesheet::pack;
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct PackArgs {
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+    #[arg(short = 'o', long = "output")]
+    output: PathBuf,
+    #[arg(long = "map")]
+    map: Option<PathBuf>,
+    #[arg(long = "max-width", default_value_t = 1024)]
+    max_width: u32,
+    #[arg(long = "padding", default_value_t = 0)]
+    padding: u32,
+    #[arg(long = "atlas")]
+    atlas: Option<PathBuf>,
+    #[arg(long = "atlas-format", default_value = "hash")]
+    atlas_format: AtlasFormat,
+}
+
+pub fn run(args: PackArgs) -> Result<()> {
+    println!("Loading {} images...", args.inputs.len());
+    let sprites = args
+        .inputs
+        .iter()
+        .map(|path| Ok((frame_name(path)?, ProcessedImage::new(path)?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    println!("Packing...");
+    let sheet = pack(&sprites, args.max_width, args.padding)?;
+
+    println!("Saving sheet to {:?}", args.output);
+    sheet.image.save(&args.output)?;
+
+    let map = args
+        .map
+        .unwrap_or_else(|| args.output.with_extension("json"));
+    println!("Saving frame map to {:?}", map);
+    sheet.save_frame_map(&map)?;
+
+    if let Some(atlas) = &args.atlas {
+        println!("Saving TexturePacker atlas to {:?}", atlas);
+        let frames = args
+            .inputs
+            .iter()
+            .zip(sheet.frames.iter())
+            .map(|(path, (_, rect))| Ok((file_name(path)?, *rect)))
+            .collect::<Result<Vec<_>>>()?;
+        save_atlas(
+            atlas,
+            &frames,
+            &file_name(&args.output)?,
+            (sheet.image.width(), sheet.image.height()),
+            args.atlas_format,
+        )?;
+    }
+
+    println!("Done.");
+    Ok(())
+}
+
+pub fn frame_name(path: &Path) -> Result<String> {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .with_context(|| format!("Invalid file name: {:?}", path))
+}
+
+pub fn file_name(path: &Path) -> Result<String> {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .with_context(|| format!("Invalid file name: {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pack::{PackArgs, run};
+    use libcrate::ProcessedImage;
+    use libcrate::export::texturepacker::AtlasFormat;
+    use libcrate::image_processing::rgb_from_tuple;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_pack_duplicate_names() {
+        let dir = env::temp_dir().join("pixelize_test_pack_duplicates");
+        let inputs = [
+            dir.join("a").join("hero.png"),
+            dir.join("b").join("hero.png"),
+        ];
+        for (input, color) in inputs.iter().zip([(255, 0, 0), (0, 0, 255)]) {
+            fs::create_dir_all(input.parent().unwrap()).unwrap();
+            let image = ProcessedImage::from_buffer(4, 4, &[rgb_from_tuple(color); 16]);
+            image.save(input).unwrap();
+        }
+        let atlas = dir.join("sheet_atlas.json");
+        let _ = fs::remove_file(&atlas);
+        let result = run(PackArgs {
+            inputs: inputs.to_vec(),
+            output: dir.join("sheet.png"),
+            map: None,
+            max_width: 64,
+            padding: 0,
+            atlas: Some(atlas.clone()),
+            atlas_format: AtlasFormat::Hash,
+        });
+        assert!(result.unwrap_err().to_string().contains("hero"));
+        assert!(!atlas.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use libcrate::export::palette::{PaletteFormat, save_palette_as};
+use libcrate::image_processing::save_palette;
+use libcrate::palette::{
+    HexPalette, detect_ramps, generate_ramps, lighten_palette, load_palette_file, saturate_palette,
+    score_palette, shift_palette_hue,
+};
+use libcrate::{Palette, ProcessedImage};
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct PaletteArgs {
+    #[command(subcommand)]
+    command: PaletteCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum PaletteCommand {
+    /// Scores how well palettes (.gpl, .hex or image) cover an image, best first
+    Score(ScoreArgs),
+    /// Shifts hue, lightness or saturation of every palette entry, e.g. for day/night variants
+    Adjust(AdjustArgs),
+    /// Builds shading ramps from key colors, with hue-shifted shadows and highlights
+    Ramps(RampsArgs),
+    /// Groups a palette into ramps by hue, each ordered by luminance
+    Analyze(AnalyzeArgs),
+}
+
+#[derive(Args, Debug)]
+struct ScoreArgs {
+    image: PathBuf,
+    #[arg(required = true)]
+    palettes: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct AdjustArgs {
+    palette: PathBuf,
+    #[arg(short, long)]
+    output: PathBuf,
+    #[arg(long, allow_negative_numbers = true)]
+    hue: Option<f32>,
+    #[arg(long, allow_negative_numbers = true)]
+    saturation: Option<f32>,
+    #[arg(long, allow_negative_numbers = true)]
+    lightness: Option<f32>,
+}
+
+#[derive(Args, Debug)]
+struct RampsArgs {
+    #[arg(required = true)]
+    colors: Vec<String>,
+    #[arg(short, long)]
+    output: PathBuf,
+    #[arg(long, default_value_t = 5)]
+    steps: usize,
+    #[arg(
+        long = "hue-shift",
+        default_value_t = 20.0,
+        allow_negative_numbers = true
+    )]
+    hue_shift: f32,
+}
+
+#[derive(Args, Debug)]
+struct AnalyzeArgs {
+    palette: PathBuf,
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    #[arg(long = "hue-band", default_value_t = 30.0)]
+    hue_band: f32,
+}
+
+pub fn run(args: PaletteArgs) -> Result<()> {
+    match args.command {
+        PaletteCommand::Score(args) => score(args),
+        PaletteCommand::Adjust(args) => adjust(args),
+        PaletteCommand::Ramps(args) => ramps(args),
+        PaletteCommand::Analyze(args) => analyze(args),
+    }
+}
+
+fn analyze(args: AnalyzeArgs) -> Result<()> {
+    let palette = load_palette_file(&args.palette)?;
+    let ramps = detect_ramps(&palette, args.hue_band);
+    println!(
+        "{} colors in {} ramps (darkest first):",
+        palette.len(),
+        ramps.len()
+    );
+    for ramp in &ramps {
+        let label = match ramp.hue {
+            Some(hue) => format!("hue {:3.0}", hue),
+            None => "gray".to_string(),
+        };
+        println!("  {}  {}", label, ramp.colors);
+    }
+    match &args.output {
+        Some(path) => {
+            let ordered = ramps
+                .into_iter()
+                .flat_map(|ramp| ramp.colors.0)
+                .collect::<Palette>();
+            save_output(path, &ordered)
+        }
+        None => Ok(()),
+    }
+}
+
+fn ramps(args: RampsArgs) -> Result<()> {
+    let keys = args.colors.join(",").parse::<HexPalette>()?;
+    let palette = generate_ramps(&keys, args.steps, args.hue_shift)?;
+    println!("Generated {} ramps of {} colors", keys.len(), args.steps);
+    save_output(&args.output, &palette)
+}
+
+fn adjust(args: AdjustArgs) -> Result<()> {
+    let mut palette = load_palette_file(&args.palette)?;
+    if let Some(degrees) = args.hue {
+        palette = shift_palette_hue(&palette, degrees);
+    }
+    if let Some(percent) = args.saturation {
+        palette = saturate_palette(&palette, percent);
+    }
+    if let Some(percent) = args.lightness {
+        palette = lighten_palette(&palette, percent);
+    }
+    save_output(&args.output, &palette)
+}
+
+fn save_output(path: &Path, palette: &Palette) -> Result<()> {
+    println!("Saving palette to {}", path.display());
+    match PaletteFormat::from_extension(path) {
+        Ok(format) => {
+            let name = path
+                .file_stem()
+                .map_or("palette".into(), |s| s.to_string_lossy());
+            save_palette_as(path, palette, format, &name)
+        }
+        Err(_) => save_palette(path, palette),
+    }
+}
+
+fn score(args: ScoreArgs) -> Result<()> {
+    println!("Loading image...");
+    let image = ProcessedImage::new(&args.image)?;
+    let mut scores = args
+        .palettes
+        .iter()
+        .map(|path| {
+            let palette = load_palette_file(path)?;
+            Ok((path, palette.len(), score_palette(&image.data, &palette)?))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    scores.sort_by(|a, b| a.2.mean.total_cmp(&b.2.mean));
+
+    println!("Delta E per pixel (lower is better):");
+    println!(
+        "  {:>8} {:>8} {:>8} {:>8} {:>7}  palette",
+        "mean", "median", "p95", "max", "colors"
+    );
+    for (path, colors, score) in scores {
+        println!(
+            "  {:8.2} {:8.2} {:8.2} {:8.2} {:7}  {}",
+            score.mean,
+            score.median,
+            score.p95,
+            score.max,
+            colors,
+            path.display()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,335 @@
+use crate::config::Config;
+use crate::output::{Overwrite, TemplateValues, check_template, expand_template, resolve_output};
+use crate::process::{load_color_reference, load_image, pixelize, prepare_stages};
+use crate::sidecar::palette_text;
+use anyhow::{Context, Result, anyhow};
+use clap::Args;
+use libcrate::ProcessedImage;
+use libcrate::export::palette::{PaletteFormat, save_palette_as};
+use libcrate::formats::png::save_with_text;
+use libcrate::pipeline::{PipelineStage, PipelineState, StageRegistry, StageSpec, stage_params};
+use libcrate::transfer::ColorReference;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    pipeline: PathBuf,
+    inputs: Vec<String>,
+    #[arg(long = "force", conflicts_with = "rename")]
+    force: bool,
+    #[arg(long = "rename")]
+    rename: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Pipeline {
+    #[serde(default)]
+    inputs: Vec<String>,
+    stages: Vec<StageSpec>,
+}
+
+impl Pipeline {
+    fn load(path: &Path) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Failed to open: {:?}", path))?;
+        let is_yaml = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("yaml") || e.eq_ignore_ascii_case("yml"));
+        let pipeline = match is_yaml {
+            true => serde_yaml::from_str(&content).map_err(anyhow::Error::from),
+            false => toml::from_str(&content).map_err(anyhow::Error::from),
+        };
+        pipeline.with_context(|| format!("Invalid pipeline file {:?}", path))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PathParams {
+    path: String,
+}
+
+struct PixelizeStage {
+    config: Config,
+    reference: Option<ColorReference>,
+}
+
+impl PixelizeStage {
+    fn config(&self, params: &Value) -> Result<Config> {
+        self.config
+            .with_overrides(&stage_params::<Map<String, Value>>(params)?)
+    }
+}
+
+impl PipelineStage for PixelizeStage {
+    fn name(&self) -> &str {
+        "pixelize"
+    }
+
+    fn apply(&self, state: &mut PipelineState, params: &Value) -> Result<()> {
+        let config = self.config(params)?;
+        // the shared reference is measured once, unless the stage points to another one
+        let reference = match config.color_reference == self.config.color_reference {
+            true => self.reference.clone(),
+            false => load_color_reference(&config)?,
+        };
+        let (image, palette) = pixelize(state.image.clone(), &config, reference.as_ref())?;
+        state.image = image;
+        state.palette = Some(palette);
+        Ok(())
+    }
+
+    fn check(&self, params: &Value) -> Result<()> {
+        let config = self.config(params)?;
+        crate::process::registry(&config, None, false)?.check(&prepare_stages(&config))
+    }
+}
+
+struct SaveStage {
+    input: String,
+    overwrite: Overwrite,
+}
+
+impl SaveStage {
+    fn output(&self, params: &Value, image: &ProcessedImage) -> Result<String> {
+        let params: PathParams = stage_params(params)?;
+        let path = expand_template(&params.path, &TemplateValues::new(&self.input, image))?;
+        resolve_output(&path, self.overwrite)
+    }
+}
+
+impl PipelineStage for SaveStage {
+    fn name(&self) -> &str {
+        "save"
+    }
+
+    fn check(&self, params: &Value) -> Result<()> {
+        check_template(&stage_params::<PathParams>(params)?.path)
+    }
+
+    fn apply(&self, state: &mut PipelineState, params: &Value) -> Result<()> {
+        let output = self.output(params, &state.image)?;
+        println!("Saving to {}", output);
+        let is_png = Path::new(&output)
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("png"));
+        if !is_png {
+            return state.image.save(&output);
+        }
+        // the pipeline has no single config, so only the palette is embedded
+        save_with_text(&output, &state.image, &palette_text(state.palette.as_ref()))
+    }
+}
+
+struct SavePaletteStage(SaveStage);
+
+impl PipelineStage for SavePaletteStage {
+    fn name(&self) -> &str {
+        "save-palette"
+    }
+
+    fn check(&self, params: &Value) -> Result<()> {
+        self.0.check(params)
+    }
+
+    fn apply(&self, state: &mut PipelineState, params: &Value) -> Result<()> {
+        let palette = state
+            .palette
+            .as_ref()
+            .ok_or_else(|| anyhow!("No palette to save, add a quantize or pixelize stage first"))?;
+        let output = self.0.output(params, &state.image)?;
+        println!("Saving palette to {}", output);
+        save_palette_as(
+            &output,
+            palette,
+            PaletteFormat::from_extension(&output)?,
+            "palette",
+        )
+    }
+}
+
+fn registry(
+    config: &Config,
+    reference: Option<&ColorReference>,
+    input: &str,
+    overwrite: Overwrite,
+) -> Result<StageRegistry> {
+    let mut registry = StageRegistry::with_builtins();
+    registry.register(PixelizeStage {
+        config: config.clone(),
+        reference: reference.cloned(),
+    })?;
+    registry.register(SaveStage {
+        input: input.to_string(),
+        overwrite,
+    })?;
+    registry.register(SavePaletteStage(SaveStage {
+        input: input.to_string(),
+        overwrite,
+    }))?;
+    Ok(registry)
+}
+
+pub fn run(args: RunArgs, config: Config) -> Result<()> {
+    let pipeline = Pipeline::load(&args.pipeline)?;
+    let inputs = match args.inputs.is_empty() {
+        true => pipeline.inputs.clone(),
+        false => args.inputs.clone(),
+    };
+    if inputs.is_empty() {
+        return Err(anyhow!(
+            "No input images, pass them after the pipeline file or list them as inputs in it"
+        ));
+    }
+    let overwrite = match (args.force, args.rename) {
+        (true, _) => Overwrite::Force,
+        (_, true) => Overwrite::Rename,
+        _ => config.overwrite.unwrap_or_default(),
+    };
+    // every stage is checked before the first input is loaded
+    registry(&config, None, "", overwrite)?.check(&pipeline.stages)?;
+    let reference = load_color_reference(&config)?;
+    for input in &inputs {
+        println!("Loading {}...", input);
+        let registry = registry(&config, reference.as_ref(), input, overwrite)?;
+        let mut state = PipelineState::new(load_image(input, &config)?);
+        for stage in &pipeline.stages {
+            println!("Running stage {}...", stage.name);
+            registry
+                .apply(stage, &mut state)
+                .with_context(|| format!("Failed to process {}", input))?;
+        }
+    }
+    println!("Processed {} images with {:?}", inputs.len(), args.pipeline);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::pipeline::{Pipeline, RunArgs, run};
+    use libcrate::ProcessedImage;
+    use libcrate::pipeline::StageSpec;
+    use serde_json::json;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    const TEST_IMAGE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../libcrate/assets/test_img_1.jpg"
+    );
+
+    fn write(dir: &str, name: &str, content: &str) -> PathBuf {
+        let dir = env::temp_dir().join(dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn run_pipeline(path: PathBuf, inputs: &[&str]) -> anyhow::Result<()> {
+        let args = RunArgs {
+            pipeline: path,
+            inputs: inputs.iter().map(|input| input.to_string()).collect(),
+            force: true,
+            rename: false,
+        };
+        run(args, Config::default())
+    }
+
+    #[test]
+    fn test_load_pipeline() {
+        let dir = "pixelize_test_load_pipeline";
+        let toml = "inputs = [\"a.png\"]\n[[stages]]\nname = \"scale\"\nparams = { width = 16 }\n";
+        let yaml = "inputs: [a.png]\nstages:\n  - name: scale\n    params: { width: 16 }\n";
+        let expected = vec![StageSpec::new("scale", json!({ "width": 16 }))];
+        for path in [
+            write(dir, "p.toml", toml),
+            write(dir, "p.yaml", yaml),
+            write(dir, "p.YML", yaml),
+        ] {
+            let pipeline = Pipeline::load(&path).unwrap();
+            assert_eq!(pipeline.inputs, ["a.png"]);
+            assert_eq!(pipeline.stages, expected);
+        }
+        // the extension picks the parser, and unknown keys are errors
+        assert!(Pipeline::load(&write(dir, "yaml.toml", yaml)).is_err());
+        let typo = format!("{}outputs: []\n", yaml);
+        let error = Pipeline::load(&write(dir, "typo.yaml", &typo)).unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("unknown field `outputs`"),
+            "{:#}",
+            error
+        );
+        fs::remove_dir_all(env::temp_dir().join(dir)).unwrap();
+    }
+
+    #[test]
+    fn test_run_pipeline() {
+        let dir = env::temp_dir().join("pixelize_test_run_pipeline");
+        let _ = fs::remove_dir_all(&dir);
+        let out = dir.to_string_lossy();
+        let pipeline = format!(
+            r#"
+            [[stages]]
+            name = "pixelize"
+            params = {{ desired_width = 8, desired_height = 6, number_of_colors = 3 }}
+            [[stages]]
+            name = "save"
+            params = {{ path = "{out}/{{stem}}_{{width}}x{{height}}.png" }}
+            [[stages]]
+            name = "save-palette"
+            params = {{ path = "{out}/{{stem}}.hex" }}
+            "#
+        );
+        let path = write("pixelize_test_run_pipeline", "p.toml", &pipeline);
+        run_pipeline(path, &[TEST_IMAGE]).unwrap();
+        let image = ProcessedImage::new(dir.join("test_img_1_8x6.png")).unwrap();
+        assert_eq!((image.width(), image.height()), (8, 6));
+        let palette = fs::read_to_string(dir.join("test_img_1.hex")).unwrap();
+        assert!((1..=3).contains(&palette.lines().count()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_pipeline_errors() {
+        let dir = "pixelize_test_pipeline_errors";
+        let stage = |name: &str, params: &str| {
+            format!("[[stages]]\nname = \"{name}\"\nparams = {params}\n")
+        };
+        // every stage is checked before the missing input is opened
+        for (stages, message) in [
+            (
+                stage("pixelize", "{ colours = 3 }"),
+                "Unknown config setting colours",
+            ),
+            (
+                stage("pixelize", "{ number_of_colors = 0 }"),
+                "number_of_colors",
+            ),
+            (stage("save", "{ path = \"{size}.png\" }"), "size"),
+            (stage("sharpen", "{}"), "sharpen"),
+        ] {
+            let path = write(dir, "p.toml", &format!("{}{}", stage("trim", "{}"), stages));
+            let error = run_pipeline(path, &["missing.png"]).unwrap_err();
+            assert!(format!("{:#}", error).contains(message), "{:#}", error);
+        }
+        let path = write(
+            dir,
+            "p.toml",
+            &stage("save-palette", "{ path = \"x.hex\" }"),
+        );
+        let error = run_pipeline(path, &[TEST_IMAGE]).unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("No palette to save"),
+            "{:#}",
+            error
+        );
+        fs::remove_dir_all(env::temp_dir().join(dir)).unwrap();
+    }
+}
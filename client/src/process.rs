@@ -0,0 +1,510 @@
+use crate::cache::PaletteCache;
+use crate::config::{ColorCount, Config};
+use anyhow::{Result, anyhow};
+use libcrate::dither::DitherOptions;
+use libcrate::image_processing::{
+    generate_shared_palette, generate_shared_weighted_palette, snap_size,
+};
+use libcrate::noise::NoiseOptions;
+use libcrate::palette::constrain_palette_hues;
+use libcrate::pipeline::{PipelineStage, PipelineState, StageRegistry, StageSpec};
+use libcrate::transfer::ColorReference;
+use libcrate::{
+    ColorWeight, DEFAULT_PROXY_PIXELS, Palette, ProcessedImage, ScaleFilter, ScaleMode,
+};
+use serde_json::{Value, json};
+use std::collections::HashSet;
+use std::path::Path;
+
+macro_rules! log {
+    ($verbose:expr, $($arg:tt)*) => {
+        if $verbose {
+            status!($($arg)*);
+        }
+    };
+}
+
+pub fn load_image<P>(path: P, config: &Config) -> Result<ProcessedImage>
+where
+    P: AsRef<Path>,
+{
+    match config.flatten {
+        Some(background) => ProcessedImage::open_flattened(path, background.0),
+        None => ProcessedImage::new(path),
+    }
+}
+
+pub fn prepare_image(image: &mut ProcessedImage, config: &Config, verbose: bool) -> Result<()> {
+    if let Some(key) = config.chroma_key {
+        log!(verbose, "Removing chroma key background...");
+        image.chroma_key(key.0, config.chroma_key_tolerance.unwrap_or(0));
+    }
+
+    if config.trim.unwrap_or(false) {
+        log!(verbose, "Trimming borders...");
+        image.trim(
+            config.trim_color.map(|color| color.0),
+            config.trim_tolerance.unwrap_or(0),
+            config.trim_padding.unwrap_or(0),
+        );
+    }
+
+    if let Some(preset) = config.preset {
+        log!(verbose, "Scaling for the {} preset...", preset);
+        preset.prepare(image);
+    } else {
+        scale_image(image, config, verbose)?;
+    }
+
+    if let Some(method) = config.auto_balance {
+        log!(verbose, "Balancing colors ({})...", method);
+        image.auto_balance(method);
+    }
+
+    if let Some(levels) = &config.levels {
+        log!(verbose, "Adjusting levels ({})...", levels);
+        image.levels(levels);
+    }
+
+    for curve in config.curves.iter().flatten() {
+        log!(verbose, "Applying curve ({})...", curve);
+        image.curve(curve);
+    }
+
+    if let Some(kind) = config.noise {
+        log!(verbose, "Adding noise...");
+        image.add_noise(&NoiseOptions {
+            seed: config.noise_seed.unwrap_or(0),
+            ..NoiseOptions::new(kind, config.noise_amplitude.unwrap_or(8.0))
+        });
+    }
+    Ok(())
+}
+
+fn scale_image(image: &mut ProcessedImage, config: &Config, verbose: bool) -> Result<()> {
+    let size = if let Some(max_dimension) = config.max_dimension {
+        log!(verbose, "Uniform scaling by the longest side...");
+        Some((
+            image.uniform_size_for_max_dimension(max_dimension),
+            ScaleMode::Stretch,
+        ))
+    } else if let Some(percent) = config.scale_percent {
+        log!(verbose, "Scaling to {}%...", percent);
+        Some((image.size_for_percent(percent), ScaleMode::Stretch))
+    } else if config.uniform_scale_by_width {
+        log!(verbose, "Uniform scaling by width...");
+        Some((
+            image.uniform_size_for_width(config.desired_width.unwrap()),
+            ScaleMode::Stretch,
+        ))
+    } else if config.uniform_scale_by_height {
+        log!(verbose, "Uniform scaling by height...");
+        Some((
+            image.uniform_size_for_height(config.desired_height.unwrap()),
+            ScaleMode::Stretch,
+        ))
+    } else if config.desired_width.is_none() && config.desired_height.is_none() {
+        log!(verbose, "Skipping scaling");
+        None
+    } else {
+        let mode = config.scale_mode.unwrap_or_default();
+        log!(verbose, "Scaling by width and height ({:?})...", mode);
+        Some((
+            (
+                config.desired_width.unwrap(),
+                config.desired_height.unwrap(),
+            ),
+            mode,
+        ))
+    };
+
+    let size = match (size, config.snap_to_multiple) {
+        (Some(((width, height), mode)), Some(multiple)) => {
+            log!(
+                verbose,
+                "Snapping the size to a multiple of {}...",
+                multiple
+            );
+            Some((snap_size(width, height, multiple), mode))
+        }
+        _ => size,
+    };
+
+    if let Some(((width, height), mode)) = size {
+        image.scale_to(
+            width,
+            height,
+            mode,
+            config.scale_filter.unwrap_or_default(),
+            config.linear_scaling.unwrap_or(false),
+            config.letterbox_color.map(|color| color.0),
+        )?;
+    }
+    Ok(())
+}
+
+pub fn load_color_reference(config: &Config) -> Result<Option<ColorReference>> {
+    config
+        .color_reference
+        .as_ref()
+        .map(|path| Ok(ColorReference::new(&ProcessedImage::new(path)?.data)))
+        .transpose()
+}
+
+pub fn match_colors(
+    image: &mut ProcessedImage,
+    reference: Option<&ColorReference>,
+    config: &Config,
+    verbose: bool,
+) {
+    if let Some(reference) = reference {
+        let transfer = config.color_transfer.unwrap_or_default();
+        log!(
+            verbose,
+            "Matching colors to the reference ({})...",
+            transfer
+        );
+        image.transfer_colors(reference, transfer);
+    }
+}
+
+struct PrepareStage {
+    config: Config,
+    reference: Option<ColorReference>,
+    verbose: bool,
+}
+
+impl PipelineStage for PrepareStage {
+    fn name(&self) -> &str {
+        "prepare"
+    }
+
+    fn apply(&self, state: &mut PipelineState, _: &Value) -> Result<()> {
+        prepare_image(&mut state.image, &self.config, self.verbose)?;
+        match_colors(
+            &mut state.image,
+            self.reference.as_ref(),
+            &self.config,
+            self.verbose,
+        );
+        Ok(())
+    }
+}
+
+// keeps a palette set by an earlier stage, e.g. quantize or bit-depth
+struct PaletteStage(Config);
+
+impl PipelineStage for PaletteStage {
+    fn name(&self) -> &str {
+        "palette"
+    }
+
+    fn apply(&self, state: &mut PipelineState, _: &Value) -> Result<()> {
+        if state.palette.is_none() {
+            state.palette = Some(build_palette(&state.image, &self.0)?);
+        }
+        Ok(())
+    }
+}
+
+struct ApplyPaletteStage {
+    config: Config,
+    verbose: bool,
+}
+
+impl PipelineStage for ApplyPaletteStage {
+    fn name(&self) -> &str {
+        "apply-palette"
+    }
+
+    fn apply(&self, state: &mut PipelineState, _: &Value) -> Result<()> {
+        let palette = state
+            .palette
+            .as_ref()
+            .ok_or_else(|| anyhow!("No palette to apply, add a palette stage first"))?;
+        // bit depth and threshold results need mapping only once their palette was reduced further
+        let reduced = self.config.bit_depth.is_some() || self.config.threshold.is_some();
+        if reduced && uses_only(&state.image, palette) {
+            return Ok(());
+        }
+        log!(self.verbose, "Applying palette...");
+        apply_palette(&mut state.image, palette, &self.config);
+        Ok(())
+    }
+}
+
+fn uses_only(image: &ProcessedImage, palette: &Palette) -> bool {
+    let colors = palette.iter().collect::<HashSet<_>>();
+    image.data.pixels().all(|pixel| colors.contains(pixel))
+}
+
+struct PadStage(Config);
+
+impl PipelineStage for PadStage {
+    fn name(&self) -> &str {
+        "pad"
+    }
+
+    fn apply(&self, state: &mut PipelineState, _: &Value) -> Result<()> {
+        pad_image(&mut state.image, &self.0)
+    }
+}
+
+// the built-in stages plus the steps of the regular pipeline, all bound to one config
+pub fn registry(
+    config: &Config,
+    reference: Option<ColorReference>,
+    verbose: bool,
+) -> Result<StageRegistry> {
+    let mut registry = StageRegistry::with_builtins();
+    registry.register(PrepareStage {
+        config: config.clone(),
+        reference,
+        verbose,
+    })?;
+    registry.register(PaletteStage(config.clone()))?;
+    registry.register(ApplyPaletteStage {
+        config: config.clone(),
+        verbose,
+    })?;
+    registry.register(PadStage(config.clone()))?;
+    Ok(registry)
+}
+
+// everything before the palette: preparation, the configured stages and direct color reduction
+pub fn prepare_stages(config: &Config) -> Vec<StageSpec> {
+    let mut specs = vec![StageSpec::new("prepare", Value::Null)];
+    specs.extend(config.stages.iter().flatten().cloned());
+    let options = dither_options(config);
+    let dither = json!({
+        "method": options.method,
+        "strength": options.strength,
+        "error_clamp": options.error_clamp,
+        "serpentine": options.serpentine,
+        "traversal": options.traversal,
+    });
+    if let Some(depth) = config.bit_depth {
+        let params = json!({ "depth": depth, "dither": dither });
+        specs.push(StageSpec::new("bit-depth", params));
+    } else if let Some(threshold) = config.threshold {
+        let params = json!({ "threshold": threshold, "dither": dither });
+        specs.push(StageSpec::new("threshold", params));
+    }
+    specs
+}
+
+pub fn palette_stages() -> Vec<StageSpec> {
+    vec![
+        StageSpec::new("palette", Value::Null),
+        StageSpec::new("apply-palette", Value::Null),
+    ]
+}
+
+// everything after the palette was applied
+pub fn finish_stages(config: &Config, image: &ProcessedImage) -> Vec<StageSpec> {
+    let mut specs = vec![];
+    if let Some(outline) = config.outline {
+        if image.alpha.is_some() {
+            specs.push(StageSpec::new("outline", json!({ "color": outline })));
+        } else {
+            status!("Warning: outline needs a transparent background (chroma_key), skipping.");
+        }
+    }
+    specs.push(StageSpec::new("pad", Value::Null));
+    specs
+}
+
+// the regular single-image pipeline without the output-specific steps
+pub fn pixelize(
+    image: ProcessedImage,
+    config: &Config,
+    reference: Option<&ColorReference>,
+) -> Result<(ProcessedImage, Palette)> {
+    let registry = registry(config, reference.cloned(), false)?;
+    let mut state = PipelineState::new(image);
+    registry.run(&prepare_stages(config), &mut state)?;
+    registry.run(&palette_stages(), &mut state)?;
+    registry.run(&finish_stages(config, &state.image), &mut state)?;
+    let palette = state
+        .palette
+        .ok_or_else(|| anyhow!("The pipeline produced no palette"))?;
+    Ok((state.image, palette))
+}
+
+pub fn build_palette(image: &ProcessedImage, config: &Config) -> Result<Palette> {
+    if let Some(preset) = config.preset {
+        status!("Using {} palette...", preset);
+        return Ok(preset.palette());
+    }
+    if config.use_custom_palette {
+        status!("Using custom palette...");
+        return Ok(config.custom_palette.to_vec());
+    }
+    let Some(dir) = &config.palette_cache else {
+        return generate_palette(image, config);
+    };
+    let cache = PaletteCache::open(dir)?;
+    let key = PaletteCache::key(image, config)?;
+    if let Some(palette) = cache.get(&key) {
+        status!("Using cached palette {}...", &key[..12]);
+        return Ok(palette);
+    }
+    let palette = generate_palette(image, config)?;
+    // the palette is already there, a cache that cannot be written only costs the next run
+    if let Err(error) = cache.put(&key, &palette) {
+        status!("Warning: {:#}, the palette is not cached.", error);
+    }
+    Ok(palette)
+}
+
+fn generate_palette(image: &ProcessedImage, config: &Config) -> Result<Palette> {
+    // blending would shift the exact background pixels the weighted palette looks for
+    let filter = match config.ignore_color {
+        Some(_) => ScaleFilter::Nearest,
+        None => ScaleFilter::Triangle,
+    };
+    let max_pixels = config.palette_proxy_pixels.unwrap_or(DEFAULT_PROXY_PIXELS);
+    let mut proxy = image.palette_proxy(max_pixels, filter);
+    let hue_ranges = config.hue_ranges.as_deref().unwrap_or_default();
+    if !hue_ranges.is_empty() {
+        status!("Constraining palette hues...");
+        proxy.to_mut().constrain_hues(hue_ranges);
+    }
+    let image = proxy.as_ref();
+    let palette = if let Some(options) = config.hue_lightness {
+        status!("Generating {} hue/lightness palette...", options);
+        image.generate_hue_lightness_palette(&options)
+    } else if let Some(weight) = background_weight(config) {
+        status!("Generating palette with weighted background color...");
+        image.generate_weighted_palette(
+            config.sample_factor.unwrap(),
+            number_of_colors(image, config),
+            &[weight],
+        )?
+    } else if let Some(strength) = config.contrast_weight
+        && strength > 0.0
+    {
+        status!("Generating contrast weighted palette...");
+        image.generate_contrast_palette(
+            config.sample_factor.unwrap(),
+            number_of_colors(image, config),
+            strength,
+        )
+    } else {
+        status!("Generating palette...");
+        image.generate_image_palette(
+            config.sample_factor.unwrap(),
+            number_of_colors(image, config),
+        )
+    };
+    // averaged colors can still fall between two ranges
+    Ok(constrain_palette_hues(&palette, hue_ranges))
+}
+
+// one palette for all frames of a sequence or video
+pub fn build_shared_palette(frames: &[&ProcessedImage], config: &Config) -> Result<Palette> {
+    if let Some(preset) = config.preset {
+        status!("Using {} palette...", preset);
+        return Ok(preset.palette());
+    }
+    if config.use_custom_palette {
+        status!("Using custom palette...");
+        return Ok(config.custom_palette.to_vec());
+    }
+    status!("Generating shared palette...");
+    let samples = frames.iter().map(|frame| &frame.data).collect::<Vec<_>>();
+    let sample_factor = config.sample_factor.unwrap();
+    let colors = number_of_colors(frames[0], config);
+    match background_weight(config) {
+        Some(weight) => {
+            generate_shared_weighted_palette(&samples, sample_factor, colors, &[weight])
+        }
+        None => Ok(generate_shared_palette(&samples, sample_factor, colors)),
+    }
+}
+
+fn background_weight(config: &Config) -> Option<ColorWeight> {
+    config.ignore_color.map(|color| ColorWeight {
+        color: color.0,
+        tolerance: config.ignore_color_tolerance.unwrap_or(0),
+        weight: config.ignore_color_weight.unwrap_or(0.0),
+    })
+}
+
+pub fn number_of_colors(image: &ProcessedImage, config: &Config) -> usize {
+    match config.number_of_colors.unwrap() {
+        ColorCount::Fixed(count) => count,
+        ColorCount::Auto => {
+            let count = image.auto_color_count(
+                config.sample_factor.unwrap(),
+                config.auto_max_colors.unwrap_or(64),
+                config.auto_max_delta_e.unwrap_or(5.0),
+            );
+            status!("Selected {} colors", count);
+            count
+        }
+    }
+}
+
+pub fn dither_options(config: &Config) -> DitherOptions {
+    let dither = config.dither.or(config.preset.map(|p| p.dither()));
+    DitherOptions {
+        strength: config.dither_strength.unwrap_or(1.0),
+        error_clamp: config.dither_error_clamp,
+        serpentine: config.dither_serpentine.unwrap_or(false),
+        traversal: config.traversal.unwrap_or_default(),
+        ..DitherOptions::new(dither.unwrap_or_default())
+    }
+}
+
+pub fn pad_image(image: &mut ProcessedImage, config: &Config) -> Result<()> {
+    if let Some((width, height)) = config.pad_to {
+        image.pad_to(
+            width,
+            height,
+            config.pad_anchor.unwrap_or_default(),
+            config.pad_color.map(|color| color.0),
+        )?;
+    }
+    if let Some(mode) = config.power_of_two {
+        image.power_of_two(
+            mode,
+            config.pad_anchor.unwrap_or_default(),
+            config.pad_color.map(|color| color.0),
+        );
+    }
+    Ok(())
+}
+
+pub fn apply_palette(image: &mut ProcessedImage, palette: &Palette, config: &Config) {
+    image.dither_with(palette, &dither_options(config));
+    finish_preset(image, config);
+}
+
+// the mapped frame before the preset finish, kept only when the next frame is held to it
+pub fn apply_palette_stable(
+    image: &mut ProcessedImage,
+    palette: &Palette,
+    config: &Config,
+    previous: Option<&ProcessedImage>,
+) -> Option<ProcessedImage> {
+    let Some(hysteresis) = config.temporal_hysteresis else {
+        apply_palette(image, palette, config);
+        return None;
+    };
+    match previous {
+        Some(previous) => {
+            image.dither_stable(palette, &dither_options(config), &previous.data, hysteresis)
+        }
+        None => image.dither_with(palette, &dither_options(config)),
+    }
+    let mapped = image.clone();
+    finish_preset(image, config);
+    Some(mapped)
+}
+
+fn finish_preset(image: &mut ProcessedImage, config: &Config) {
+    if let Some(preset) = config.preset {
+        preset.finish(image);
+    }
+}
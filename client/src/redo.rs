@@ -0,0 +1,114 @@
+use crate::output::{OutputClaims, Overwrite};
+use crate::process::load_color_reference;
+use crate::sidecar::load_settings;
+use anyhow::{Result, anyhow};
+use clap::Args;
+use libcrate::palette::HexPalette;
+
+#[derive(Args, Debug)]
+pub struct RedoArgs {
+    input: String,
+    #[arg(long = "like")]
+    like: String,
+    #[arg(short = 'o', long = "output")]
+    output: Option<String>,
+    #[arg(long = "reuse-palette")]
+    reuse_palette: bool,
+    #[arg(long = "force", conflicts_with = "rename")]
+    force: bool,
+    #[arg(long = "rename")]
+    rename: bool,
+}
+
+pub fn run(args: RedoArgs) -> Result<()> {
+    println!("Reading settings of {}...", args.like);
+    let (mut config, palette) = load_settings(&args.like)?;
+    // how the previous output was written is not a processing setting
+    config.overwrite = if args.force {
+        Some(Overwrite::Force)
+    } else if args.rename {
+        Some(Overwrite::Rename)
+    } else {
+        None
+    };
+    if args.reuse_palette {
+        let palette = palette.ok_or_else(|| anyhow!("{} has no palette to reuse", args.like))?;
+        config.custom_palette = HexPalette(palette);
+        config.use_custom_palette = true;
+    }
+    // without -o the previous output is the target, still guarded by --force / --rename
+    let output = args.output.unwrap_or(args.like);
+    let cli = crate::parse_args(["pixelize", &args.input, &output]);
+    let config = crate::apply_args(&cli, config)?;
+    let reference = load_color_reference(&config)?;
+    let claims = OutputClaims::default();
+    crate::run_with_config(
+        &cli,
+        config,
+        reference.as_ref(),
+        &claims,
+        &args.input,
+        &output,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{ColorCount, Config};
+    use crate::redo::{RedoArgs, run};
+    use crate::sidecar::{load_settings, png_text};
+    use libcrate::ProcessedImage;
+    use libcrate::formats::png::save_with_text;
+    use libcrate::image_processing::rgb_from_tuple;
+    use std::collections::HashSet;
+    use std::env;
+    use std::fs;
+
+    const TEST_IMAGE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../libcrate/assets/test_img_1.jpg"
+    );
+
+    #[test]
+    fn test_redo() {
+        let dir = env::temp_dir().join("pixelize_test_redo");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let palette = vec![rgb_from_tuple((255, 0, 0)), rgb_from_tuple((0, 0, 255))];
+        let config = Config {
+            number_of_colors: Some(ColorCount::Fixed(3)),
+            desired_width: Some(10),
+            desired_height: Some(7),
+            ..Config::default()
+        };
+        let previous = ProcessedImage::from_buffer(1, 1, &palette[..1]);
+        let text = png_text(Some(&palette), &config, None).unwrap();
+        save_with_text(path("previous.png"), &previous, &text).unwrap();
+
+        let redo = |output: &str, reuse_palette: bool| {
+            run(RedoArgs {
+                input: TEST_IMAGE.to_string(),
+                like: path("previous.png"),
+                output: Some(path(output)),
+                reuse_palette,
+                force: false,
+                rename: false,
+            })
+            .unwrap();
+            ProcessedImage::new(path(output)).unwrap()
+        };
+        let colors = |image: &ProcessedImage| image.data.pixels().copied().collect::<HashSet<_>>();
+
+        let settings = redo("settings.png", false);
+        assert_eq!((settings.width(), settings.height()), (10, 7));
+        assert!(colors(&settings).len() <= 3);
+        let (stored, _) = load_settings(&path("settings.png")).unwrap();
+        assert_eq!(stored.desired_width, Some(10));
+
+        let reused = redo("reused.png", true);
+        assert_eq!((reused.width(), reused.height()), (10, 7));
+        assert!(colors(&reused).iter().all(|color| palette.contains(color)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+use anyhow::{Context, Result, anyhow};
+use libcrate::palette::{HexPalette, PaletteScore};
+use libcrate::{Palette, ProcessedImage};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+// set while stdout carries the JSON reports, progress messages then go to stderr
+static STDOUT_RESERVED: AtomicBool = AtomicBool::new(false);
+// batch workers emit whole documents, never interleaved halves
+static EMIT_LOCK: Mutex<()> = Mutex::new(());
+
+pub fn reserve_stdout() {
+    STDOUT_RESERVED.store(true, Ordering::Relaxed);
+}
+
+pub fn stdout_reserved() -> bool {
+    STDOUT_RESERVED.load(Ordering::Relaxed)
+}
+
+thread_local! {
+    // the input of a parallel batch worker, so its progress messages can be told apart
+    static STATUS_LABEL: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+pub fn set_status_label(label: Option<&str>) {
+    STATUS_LABEL.with_borrow_mut(|current| *current = label.map(str::to_string));
+}
+
+pub fn status_line(message: fmt::Arguments) -> String {
+    STATUS_LABEL.with_borrow(|label| match label {
+        Some(label) => format!("[{}] {}", label, message),
+        None => message.to_string(),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(anyhow!("Unknown report format {}, expected json", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl From<&ProcessedImage> for Size {
+    fn from(image: &ProcessedImage) -> Self {
+        Size {
+            width: image.width(),
+            height: image.height(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Timing {
+    pub load_ms: f64,
+    pub prepare_ms: f64,
+    pub palette_ms: f64,
+    pub apply_ms: f64,
+    pub save_ms: f64,
+    pub total_ms: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub input: String,
+    pub output: String,
+    pub source: Size,
+    pub result: Size,
+    pub palette: HexPalette,
+    pub timing: Timing,
+    pub quality: Option<PaletteScore>,
+}
+
+impl Report {
+    pub fn new(input: &str, output: &str) -> Self {
+        Report {
+            input: input.to_string(),
+            output: output.to_string(),
+            ..Report::default()
+        }
+    }
+
+    pub fn set_palette(&mut self, palette: &Palette) {
+        self.palette = HexPalette(palette.clone());
+    }
+
+    // one JSON document per line, so batches and sequences report every file
+    pub fn emit(&self, format: ReportFormat, path: Option<&str>) -> Result<()> {
+        let line = match format {
+            ReportFormat::Json => serde_json::to_string(self)?,
+        };
+        let _lock = EMIT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        match path {
+            Some(path) => OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{}", line))
+                .with_context(|| format!("Failed to save: {:?}", path)),
+            None => {
+                println!("{}", line);
+                Ok(())
+            }
+        }
+    }
+}
+
+pub struct Stopwatch {
+    start: Instant,
+    last: Instant,
+}
+
+impl Stopwatch {
+    pub fn start() -> Self {
+        let now = Instant::now();
+        Stopwatch {
+            start: now,
+            last: now,
+        }
+    }
+
+    pub fn lap(&mut self) -> f64 {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last);
+        self.last = now;
+        elapsed.as_secs_f64() * 1000.0
+    }
+
+    pub fn total(&self) -> f64 {
+        self.start.elapsed().as_secs_f64() * 1000.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::report::{Report, ReportFormat, Size};
+    use libcrate::image_processing::rgb_from_tuple;
+    use serde_json::Value;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_report() {
+        let mut report = Report::new("in.jpg", "out.png");
+        report.source = Size {
+            width: 640,
+            height: 480,
+        };
+        report.set_palette(&vec![
+            rgb_from_tuple((255, 0, 0)),
+            rgb_from_tuple((0, 16, 255)),
+        ]);
+        report.timing.total_ms = 12.5;
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["input"], "in.jpg");
+        assert_eq!(json["source"]["width"], 640);
+        assert_eq!(json["palette"], serde_json::json!(["#ff0000", "#0010ff"]));
+        assert_eq!(json["timing"]["total_ms"], 12.5);
+        assert_eq!(json["quality"], Value::Null);
+
+        // every report is one line of the file
+        let path = env::temp_dir().join("pixelize_test_report.jsonl");
+        let _ = fs::remove_file(&path);
+        let path = path.to_string_lossy().into_owned();
+        report.emit(ReportFormat::Json, Some(&path)).unwrap();
+        report.emit(ReportFormat::Json, Some(&path)).unwrap();
+        let lines = fs::read_to_string(&path).unwrap();
+        assert_eq!(lines.lines().count(), 2);
+        for line in lines.lines() {
+            assert_eq!(serde_json::from_str::<Value>(line).unwrap(), json);
+        }
+        assert!("yaml".parse::<ReportFormat>().is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
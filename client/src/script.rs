@@ -0,0 +1,272 @@
+use crate::config::Config;
+use crate::output::{Overwrite, resolve_output};
+use crate::process::{load_color_reference, load_image, pixelize};
+use anyhow::{Context, Result, anyhow};
+use clap::Args;
+use libcrate::dither::{Dither, DitherOptions};
+use libcrate::palette::load_palette_file;
+use libcrate::pipeline::{PipelineState, StageRegistry, StageSpec};
+use libcrate::preset::Preset;
+use libcrate::{Palette, ProcessedImage, ScaleFilter};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct ScriptArgs {
+    #[arg(long = "force", conflicts_with = "rename")]
+    force: bool,
+    #[arg(long = "rename")]
+    rename: bool,
+    script: PathBuf,
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
+}
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+fn script_error(error: anyhow::Error) -> Box<EvalAltResult> {
+    format!("{:#}", error).into()
+}
+
+// script integers are i64, sizes and counts must fit and not be zero
+fn positive(value: i64, what: &str) -> ScriptResult<u32> {
+    u32::try_from(value)
+        .ok()
+        .filter(|&value| value > 0)
+        .ok_or_else(|| {
+            script_error(anyhow!(
+                "Invalid {} {}, expected a positive number",
+                what,
+                value
+            ))
+        })
+}
+
+#[derive(Clone)]
+struct Image {
+    image: ProcessedImage,
+    palette: Option<Palette>,
+    config: Config,
+}
+
+// config overrides from a script map, e.g. #{ number_of_colors: 16, dither: "bayer4" }
+fn process(image: &mut Image, overrides: Map) -> Result<()> {
+    let config = image
+        .config
+        .with_overrides(&rhai::serde::from_dynamic(&overrides.into())?)?;
+    let reference = load_color_reference(&config)?;
+    let (processed, palette) = pixelize(image.image.clone(), &config, reference.as_ref())?;
+    image.image = processed;
+    image.palette = Some(palette);
+    image.config = config;
+    Ok(())
+}
+
+fn stage(image: &mut Image, name: &str, params: Dynamic) -> Result<()> {
+    let spec = StageSpec {
+        name: name.to_string(),
+        params: rhai::serde::from_dynamic(&params)?,
+    };
+    let mut state = PipelineState::new(image.image.clone());
+    state.palette = image.palette.clone();
+    StageRegistry::with_builtins().apply(&spec, &mut state)?;
+    image.image = state.image;
+    image.palette = state.palette;
+    Ok(())
+}
+
+fn engine(config: Config) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .register_type_with_name::<Image>("Image")
+        .register_type_with_name::<Palette>("Palette")
+        .register_fn("load", move |path: &str| -> ScriptResult<Image> {
+            Ok(Image {
+                image: load_image(path, &config).map_err(script_error)?,
+                palette: None,
+                config: config.clone(),
+            })
+        })
+        .register_get("width", |image: &mut Image| image.image.width() as i64)
+        .register_get("height", |image: &mut Image| image.image.height() as i64)
+        .register_get("colors", |image: &mut Image| {
+            image.image.get_color_histogram().len() as i64
+        })
+        .register_get("palette", |image: &mut Image| -> ScriptResult<Palette> {
+            image
+                .palette
+                .clone()
+                .ok_or_else(|| "The image has no palette yet".into())
+        })
+        .register_fn(
+            "scale",
+            |image: &mut Image, width: i64, height: i64| -> ScriptResult<()> {
+                let (width, height) = (positive(width, "width")?, positive(height, "height")?);
+                image.image.scale(width, height, ScaleFilter::default());
+                Ok(())
+            },
+        )
+        .register_fn(
+            "scale_width",
+            |image: &mut Image, width: i64| -> ScriptResult<()> {
+                let width = positive(width, "width")?;
+                image
+                    .image
+                    .uniform_scale_width(width, ScaleFilter::default());
+                Ok(())
+            },
+        )
+        .register_fn(
+            "scale_height",
+            |image: &mut Image, height: i64| -> ScriptResult<()> {
+                let height = positive(height, "height")?;
+                image
+                    .image
+                    .uniform_scale_height(height, ScaleFilter::default());
+                Ok(())
+            },
+        )
+        .register_fn("process", |image: &mut Image| -> ScriptResult<()> {
+            process(image, Map::new()).map_err(script_error)
+        })
+        .register_fn(
+            "process",
+            |image: &mut Image, overrides: Map| -> ScriptResult<()> {
+                process(image, overrides).map_err(script_error)
+            },
+        )
+        .register_fn(
+            "stage",
+            |image: &mut Image, name: &str| -> ScriptResult<()> {
+                stage(image, name, Dynamic::UNIT).map_err(script_error)
+            },
+        )
+        .register_fn(
+            "stage",
+            |image: &mut Image, name: &str, params: Map| -> ScriptResult<()> {
+                stage(image, name, params.into()).map_err(script_error)
+            },
+        )
+        .register_fn("apply", |image: &mut Image, palette: Palette| {
+            image.image.apply_palette(&palette);
+            image.palette = Some(palette);
+        })
+        .register_fn(
+            "dither",
+            |image: &mut Image, palette: Palette, method: &str| -> ScriptResult<()> {
+                let method = method.parse::<Dither>().map_err(script_error)?;
+                image
+                    .image
+                    .dither_with(&palette, &DitherOptions::new(method));
+                image.palette = Some(palette);
+                Ok(())
+            },
+        )
+        .register_fn(
+            "generate_palette",
+            |image: &mut Image, colors: i64| -> ScriptResult<Palette> {
+                let colors = positive(colors, "number of colors")? as usize;
+                Ok(image.image.generate_image_palette(10, colors))
+            },
+        )
+        .register_fn(
+            "save",
+            |image: &mut Image, path: &str| -> ScriptResult<()> {
+                let overwrite = image.config.overwrite.unwrap_or_default();
+                let path = resolve_output(path, overwrite).map_err(script_error)?;
+                crate::save_image(
+                    &image.image,
+                    &path,
+                    image.palette.as_ref(),
+                    &image.config,
+                    None,
+                    false,
+                )
+                .map_err(script_error)
+            },
+        )
+        .register_fn("palette_file", |path: &str| -> ScriptResult<Palette> {
+            load_palette_file(path).map_err(script_error)
+        })
+        .register_fn("preset_palette", |name: &str| -> ScriptResult<Palette> {
+            Ok(name.parse::<Preset>().map_err(script_error)?.palette())
+        })
+        .register_get("len", |palette: &mut Palette| palette.len() as i64);
+    engine
+}
+
+pub fn run(args: ScriptArgs, mut config: Config) -> Result<()> {
+    if !config.is_valid() {
+        return Err(anyhow!("Config is not valid."));
+    }
+    if args.force {
+        config.overwrite = Some(Overwrite::Force);
+    } else if args.rename {
+        config.overwrite = Some(Overwrite::Rename);
+    }
+    let source = fs::read_to_string(&args.script)
+        .with_context(|| format!("Failed to open: {:?}", args.script))?;
+    let engine = engine(config);
+    let mut scope = Scope::new();
+    scope.push(
+        "args",
+        args.args.into_iter().map(Dynamic::from).collect::<Array>(),
+    );
+    engine
+        .run_with_scope(&mut scope, &source)
+        .map_err(|e| anyhow!("Script {:?} failed: {}", args.script, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::script::engine;
+
+    const TEST_IMAGE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../libcrate/assets/test_img_1.jpg"
+    );
+
+    #[test]
+    fn test_script_engine() {
+        let engine = engine(Config::default());
+        let script = format!(
+            r#"
+            let image = load("{}");
+            image.scale(40, 30);
+            image.process(#{{ number_of_colors: 4, desired_width: 16, desired_height: 12 }});
+            let processed = [image.width, image.height, image.palette.len];
+            image.apply(preset_palette("gameboy"));
+            image.stage("dither", #{{ method: "bayer4" }});
+            processed + [image.colors]
+            "#,
+            TEST_IMAGE
+        );
+        let result = engine.eval::<rhai::Array>(&script).unwrap();
+        let result = result
+            .into_iter()
+            .map(|v| v.as_int().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(result[..2], [16, 12]);
+        assert!((1..=4).contains(&result[2]));
+        assert!((1..=4).contains(&result[3]));
+    }
+
+    #[test]
+    fn test_script_errors() {
+        let engine = engine(Config::default());
+        let load = format!(r#"let image = load("{}");"#, TEST_IMAGE);
+        for (call, message) in [
+            ("image.scale(-4, 8);", "Invalid width -4"),
+            ("image.scale_width(0);", "Invalid width 0"),
+            ("image.stage(\"sharpen\");", "sharpen"),
+            ("image.palette;", "no palette"),
+            ("palette_file(\"missing.hex\");", "missing.hex"),
+            ("preset_palette(\"nes2\");", "nes2"),
+        ] {
+            let error = engine.run(&format!("{} {}", load, call)).unwrap_err();
+            assert!(error.to_string().contains(message), "{}", error);
+        }
+    }
+}
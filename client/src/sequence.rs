@@ -0,0 +1,186 @@
+use crate::config::Config;
+use crate::output::{
+    Overwrite, TemplateValues, check_template, expand_template, is_template, resolve_output,
+};
+use crate::process::{
+    apply_palette, apply_palette_stable, build_palette, build_shared_palette, load_image,
+    match_colors, pad_image, prepare_image,
+};
+use crate::report::{Report, ReportFormat, Stopwatch};
+use anyhow::{Context, Result, ensure};
+use libcrate::palette::score_palette;
+use libcrate::preview::montage;
+use libcrate::sequence::{detect_frames, format_pattern, is_pattern};
+use libcrate::transfer::ColorReference;
+use libcrate::{Palette, ProcessedImage};
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+pub struct SequenceOptions {
+    pub frames: Option<RangeInclusive<u32>>,
+    pub shared_palette: bool,
+    pub montage: Option<String>,
+    pub montage_columns: u32,
+    pub overwrite: Overwrite,
+    pub report: Option<ReportFormat>,
+    pub report_output: Option<String>,
+}
+
+pub fn run(
+    input: &str,
+    output: &str,
+    config: &Config,
+    reference: Option<&ColorReference>,
+    options: SequenceOptions,
+) -> Result<()> {
+    let frames = match options.frames {
+        Some(frames) => frames,
+        None => detect_frames(input)?,
+    };
+    check_output(input, output, &frames)?;
+    status!(
+        "Processing frames {}-{} of {}...",
+        frames.start(),
+        frames.end(),
+        input
+    );
+
+    let mut outputs = vec![];
+    // finishes the report started when the frame was loaded
+    let mut save = |frame: u32,
+                    image: &ProcessedImage,
+                    palette: &Palette,
+                    mut report: Report,
+                    mut stopwatch: Stopwatch|
+     -> Result<()> {
+        // the output is a frame pattern, a template filled in from each frame, or both
+        let path = match is_pattern(output) {
+            true => format_pattern(output, frame)?,
+            false => output.to_string(),
+        };
+        let path = match is_template(&path) {
+            true => expand_template(
+                &path,
+                &TemplateValues::new(&format_pattern(input, frame)?, image),
+            )?,
+            false => path,
+        };
+        let path = resolve_output(&path, options.overwrite)?;
+        image.save(&path)?;
+        if let Some(format) = options.report {
+            report.timing.save_ms = stopwatch.lap();
+            report.timing.total_ms += stopwatch.total();
+            report.output = path.clone();
+            report.result = image.into();
+            report.set_palette(palette);
+            report.quality = Some(score_palette(&image.data, palette)?);
+            report.emit(format, options.report_output.as_deref())?;
+        }
+        if options.montage.is_some() {
+            let label = Path::new(&path)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            outputs.push((label, image.data.clone()));
+        }
+        Ok(())
+    };
+
+    if !options.shared_palette && config.temporal_hysteresis.is_none() {
+        for frame in frames.clone() {
+            let (mut image, mut report, mut stopwatch) = load_frame(input, frame, config)?;
+            prepare_image(&mut image, config, false)?;
+            match_colors(&mut image, reference, config, false);
+            report.timing.prepare_ms = stopwatch.lap();
+            let palette = build_palette(&image, config)?;
+            report.timing.palette_ms = stopwatch.lap();
+            apply_palette(&mut image, &palette, config);
+            pad_image(&mut image, config)?;
+            report.timing.apply_ms = stopwatch.lap();
+            save(frame, &image, &palette, report, stopwatch)?;
+        }
+    } else {
+        let images = frames
+            .clone()
+            .map(|frame| {
+                let (mut image, mut report, mut stopwatch) = load_frame(input, frame, config)?;
+                prepare_image(&mut image, config, false)?;
+                match_colors(&mut image, reference, config, false);
+                report.timing.prepare_ms = stopwatch.lap();
+                report.timing.total_ms = stopwatch.total();
+                Ok((image, report))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut stopwatch = Stopwatch::start();
+        let prepared = images.iter().map(|(image, _)| image).collect::<Vec<_>>();
+        let palette = build_shared_palette(&prepared, config)?;
+        // the shared palette is built once, each frame reports the time it took
+        let palette_ms = stopwatch.lap();
+        let mut previous = None;
+        for (frame, (mut image, mut report)) in frames.clone().zip(images) {
+            let mut stopwatch = Stopwatch::start();
+            previous = apply_palette_stable(&mut image, &palette, config, previous.as_ref());
+            pad_image(&mut image, config)?;
+            report.timing.palette_ms = palette_ms;
+            report.timing.apply_ms = stopwatch.lap();
+            save(frame, &image, &palette, report, stopwatch)?;
+        }
+    }
+    status!("Saved {} frames to {}", frames.count(), output);
+
+    if let Some(path) = &options.montage {
+        status!("Saving montage to {}", path);
+        let images = outputs
+            .iter()
+            .map(|(label, data)| (label.clone(), data))
+            .collect::<Vec<_>>();
+        montage(&images, options.montage_columns, true)?
+            .save(path)
+            .with_context(|| "Failed to save image")?;
+    }
+    Ok(())
+}
+
+// a bad output fails before any frame is processed, not when the first one is saved
+fn check_output(input: &str, output: &str, frames: &RangeInclusive<u32>) -> Result<()> {
+    if is_pattern(output) {
+        return Ok(());
+    }
+    if !is_template(output) {
+        format_pattern(output, *frames.start()).with_context(|| {
+            format!(
+                "Output {} needs a frame pattern like frame_%04d.png or a placeholder like {{stem}}",
+                output
+            )
+        })?;
+    }
+    check_template(output)?;
+    // placeholders known before processing must still tell the frames apart
+    let mut outputs = HashSet::new();
+    for frame in frames.clone() {
+        let values = TemplateValues::from_input(&format_pattern(input, frame)?);
+        if let Ok(path) = expand_template(output, &values) {
+            ensure!(
+                outputs.insert(path.clone()),
+                "Every frame would be saved to {}, add {{stem}} or a frame pattern to the output",
+                path
+            );
+        }
+    }
+    Ok(())
+}
+
+fn load_frame(
+    input: &str,
+    frame: u32,
+    config: &Config,
+) -> Result<(ProcessedImage, Report, Stopwatch)> {
+    let mut stopwatch = Stopwatch::start();
+    let path = format_pattern(input, frame)?;
+    let image = load_image(&path, config)?;
+    let mut report = Report::new(&path, "");
+    report.source = (&image).into();
+    report.timing.load_ms = stopwatch.lap();
+    Ok((image, report, stopwatch))
+}
@@ -0,0 +1,217 @@
+use crate::config::Config;
+use crate::report::Size;
+use anyhow::{Context, Result};
+use libcrate::formats::png::{OPTIONS_KEY, PALETTE_KEY, SETTINGS_KEY, SOFTWARE_KEY, load_text};
+use libcrate::palette::HexPalette;
+use libcrate::{Palette, ProcessedImage};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sidecar {
+    pub source: String,
+    pub source_sha256: String,
+    pub source_size: Size,
+    pub size: Size,
+    pub palette: Option<HexPalette>,
+    pub settings: Config,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<String>,
+}
+
+impl Sidecar {
+    pub fn new(
+        source: &str,
+        source_size: Size,
+        image: &ProcessedImage,
+        palette: Option<&Palette>,
+        config: &Config,
+        options: Option<&str>,
+    ) -> Result<Self> {
+        let bytes = fs::read(source).with_context(|| format!("Failed to read: {:?}", source))?;
+        Ok(Sidecar {
+            source: source.to_string(),
+            source_sha256: to_hex(&Sha256::digest(&bytes)),
+            source_size,
+            size: image.into(),
+            palette: palette.map(|p| HexPalette(p.clone())),
+            settings: config.clone(),
+            options: options.map(str::to_string),
+        })
+    }
+
+    pub fn path(output: &str) -> String {
+        format!("{}.json", output)
+    }
+
+    pub fn save(&self, output: &str) -> Result<()> {
+        let path = Sidecar::path(output);
+        fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to save: {:?}", path))
+    }
+
+    pub fn load(output: &str) -> Result<Self> {
+        let path = Sidecar::path(output);
+        let content =
+            fs::read_to_string(&path).with_context(|| format!("Failed to open: {:?}", path))?;
+        serde_json::from_str(&content).with_context(|| format!("Invalid sidecar: {:?}", path))
+    }
+}
+
+pub fn png_text(
+    palette: Option<&Palette>,
+    config: &Config,
+    options: Option<&str>,
+) -> Result<Vec<(&'static str, String)>> {
+    let mut text = palette_text(palette);
+    text.push((SETTINGS_KEY, serde_json::to_string(config)?));
+    if let Some(options) = options {
+        text.push((OPTIONS_KEY, options.to_string()));
+    }
+    Ok(text)
+}
+
+pub fn palette_text(palette: Option<&Palette>) -> Vec<(&'static str, String)> {
+    let mut text = vec![(
+        SOFTWARE_KEY,
+        format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+    )];
+    if let Some(palette) = palette {
+        text.push((PALETTE_KEY, HexPalette(palette.clone()).to_string()));
+    }
+    text
+}
+
+pub fn load_settings(output: &str) -> Result<(Config, Option<Palette>)> {
+    let text = load_text(output).unwrap_or_default();
+    let value = |key: &str| text.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+    match value(SETTINGS_KEY) {
+        Some(settings) => {
+            let config = serde_json::from_str(settings)
+                .with_context(|| format!("Invalid settings embedded in {}", output))?;
+            let palette = match value(PALETTE_KEY) {
+                Some(palette) => Some(palette.parse::<HexPalette>()?.0),
+                None => None,
+            };
+            Ok((config, palette))
+        }
+        None => {
+            let sidecar = Sidecar::load(output).with_context(|| {
+                format!(
+                    "{} has no embedded settings and no readable sidecar",
+                    output
+                )
+            })?;
+            Ok((sidecar.settings, sidecar.palette.map(|p| p.0)))
+        }
+    }
+}
+
+// the fingerprint of the command line options a previous output was made with
+pub fn load_options(output: &str) -> Option<String> {
+    let text = load_text(output).unwrap_or_default();
+    match text.iter().any(|(k, _)| k == SETTINGS_KEY) {
+        true => text
+            .into_iter()
+            .find(|(k, _)| k == OPTIONS_KEY)
+            .map(|(_, v)| v),
+        false => Sidecar::load(output).ok()?.options,
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{ColorCount, Config};
+    use crate::sidecar::{Sidecar, load_options, load_settings, png_text};
+    use libcrate::ProcessedImage;
+    use libcrate::formats::png::save_with_text;
+    use libcrate::image_processing::rgb_from_tuple;
+    use std::env;
+    use std::fs;
+
+    const TEST_IMAGE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../libcrate/assets/test_img_1.jpg"
+    );
+
+    fn test_config() -> Config {
+        Config {
+            number_of_colors: Some(ColorCount::Fixed(5)),
+            desired_width: Some(12),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_sidecar_round_trip() {
+        let dir = env::temp_dir().join("pixelize_test_sidecar_round_trip");
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.bmp").to_string_lossy().into_owned();
+        let image = ProcessedImage::from_buffer(2, 1, &[rgb_from_tuple((9, 8, 7)); 2]);
+        let palette = vec![rgb_from_tuple((9, 8, 7))];
+        let source = ProcessedImage::new(TEST_IMAGE).unwrap();
+        let sidecar = Sidecar::new(
+            TEST_IMAGE,
+            (&source).into(),
+            &image,
+            Some(&palette),
+            &test_config(),
+            Some("--dither bayer4"),
+        )
+        .unwrap();
+        sidecar.save(&output).unwrap();
+        let loaded = Sidecar::load(&output).unwrap();
+        assert_eq!(loaded.source, TEST_IMAGE);
+        assert_eq!(loaded.source_sha256, sidecar.source_sha256);
+        assert_eq!(loaded.source_sha256.len(), 64);
+        assert_eq!((loaded.size.width, loaded.size.height), (2, 1));
+        assert_eq!(loaded.source_size.width, source.width());
+        assert_eq!(loaded.palette.unwrap().0, palette);
+        assert_eq!(loaded.settings.desired_width, Some(12));
+        assert_eq!(loaded.options.as_deref(), Some("--dither bayer4"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_settings() {
+        let dir = env::temp_dir().join("pixelize_test_load_settings");
+        fs::create_dir_all(&dir).unwrap();
+        let image = ProcessedImage::from_buffer(2, 1, &[rgb_from_tuple((9, 8, 7)); 2]);
+        let palette = vec![rgb_from_tuple((9, 8, 7)), rgb_from_tuple((0, 0, 0))];
+        let config = test_config();
+
+        let png = dir.join("embedded.png").to_string_lossy().into_owned();
+        let text = png_text(Some(&palette), &config, Some("--trim")).unwrap();
+        save_with_text(&png, &image, &text).unwrap();
+        let (settings, embedded) = load_settings(&png).unwrap();
+        assert_eq!(settings.number_of_colors, Some(ColorCount::Fixed(5)));
+        assert_eq!(embedded, Some(palette.clone()));
+        assert_eq!(load_options(&png).as_deref(), Some("--trim"));
+
+        // formats without text chunks fall back to the sidecar next to them
+        let bmp = dir.join("plain.bmp").to_string_lossy().into_owned();
+        image.save(&bmp).unwrap();
+        assert!(load_settings(&bmp).is_err());
+        assert_eq!(load_options(&bmp), None);
+        let sidecar = Sidecar::new(
+            TEST_IMAGE,
+            (&image).into(),
+            &image,
+            Some(&palette),
+            &config,
+            Some("--flip"),
+        )
+        .unwrap();
+        sidecar.save(&bmp).unwrap();
+        let (settings, from_sidecar) = load_settings(&bmp).unwrap();
+        assert_eq!(settings.desired_width, Some(12));
+        assert_eq!(from_sidecar, Some(palette));
+        assert_eq!(load_options(&bmp).as_deref(), Some("--flip"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
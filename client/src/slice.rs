@@ -0,0 +1,61 @@
+use crate::pack::{file_name, frame_name};
+use anyhow::Result;
+use clap::Args;
+use libcrate::ProcessedImage;
+use libcrate::export::texturepacker::{AtlasFormat, save_atlas};
+use libcrate::spritesheet::{SheetLayout, save_cells, slice};
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct SliceArgs {
+    input: PathBuf,
+    #[arg(short = 'o', long = "output")]
+    output: PathBuf,
+    #[arg(long = "cell", value_parser = crate::parse_size)]
+    cell: (u32, u32),
+    #[arg(long = "margin", default_value_t = 0)]
+    margin: u32,
+    #[arg(long = "spacing", default_value_t = 0)]
+    spacing: u32,
+    #[arg(long = "extension", default_value = "png")]
+    extension: String,
+    #[arg(long = "atlas")]
+    atlas: Option<PathBuf>,
+    #[arg(long = "atlas-format", default_value = "hash")]
+    atlas_format: AtlasFormat,
+}
+
+pub fn run(args: SliceArgs) -> Result<()> {
+    println!("Loading image...");
+    let image = ProcessedImage::new(&args.input)?;
+    let layout = SheetLayout {
+        margin: args.margin,
+        spacing: args.spacing,
+        ..SheetLayout::new(args.cell.0, args.cell.1)
+    };
+
+    println!("Slicing...");
+    let cells = slice(&image, &layout)?;
+    let prefix = frame_name(&args.input)?;
+    println!("Saving {} cells to {:?}", cells.len(), args.output);
+    let paths = save_cells(&cells, &args.output, &prefix, &args.extension)?;
+
+    if let Some(atlas) = &args.atlas {
+        println!("Saving TexturePacker atlas to {:?}", atlas);
+        let frames = paths
+            .iter()
+            .zip(layout.cells(image.width(), image.height())?)
+            .map(|(path, rect)| Ok((file_name(path)?, rect)))
+            .collect::<Result<Vec<_>>>()?;
+        save_atlas(
+            atlas,
+            &frames,
+            &file_name(&args.input)?,
+            (image.width(), image.height()),
+            args.atlas_format,
+        )?;
+    }
+
+    println!("Done.");
+    Ok(())
+}
@@ -0,0 +1,50 @@
+use crate::config::Config;
+use crate::process::{
+    apply_palette_stable, build_shared_palette, load_color_reference, match_colors, prepare_image,
+};
+use anyhow::{Result, anyhow};
+use clap::Args;
+use libcrate::video::process_video;
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct VideoArgs {
+    input: PathBuf,
+    output: PathBuf,
+    #[arg(long = "fps", default_value_t = 12.0)]
+    fps: f64,
+    #[arg(long = "palette-frames", default_value_t = 8)]
+    palette_frames: usize,
+}
+
+pub fn run(args: VideoArgs, config: Config) -> Result<()> {
+    if !config.is_valid() {
+        return Err(anyhow!("Config is not valid."));
+    }
+    let reference = load_color_reference(&config)?;
+    println!("Extracting frames from {:?}...", args.input);
+    let count = process_video(&args.input, &args.output, args.fps, |frames| {
+        println!("Scaling {} frames...", frames.len());
+        for frame in frames.iter_mut() {
+            if let Some(background) = config.flatten {
+                frame.flatten(background.0);
+            }
+            prepare_image(frame, &config, false)?;
+            match_colors(frame, reference.as_ref(), &config, false);
+        }
+
+        let step = frames.len().div_ceil(args.palette_frames.max(1));
+        let samples = frames.iter().step_by(step).collect::<Vec<_>>();
+        let palette = build_shared_palette(&samples, &config)?;
+
+        println!("Applying palette...");
+        let mut previous = None;
+        for frame in frames.iter_mut() {
+            previous = apply_palette_stable(frame, &palette, &config, previous.as_ref());
+        }
+        println!("Encoding to {:?}...", args.output);
+        Ok(())
+    })?;
+    println!("Done, {} frames processed.", count);
+    Ok(())
+}
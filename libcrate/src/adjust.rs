@@ -0,0 +1,420 @@
+use crate::image_processing::luminance;
+use crate::parallel::*;
+use anyhow::{Result, anyhow, ensure};
+use image::{Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+// share of the darkest and brightest pixels ignored by the percentile balance
+pub const BALANCE_CLIP: f64 = 0.005;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AutoBalance {
+    GrayWorld,
+    Percentile,
+}
+
+impl FromStr for AutoBalance {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "gray-world" | "grayworld" | "grey-world" => Ok(AutoBalance::GrayWorld),
+            "percentile" => Ok(AutoBalance::Percentile),
+            _ => Err(anyhow!(
+                "Unknown auto balance {}, expected gray-world or percentile",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for AutoBalance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AutoBalance::GrayWorld => "gray-world",
+            AutoBalance::Percentile => "percentile",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Levels {
+    pub black: u8,
+    pub white: u8,
+    pub gamma: f32,
+}
+
+impl Levels {
+    pub fn new(black: u8, white: u8, gamma: f32) -> Result<Self> {
+        ensure!(
+            black < white && gamma > 0.0,
+            "Invalid levels {}:{}:{}, the black point has to be below the white point and gamma above 0",
+            black,
+            white,
+            gamma
+        );
+        Ok(Levels {
+            black,
+            white,
+            gamma,
+        })
+    }
+
+    pub fn lookup(&self) -> [u8; 256] {
+        let range = self.white.saturating_sub(self.black).max(1) as f32;
+        std::array::from_fn(|level| {
+            let t = ((level as f32 - self.black as f32) / range).clamp(0.0, 1.0);
+            (t.powf(1.0 / self.gamma) * 255.0).round() as u8
+        })
+    }
+}
+
+impl FromStr for Levels {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            anyhow!(
+                "Invalid levels {}, expected BLACK:WHITE[:GAMMA], e.g. 16:235:1.2",
+                s
+            )
+        };
+        let parts = s.split(':').collect::<Vec<_>>();
+        let (black, white, gamma) = match parts[..] {
+            [black, white] => (black, white, "1"),
+            [black, white, gamma] => (black, white, gamma),
+            _ => return Err(invalid()),
+        };
+        Levels::new(
+            black.trim().parse().map_err(|_| invalid())?,
+            white.trim().parse().map_err(|_| invalid())?,
+            gamma.trim().parse().map_err(|_| invalid())?,
+        )
+    }
+}
+
+impl fmt::Display for Levels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.black, self.white, self.gamma)
+    }
+}
+
+impl TryFrom<String> for Levels {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<Levels> for String {
+    fn from(levels: Levels) -> Self {
+        levels.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CurveChannel {
+    #[default]
+    Rgb,
+    Red,
+    Green,
+    Blue,
+    Luma,
+}
+
+impl FromStr for CurveChannel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "rgb" => Ok(CurveChannel::Rgb),
+            "r" | "red" => Ok(CurveChannel::Red),
+            "g" | "green" => Ok(CurveChannel::Green),
+            "b" | "blue" => Ok(CurveChannel::Blue),
+            "luma" | "luminance" => Ok(CurveChannel::Luma),
+            _ => Err(anyhow!(
+                "Unknown curve channel {}, expected rgb, red, green, blue or luma",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CurveChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CurveChannel::Rgb => "rgb",
+            CurveChannel::Red => "red",
+            CurveChannel::Green => "green",
+            CurveChannel::Blue => "blue",
+            CurveChannel::Luma => "luma",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// a tone curve through control points (input, output), straight lines between them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Curve {
+    pub channel: CurveChannel,
+    pub points: Vec<(u8, u8)>,
+}
+
+impl Curve {
+    pub fn new(channel: CurveChannel, mut points: Vec<(u8, u8)>) -> Result<Self> {
+        ensure!(!points.is_empty(), "A curve needs at least one point");
+        points.sort_by_key(|&(input, _)| input);
+        points.dedup_by_key(|&mut (input, _)| input);
+        Ok(Curve { channel, points })
+    }
+
+    pub fn lookup(&self) -> [u8; 256] {
+        std::array::from_fn(|level| {
+            let level = level as u8;
+            let after = self.points.partition_point(|&(input, _)| input < level);
+            match (
+                self.points.get(after.wrapping_sub(1)),
+                self.points.get(after),
+            ) {
+                (_, Some(&(input, output))) if input == level => output,
+                (Some(&(x0, y0)), Some(&(x1, y1))) => {
+                    let t = (level - x0) as f32 / (x1 - x0) as f32;
+                    (y0 as f32 + (y1 as f32 - y0 as f32) * t).round() as u8
+                }
+                (Some(&(_, output)), None) | (None, Some(&(_, output))) => output,
+                (None, None) => level,
+            }
+        })
+    }
+}
+
+impl FromStr for Curve {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (channel, points) = match s.split_once(':') {
+            Some((channel, points)) => (channel.parse()?, points),
+            None => (CurveChannel::default(), s),
+        };
+        let points = points
+            .split(',')
+            .map(|point| {
+                let (input, output) = point.split_once('/')?;
+                Some((input.trim().parse().ok()?, output.trim().parse().ok()?))
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| {
+                anyhow!(
+                    "Invalid curve {}, expected [CHANNEL:]IN/OUT,IN/OUT,..., e.g. luma:0/0,128/100,255/255",
+                    s
+                )
+            })?;
+        Curve::new(channel, points)
+    }
+}
+
+impl fmt::Display for Curve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let points = self
+            .points
+            .iter()
+            .map(|(input, output)| format!("{}/{}", input, output))
+            .collect::<Vec<_>>();
+        write!(f, "{}:{}", self.channel, points.join(","))
+    }
+}
+
+impl TryFrom<String> for Curve {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<Curve> for String {
+    fn from(curve: Curve) -> Self {
+        curve.to_string()
+    }
+}
+
+pub fn apply_levels(img: &RgbImage, levels: &Levels) -> RgbImage {
+    let lookup = levels.lookup();
+    apply_lookup(img, &[lookup; 3])
+}
+
+pub fn apply_curve(img: &RgbImage, curve: &Curve) -> RgbImage {
+    let lookup = curve.lookup();
+    let identity = std::array::from_fn(|level| level as u8);
+    match curve.channel {
+        CurveChannel::Rgb => apply_lookup(img, &[lookup; 3]),
+        CurveChannel::Red => apply_lookup(img, &[lookup, identity, identity]),
+        CurveChannel::Green => apply_lookup(img, &[identity, lookup, identity]),
+        CurveChannel::Blue => apply_lookup(img, &[identity, identity, lookup]),
+        CurveChannel::Luma => {
+            // scales the color with the luminance so the hue stays
+            let mut result = img.clone();
+            result.par_pixels_mut().for_each(|p| {
+                let luma = luminance(p);
+                let target = lookup[luma.round().clamp(0.0, 255.0) as usize] as f64;
+                *p = match luma > 0.0 {
+                    true => Rgb(p
+                        .0
+                        .map(|c| (c as f64 * target / luma).round().clamp(0.0, 255.0) as u8)),
+                    false => Rgb([target as u8; 3]),
+                };
+            });
+            result
+        }
+    }
+}
+
+pub fn auto_balance(img: &RgbImage, method: AutoBalance) -> RgbImage {
+    let lookup = match method {
+        AutoBalance::GrayWorld => gray_world(img),
+        AutoBalance::Percentile => percentile_stretch(img, BALANCE_CLIP),
+    };
+    apply_lookup(img, &lookup)
+}
+
+pub fn apply_lookup(img: &RgbImage, lookup: &[[u8; 256]; 3]) -> RgbImage {
+    let mut result = img.clone();
+    result
+        .par_pixels_mut()
+        .for_each(|p| *p = Rgb([0, 1, 2].map(|c| lookup[c][p[c] as usize])));
+    result
+}
+
+fn channel_histograms(img: &RgbImage) -> [[u64; 256]; 3] {
+    let mut counts = [[0u64; 256]; 3];
+    for p in img.pixels() {
+        for c in 0..3 {
+            counts[c][p[c] as usize] += 1;
+        }
+    }
+    counts
+}
+
+// scales every channel so that the average color becomes neutral gray
+fn gray_world(img: &RgbImage) -> [[u8; 256]; 3] {
+    let total = (img.width() as u64 * img.height() as u64).max(1) as f64;
+    let means = channel_histograms(img).map(|channel| {
+        let sum = channel
+            .iter()
+            .enumerate()
+            .map(|(level, &count)| level as f64 * count as f64)
+            .sum::<f64>();
+        sum / total
+    });
+    let gray = means.iter().sum::<f64>() / 3.0;
+    means.map(|mean| {
+        let gain = match mean > 0.0 {
+            true => gray / mean,
+            false => 1.0,
+        };
+        std::array::from_fn(|level| (level as f64 * gain).round().clamp(0.0, 255.0) as u8)
+    })
+}
+
+// stretches every channel so that its darkest and brightest levels span the full range
+fn percentile_stretch(img: &RgbImage, clip: f64) -> [[u8; 256]; 3] {
+    let total = img.width() as u64 * img.height() as u64;
+    let skip = (total as f64 * clip) as u64;
+    channel_histograms(img).map(|channel| {
+        let low = level_past(&channel, 0..256, skip);
+        let high = level_past(&channel, (0..256).rev(), skip);
+        if high <= low {
+            return std::array::from_fn(|level| level as u8);
+        }
+        let range = (high - low) as f64;
+        std::array::from_fn(|level| {
+            ((level as f64 - low as f64) / range * 255.0)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        })
+    })
+}
+
+// the first level in the given order after more than `skip` pixels
+fn level_past<I>(channel: &[u64; 256], mut levels: I, skip: u64) -> usize
+where
+    I: Iterator<Item = usize>,
+{
+    let mut seen = 0;
+    levels
+        .find(|&level| {
+            seen += channel[level];
+            seen > skip
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::adjust::{
+        AutoBalance, Curve, CurveChannel, Levels, apply_curve, apply_levels, auto_balance,
+    };
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_auto_balance() {
+        // a blue cast over a gray ramp
+        let img = RgbImage::from_fn(100, 1, |x, _| {
+            let v = x as u8 + 60;
+            Rgb([v - 40, v - 20, v + 40])
+        });
+        let balanced = auto_balance(&img, AutoBalance::GrayWorld);
+        let middle = balanced.get_pixel(50, 0);
+        assert!(middle[0].abs_diff(middle[2]) <= 2, "{:?}", middle);
+
+        let stretched = auto_balance(&img, AutoBalance::Percentile);
+        assert_eq!(*stretched.get_pixel(0, 0), Rgb([0, 0, 0]));
+        assert_eq!(*stretched.get_pixel(99, 0), Rgb([255, 255, 255]));
+        assert_eq!(
+            "grey-world".parse::<AutoBalance>().unwrap(),
+            AutoBalance::GrayWorld
+        );
+    }
+
+    #[test]
+    fn test_levels_and_curves() {
+        let levels = "16:235".parse::<Levels>().unwrap();
+        let lookup = levels.lookup();
+        assert_eq!(
+            (lookup[0], lookup[16], lookup[235], lookup[255]),
+            (0, 0, 255, 255)
+        );
+        assert!("16:235:2".parse::<Levels>().unwrap().lookup()[64] > lookup[64]);
+        assert!("200:100".parse::<Levels>().is_err());
+
+        let curve = "0/0,128/64,255/255".parse::<Curve>().unwrap();
+        assert_eq!(curve.channel, CurveChannel::Rgb);
+        let lookup = curve.lookup();
+        assert_eq!((lookup[64], lookup[128], lookup[192]), (32, 64, 160));
+        assert_eq!(curve.to_string().parse::<Curve>().unwrap(), curve);
+
+        let img = RgbImage::from_pixel(2, 2, Rgb([128, 128, 128]));
+        assert_eq!(
+            *apply_levels(&img, &levels).get_pixel(0, 0),
+            Rgb([130, 130, 130])
+        );
+        let red = "red:0/0,128/255".parse::<Curve>().unwrap();
+        assert_eq!(
+            *apply_curve(&img, &red).get_pixel(0, 0),
+            Rgb([255, 128, 128])
+        );
+        let luma = "luma:0/0,128/64,255/255".parse::<Curve>().unwrap();
+        let tinted = RgbImage::from_pixel(1, 1, Rgb([160, 120, 100]));
+        let darker = apply_curve(&tinted, &luma);
+        assert!(darker.get_pixel(0, 0)[0] > darker.get_pixel(0, 0)[2]);
+        assert!(darker.get_pixel(0, 0)[0] < 160);
+    }
+}
@@ -0,0 +1,97 @@
+use crate::ProcessedImage;
+use crate::image_processing::ensure_encoder;
+use anyhow::{Context, Result};
+use image::ImageFormat;
+use std::path::Path;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::task;
+
+// decoding and encoding are CPU bound, so they run on the blocking pool instead of the async workers
+impl ProcessedImage {
+    pub async fn new_async<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let bytes = tokio::fs::read(path.as_ref())
+            .await
+            .with_context(|| format!("Failed to open: {:?}", path.as_ref()))?;
+        Self::from_bytes_async(bytes).await
+    }
+
+    pub async fn from_reader_async<R>(mut reader: R) -> Result<Self>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut bytes = vec![];
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .with_context(|| "Failed to read the image data")?;
+        Self::from_bytes_async(bytes).await
+    }
+
+    async fn from_bytes_async(bytes: Vec<u8>) -> Result<Self> {
+        task::spawn_blocking(move || ProcessedImage::try_from(bytes.as_slice())).await?
+    }
+
+    pub async fn save_async<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        ensure_encoder(path.as_ref())?;
+        let format = ImageFormat::from_path(path.as_ref())
+            .with_context(|| format!("Unknown image format: {:?}", path.as_ref()))?;
+        let bytes = self.encode_async(format).await?;
+        tokio::fs::write(path.as_ref(), bytes)
+            .await
+            .with_context(|| format!("Failed to save: {:?}", path.as_ref()))
+    }
+
+    pub async fn write_async<W>(&self, mut writer: W, format: ImageFormat) -> Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let bytes = self.encode_async(format).await?;
+        writer
+            .write_all(&bytes)
+            .await
+            .with_context(|| "Failed to write the image data")?;
+        writer.flush().await?;
+        Ok(())
+    }
+
+    async fn encode_async(&self, format: ImageFormat) -> Result<Vec<u8>> {
+        let image = self.clone();
+        task::spawn_blocking(move || image.encode(format)).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ProcessedImage;
+    use image::{ImageFormat, Rgb, RgbImage};
+    use std::env;
+
+    #[test]
+    fn test_async_roundtrip() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let image = ProcessedImage::from(RgbImage::from_fn(4, 3, |x, y| {
+            Rgb([x as u8 * 60, y as u8 * 80, 7])
+        }));
+        let path = env::temp_dir().join("pixelize_async_test.png");
+        runtime.block_on(async {
+            image.save_async(&path).await.unwrap();
+            assert_eq!(ProcessedImage::new_async(&path).await.unwrap(), image);
+            let mut bytes = vec![];
+            image
+                .write_async(&mut bytes, ImageFormat::Png)
+                .await
+                .unwrap();
+            let read = ProcessedImage::from_reader_async(bytes.as_slice()).await;
+            assert_eq!(read.unwrap(), image);
+        });
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+use crate::dither::{DitherOptions, Quantizer, quantize_with};
+use anyhow::{Result, anyhow};
+use image::{Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BitDepth {
+    Rgb565,
+    Rgb555,
+    Rgb332,
+}
+
+impl BitDepth {
+    pub fn bits(&self) -> [u32; 3] {
+        match self {
+            BitDepth::Rgb565 => [5, 6, 5],
+            BitDepth::Rgb555 => [5, 5, 5],
+            BitDepth::Rgb332 => [3, 3, 2],
+        }
+    }
+
+    pub fn pack(&self, color: &Rgb<u8>) -> u16 {
+        let bits = self.bits();
+        (0..3).fold(0u16, |packed, c| {
+            (packed << bits[c]) | reduce_channel(color[c], bits[c]) as u16
+        })
+    }
+
+    pub fn unpack(&self, value: u16) -> Rgb<u8> {
+        let bits = self.bits();
+        let shifts = [bits[1] + bits[2], bits[2], 0];
+        Rgb([0, 1, 2].map(|c| {
+            let level = (value >> shifts[c]) & ((1 << bits[c]) - 1);
+            expand_channel(level as u32, bits[c])
+        }))
+    }
+}
+
+impl Quantizer for BitDepth {
+    fn quantize(&self, color: &Rgb<u8>) -> Rgb<u8> {
+        let bits = self.bits();
+        Rgb([0, 1, 2].map(|c| expand_channel(reduce_channel(color[c], bits[c]), bits[c])))
+    }
+
+    fn spread(&self) -> [f32; 3] {
+        self.bits().map(|b| 255.0 / ((1 << b) - 1) as f32)
+    }
+}
+
+impl FromStr for BitDepth {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "rgb565" | "565" => Ok(BitDepth::Rgb565),
+            "rgb555" | "555" => Ok(BitDepth::Rgb555),
+            "rgb332" | "332" => Ok(BitDepth::Rgb332),
+            _ => Err(anyhow!(
+                "Unknown bit depth {}, expected rgb565, rgb555 or rgb332",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for BitDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BitDepth::Rgb565 => "rgb565",
+            BitDepth::Rgb555 => "rgb555",
+            BitDepth::Rgb332 => "rgb332",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+fn reduce_channel(value: u8, bits: u32) -> u32 {
+    let max = (1 << bits) - 1;
+    (value as u32 * max + 127) / 255
+}
+
+fn expand_channel(level: u32, bits: u32) -> u8 {
+    let max = (1 << bits) - 1;
+    ((level * 255 + max / 2) / max) as u8
+}
+
+pub fn reduce_bit_depth(
+    img: &RgbImage,
+    depth: BitDepth,
+    options: &DitherOptions,
+) -> Result<RgbImage> {
+    quantize_with(img, &depth, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bitdepth::{BitDepth, reduce_bit_depth};
+    use crate::dither::{Dither, DitherOptions, Quantizer};
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_pack() {
+        let depth = BitDepth::Rgb565;
+        assert_eq!(depth.pack(&Rgb([255, 255, 255])), 0xFFFF);
+        assert_eq!(depth.pack(&Rgb([255, 0, 0])), 0xF800);
+        assert_eq!(depth.pack(&Rgb([0, 255, 0])), 0x07E0);
+        assert_eq!(BitDepth::Rgb332.pack(&Rgb([0, 0, 255])), 0x03);
+        for color in [Rgb([12u8, 200, 99]), Rgb([255, 0, 128])] {
+            for depth in [BitDepth::Rgb565, BitDepth::Rgb555, BitDepth::Rgb332] {
+                assert_eq!(depth.unpack(depth.pack(&color)), depth.quantize(&color));
+            }
+        }
+        assert!("rgb888".parse::<BitDepth>().is_err());
+    }
+
+    #[test]
+    fn test_reduce_bit_depth() {
+        let img = RgbImage::from_fn(16, 16, |x, y| Rgb([(x * 16) as u8, (y * 16) as u8, 100]));
+        let reduced = reduce_bit_depth(&img, BitDepth::Rgb332, &DitherOptions::default()).unwrap();
+        assert!(reduced.pixels().all(|p| p[2] % 85 == 0));
+        let gray = RgbImage::from_pixel(16, 16, Rgb([128, 128, 128]));
+        let dithered = reduce_bit_depth(
+            &gray,
+            BitDepth::Rgb332,
+            &DitherOptions::new(Dither::FloydSteinberg),
+        )
+        .unwrap();
+        let mean = dithered.pixels().map(|p| p[2] as f32).sum::<f32>() / 256.0;
+        assert!((mean - 128.0).abs() < 4.0, "{}", mean);
+        assert!(dithered.pixels().any(|p| *p != dithered[(0, 0)]));
+        // mixing needs palette colors to pair up
+        assert!(
+            reduce_bit_depth(&gray, BitDepth::Rgb332, &DitherOptions::new(Dither::Mix)).is_err()
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use crate::Palette;
+use crate::image_processing::{linear_to_srgb, srgb_to_linear};
+use crate::parallel::*;
+use anyhow::{Result, anyhow};
+use image::{Rgb, RgbImage};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deficiency {
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl Deficiency {
+    pub const ALL: [Deficiency; 3] = [
+        Deficiency::Protanopia,
+        Deficiency::Deuteranopia,
+        Deficiency::Tritanopia,
+    ];
+
+    // Machado, Oliveira & Fernandes (2009), severity 1.0, applied in linear RGB
+    fn matrix(&self) -> [[f32; 3]; 3] {
+        match self {
+            Deficiency::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            Deficiency::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            Deficiency::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+        }
+    }
+}
+
+impl FromStr for Deficiency {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "protanopia" | "protan" => Ok(Deficiency::Protanopia),
+            "deuteranopia" | "deutan" => Ok(Deficiency::Deuteranopia),
+            "tritanopia" | "tritan" => Ok(Deficiency::Tritanopia),
+            _ => Err(anyhow!(
+                "Unknown color vision deficiency {}, expected protanopia, deuteranopia or tritanopia",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Deficiency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Deficiency::Protanopia => "protanopia",
+            Deficiency::Deuteranopia => "deuteranopia",
+            Deficiency::Tritanopia => "tritanopia",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConfusablePair {
+    pub first: usize,
+    pub second: usize,
+    pub distance: f64,
+}
+
+pub fn simulate(color: &Rgb<u8>, deficiency: Deficiency) -> Rgb<u8> {
+    let linear = color.0.map(srgb_to_linear);
+    let matrix = deficiency.matrix();
+    Rgb(matrix.map(|row| {
+        linear_to_srgb(
+            row.iter()
+                .zip(linear.iter())
+                .map(|(m, c)| m * c)
+                .sum::<f32>(),
+        )
+    }))
+}
+
+pub fn simulate_image(img: &RgbImage, deficiency: Deficiency) -> RgbImage {
+    let mut result = img.clone();
+    result
+        .par_pixels_mut()
+        .for_each(|p| *p = simulate(p, deficiency));
+    result
+}
+
+pub fn color_distance(a: &Rgb<u8>, b: &Rgb<u8>) -> f64 {
+    a.0.iter()
+        .zip(b.0.iter())
+        .map(|(x, y)| (*x as f64 - *y as f64).powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+pub fn confusable_pairs(
+    palette: &Palette,
+    deficiency: Deficiency,
+    threshold: f64,
+) -> Vec<ConfusablePair> {
+    let simulated = palette
+        .iter()
+        .map(|c| simulate(c, deficiency))
+        .collect::<Vec<_>>();
+    let mut pairs = Vec::new();
+    for first in 0..palette.len() {
+        for second in first + 1..palette.len() {
+            let distance = color_distance(&simulated[first], &simulated[second]);
+            if distance < threshold
+                && color_distance(&palette[first], &palette[second]) >= threshold
+            {
+                pairs.push(ConfusablePair {
+                    first,
+                    second,
+                    distance,
+                });
+            }
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cvd::{Deficiency, confusable_pairs, simulate};
+    use image::Rgb;
+
+    #[test]
+    fn test_simulate() {
+        for deficiency in Deficiency::ALL {
+            assert_eq!(simulate(&Rgb([0, 0, 0]), deficiency), Rgb([0, 0, 0]));
+            let gray = simulate(&Rgb([128, 128, 128]), deficiency);
+            gray.0.iter().for_each(|c| assert!(c.abs_diff(128) <= 2));
+        }
+        let red = simulate(&Rgb([255, 0, 0]), Deficiency::Protanopia);
+        assert!(red[0] < 150);
+    }
+
+    #[test]
+    fn test_confusable_pairs() {
+        let palette = vec![Rgb([200u8, 60, 40]), Rgb([90, 140, 40]), Rgb([0, 0, 0])];
+        let pairs = confusable_pairs(&palette, Deficiency::Deuteranopia, 30.0);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!((pairs[0].first, pairs[0].second), (0, 1));
+        assert!(confusable_pairs(&palette, Deficiency::Tritanopia, 30.0).is_empty());
+    }
+}
@@ -0,0 +1,437 @@
+use crate::Palette;
+use crate::image_processing::{PaletteMatcher, apply_palette_with};
+use crate::parallel::*;
+use crate::traversal::Traversal;
+use anyhow::{Result, anyhow, ensure};
+use image::{Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dither {
+    #[default]
+    None,
+    Bayer2,
+    Bayer4,
+    Bayer8,
+    FloydSteinberg,
+    Riemersma,
+    Mix,
+}
+
+impl Dither {
+    pub const ALL: [Dither; 7] = [
+        Dither::None,
+        Dither::Bayer2,
+        Dither::Bayer4,
+        Dither::Bayer8,
+        Dither::FloydSteinberg,
+        Dither::Riemersma,
+        Dither::Mix,
+    ];
+
+    pub(crate) fn bayer_size(self) -> Option<u32> {
+        match self {
+            Dither::Bayer2 => Some(2),
+            Dither::Bayer4 => Some(4),
+            Dither::Bayer8 => Some(8),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Dither {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Dither::None),
+            "bayer2" => Ok(Dither::Bayer2),
+            "bayer" | "bayer4" => Ok(Dither::Bayer4),
+            "bayer8" => Ok(Dither::Bayer8),
+            "floyd-steinberg" | "fs" => Ok(Dither::FloydSteinberg),
+            "riemersma" | "hilbert" => Ok(Dither::Riemersma),
+            "mix" | "two-color" => Ok(Dither::Mix),
+            _ => Err(anyhow!(
+                "Unknown dither {}, expected none, bayer2, bayer4, bayer8, floyd-steinberg, riemersma or mix",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DitherOptions {
+    pub method: Dither,
+    pub strength: f32,
+    pub error_clamp: Option<f32>,
+    pub serpentine: bool,
+    pub traversal: Traversal,
+}
+
+impl DitherOptions {
+    pub fn new(method: Dither) -> Self {
+        DitherOptions {
+            method,
+            strength: 1.0,
+            error_clamp: None,
+            serpentine: false,
+            traversal: Traversal::default(),
+        }
+    }
+
+    fn clamp_error(&self, error: f32) -> f32 {
+        let error = error * self.strength.clamp(0.0, 1.0);
+        match self.error_clamp {
+            Some(limit) => error.clamp(-limit.abs(), limit.abs()),
+            None => error,
+        }
+    }
+}
+
+impl Default for DitherOptions {
+    fn default() -> Self {
+        DitherOptions::new(Dither::None)
+    }
+}
+
+impl From<Dither> for DitherOptions {
+    fn from(method: Dither) -> Self {
+        DitherOptions::new(method)
+    }
+}
+
+pub fn dither(img: &RgbImage, palette: &Palette, method: Dither) -> RgbImage {
+    dither_with(img, palette, &DitherOptions::new(method))
+}
+
+pub trait Quantizer: Sync {
+    fn quantize(&self, color: &Rgb<u8>) -> Rgb<u8>;
+    fn spread(&self) -> [f32; 3];
+}
+
+impl Quantizer for PaletteMatcher {
+    fn quantize(&self, color: &Rgb<u8>) -> Rgb<u8> {
+        self.nearest(color)
+    }
+
+    fn spread(&self) -> [f32; 3] {
+        [255.0 / (self.palette().len().max(2) as f32).cbrt(); 3]
+    }
+}
+
+pub fn dither_with(img: &RgbImage, palette: &Palette, options: &DitherOptions) -> RgbImage {
+    match options.method {
+        Dither::None => apply_palette_with(img, palette, options.traversal),
+        Dither::Mix => mix(img, palette, options),
+        _ => quantize(img, &PaletteMatcher::new(palette), options),
+    }
+}
+
+// mixing picks the two nearest palette colors, which a bare quantizer cannot enumerate
+pub fn check_quantizer_dither(method: Dither) -> Result<()> {
+    ensure!(
+        method != Dither::Mix,
+        "Dither mix needs a palette, expected none, bayer2, bayer4, bayer8, floyd-steinberg or riemersma"
+    );
+    Ok(())
+}
+
+pub fn quantize_with<Q>(img: &RgbImage, quantizer: &Q, options: &DitherOptions) -> Result<RgbImage>
+where
+    Q: Quantizer,
+{
+    check_quantizer_dither(options.method)?;
+    Ok(quantize(img, quantizer, options))
+}
+
+fn quantize<Q>(img: &RgbImage, quantizer: &Q, options: &DitherOptions) -> RgbImage
+where
+    Q: Quantizer,
+{
+    match options.method {
+        Dither::None => {
+            let mut result = img.clone();
+            result
+                .par_pixels_mut()
+                .for_each(|p| *p = quantizer.quantize(p));
+            result
+        }
+        Dither::Bayer2 => ordered(img, quantizer, 2, options),
+        Dither::Bayer4 => ordered(img, quantizer, 4, options),
+        Dither::Bayer8 => ordered(img, quantizer, 8, options),
+        Dither::FloydSteinberg => floyd_steinberg(img, quantizer, options),
+        Dither::Riemersma => riemersma(img, quantizer, options),
+        Dither::Mix => unreachable!("mix is dithered against the palette"),
+    }
+}
+
+pub(crate) fn bayer_matrix(size: u32) -> Vec<u32> {
+    if size == 1 {
+        return vec![0];
+    }
+    let half = size / 2;
+    let smaller = bayer_matrix(half);
+    (0..size * size)
+        .map(|i| {
+            let (x, y) = (i % size, i / size);
+            let quadrant = match (x < half, y < half) {
+                (true, true) => 0,
+                (false, false) => 1,
+                (false, true) => 2,
+                (true, false) => 3,
+            };
+            4 * smaller[((y % half) * half + x % half) as usize] + quadrant
+        })
+        .collect()
+}
+
+fn ordered<Q>(img: &RgbImage, quantizer: &Q, size: u32, options: &DitherOptions) -> RgbImage
+where
+    Q: Quantizer,
+{
+    let matrix = bayer_matrix(size);
+    let levels = (size * size) as f32;
+    let spread = quantizer.spread();
+    let mut result = img.clone();
+    result.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let threshold = matrix[((y % size) * size + x % size) as usize] as f32;
+        let offset = (threshold + 0.5) / levels - 0.5;
+        let shifted = Rgb([0, 1, 2].map(|c| {
+            (pixel[c] as f32 + options.clamp_error(offset * spread[c])).clamp(0.0, 255.0) as u8
+        }));
+        *pixel = quantizer.quantize(&shifted);
+    });
+    result
+}
+
+fn mix(img: &RgbImage, palette: &Palette, options: &DitherOptions) -> RgbImage {
+    let matcher = PaletteMatcher::new(palette);
+    let matrix = bayer_matrix(MIX_MATRIX_SIZE);
+    let levels = (MIX_MATRIX_SIZE * MIX_MATRIX_SIZE) as f32;
+    let mut result = img.clone();
+    result.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let (first, second) = matcher.nearest_two(pixel);
+        let (a, b) = (
+            palette[first].0.map(|c| c as f32),
+            palette[second].0.map(|c| c as f32),
+        );
+        let p = pixel.0.map(|c| c as f32);
+        let along = (0..3).map(|c| (p[c] - a[c]) * (b[c] - a[c])).sum::<f32>();
+        let length = (0..3).map(|c| (b[c] - a[c]).powi(2)).sum::<f32>();
+        let ratio = if length > 0.0 {
+            (along / length).clamp(0.0, 1.0) * options.strength.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let threshold = (matrix
+            [((y % MIX_MATRIX_SIZE) * MIX_MATRIX_SIZE + x % MIX_MATRIX_SIZE) as usize]
+            as f32
+            + 0.5)
+            / levels;
+        *pixel = if ratio > threshold {
+            palette[second]
+        } else {
+            palette[first]
+        };
+    });
+    result
+}
+
+fn floyd_steinberg<Q>(img: &RgbImage, quantizer: &Q, options: &DitherOptions) -> RgbImage
+where
+    Q: Quantizer,
+{
+    let (width, height) = img.dimensions();
+    let mut errors = vec![[0f32; 3]; (width * height) as usize];
+    let mut result = RgbImage::new(width, height);
+    for y in 0..height {
+        let reverse = options.serpentine && y % 2 == 1;
+        let direction = if reverse { -1 } else { 1 };
+        for column in 0..width {
+            let x = if reverse { width - 1 - column } else { column };
+            let idx = (y * width + x) as usize;
+            let pixel = img.get_pixel(x, y);
+            let wanted = [0, 1, 2]
+                .map(|c| (pixel[c] as f32 + options.clamp_error(errors[idx][c])).clamp(0.0, 255.0));
+            let color = quantizer.quantize(&Rgb(wanted.map(|c| c.round() as u8)));
+            result.put_pixel(x, y, color);
+            let error = [0, 1, 2].map(|c| wanted[c] - color[c] as f32);
+            let mut spread = |dx: i64, dy: i64, factor: f32| {
+                let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                if nx >= 0 && nx < width as i64 && ny < height as i64 {
+                    let target = &mut errors[(ny as u32 * width + nx as u32) as usize];
+                    (0..3).for_each(|c| target[c] += error[c] * factor);
+                }
+            };
+            spread(direction, 0, 7.0 / 16.0);
+            spread(-direction, 1, 3.0 / 16.0);
+            spread(0, 1, 5.0 / 16.0);
+            spread(direction, 1, 1.0 / 16.0);
+        }
+    }
+    result
+}
+
+pub fn hilbert_curve(width: u32, height: u32) -> Vec<(u32, u32)> {
+    let side = width.max(height).max(1).next_power_of_two();
+    (0..side as u64 * side as u64)
+        .map(|d| hilbert_point(side, d))
+        .filter(|(x, y)| *x < width && *y < height)
+        .collect()
+}
+
+fn hilbert_point(side: u32, d: u64) -> (u32, u32) {
+    let (mut x, mut y, mut t) = (0u64, 0u64, d);
+    let mut s = 1u64;
+    while s < side as u64 {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    (x as u32, y as u32)
+}
+
+const MIX_MATRIX_SIZE: u32 = 4;
+const RIEMERSMA_HISTORY: usize = 16;
+const RIEMERSMA_RATIO: f32 = 16.0;
+
+fn riemersma<Q>(img: &RgbImage, quantizer: &Q, options: &DitherOptions) -> RgbImage
+where
+    Q: Quantizer,
+{
+    let base = RIEMERSMA_RATIO.ln() / (RIEMERSMA_HISTORY - 1) as f32;
+    let weights: Vec<f32> = (0..RIEMERSMA_HISTORY)
+        .map(|i| (base * i as f32).exp() / RIEMERSMA_RATIO)
+        .collect();
+    let mut history = [[0f32; 3]; RIEMERSMA_HISTORY];
+    let mut result = RgbImage::new(img.width(), img.height());
+    for (x, y) in hilbert_curve(img.width(), img.height()) {
+        let pixel = img.get_pixel(x, y);
+        let wanted = [0, 1, 2].map(|c| {
+            let error = history
+                .iter()
+                .zip(weights.iter())
+                .map(|(e, w)| e[c] * w)
+                .sum::<f32>();
+            (pixel[c] as f32 + options.clamp_error(error)).clamp(0.0, 255.0)
+        });
+        let color = quantizer.quantize(&Rgb(wanted.map(|c| c.round() as u8)));
+        result.put_pixel(x, y, color);
+        history.rotate_left(1);
+        history[RIEMERSMA_HISTORY - 1] = [0, 1, 2].map(|c| pixel[c] as f32 - color[c] as f32);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dither::{Dither, DitherOptions, bayer_matrix, dither, dither_with, hilbert_curve};
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_bayer_matrix() {
+        assert_eq!(bayer_matrix(2), vec![0, 2, 3, 1]);
+        let mut matrix = bayer_matrix(8);
+        matrix.sort();
+        assert_eq!(matrix, (0..64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_dither_gray() {
+        let palette = vec![Rgb([0u8, 0, 0]), Rgb([255, 255, 255])];
+        let img = RgbImage::from_pixel(16, 16, Rgb([128, 128, 128]));
+        assert!(
+            dither(&img, &palette, Dither::None)
+                .pixels()
+                .all(|p| *p == palette[1])
+        );
+        for method in [Dither::Bayer4, Dither::FloydSteinberg, Dither::Riemersma] {
+            let result = dither(&img, &palette, method);
+            let white = result.pixels().filter(|p| **p == palette[1]).count();
+            assert!((96..=160).contains(&white), "{:?}: {}", method, white);
+        }
+    }
+
+    #[test]
+    fn test_dither_strength() {
+        let palette = vec![Rgb([0u8, 0, 0]), Rgb([255, 255, 255])];
+        let img = RgbImage::from_pixel(16, 16, Rgb([100, 100, 100]));
+        let white = |options: DitherOptions| {
+            dither_with(&img, &palette, &options)
+                .pixels()
+                .filter(|p| **p == palette[1])
+                .count()
+        };
+        for method in [Dither::Bayer4, Dither::FloydSteinberg] {
+            let full = white(DitherOptions::new(method));
+            let none = white(DitherOptions {
+                strength: 0.0,
+                ..DitherOptions::new(method)
+            });
+            let clamped = white(DitherOptions {
+                error_clamp: Some(4.0),
+                ..DitherOptions::new(method)
+            });
+            assert!(full > 50, "{:?}: {}", method, full);
+            assert_eq!(none, 0);
+            assert_eq!(clamped, 0);
+        }
+    }
+
+    #[test]
+    fn test_serpentine() {
+        let palette = vec![Rgb([0u8, 0, 0]), Rgb([255, 255, 255])];
+        let img = RgbImage::from_fn(16, 16, |x, _| Rgb([(x * 16) as u8; 3]));
+        let options = DitherOptions {
+            serpentine: true,
+            ..DitherOptions::new(Dither::FloydSteinberg)
+        };
+        let plain = dither(&img, &palette, Dither::FloydSteinberg);
+        let serpentine = dither_with(&img, &palette, &options);
+        assert_eq!(plain.get_pixel(0, 0), serpentine.get_pixel(0, 0));
+        assert_ne!(plain, serpentine);
+        let white = serpentine.pixels().filter(|p| **p == palette[1]).count();
+        assert!((100..=156).contains(&white), "{}", white);
+    }
+
+    #[test]
+    fn test_hilbert_curve() {
+        let curve = hilbert_curve(5, 3);
+        assert_eq!(curve.len(), 15);
+        assert_eq!(curve[0], (0, 0));
+        let mut sorted = curve.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted.len(), 15);
+        let full = hilbert_curve(8, 8);
+        assert!(
+            full.windows(2)
+                .all(|w| { w[0].0.abs_diff(w[1].0) + w[0].1.abs_diff(w[1].1) == 1 })
+        );
+    }
+
+    #[test]
+    fn test_mix() {
+        let palette = vec![Rgb([0u8, 0, 0]), Rgb([255, 255, 255])];
+        let img = RgbImage::from_pixel(4, 4, Rgb([128, 128, 128]));
+        let mixed = dither(&img, &palette, Dither::Mix);
+        assert_eq!(mixed.pixels().filter(|p| **p == palette[0]).count(), 8);
+        assert_eq!(mixed.pixels().filter(|p| **p == palette[1]).count(), 8);
+        assert_ne!(mixed.get_pixel(0, 0), mixed.get_pixel(1, 0));
+        let palette = vec![Rgb([0u8, 0, 0]), Rgb([255, 255, 255]), Rgb([255, 0, 0])];
+        let red = RgbImage::from_pixel(4, 4, palette[2]);
+        assert_eq!(dither(&red, &palette, Dither::Mix), red);
+    }
+}
@@ -0,0 +1,14 @@
+pub mod cycling;
+pub mod dds;
+pub mod gameboy;
+pub mod godot;
+pub mod ico;
+pub mod mipmap;
+pub mod nes;
+pub mod palette;
+pub mod pico8;
+pub mod source;
+pub mod svg;
+#[cfg(feature = "textures")]
+pub mod texture;
+pub mod texturepacker;
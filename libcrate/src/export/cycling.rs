@@ -0,0 +1,185 @@
+use crate::Palette;
+use crate::image_processing::palette_indices;
+#[cfg(feature = "io")]
+use anyhow::Context;
+use anyhow::{Result, anyhow, ensure};
+use image::RgbImage;
+#[cfg(feature = "io")]
+use image::codecs::gif::{GifEncoder, Repeat};
+#[cfg(feature = "io")]
+use image::{Delay, Frame, Rgb, RgbaImage};
+#[cfg(feature = "io")]
+use std::fs::File;
+#[cfg(feature = "io")]
+use std::path::Path;
+use std::str::FromStr;
+
+const MAX_FRAMES: usize = 600;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CycleRange {
+    pub start: usize,
+    pub end: usize,
+    pub rate: f32,
+}
+
+impl CycleRange {
+    fn len(&self) -> usize {
+        self.end - self.start + 1
+    }
+
+    fn period(&self, fps: u32) -> usize {
+        ((self.len() as f32 * fps as f32 / self.rate.abs()).round() as usize).max(1)
+    }
+
+    fn shift(&self, frame: usize, fps: u32) -> usize {
+        let steps = (frame as f32 * self.rate.abs() / fps as f32).floor() as usize % self.len();
+        if self.rate < 0.0 {
+            (self.len() - steps) % self.len()
+        } else {
+            steps
+        }
+    }
+}
+
+impl FromStr for CycleRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let error = || anyhow!("Invalid cycle {}, expected START-END@RATE, e.g. 4-7@8", s);
+        let (range, rate) = s.split_once('@').ok_or_else(error)?;
+        let (start, end) = range.split_once('-').ok_or_else(error)?;
+        let cycle = CycleRange {
+            start: start.trim().parse().map_err(|_| error())?,
+            end: end.trim().parse().map_err(|_| error())?,
+            rate: rate.trim().parse().map_err(|_| error())?,
+        };
+        ensure!(
+            cycle.start < cycle.end && cycle.rate != 0.0,
+            "Cycle {} must cover at least two colors with a non-zero rate",
+            s
+        );
+        Ok(cycle)
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+pub fn cycle_palette(palette: &Palette, cycles: &[CycleRange], frame: usize, fps: u32) -> Palette {
+    let mut result = palette.clone();
+    for cycle in cycles.iter().filter(|c| c.end < palette.len()) {
+        let shift = cycle.shift(frame, fps);
+        for i in 0..cycle.len() {
+            result[cycle.start + (i + shift) % cycle.len()] = palette[cycle.start + i];
+        }
+    }
+    result
+}
+
+pub fn cycle_frames(
+    img: &RgbImage,
+    palette: &Palette,
+    cycles: &[CycleRange],
+    fps: u32,
+) -> Result<Vec<RgbImage>> {
+    ensure!(fps > 0, "Frame rate must be positive");
+    for cycle in cycles {
+        ensure!(
+            cycle.end < palette.len(),
+            "Cycle {}-{} is outside of the {} color palette",
+            cycle.start,
+            cycle.end,
+            palette.len()
+        );
+    }
+    let frames = cycles
+        .iter()
+        .map(|c| c.period(fps))
+        .fold(1, |acc, p| (acc * p / gcd(acc, p)).min(MAX_FRAMES));
+    let indices = palette_indices(img, palette);
+    let (width, height) = img.dimensions();
+    Ok((0..frames)
+        .map(|frame| {
+            let colors = cycle_palette(palette, cycles, frame, fps);
+            let mut result = RgbImage::new(width, height);
+            result
+                .pixels_mut()
+                .zip(indices.iter())
+                .for_each(|(p, i)| *p = colors[*i]);
+            result
+        })
+        .collect())
+}
+
+#[cfg(feature = "io")]
+pub fn save_cycling_gif<P>(
+    path: P,
+    img: &RgbImage,
+    palette: &Palette,
+    cycles: &[CycleRange],
+    fps: u32,
+) -> Result<usize>
+where
+    P: AsRef<Path>,
+{
+    let frames = cycle_frames(img, palette, cycles, fps)?;
+    let file = File::create(path.as_ref())
+        .with_context(|| format!("Failed to save: {:?}", path.as_ref()))?;
+    let mut encoder = GifEncoder::new(file);
+    encoder.set_repeat(Repeat::Infinite)?;
+    let delay = Delay::from_numer_denom_ms(1000, fps);
+    for frame in &frames {
+        let rgba = RgbaImage::from_fn(frame.width(), frame.height(), |x, y| {
+            let Rgb([r, g, b]) = *frame.get_pixel(x, y);
+            image::Rgba([r, g, b, 255])
+        });
+        encoder
+            .encode_frame(Frame::from_parts(rgba, 0, 0, delay))
+            .with_context(|| "Failed to save image")?;
+    }
+    Ok(frames.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::cycling::{CycleRange, cycle_frames, cycle_palette};
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_cycle_palette() {
+        let palette = (0..5u8).map(|i| Rgb([i, i, i])).collect::<Vec<_>>();
+        let cycle = "1-3@10".parse::<CycleRange>().unwrap();
+        assert_eq!(cycle_palette(&palette, &[cycle], 0, 10), palette);
+        let shifted = cycle_palette(&palette, &[cycle], 1, 10);
+        assert_eq!(
+            shifted.iter().map(|c| c[0]).collect::<Vec<_>>(),
+            vec![0, 3, 1, 2, 4]
+        );
+        let reverse = CycleRange {
+            rate: -10.0,
+            ..cycle
+        };
+        let shifted = cycle_palette(&palette, &[reverse], 1, 10);
+        assert_eq!(
+            shifted.iter().map(|c| c[0]).collect::<Vec<_>>(),
+            vec![0, 2, 3, 1, 4]
+        );
+        assert!("3-1@2".parse::<CycleRange>().is_err());
+        assert!("1-3".parse::<CycleRange>().is_err());
+    }
+
+    #[test]
+    fn test_cycle_frames() {
+        let palette = vec![Rgb([0u8, 0, 0]), Rgb([255, 0, 0]), Rgb([0, 255, 0])];
+        let img = RgbImage::from_fn(3, 1, |x, _| palette[x as usize]);
+        let cycles = ["1-2@5".parse().unwrap()];
+        let frames = cycle_frames(&img, &palette, &cycles, 10).unwrap();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0], img);
+        assert_eq!(frames[1], img);
+        assert_eq!(frames[2].get_pixel(1, 0), &palette[2]);
+        assert_eq!(frames[2].get_pixel(0, 0), &palette[0]);
+    }
+}
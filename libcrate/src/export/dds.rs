@@ -0,0 +1,112 @@
+#[cfg(feature = "io")]
+use anyhow::Context;
+use anyhow::{Result, ensure};
+use image::RgbaImage;
+#[cfg(feature = "io")]
+use std::fs;
+#[cfg(feature = "io")]
+use std::path::Path;
+
+const HEADER_SIZE: u32 = 124;
+const PIXEL_FORMAT_SIZE: u32 = 32;
+
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PITCH: u32 = 0x8;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDSD_LINEARSIZE: u32 = 0x80000;
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDSCAPS_COMPLEX: u32 = 0x8;
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+const DDSCAPS_MIPMAP: u32 = 0x400000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DdsPixels {
+    Rgba,
+    FourCc([u8; 4]),
+}
+
+pub fn encode_dds_levels(
+    width: u32,
+    height: u32,
+    pixels: DdsPixels,
+    levels: &[Vec<u8>],
+) -> Result<Vec<u8>> {
+    ensure!(!levels.is_empty(), "Cannot write a DDS without any image");
+    let mut flags = DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT;
+    let mut caps = DDSCAPS_TEXTURE;
+    if levels.len() > 1 {
+        flags |= DDSD_MIPMAPCOUNT;
+        caps |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP;
+    }
+    let (pitch, pixel_format) = match pixels {
+        DdsPixels::Rgba => {
+            flags |= DDSD_PITCH;
+            (
+                width * 4,
+                [
+                    DDPF_RGB | DDPF_ALPHAPIXELS,
+                    0,
+                    32,
+                    0x0000_00FF,
+                    0x0000_FF00,
+                    0x00FF_0000,
+                    0xFF00_0000,
+                ],
+            )
+        }
+        DdsPixels::FourCc(code) => {
+            flags |= DDSD_LINEARSIZE;
+            (
+                levels[0].len() as u32,
+                [DDPF_FOURCC, u32::from_le_bytes(code), 0, 0, 0, 0, 0],
+            )
+        }
+    };
+
+    let mut header = vec![
+        HEADER_SIZE,
+        flags,
+        height,
+        width,
+        pitch,
+        0,
+        levels.len() as u32,
+    ];
+    header.extend([0; 11]);
+    header.push(PIXEL_FORMAT_SIZE);
+    header.extend(pixel_format);
+    header.extend([caps, 0, 0, 0, 0]);
+
+    let mut bytes = b"DDS ".to_vec();
+    header
+        .iter()
+        .for_each(|v| bytes.extend_from_slice(&v.to_le_bytes()));
+    levels
+        .iter()
+        .for_each(|level| bytes.extend_from_slice(level));
+    Ok(bytes)
+}
+
+pub fn encode_dds(levels: &[RgbaImage]) -> Result<Vec<u8>> {
+    ensure!(!levels.is_empty(), "Cannot write a DDS without any image");
+    let (width, height) = levels[0].dimensions();
+    let data = levels
+        .iter()
+        .map(|level| level.as_raw().clone())
+        .collect::<Vec<_>>();
+    encode_dds_levels(width, height, DdsPixels::Rgba, &data)
+}
+
+#[cfg(feature = "io")]
+pub fn save_dds<P>(path: P, levels: &[RgbaImage]) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    fs::write(path.as_ref(), encode_dds(levels)?)
+        .with_context(|| format!("Failed to save: {:?}", path.as_ref()))
+}
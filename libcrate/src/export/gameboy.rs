@@ -0,0 +1,196 @@
+use crate::Palette;
+use crate::image_processing::{luminance, palette_from_tuples, palette_indices};
+#[cfg(feature = "io")]
+use anyhow::Context;
+use anyhow::{Result, anyhow, ensure};
+use image::RgbImage;
+#[cfg(feature = "io")]
+use std::fs;
+#[cfg(feature = "io")]
+use std::path::Path;
+use std::str::FromStr;
+
+const TILE_SIZE: u32 = 8;
+
+pub const DMG_PALETTE: [(u8, u8, u8); 4] = [
+    (0xE0, 0xF8, 0xD0),
+    (0x88, 0xC0, 0x70),
+    (0x34, 0x68, 0x56),
+    (0x08, 0x18, 0x20),
+];
+
+pub fn dmg_palette() -> Palette {
+    palette_from_tuples(&DMG_PALETTE)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShadeMapping {
+    Luminance,
+    Palette(Palette),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileOrder {
+    RowMajor,
+    ColumnMajor,
+    Sprites8x16,
+}
+
+impl FromStr for TileOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "row" => Ok(TileOrder::RowMajor),
+            "column" => Ok(TileOrder::ColumnMajor),
+            "8x16" => Ok(TileOrder::Sprites8x16),
+            _ => Err(anyhow!(
+                "Unknown tile order {}, expected row, column or 8x16",
+                s
+            )),
+        }
+    }
+}
+
+pub fn shades(img: &RgbImage, mapping: &ShadeMapping) -> Result<Vec<u8>> {
+    match mapping {
+        ShadeMapping::Luminance => Ok(img
+            .pixels()
+            .map(|p| 3 - (luminance(p) * 4.0 / 256.0).min(3.0) as u8)
+            .collect()),
+        ShadeMapping::Palette(palette) => {
+            ensure!(
+                palette.len() == 4,
+                "Game Boy palette has to have 4 shades, got {}",
+                palette.len()
+            );
+            Ok(palette_indices(img, palette)
+                .into_iter()
+                .map(|i| i as u8)
+                .collect())
+        }
+    }
+}
+
+pub fn to_2bpp(img: &RgbImage, mapping: &ShadeMapping, order: TileOrder) -> Result<Vec<u8>> {
+    let (width, height) = img.dimensions();
+    let tile_height = match order {
+        TileOrder::Sprites8x16 => 2 * TILE_SIZE,
+        _ => TILE_SIZE,
+    };
+    ensure!(
+        width % TILE_SIZE == 0 && height % tile_height == 0,
+        "Image dimensions {}x{} are not a multiple of {}x{}",
+        width,
+        height,
+        TILE_SIZE,
+        tile_height
+    );
+    let shades = shades(img, mapping)?;
+    let (columns, rows) = (width / TILE_SIZE, height / TILE_SIZE);
+    let tiles: Vec<(u32, u32)> = match order {
+        TileOrder::RowMajor => (0..rows)
+            .flat_map(|r| (0..columns).map(move |c| (c, r)))
+            .collect(),
+        TileOrder::ColumnMajor => (0..columns)
+            .flat_map(|c| (0..rows).map(move |r| (c, r)))
+            .collect(),
+        TileOrder::Sprites8x16 => (0..rows / 2)
+            .flat_map(|r| (0..columns).flat_map(move |c| [(c, 2 * r), (c, 2 * r + 1)]))
+            .collect(),
+    };
+    let mut data = Vec::with_capacity(tiles.len() * 16);
+    for (column, row) in tiles {
+        for y in 0..TILE_SIZE {
+            let (mut low, mut high) = (0u8, 0u8);
+            for x in 0..TILE_SIZE {
+                let idx = ((row * TILE_SIZE + y) * width + column * TILE_SIZE + x) as usize;
+                let shade = shades[idx];
+                low |= (shade & 1) << (7 - x);
+                high |= ((shade >> 1) & 1) << (7 - x);
+            }
+            data.push(low);
+            data.push(high);
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(feature = "io")]
+pub fn save_2bpp<P>(path: P, img: &RgbImage, mapping: &ShadeMapping, order: TileOrder) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    fs::write(path.as_ref(), to_2bpp(img, mapping, order)?)
+        .with_context(|| format!("Failed to save: {:?}", path.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::gameboy::{ShadeMapping, TileOrder, dmg_palette, to_2bpp};
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_2bpp() {
+        // left tile: vertical stripes of all 4 shades, right tile: solid darkest shade
+        let shades = [
+            Rgb([255u8, 255, 255]),
+            Rgb([170, 170, 170]),
+            Rgb([85, 85, 85]),
+            Rgb([0, 0, 0]),
+        ];
+        let img = RgbImage::from_fn(16, 8, |x, _| {
+            if x < 8 {
+                shades[(x % 4) as usize]
+            } else {
+                shades[3]
+            }
+        });
+        let data = to_2bpp(&img, &ShadeMapping::Luminance, TileOrder::RowMajor).unwrap();
+        assert_eq!(data.len(), 32);
+        assert_eq!(&data[0..2], &[0b0101_0101, 0b0011_0011]);
+        assert_eq!(&data[16..18], &[0xFF, 0xFF]);
+
+        let column = to_2bpp(&img, &ShadeMapping::Luminance, TileOrder::ColumnMajor).unwrap();
+        assert_eq!(column, data);
+        let dmg = to_2bpp(
+            &img,
+            &ShadeMapping::Palette(dmg_palette()),
+            TileOrder::RowMajor,
+        )
+        .unwrap();
+        assert_eq!(dmg, data);
+        assert!(to_2bpp(&img, &ShadeMapping::Luminance, TileOrder::Sprites8x16).is_err());
+        assert!(
+            to_2bpp(
+                &img,
+                &ShadeMapping::Palette(vec![Rgb([0, 0, 0])]),
+                TileOrder::RowMajor
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_tile_order() {
+        let img = RgbImage::from_fn(16, 16, |x, y| {
+            let v = if (x / 8 + 2 * (y / 8)) % 2 == 1 {
+                0
+            } else {
+                255
+            };
+            Rgb([v, v, v])
+        });
+        let first_bytes = |order| {
+            to_2bpp(&img, &ShadeMapping::Luminance, order)
+                .unwrap()
+                .chunks(16)
+                .map(|tile| tile[0])
+                .collect::<Vec<_>>()
+        };
+        // tiles: (0,0) light, (1,0) dark, (0,1) light, (1,1) dark
+        assert_eq!(first_bytes(TileOrder::RowMajor), vec![0, 0xFF, 0, 0xFF]);
+        assert_eq!(first_bytes(TileOrder::ColumnMajor), vec![0, 0, 0xFF, 0xFF]);
+        assert_eq!(first_bytes(TileOrder::Sprites8x16), vec![0, 0, 0xFF, 0xFF]);
+    }
+}
@@ -0,0 +1,109 @@
+#[cfg(feature = "io")]
+use crate::ProcessedImage;
+use crate::spritesheet::Rect;
+#[cfg(feature = "io")]
+use anyhow::{Context, Result};
+#[cfg(feature = "io")]
+use std::fs;
+#[cfg(feature = "io")]
+use std::path::{Path, PathBuf};
+
+pub fn resource_path(res_dir: &str, file_name: &str) -> String {
+    let res_dir = res_dir.trim_matches('/');
+    if res_dir.is_empty() {
+        format!("res://{}", file_name)
+    } else {
+        format!("res://{}/{}", res_dir, file_name)
+    }
+}
+
+pub fn import_stub(res_path: &str) -> String {
+    format!(
+        "[remap]\n\nimporter=\"texture\"\ntype=\"CompressedTexture2D\"\n\n\
+         [deps]\n\nsource_file=\"{}\"\n\n\
+         [params]\n\ncompress/mode=0\ncompress/lossy_quality=1.0\nmipmaps/generate=false\n\
+         process/fix_alpha_border=false\nprocess/premult_alpha=false\ndetect_3d/compress_to=0\n",
+        res_path
+    )
+}
+
+pub fn canvas_texture(res_path: &str) -> String {
+    format!(
+        "[gd_resource type=\"CanvasTexture\" load_steps=2 format=3]\n\n\
+         [ext_resource type=\"Texture2D\" path=\"{}\" id=\"1\"]\n\n\
+         [resource]\ndiffuse_texture = ExtResource(\"1\")\ntexture_filter = 1\n",
+        res_path
+    )
+}
+
+pub fn atlas_texture(res_path: &str, rect: &Rect) -> String {
+    format!(
+        "[gd_resource type=\"AtlasTexture\" load_steps=2 format=3]\n\n\
+         [ext_resource type=\"Texture2D\" path=\"{}\" id=\"1\"]\n\n\
+         [resource]\natlas = ExtResource(\"1\")\nregion = Rect2({}, {}, {}, {})\nfilter_clip = true\n",
+        res_path, rect.x, rect.y, rect.width, rect.height
+    )
+}
+
+#[cfg(feature = "io")]
+pub fn save_bundle<P>(
+    path: P,
+    image: &ProcessedImage,
+    res_dir: &str,
+    frames: &[(String, Rect)],
+) -> Result<Vec<PathBuf>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    image.save(path)?;
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("Invalid file name: {:?}", path))?
+        .to_string_lossy();
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let res_path = resource_path(res_dir, &file_name);
+
+    let mut written = vec![path.to_path_buf()];
+    let mut write = |target: PathBuf, content: String| -> Result<()> {
+        fs::write(&target, content).with_context(|| format!("Failed to save: {:?}", target))?;
+        written.push(target);
+        Ok(())
+    };
+    write(
+        path.with_file_name(format!("{}.import", file_name)),
+        import_stub(&res_path),
+    )?;
+    write(path.with_extension("tres"), canvas_texture(&res_path))?;
+    for (name, rect) in frames {
+        write(
+            path.with_file_name(format!("{}_{}.tres", stem, name)),
+            atlas_texture(&res_path, rect),
+        )?;
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::godot::{atlas_texture, canvas_texture, import_stub, resource_path};
+    use crate::spritesheet::Rect;
+
+    #[test]
+    fn test_godot_resources() {
+        let res_path = resource_path("/sprites/", "hero.png");
+        assert_eq!(res_path, "res://sprites/hero.png");
+        assert_eq!(resource_path("", "hero.png"), "res://hero.png");
+        let import = import_stub(&res_path);
+        assert!(import.contains("source_file=\"res://sprites/hero.png\""));
+        assert!(import.contains("mipmaps/generate=false"));
+        assert!(canvas_texture(&res_path).contains("texture_filter = 1"));
+        let rect = Rect {
+            x: 16,
+            y: 0,
+            width: 16,
+            height: 8,
+        };
+        assert!(atlas_texture(&res_path, &rect).contains("region = Rect2(16, 0, 16, 8)"));
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{Palette, ProcessedImage, ScaleFilter, ScaleMode};
+#[cfg(feature = "io")]
+use anyhow::Context;
+use anyhow::{Result, ensure};
+use image::ExtendedColorType;
+use image::codecs::ico::{IcoEncoder, IcoFrame};
+#[cfg(feature = "io")]
+use std::fs::File;
+#[cfg(feature = "io")]
+use std::path::Path;
+
+pub const ICO_SIZES: [u32; 4] = [16, 32, 48, 64];
+const MAX_ICO_SIZE: u32 = 256;
+
+pub fn icon_frames(
+    image: &ProcessedImage,
+    palette: &Palette,
+    sizes: &[u32],
+) -> Result<Vec<ProcessedImage>> {
+    sizes
+        .iter()
+        .map(|size| {
+            let mut frame = image.clone();
+            frame.scale_to(
+                *size,
+                *size,
+                ScaleMode::Fit,
+                ScaleFilter::Nearest,
+                false,
+                None,
+            )?;
+            frame.apply_palette(palette);
+            Ok(frame)
+        })
+        .collect()
+}
+
+pub fn encode_ico(frames: &[ProcessedImage]) -> Result<Vec<u8>> {
+    ensure!(!frames.is_empty(), "Cannot write an ICO without any image");
+    let frames = frames
+        .iter()
+        .map(|frame| {
+            ensure!(
+                (1..=MAX_ICO_SIZE).contains(&frame.width())
+                    && (1..=MAX_ICO_SIZE).contains(&frame.height()),
+                "Icon size {}x{} is out of the 1-{} range",
+                frame.width(),
+                frame.height(),
+                MAX_ICO_SIZE
+            );
+            Ok(IcoFrame::as_png(
+                frame.to_rgba().as_raw(),
+                frame.width(),
+                frame.height(),
+                ExtendedColorType::Rgba8,
+            )?)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut bytes = vec![];
+    IcoEncoder::new(&mut bytes).encode_images(&frames)?;
+    Ok(bytes)
+}
+
+#[cfg(feature = "io")]
+pub fn save_ico<P>(path: P, frames: &[ProcessedImage]) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let bytes = encode_ico(frames)?;
+    let mut file = File::create(path.as_ref())
+        .with_context(|| format!("Failed to save: {:?}", path.as_ref()))?;
+    std::io::Write::write_all(&mut file, &bytes)
+        .with_context(|| format!("Failed to save: {:?}", path.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::ico::{ICO_SIZES, encode_ico, icon_frames};
+    use crate::{Palette, ProcessedImage};
+    use image::{ImageFormat, Rgb};
+
+    #[test]
+    fn test_icon_frames() {
+        let palette: Palette = vec![Rgb([255, 0, 0]), Rgb([0, 0, 255])];
+        let buffer = (0..40 * 20)
+            .map(|i| palette[(i % 40 / 20) as usize])
+            .collect::<Vec<_>>();
+        let image = ProcessedImage::from_buffer(40, 20, &buffer);
+        let frames = icon_frames(&image, &palette, &ICO_SIZES).unwrap();
+        let sizes = frames
+            .iter()
+            .map(|f| f.data.dimensions())
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, vec![(16, 16), (32, 32), (48, 48), (64, 64)]);
+        assert!(frames[0].alpha.as_ref().unwrap().get_pixel(0, 0)[0] == 0);
+        assert!(
+            frames
+                .iter()
+                .all(|f| f.data.pixels().all(|p| palette.contains(p)))
+        );
+
+        let bytes = encode_ico(&frames).unwrap();
+        assert_eq!(u16::from_le_bytes([bytes[4], bytes[5]]), 4);
+        let decoded = image::load_from_memory_with_format(&bytes, ImageFormat::Ico).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (64, 64));
+    }
+}
@@ -0,0 +1,91 @@
+#[cfg(feature = "io")]
+use crate::export::dds::save_dds;
+use crate::{Palette, ProcessedImage, ScaleFilter};
+#[cfg(feature = "io")]
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+pub fn mipmap_chain(image: &ProcessedImage, palette: Option<&Palette>) -> Vec<ProcessedImage> {
+    let mut levels = vec![image.clone()];
+    while let Some(last) = levels.last()
+        && (last.width() > 1 || last.height() > 1)
+    {
+        let mut level = last.clone();
+        level.scale(
+            (last.width() / 2).max(1),
+            (last.height() / 2).max(1),
+            ScaleFilter::Triangle,
+        );
+        if let Some(palette) = palette {
+            level.apply_palette(palette);
+        }
+        levels.push(level);
+    }
+    levels
+}
+
+pub fn level_path(path: &Path, level: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}_mip{}.{}", stem, level, extension.to_string_lossy()),
+        None => format!("{}_mip{}", stem, level),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(feature = "io")]
+pub fn save_mipmaps<P>(path: P, levels: &[ProcessedImage]) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let dds = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("dds"));
+    if dds {
+        let levels = levels.iter().map(|l| l.to_rgba()).collect::<Vec<_>>();
+        return save_dds(path, &levels);
+    }
+    for (idx, level) in levels.iter().enumerate() {
+        level.save(level_path(path, idx))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::dds::encode_dds;
+    use crate::export::mipmap::{level_path, mipmap_chain};
+    use crate::{Palette, ProcessedImage};
+    use image::Rgb;
+    use std::path::Path;
+
+    #[test]
+    fn test_mipmap_chain() {
+        let buffer = (0..8 * 4)
+            .map(|i| Rgb([(i * 8) as u8, 0, 255 - (i * 8) as u8]))
+            .collect::<Vec<_>>();
+        let image = ProcessedImage::from_buffer(8, 4, &buffer);
+        let palette: Palette = vec![Rgb([0, 0, 255]), Rgb([255, 0, 0])];
+        let levels = mipmap_chain(&image, Some(&palette));
+        let sizes = levels
+            .iter()
+            .map(|l| l.data.dimensions())
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, vec![(8, 4), (4, 2), (2, 1), (1, 1)]);
+        assert!(
+            levels[1..]
+                .iter()
+                .all(|l| l.data.pixels().all(|p| palette.contains(p)))
+        );
+
+        let bytes = encode_dds(&levels.iter().map(|l| l.to_rgba()).collect::<Vec<_>>()).unwrap();
+        assert_eq!(&bytes[..4], b"DDS ");
+        assert_eq!(u32::from_le_bytes(bytes[28..32].try_into().unwrap()), 4);
+        assert_eq!(bytes.len(), 128 + (32 + 8 + 2 + 1) * 4);
+        assert_eq!(
+            level_path(Path::new("out/tex.png"), 2),
+            Path::new("out/tex_mip2.png")
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use crate::image_processing::{PaletteMatcher, get_color_histogram};
+use crate::{Palette, RgbHistogram};
+#[cfg(feature = "io")]
+use anyhow::Context;
+use anyhow::{Result, ensure};
+use image::{Rgb, RgbImage};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+#[cfg(feature = "io")]
+use std::fs;
+#[cfg(feature = "io")]
+use std::path::Path;
+
+pub const TILE_SIZE: u32 = 8;
+pub const ATTRIBUTE_SIZE: u32 = 16;
+const COLORS_PER_TILE: usize = 3;
+const MAX_SUBPALETTES: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileViolation {
+    pub x: u32,
+    pub y: u32,
+    pub colors: usize,
+}
+
+pub struct NesExport {
+    pub background: Rgb<u8>,
+    pub subpalettes: Vec<Vec<Rgb<u8>>>,
+    pub chr: Vec<u8>,
+    pub attributes: Vec<u8>,
+}
+
+impl NesExport {
+    #[cfg(feature = "io")]
+    pub fn save<P>(&self, chr_path: P, attributes_path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        fs::write(chr_path.as_ref(), &self.chr)
+            .with_context(|| format!("Failed to save: {:?}", chr_path.as_ref()))?;
+        fs::write(attributes_path.as_ref(), &self.attributes)
+            .with_context(|| format!("Failed to save: {:?}", attributes_path.as_ref()))
+    }
+}
+
+pub fn most_common_color(img: &RgbImage) -> Rgb<u8> {
+    get_color_histogram(img)
+        .into_iter()
+        .max_by_key(|(color, count)| (*count, std::cmp::Reverse(color.0)))
+        .map(|(color, _)| color)
+        .unwrap_or(Rgb([0, 0, 0]))
+}
+
+fn block_colors(
+    img: &RgbImage,
+    background: Rgb<u8>,
+    block_size: u32,
+) -> Vec<((u32, u32), RgbHistogram)> {
+    let (width, height) = img.dimensions();
+    let mut blocks = vec![];
+    for by in (0..height).step_by(block_size as usize) {
+        for bx in (0..width).step_by(block_size as usize) {
+            let mut colors = HashMap::new();
+            for y in by..(by + block_size).min(height) {
+                for x in bx..(bx + block_size).min(width) {
+                    let color = *img.get_pixel(x, y);
+                    if color != background {
+                        *colors.entry(color).or_insert(0) += 1;
+                    }
+                }
+            }
+            blocks.push(((bx / block_size, by / block_size), colors));
+        }
+    }
+    blocks
+}
+
+pub fn check_tiles(img: &RgbImage, background: Rgb<u8>, block_size: u32) -> Vec<TileViolation> {
+    block_colors(img, background, block_size)
+        .into_iter()
+        .filter(|(_, colors)| colors.len() > COLORS_PER_TILE)
+        .map(|((x, y), colors)| TileViolation {
+            x,
+            y,
+            colors: colors.len(),
+        })
+        .collect()
+}
+
+pub fn fix_tiles(img: &RgbImage, background: Rgb<u8>, block_size: u32) -> RgbImage {
+    let mut fixed = img.clone();
+    for ((bx, by), colors) in block_colors(img, background, block_size) {
+        if colors.len() <= COLORS_PER_TILE {
+            continue;
+        }
+        let mut ranked = colors.into_iter().collect::<Vec<_>>();
+        ranked.sort_by_key(|(color, count)| (Reverse(*count), color.0));
+        let mut allowed = vec![background];
+        allowed.extend(ranked.iter().take(COLORS_PER_TILE).map(|(c, _)| *c));
+        remap_block(&mut fixed, (bx, by), block_size, &allowed);
+    }
+    limit_subpalettes(&mut fixed, background, block_size);
+    fixed
+}
+
+// the least used sub-palettes are dropped, their areas take the closest of the kept ones
+fn limit_subpalettes(img: &mut RgbImage, background: Rgb<u8>, block_size: u32) {
+    let blocks = block_colors(img, background, block_size);
+    let (subpalettes, assignment) = pack_subpalettes(&area_colors(&blocks));
+    if subpalettes.len() <= MAX_SUBPALETTES {
+        return;
+    }
+    let mut coverage = vec![0u64; subpalettes.len()];
+    for ((_, colors), &slot) in blocks.iter().zip(&assignment) {
+        coverage[slot] += colors.values().map(|&n| n as u64).sum::<u64>();
+    }
+    let mut ranked = (0..subpalettes.len()).collect::<Vec<_>>();
+    ranked.sort_by_key(|&slot| (Reverse(coverage[slot]), slot));
+    let kept = &ranked[..MAX_SUBPALETTES];
+    let palettes = kept
+        .iter()
+        .map(|&slot| {
+            let mut palette = vec![background];
+            palette.extend(subpalettes[slot].iter().map(|c| Rgb(*c)));
+            palette
+        })
+        .collect::<Vec<_>>();
+    for ((position, colors), slot) in blocks.into_iter().zip(assignment) {
+        if kept.contains(&slot) {
+            continue;
+        }
+        let cost = |palette: &&Palette| {
+            colors
+                .iter()
+                .map(|(color, &count)| {
+                    let nearest = palette.iter().map(|c| distance(color, c)).min();
+                    count as u64 * nearest.unwrap_or(0)
+                })
+                .sum::<u64>()
+        };
+        if let Some(palette) = palettes.iter().min_by_key(cost) {
+            remap_block(img, position, block_size, palette);
+        }
+    }
+}
+
+fn distance(a: &Rgb<u8>, b: &Rgb<u8>) -> u64 {
+    a.0.iter()
+        .zip(b.0)
+        .map(|(&a, b)| (a as i64 - b as i64).pow(2) as u64)
+        .sum()
+}
+
+fn remap_block(img: &mut RgbImage, (bx, by): (u32, u32), block_size: u32, allowed: &Palette) {
+    let matcher = PaletteMatcher::new(allowed);
+    let (x0, y0) = (bx * block_size, by * block_size);
+    for y in y0..(y0 + block_size).min(img.height()) {
+        for x in x0..(x0 + block_size).min(img.width()) {
+            let pixel = img.get_pixel_mut(x, y);
+            *pixel = matcher.nearest(pixel);
+        }
+    }
+}
+
+fn area_colors(blocks: &[((u32, u32), RgbHistogram)]) -> Vec<BTreeSet<[u8; 3]>> {
+    blocks
+        .iter()
+        .map(|(_, colors)| colors.keys().map(|c| c.0).collect())
+        .collect()
+}
+
+// first fit from the most colorful area down, returns the sub-palettes and the one of each area
+fn pack_subpalettes(areas: &[BTreeSet<[u8; 3]>]) -> (Vec<BTreeSet<[u8; 3]>>, Vec<usize>) {
+    let mut order = (0..areas.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| Reverse(areas[i].len()));
+    let mut subpalettes: Vec<BTreeSet<[u8; 3]>> = vec![];
+    let mut assignment = vec![0; areas.len()];
+    for i in order {
+        let colors = &areas[i];
+        let slot = subpalettes
+            .iter()
+            .position(|p| p.union(colors).count() <= COLORS_PER_TILE);
+        assignment[i] = match slot {
+            Some(slot) => {
+                subpalettes[slot].extend(colors);
+                slot
+            }
+            None => {
+                subpalettes.push(colors.clone());
+                subpalettes.len() - 1
+            }
+        };
+    }
+    (subpalettes, assignment)
+}
+
+pub fn export(img: &RgbImage, background: Rgb<u8>) -> Result<NesExport> {
+    let (width, height) = img.dimensions();
+    ensure!(
+        width % ATTRIBUTE_SIZE == 0 && height % ATTRIBUTE_SIZE == 0,
+        "Image dimensions {}x{} are not a multiple of {}",
+        width,
+        height,
+        ATTRIBUTE_SIZE
+    );
+    let violations = check_tiles(img, background, ATTRIBUTE_SIZE);
+    ensure!(
+        violations.is_empty(),
+        "{} attribute areas use more than {} colors besides the background, first at {}x{}",
+        violations.len(),
+        COLORS_PER_TILE,
+        violations[0].x,
+        violations[0].y
+    );
+
+    let blocks = block_colors(img, background, ATTRIBUTE_SIZE);
+    let (subpalettes, assignment) = pack_subpalettes(&area_colors(&blocks));
+    ensure!(
+        subpalettes.len() <= MAX_SUBPALETTES,
+        "The image needs {} sub-palettes, at most {} are possible",
+        subpalettes.len(),
+        MAX_SUBPALETTES
+    );
+    let subpalettes = subpalettes
+        .into_iter()
+        .map(|p| p.into_iter().map(Rgb).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    let areas_per_row = width / ATTRIBUTE_SIZE;
+    let area_of =
+        |x: u32, y: u32| ((y / ATTRIBUTE_SIZE) * areas_per_row + x / ATTRIBUTE_SIZE) as usize;
+    let color_index = |x: u32, y: u32| -> u8 {
+        let color = img.get_pixel(x, y);
+        if *color == background {
+            0
+        } else {
+            let palette = &subpalettes[assignment[area_of(x, y)]];
+            palette.iter().position(|c| c == color).unwrap() as u8 + 1
+        }
+    };
+
+    let mut chr = Vec::with_capacity(((width / TILE_SIZE) * (height / TILE_SIZE) * 16) as usize);
+    for ty in (0..height).step_by(TILE_SIZE as usize) {
+        for tx in (0..width).step_by(TILE_SIZE as usize) {
+            let mut planes = [[0u8; 8]; 2];
+            for y in 0..TILE_SIZE {
+                for x in 0..TILE_SIZE {
+                    let index = color_index(tx + x, ty + y);
+                    planes[0][y as usize] |= (index & 1) << (7 - x);
+                    planes[1][y as usize] |= ((index >> 1) & 1) << (7 - x);
+                }
+            }
+            chr.extend(planes.concat());
+        }
+    }
+
+    let quad_size = 2 * ATTRIBUTE_SIZE;
+    let mut attributes = vec![];
+    for qy in (0..height).step_by(quad_size as usize) {
+        for qx in (0..width).step_by(quad_size as usize) {
+            let mut byte = 0u8;
+            for (shift, (dx, dy)) in [(0, 0), (1, 0), (0, 1), (1, 1)].into_iter().enumerate() {
+                let (x, y) = (qx + dx * ATTRIBUTE_SIZE, qy + dy * ATTRIBUTE_SIZE);
+                if x < width && y < height {
+                    byte |= (assignment[area_of(x, y)] as u8) << (2 * shift);
+                }
+            }
+            attributes.push(byte);
+        }
+    }
+
+    Ok(NesExport {
+        background,
+        subpalettes,
+        chr,
+        attributes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::nes::{ATTRIBUTE_SIZE, check_tiles, export, fix_tiles, most_common_color};
+    use image::{Rgb, RgbImage};
+
+    const BG: Rgb<u8> = Rgb([0, 0, 0]);
+
+    fn get_image() -> RgbImage {
+        // left area uses red/green/blue, right area uses 4 colors
+        let colors = [
+            Rgb([255u8, 0, 0]),
+            Rgb([0, 255, 0]),
+            Rgb([0, 0, 255]),
+            Rgb([200, 200, 255]),
+        ];
+        RgbImage::from_fn(32, 16, |x, y| match (x, y) {
+            (_, 0..6) => BG,
+            (0..16, _) => colors[(y % 3) as usize],
+            (_, 6) => colors[3],
+            _ => colors[(y % 3) as usize],
+        })
+    }
+
+    #[test]
+    fn test_check_and_fix() {
+        let img = get_image();
+        assert_eq!(most_common_color(&img), BG);
+        let violations = check_tiles(&img, BG, ATTRIBUTE_SIZE);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(
+            (violations[0].x, violations[0].y, violations[0].colors),
+            (1, 0, 4)
+        );
+        assert!(export(&img, BG).is_err());
+        let fixed = fix_tiles(&img, BG, ATTRIBUTE_SIZE);
+        assert!(check_tiles(&fixed, BG, ATTRIBUTE_SIZE).is_empty());
+        assert_eq!(fixed.get_pixel(20, 6), &Rgb([0, 0, 255]));
+        assert_eq!(fixed.get_pixel(0, 6), img.get_pixel(0, 6));
+    }
+
+    #[test]
+    fn test_fix_subpalette_limit() {
+        // five areas with three colors each, no two of them fit one sub-palette
+        let img = RgbImage::from_fn(80, 16, |x, y| {
+            let level = 10 + 40 * (x / ATTRIBUTE_SIZE) as u8;
+            match y {
+                0..10 => BG,
+                10..12 => Rgb([level, 0, 0]),
+                12..14 => Rgb([0, level, 0]),
+                _ => Rgb([0, 0, level]),
+            }
+        });
+        assert!(check_tiles(&img, BG, ATTRIBUTE_SIZE).is_empty());
+        assert!(export(&img, BG).is_err());
+        let fixed = fix_tiles(&img, BG, ATTRIBUTE_SIZE);
+        let nes = export(&fixed, BG).unwrap();
+        assert_eq!(nes.subpalettes.len(), 4);
+        assert_eq!(fixed.get_pixel(0, 0), &BG);
+    }
+
+    #[test]
+    fn test_export() {
+        let img = fix_tiles(&get_image(), BG, ATTRIBUTE_SIZE);
+        let nes = export(&img, BG).unwrap();
+        assert_eq!(nes.subpalettes.len(), 1);
+        assert_eq!(nes.chr.len(), 8 * 16);
+        assert_eq!(nes.attributes, vec![0]);
+        // row 6 of the first tile is a single color of the subpalette: both planes set
+        let index = nes.subpalettes[0]
+            .iter()
+            .position(|c| c == img.get_pixel(0, 6))
+            .unwrap() as u8
+            + 1;
+        let expected = |bit: u8| if (index >> bit) & 1 == 1 { 0xFF } else { 0 };
+        assert_eq!(nes.chr[6], expected(0));
+        assert_eq!(nes.chr[8 + 6], expected(1));
+        assert_eq!(nes.chr[0], 0);
+    }
+}
@@ -0,0 +1,290 @@
+use crate::font::{draw_text, text_height, text_width};
+#[cfg(feature = "io")]
+use crate::image_processing::palette_usage;
+use crate::image_processing::rgb_to_hex;
+use crate::names::ColorNames;
+use crate::{Palette, RgbHistogram};
+#[cfg(feature = "io")]
+use anyhow::Context;
+use anyhow::{Result, anyhow};
+use image::{Rgb, RgbImage};
+use serde_json::{Value, json};
+#[cfg(feature = "io")]
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteFormat {
+    Css,
+    Scss,
+    Tailwind,
+    Json,
+    Hex,
+    Gpl,
+}
+
+impl PaletteFormat {
+    pub fn from_extension<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let extension = path
+            .as_ref()
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("css") => Ok(PaletteFormat::Css),
+            Some("scss") => Ok(PaletteFormat::Scss),
+            Some("js") | Some("cjs") | Some("mjs") => Ok(PaletteFormat::Tailwind),
+            Some("json") => Ok(PaletteFormat::Json),
+            Some("hex") => Ok(PaletteFormat::Hex),
+            Some("gpl") => Ok(PaletteFormat::Gpl),
+            _ => Err(anyhow!(
+                "Unknown palette format of {:?}, expected .css, .scss, .js, .json, .hex or .gpl",
+                path.as_ref()
+            )),
+        }
+    }
+}
+
+pub fn palette_json(palette: &Palette, name: &str, names: &ColorNames) -> Value {
+    let colors = palette
+        .iter()
+        .enumerate()
+        .map(|(i, color)| {
+            json!({
+                "index": i,
+                "hex": rgb_to_hex(color),
+                "rgb": color.0,
+                "name": names.nearest(color),
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({ "name": name, "colors": colors })
+}
+
+pub fn histogram_json(histogram: &RgbHistogram, names: &ColorNames) -> Value {
+    let total = histogram.values().map(|&c| c as u64).sum::<u64>().max(1) as f64;
+    let mut entries = histogram.iter().collect::<Vec<_>>();
+    entries.sort_by(|a, b| b.1.cmp(a.1).then(a.0.0.cmp(&b.0.0)));
+    let colors = entries
+        .into_iter()
+        .map(|(color, &count)| {
+            json!({
+                "hex": rgb_to_hex(color),
+                "rgb": color.0,
+                "count": count,
+                "percentage": count as f64 * 100.0 / total,
+                "name": names.nearest(color),
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({ "colors": colors })
+}
+
+pub fn palette_to_string(palette: &Palette, format: PaletteFormat, name: &str) -> String {
+    let colors = palette.iter().map(rgb_to_hex).enumerate();
+    match format {
+        PaletteFormat::Css => {
+            let vars = colors
+                .map(|(i, hex)| format!("  --{}-{}: {};\n", name, i, hex))
+                .collect::<String>();
+            format!(":root {{\n{}}}\n", vars)
+        }
+        PaletteFormat::Scss => colors
+            .map(|(i, hex)| format!("${}-{}: {};\n", name, i, hex))
+            .collect(),
+        PaletteFormat::Tailwind => {
+            let entries = colors
+                .map(|(i, hex)| format!("          '{}': '{}',\n", i, hex))
+                .collect::<String>();
+            format!(
+                "module.exports = {{\n  theme: {{\n    extend: {{\n      colors: {{\n        '{}': {{\n{}        }},\n      }},\n    }},\n  }},\n}};\n",
+                name, entries
+            )
+        }
+        PaletteFormat::Json => format!("{:#}\n", palette_json(palette, name, &ColorNames::css())),
+        PaletteFormat::Hex => palette
+            .iter()
+            .map(|c| format!("{:02x}{:02x}{:02x}\n", c[0], c[1], c[2]))
+            .collect(),
+        PaletteFormat::Gpl => {
+            let entries = palette
+                .iter()
+                .map(|c| format!("{:3} {:3} {:3}\t{}\n", c[0], c[1], c[2], rgb_to_hex(c)))
+                .collect::<String>();
+            format!("GIMP Palette\nName: {}\nColumns: 0\n#\n{}", name, entries)
+        }
+    }
+}
+
+#[cfg(feature = "io")]
+pub fn save_palette_as<P>(
+    path: P,
+    palette: &Palette,
+    format: PaletteFormat,
+    name: &str,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    fs::write(path.as_ref(), palette_to_string(palette, format, name))
+        .with_context(|| format!("Failed to save: {:?}", path.as_ref()))
+}
+
+#[cfg(feature = "io")]
+pub fn save_palette_json<P>(
+    path: P,
+    palette: &Palette,
+    name: &str,
+    names: &ColorNames,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    fs::write(
+        path.as_ref(),
+        serde_json::to_string_pretty(&palette_json(palette, name, names))?,
+    )
+    .with_context(|| format!("Failed to save: {:?}", path.as_ref()))
+}
+
+#[cfg(feature = "io")]
+pub fn save_histogram_json<P>(path: P, histogram: &RgbHistogram, names: &ColorNames) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    fs::write(
+        path.as_ref(),
+        serde_json::to_string_pretty(&histogram_json(histogram, names))?,
+    )
+    .with_context(|| format!("Failed to save: {:?}", path.as_ref()))
+}
+
+const CARD_BACKGROUND: Rgb<u8> = Rgb([32, 32, 32]);
+const CARD_TEXT: Rgb<u8> = Rgb([230, 230, 230]);
+const CARD_SWATCH: u32 = 24;
+const CARD_GAP: u32 = 6;
+const CARD_TEXT_SCALE: u32 = 2;
+
+pub fn palette_card(palette: &Palette, usage: &[u32]) -> RgbImage {
+    let total = usage.iter().sum::<u32>().max(1) as f64;
+    let labels = palette
+        .iter()
+        .enumerate()
+        .map(|(i, color)| {
+            let count = usage.get(i).copied().unwrap_or(0);
+            format!(
+                "{} {:5.1}%",
+                rgb_to_hex(color),
+                100.0 * count as f64 / total
+            )
+        })
+        .collect::<Vec<_>>();
+    let label_width = labels
+        .iter()
+        .map(|l| text_width(l, CARD_TEXT_SCALE))
+        .max()
+        .unwrap_or(0);
+    let mut card = RgbImage::from_pixel(
+        CARD_GAP + CARD_SWATCH + CARD_GAP + label_width + CARD_GAP,
+        CARD_GAP + palette.len() as u32 * (CARD_SWATCH + CARD_GAP),
+        CARD_BACKGROUND,
+    );
+    let text_offset = (CARD_SWATCH - text_height(CARD_TEXT_SCALE)) / 2;
+    for (i, (color, label)) in palette.iter().zip(labels.iter()).enumerate() {
+        let y = CARD_GAP + i as u32 * (CARD_SWATCH + CARD_GAP);
+        let swatch = RgbImage::from_pixel(CARD_SWATCH, CARD_SWATCH, *color);
+        image::imageops::replace(&mut card, &swatch, CARD_GAP as i64, y as i64);
+        draw_text(
+            &mut card,
+            2 * CARD_GAP + CARD_SWATCH,
+            y + text_offset,
+            label,
+            CARD_TEXT,
+            CARD_TEXT_SCALE,
+        );
+    }
+    card
+}
+
+#[cfg(feature = "io")]
+pub fn save_palette_card<P>(path: P, palette: &Palette, img: &RgbImage) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    palette_card(palette, &palette_usage(img, palette))
+        .save(path.as_ref())
+        .with_context(|| "Failed to save image")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::palette::{
+        CARD_GAP, CARD_SWATCH, CARD_TEXT, PaletteFormat, histogram_json, palette_card,
+        palette_json, palette_to_string,
+    };
+    use crate::image_processing::palette_usage;
+    use crate::names::ColorNames;
+    use crate::palette::parse_gpl;
+    use image::{Rgb, RgbImage};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_palette_formats() {
+        let palette = vec![Rgb([255u8, 0, 16]), Rgb([0, 170, 255])];
+        assert_eq!(
+            palette_to_string(&palette, PaletteFormat::Css, "brand"),
+            ":root {\n  --brand-0: #ff0010;\n  --brand-1: #00aaff;\n}\n"
+        );
+        assert_eq!(
+            palette_to_string(&palette, PaletteFormat::Scss, "brand"),
+            "$brand-0: #ff0010;\n$brand-1: #00aaff;\n"
+        );
+        assert_eq!(
+            palette_to_string(&palette, PaletteFormat::Hex, "brand"),
+            "ff0010\n00aaff\n"
+        );
+        let gpl = palette_to_string(&palette, PaletteFormat::Gpl, "brand");
+        assert_eq!(parse_gpl(&gpl).unwrap(), palette);
+        let tailwind = palette_to_string(&palette, PaletteFormat::Tailwind, "brand");
+        assert!(tailwind.starts_with("module.exports = {"));
+        assert!(tailwind.contains("        'brand': {\n          '0': '#ff0010',\n"));
+        assert_eq!(
+            PaletteFormat::from_extension("x/colors.SCSS").unwrap(),
+            PaletteFormat::Scss
+        );
+        assert!(PaletteFormat::from_extension("colors.txt").is_err());
+        let json = palette_json(&palette, "brand", &ColorNames::css());
+        assert_eq!(json["colors"][0]["hex"], "#ff0010");
+        assert_eq!(json["colors"][0]["name"], "red");
+        assert_eq!(json["colors"][1]["name"], "deepskyblue");
+    }
+
+    #[test]
+    fn test_histogram_json() {
+        let histogram = HashMap::from([(Rgb([0u8, 170, 255]), 1), (Rgb([255u8, 0, 16]), 3)]);
+        let json = histogram_json(&histogram, &ColorNames::css());
+        assert_eq!(json["colors"][0]["hex"], "#ff0010");
+        assert_eq!(json["colors"][0]["count"], 3);
+        assert_eq!(json["colors"][0]["percentage"], 75.0);
+        assert_eq!(json["colors"][0]["name"], "red");
+        assert_eq!(json["colors"][1]["name"], "deepskyblue");
+    }
+
+    #[test]
+    fn test_palette_card() {
+        let palette = vec![Rgb([255u8, 0, 16]), Rgb([0, 170, 255]), Rgb([0, 0, 0])];
+        let img = RgbImage::from_fn(4, 1, |x, _| palette[(x % 2) as usize]);
+        let usage = palette_usage(&img, &palette);
+        assert_eq!(usage, vec![2, 2, 0]);
+        let card = palette_card(&palette, &usage);
+        assert_eq!(card.height(), CARD_GAP + 3 * (CARD_SWATCH + CARD_GAP));
+        assert_eq!(card.get_pixel(CARD_GAP, CARD_GAP), &palette[0]);
+        assert_eq!(
+            card.get_pixel(CARD_GAP, 2 * CARD_GAP + CARD_SWATCH),
+            &palette[1]
+        );
+        assert!(card.pixels().any(|p| *p == CARD_TEXT));
+    }
+}
@@ -0,0 +1,105 @@
+use crate::Palette;
+use crate::image_processing::{palette_from_tuples, palette_indices};
+#[cfg(feature = "io")]
+use anyhow::Context;
+use anyhow::{Result, ensure};
+use image::RgbImage;
+#[cfg(feature = "io")]
+use std::fs;
+#[cfg(feature = "io")]
+use std::path::Path;
+
+pub const PICO8_SIZE: u32 = 128;
+
+pub const PICO8_PALETTE: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0x1D, 0x2B, 0x53),
+    (0x7E, 0x25, 0x53),
+    (0x00, 0x87, 0x51),
+    (0xAB, 0x52, 0x36),
+    (0x5F, 0x57, 0x4F),
+    (0xC2, 0xC3, 0xC7),
+    (0xFF, 0xF1, 0xE8),
+    (0xFF, 0x00, 0x4D),
+    (0xFF, 0xA3, 0x00),
+    (0xFF, 0xEC, 0x27),
+    (0x00, 0xE4, 0x36),
+    (0x29, 0xAD, 0xFF),
+    (0x83, 0x76, 0x9C),
+    (0xFF, 0x77, 0xA8),
+    (0xFF, 0xCC, 0xAA),
+];
+
+pub fn pico8_palette() -> Palette {
+    palette_from_tuples(&PICO8_PALETTE)
+}
+
+pub fn gfx_section(img: &RgbImage) -> Result<String> {
+    let (width, height) = img.dimensions();
+    ensure!(
+        width <= PICO8_SIZE && height <= PICO8_SIZE,
+        "Image {}x{} does not fit into the PICO-8 sprite sheet {}x{}",
+        width,
+        height,
+        PICO8_SIZE,
+        PICO8_SIZE
+    );
+    let indices = palette_indices(img, &pico8_palette());
+    let mut section = String::from("__gfx__\n");
+    for row in indices.chunks(width as usize) {
+        row.iter()
+            .for_each(|i| section.push(char::from_digit(*i as u32, 16).unwrap()));
+        (width..PICO8_SIZE).for_each(|_| section.push('0'));
+        section.push('\n');
+    }
+    Ok(section)
+}
+
+pub fn cart(img: &RgbImage) -> Result<String> {
+    Ok(format!(
+        "pico-8 cartridge // http://www.pico-8.com\nversion 41\n{}",
+        gfx_section(img)?
+    ))
+}
+
+#[cfg(feature = "io")]
+pub fn save_pico8<P>(path: P, img: &RgbImage) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let is_cart = path
+        .as_ref()
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("p8"));
+    let content = if is_cart {
+        cart(img)?
+    } else {
+        gfx_section(img)?
+    };
+    fs::write(path.as_ref(), content)
+        .with_context(|| format!("Failed to save: {:?}", path.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::pico8::{cart, gfx_section};
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_gfx_section() {
+        let img = RgbImage::from_fn(3, 2, |x, y| match (x, y) {
+            (0, 0) => Rgb([250, 0, 70]),
+            (1, 0) => Rgb([255, 255, 255]),
+            (2, 0) => Rgb([20, 40, 80]),
+            _ => Rgb([255, 200, 170]),
+        });
+        let section = gfx_section(&img).unwrap();
+        let lines = section.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "__gfx__");
+        assert_eq!(lines[1], format!("871{}", "0".repeat(125)));
+        assert_eq!(lines[2], format!("fff{}", "0".repeat(125)));
+        assert!(cart(&img).unwrap().starts_with("pico-8 cartridge"));
+        assert!(gfx_section(&RgbImage::new(129, 1)).is_err());
+    }
+}
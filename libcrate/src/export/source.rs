@@ -0,0 +1,247 @@
+use crate::Palette;
+use crate::image_processing::palette_indices;
+#[cfg(feature = "io")]
+use anyhow::Context;
+use anyhow::{Result, anyhow, ensure};
+use image::{Rgb, RgbImage};
+#[cfg(feature = "io")]
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+const VALUES_PER_LINE: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceLanguage {
+    C,
+    Rust,
+}
+
+impl SourceLanguage {
+    pub fn from_extension<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let extension = path
+            .as_ref()
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("h") | Some("c") => Ok(SourceLanguage::C),
+            Some("rs") => Ok(SourceLanguage::Rust),
+            _ => Err(anyhow!(
+                "Unknown source language of {:?}, expected .h, .c or .rs",
+                path.as_ref()
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelEncoding {
+    Indexed,
+    Rgb565,
+}
+
+impl FromStr for PixelEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "indexed" => Ok(PixelEncoding::Indexed),
+            "rgb565" => Ok(PixelEncoding::Rgb565),
+            _ => Err(anyhow!(
+                "Unknown pixel encoding {}, expected indexed or rgb565",
+                s
+            )),
+        }
+    }
+}
+
+pub fn rgb565(color: &Rgb<u8>) -> u16 {
+    ((color[0] as u16 >> 3) << 11) | ((color[1] as u16 >> 2) << 5) | (color[2] as u16 >> 3)
+}
+
+pub fn image_to_source(
+    img: &RgbImage,
+    palette: &Palette,
+    name: &str,
+    language: SourceLanguage,
+    encoding: PixelEncoding,
+) -> Result<String> {
+    ensure!(!palette.is_empty(), "Palette is empty");
+    let indices = palette_indices(img, palette);
+    let (palette_values, data_values) = match encoding {
+        PixelEncoding::Indexed => {
+            ensure!(
+                palette.len() <= 256,
+                "Palette with {} colors cannot be indexed by u8",
+                palette.len()
+            );
+            let palette_values = palette
+                .iter()
+                .map(|c| match language {
+                    SourceLanguage::C => format!("{{{}, {}, {}}}", c[0], c[1], c[2]),
+                    SourceLanguage::Rust => format!("[{}, {}, {}]", c[0], c[1], c[2]),
+                })
+                .collect::<Vec<_>>();
+            let data_values = indices.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+            (palette_values, data_values)
+        }
+        PixelEncoding::Rgb565 => {
+            let palette_values = palette
+                .iter()
+                .map(|c| format!("0x{:04X}", rgb565(c)))
+                .collect::<Vec<_>>();
+            let data_values = indices
+                .iter()
+                .map(|&i| format!("0x{:04X}", rgb565(&palette[i])))
+                .collect::<Vec<_>>();
+            (palette_values, data_values)
+        }
+    };
+    let (width, height) = img.dimensions();
+    let upper = name.to_uppercase();
+    let palette_len = palette_values.len();
+    let data_len = data_values.len();
+    let palette_body = format_values(&palette_values);
+    let data_body = format_values(&data_values);
+    let source = match language {
+        SourceLanguage::C => {
+            let (palette_decl, data_type) = match encoding {
+                PixelEncoding::Indexed => (
+                    format!("static const uint8_t {}_palette[{}][3]", name, palette_len),
+                    "uint8_t",
+                ),
+                PixelEncoding::Rgb565 => (
+                    format!("static const uint16_t {}_palette[{}]", name, palette_len),
+                    "uint16_t",
+                ),
+            };
+            format!(
+                "#ifndef {upper}_H\n#define {upper}_H\n\n#include <stdint.h>\n\n\
+                 #define {upper}_WIDTH {width}\n#define {upper}_HEIGHT {height}\n\n\
+                 {palette_decl} = {{\n{palette_body}}};\n\n\
+                 static const {data_type} {name}_data[{data_len}] = {{\n{data_body}}};\n\n\
+                 #endif\n"
+            )
+        }
+        SourceLanguage::Rust => {
+            let (palette_type, data_type) = match encoding {
+                PixelEncoding::Indexed => (format!("[[u8; 3]; {}]", palette_len), "u8"),
+                PixelEncoding::Rgb565 => (format!("[u16; {}]", palette_len), "u16"),
+            };
+            format!(
+                "pub const {upper}_WIDTH: usize = {width};\npub const {upper}_HEIGHT: usize = {height};\n\n\
+                 pub const {upper}_PALETTE: {palette_type} = [\n{palette_body}];\n\n\
+                 pub const {upper}_DATA: [{data_type}; {data_len}] = [\n{data_body}];\n"
+            )
+        }
+    };
+    Ok(source)
+}
+
+#[cfg(feature = "io")]
+pub fn save_source<P>(
+    path: P,
+    img: &RgbImage,
+    palette: &Palette,
+    language: SourceLanguage,
+    encoding: PixelEncoding,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let name = identifier(
+        &path
+            .as_ref()
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    );
+    let source = image_to_source(img, palette, &name, language, encoding)?;
+    fs::write(path.as_ref(), source).with_context(|| format!("Failed to save: {:?}", path.as_ref()))
+}
+
+#[cfg(feature = "io")]
+fn identifier(name: &str) -> String {
+    let ident = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    match ident.chars().next() {
+        Some(c) if !c.is_ascii_digit() => ident,
+        _ => format!("image_{}", ident),
+    }
+}
+
+fn format_values(values: &[String]) -> String {
+    values
+        .chunks(VALUES_PER_LINE)
+        .map(|line| format!("    {},\n", line.join(", ")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "io")]
+    use crate::export::source::identifier;
+    use crate::export::source::{PixelEncoding, SourceLanguage, image_to_source, rgb565};
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_source_export() {
+        let img = RgbImage::from_fn(2, 2, |x, y| {
+            if (x + y) % 2 == 0 {
+                Rgb([250, 10, 10])
+            } else {
+                Rgb([0, 0, 250])
+            }
+        });
+        let palette = vec![Rgb([255u8, 0, 0]), Rgb([0, 0, 255])];
+        assert_eq!(rgb565(&Rgb([255, 0, 0])), 0xF800);
+        assert_eq!(rgb565(&Rgb([0, 255, 0])), 0x07E0);
+
+        let rust = image_to_source(
+            &img,
+            &palette,
+            "sprite",
+            SourceLanguage::Rust,
+            PixelEncoding::Indexed,
+        )
+        .unwrap();
+        assert_eq!(
+            rust,
+            "pub const SPRITE_WIDTH: usize = 2;\npub const SPRITE_HEIGHT: usize = 2;\n\n\
+             pub const SPRITE_PALETTE: [[u8; 3]; 2] = [\n    [255, 0, 0], [0, 0, 255],\n];\n\n\
+             pub const SPRITE_DATA: [u8; 4] = [\n    0, 1, 1, 0,\n];\n"
+        );
+
+        let c = image_to_source(
+            &img,
+            &palette,
+            "sprite",
+            SourceLanguage::C,
+            PixelEncoding::Rgb565,
+        )
+        .unwrap();
+        assert!(c.starts_with("#ifndef SPRITE_H\n#define SPRITE_H\n"));
+        assert!(c.contains("static const uint16_t sprite_palette[2] = {\n    0xF800, 0x001F,\n};"));
+        assert!(c.contains(
+            "static const uint16_t sprite_data[4] = {\n    0xF800, 0x001F, 0x001F, 0xF800,\n};"
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "io")]
+    fn test_identifier() {
+        assert_eq!(identifier("my-sprite 2"), "my_sprite_2");
+        assert_eq!(identifier("8x8"), "image_8x8");
+    }
+}
@@ -0,0 +1,142 @@
+use crate::ProcessedImage;
+use crate::image_processing::rgb_to_hex;
+#[cfg(feature = "io")]
+use anyhow::Context;
+use anyhow::{Result, ensure};
+use image::Rgb;
+use std::collections::HashMap;
+use std::fmt::Write;
+#[cfg(feature = "io")]
+use std::fs;
+#[cfg(feature = "io")]
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SvgRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub color: Rgb<u8>,
+}
+
+fn row_runs(image: &ProcessedImage, y: u32) -> Vec<(u32, u32, Rgb<u8>)> {
+    let visible = |x: u32| {
+        image
+            .alpha
+            .as_ref()
+            .is_none_or(|alpha| alpha.get_pixel(x, y)[0] > 0)
+    };
+    let mut runs: Vec<(u32, u32, Rgb<u8>)> = vec![];
+    for x in 0..image.width() {
+        if !visible(x) {
+            continue;
+        }
+        let color = *image.data.get_pixel(x, y);
+        match runs.last_mut() {
+            Some((start, width, last)) if *start + *width == x && *last == color => *width += 1,
+            _ => runs.push((x, 1, color)),
+        }
+    }
+    runs
+}
+
+pub fn svg_rects(image: &ProcessedImage) -> Vec<SvgRect> {
+    let mut rects: Vec<SvgRect> = vec![];
+    let mut open: HashMap<(u32, u32, Rgb<u8>), usize> = HashMap::new();
+    for y in 0..image.height() {
+        let mut next = HashMap::new();
+        for run in row_runs(image, y) {
+            let idx = match open.get(&run) {
+                Some(&idx) => {
+                    rects[idx].height += 1;
+                    idx
+                }
+                None => {
+                    let (x, width, color) = run;
+                    rects.push(SvgRect {
+                        x,
+                        y,
+                        width,
+                        height: 1,
+                        color,
+                    });
+                    rects.len() - 1
+                }
+            };
+            next.insert(run, idx);
+        }
+        open = next;
+    }
+    rects
+}
+
+pub fn image_to_svg(image: &ProcessedImage, scale: u32) -> Result<String> {
+    ensure!(scale > 0, "SVG scale must be positive");
+    let (width, height) = (image.width(), image.height());
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" shape-rendering=\"crispEdges\">\n",
+        width * scale,
+        height * scale,
+        width,
+        height
+    );
+    for rect in svg_rects(image) {
+        writeln!(
+            svg,
+            "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+            rect.x,
+            rect.y,
+            rect.width,
+            rect.height,
+            rgb_to_hex(&rect.color)
+        )?;
+    }
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+#[cfg(feature = "io")]
+pub fn save_svg<P>(path: P, image: &ProcessedImage, scale: u32) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    fs::write(path.as_ref(), image_to_svg(image, scale)?)
+        .with_context(|| format!("Failed to save: {:?}", path.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ProcessedImage;
+    use crate::export::svg::{SvgRect, image_to_svg, svg_rects};
+    use image::{GrayImage, Luma, Rgb, RgbImage};
+
+    #[test]
+    fn test_svg_rects() {
+        let (red, blue) = (Rgb([255u8, 0, 0]), Rgb([0u8, 0, 255]));
+        let data = RgbImage::from_fn(4, 3, |x, y| if x < 2 && y < 2 { red } else { blue });
+        let mut image = ProcessedImage { data, alpha: None };
+        let rect = |x, y, width, height, color| SvgRect {
+            x,
+            y,
+            width,
+            height,
+            color,
+        };
+        assert_eq!(
+            svg_rects(&image),
+            vec![
+                rect(0, 0, 2, 2, red),
+                rect(2, 0, 2, 2, blue),
+                rect(0, 2, 4, 1, blue)
+            ]
+        );
+
+        image.alpha = Some(GrayImage::from_fn(4, 3, |x, _| Luma([(x > 0) as u8 * 255])));
+        assert_eq!(svg_rects(&image)[0], rect(1, 0, 1, 2, red));
+        let svg = image_to_svg(&image, 10).unwrap();
+        assert!(svg.contains("width=\"40\" height=\"30\" viewBox=\"0 0 4 3\""));
+        assert!(svg.contains("<rect x=\"1\" y=\"0\" width=\"1\" height=\"2\" fill=\"#ff0000\"/>"));
+        assert!(image_to_svg(&image, 0).is_err());
+    }
+}
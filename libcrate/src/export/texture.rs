@@ -0,0 +1,280 @@
+use crate::export::dds::{DdsPixels, encode_dds_levels};
+#[cfg(feature = "io")]
+use anyhow::Context;
+use anyhow::{Result, anyhow, ensure};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "io")]
+use std::fs;
+#[cfg(feature = "io")]
+use std::path::Path;
+use std::str::FromStr;
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+const KTX2_HEADER_SIZE: usize = 80;
+const KTX2_LEVEL_INDEX_SIZE: usize = 24;
+
+const VK_FORMAT_R8G8B8A8_SRGB: u32 = 43;
+const VK_FORMAT_BC1_RGBA_SRGB_BLOCK: u32 = 134;
+const VK_FORMAT_BC3_SRGB_BLOCK: u32 = 138;
+
+const DF_MODEL_RGBSDA: u8 = 1;
+const DF_MODEL_BC1A: u8 = 128;
+const DF_MODEL_BC3: u8 = 130;
+const DF_PRIMARIES_BT709: u8 = 1;
+const DF_TRANSFER_SRGB: u8 = 2;
+const DF_SAMPLE_LINEAR: u8 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextureFormat {
+    #[default]
+    Rgba8,
+    Bc1,
+    Bc3,
+}
+
+struct Sample {
+    channel: u8,
+    offset: u16,
+    bits: u8,
+    upper: u32,
+}
+
+impl TextureFormat {
+    pub fn encode(&self, img: &RgbaImage) -> Vec<u8> {
+        let format = match self {
+            TextureFormat::Rgba8 => return img.as_raw().clone(),
+            TextureFormat::Bc1 => texpresso::Format::Bc1,
+            TextureFormat::Bc3 => texpresso::Format::Bc3,
+        };
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let mut output = vec![0; format.compressed_size(width, height)];
+        format.compress(
+            img.as_raw(),
+            width,
+            height,
+            texpresso::Params::default(),
+            &mut output,
+        );
+        output
+    }
+
+    fn block_bytes(&self) -> usize {
+        match self {
+            TextureFormat::Rgba8 => 4,
+            TextureFormat::Bc1 => 8,
+            TextureFormat::Bc3 => 16,
+        }
+    }
+
+    fn vk_format(&self) -> u32 {
+        match self {
+            TextureFormat::Rgba8 => VK_FORMAT_R8G8B8A8_SRGB,
+            TextureFormat::Bc1 => VK_FORMAT_BC1_RGBA_SRGB_BLOCK,
+            TextureFormat::Bc3 => VK_FORMAT_BC3_SRGB_BLOCK,
+        }
+    }
+
+    fn dds_pixels(&self) -> DdsPixels {
+        match self {
+            TextureFormat::Rgba8 => DdsPixels::Rgba,
+            TextureFormat::Bc1 => DdsPixels::FourCc(*b"DXT1"),
+            TextureFormat::Bc3 => DdsPixels::FourCc(*b"DXT5"),
+        }
+    }
+
+    fn samples(&self) -> Vec<Sample> {
+        let channel = |channel, offset, bits, upper| Sample {
+            channel,
+            offset,
+            bits,
+            upper,
+        };
+        match self {
+            TextureFormat::Rgba8 => vec![
+                channel(0, 0, 8, 255),
+                channel(1, 8, 8, 255),
+                channel(2, 16, 8, 255),
+                channel(15 | DF_SAMPLE_LINEAR, 24, 8, 255),
+            ],
+            TextureFormat::Bc1 => vec![channel(1, 0, 64, u32::MAX)],
+            TextureFormat::Bc3 => vec![channel(15, 0, 64, u32::MAX), channel(0, 64, 64, u32::MAX)],
+        }
+    }
+
+    fn data_format_descriptor(&self) -> Vec<u8> {
+        let samples = self.samples();
+        let block_size = 24 + 16 * samples.len();
+        let (model, block) = match self {
+            TextureFormat::Rgba8 => (DF_MODEL_RGBSDA, [0, 0, 0, 0]),
+            TextureFormat::Bc1 => (DF_MODEL_BC1A, [3, 3, 0, 0]),
+            TextureFormat::Bc3 => (DF_MODEL_BC3, [3, 3, 0, 0]),
+        };
+        let mut dfd = vec![];
+        dfd.extend_from_slice(&(4 + block_size as u32).to_le_bytes());
+        dfd.extend_from_slice(&0u32.to_le_bytes());
+        dfd.extend_from_slice(&(2u32 | (block_size as u32) << 16).to_le_bytes());
+        dfd.extend_from_slice(&[model, DF_PRIMARIES_BT709, DF_TRANSFER_SRGB, 0]);
+        dfd.extend_from_slice(&block);
+        dfd.extend_from_slice(&[self.block_bytes() as u8, 0, 0, 0, 0, 0, 0, 0]);
+        for sample in samples {
+            dfd.extend_from_slice(&sample.offset.to_le_bytes());
+            dfd.extend_from_slice(&[sample.bits - 1, sample.channel, 0, 0, 0, 0]);
+            dfd.extend_from_slice(&0u32.to_le_bytes());
+            dfd.extend_from_slice(&sample.upper.to_le_bytes());
+        }
+        dfd
+    }
+}
+
+impl FromStr for TextureFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "rgba8" | "rgba" => Ok(TextureFormat::Rgba8),
+            "bc1" | "dxt1" => Ok(TextureFormat::Bc1),
+            "bc3" | "dxt5" => Ok(TextureFormat::Bc3),
+            _ => Err(anyhow!(
+                "Unknown texture format {}, expected rgba8, bc1 or bc3",
+                s
+            )),
+        }
+    }
+}
+
+pub fn encode_ktx2(levels: &[RgbaImage], format: TextureFormat) -> Result<Vec<u8>> {
+    ensure!(!levels.is_empty(), "Cannot write a KTX2 without any image");
+    let (width, height) = levels[0].dimensions();
+    let dfd = format.data_format_descriptor();
+    let dfd_offset = KTX2_HEADER_SIZE + KTX2_LEVEL_INDEX_SIZE * levels.len();
+
+    let mut data = vec![];
+    let mut index = vec![(0, 0); levels.len()];
+    let alignment = format.block_bytes().max(4);
+    for (level, img) in levels.iter().enumerate().rev() {
+        let encoded = format.encode(img);
+        let start = (dfd_offset + dfd.len() + data.len()).next_multiple_of(alignment);
+        data.resize(start - dfd_offset - dfd.len(), 0);
+        index[level] = (start as u64, encoded.len() as u64);
+        data.extend(encoded);
+    }
+
+    let mut bytes = KTX2_IDENTIFIER.to_vec();
+    let header = [
+        format.vk_format(),
+        1,
+        width,
+        height,
+        0,
+        0,
+        1,
+        levels.len() as u32,
+        0,
+        dfd_offset as u32,
+        dfd.len() as u32,
+        0,
+        0,
+    ];
+    header
+        .iter()
+        .for_each(|v| bytes.extend_from_slice(&v.to_le_bytes()));
+    bytes.extend_from_slice(&[0; 16]);
+    for (offset, length) in index {
+        [offset, length, length]
+            .iter()
+            .for_each(|v| bytes.extend_from_slice(&v.to_le_bytes()));
+    }
+    bytes.extend(dfd);
+    bytes.extend(data);
+    Ok(bytes)
+}
+
+pub fn encode_dds_texture(levels: &[RgbaImage], format: TextureFormat) -> Result<Vec<u8>> {
+    ensure!(!levels.is_empty(), "Cannot write a DDS without any image");
+    let (width, height) = levels[0].dimensions();
+    let data = levels
+        .iter()
+        .map(|level| format.encode(level))
+        .collect::<Vec<_>>();
+    encode_dds_levels(width, height, format.dds_pixels(), &data)
+}
+
+#[cfg(feature = "io")]
+pub fn save_texture<P>(path: P, levels: &[RgbaImage], format: TextureFormat) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let extension = path
+        .as_ref()
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+    let bytes = match extension.as_deref() {
+        Some("dds") => encode_dds_texture(levels, format)?,
+        Some("ktx2") => encode_ktx2(levels, format)?,
+        _ => {
+            return Err(anyhow!(
+                "Unknown texture container of {:?}, expected .dds or .ktx2",
+                path.as_ref()
+            ));
+        }
+    };
+    fs::write(path.as_ref(), bytes).with_context(|| format!("Failed to save: {:?}", path.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::texture::{TextureFormat, encode_dds_texture, encode_ktx2};
+    use image::{ImageFormat, Rgba, RgbaImage};
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_dds_bc1() {
+        let img = RgbaImage::from_fn(8, 8, |x, _| {
+            if x < 4 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        });
+        let bytes = encode_dds_texture(std::slice::from_ref(&img), TextureFormat::Bc1).unwrap();
+        assert_eq!(&bytes[84..88], b"DXT1");
+        assert_eq!(bytes.len(), 128 + 4 * 8);
+        let decoded = image::load_from_memory_with_format(&bytes, ImageFormat::Dds)
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(decoded, img);
+    }
+
+    #[test]
+    fn test_ktx2_layout() {
+        let levels = [
+            RgbaImage::from_pixel(4, 2, Rgba([1, 2, 3, 4])),
+            RgbaImage::from_pixel(2, 1, Rgba([5, 6, 7, 8])),
+            RgbaImage::from_pixel(1, 1, Rgba([9, 10, 11, 12])),
+        ];
+        let bytes = encode_ktx2(&levels, TextureFormat::Rgba8).unwrap();
+        assert_eq!(read_u32(&bytes, 12), 43);
+        assert_eq!(read_u32(&bytes, 40), 3);
+        let dfd_offset = read_u32(&bytes, 48) as usize;
+        assert_eq!(dfd_offset, 80 + 3 * 24);
+        assert_eq!(
+            read_u32(&bytes, dfd_offset) as usize,
+            read_u32(&bytes, 52) as usize
+        );
+        let level = |idx: usize| {
+            let offset = read_u32(&bytes, 80 + idx * 24) as usize;
+            let length = read_u32(&bytes, 88 + idx * 24) as usize;
+            &bytes[offset..offset + length]
+        };
+        assert_eq!(level(0), levels[0].as_raw().as_slice());
+        assert_eq!(level(2), &[9, 10, 11, 12]);
+        assert!(read_u32(&bytes, 80) > read_u32(&bytes, 80 + 48));
+    }
+}
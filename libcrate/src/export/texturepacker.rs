@@ -0,0 +1,134 @@
+use crate::spritesheet::Rect;
+#[cfg(feature = "io")]
+use anyhow::Context;
+use anyhow::{Result, anyhow, ensure};
+use serde_json::{Value, json};
+use std::collections::HashSet;
+#[cfg(feature = "io")]
+use std::fs;
+#[cfg(feature = "io")]
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtlasFormat {
+    Hash,
+    Array,
+}
+
+impl FromStr for AtlasFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "hash" => Ok(AtlasFormat::Hash),
+            "array" => Ok(AtlasFormat::Array),
+            _ => Err(anyhow!(
+                "Unknown atlas format {}, expected hash or array",
+                s
+            )),
+        }
+    }
+}
+
+fn frame(rect: &Rect) -> Value {
+    json!({
+        "frame": { "x": rect.x, "y": rect.y, "w": rect.width, "h": rect.height },
+        "rotated": false,
+        "trimmed": false,
+        "spriteSourceSize": { "x": 0, "y": 0, "w": rect.width, "h": rect.height },
+        "sourceSize": { "w": rect.width, "h": rect.height },
+    })
+}
+
+pub fn atlas_json(
+    frames: &[(String, Rect)],
+    image_name: &str,
+    size: (u32, u32),
+    format: AtlasFormat,
+) -> Result<Value> {
+    // the hash format is keyed by name, check both so they always describe the same frames
+    let mut names = HashSet::new();
+    for (name, _) in frames {
+        ensure!(names.insert(name), "Duplicate frame name {}", name);
+    }
+    let frames = match format {
+        AtlasFormat::Hash => Value::Object(
+            frames
+                .iter()
+                .map(|(name, rect)| (name.clone(), frame(rect)))
+                .collect(),
+        ),
+        AtlasFormat::Array => Value::Array(
+            frames
+                .iter()
+                .map(|(name, rect)| {
+                    let mut value = frame(rect);
+                    value["filename"] = json!(name);
+                    value
+                })
+                .collect(),
+        ),
+    };
+    Ok(json!({
+        "frames": frames,
+        "meta": {
+            "app": "https://github.com/sramekj/pixelize",
+            "version": "1.0",
+            "image": image_name,
+            "format": "RGBA8888",
+            "size": { "w": size.0, "h": size.1 },
+            "scale": "1",
+        },
+    }))
+}
+
+#[cfg(feature = "io")]
+pub fn save_atlas<P>(
+    path: P,
+    frames: &[(String, Rect)],
+    image_name: &str,
+    size: (u32, u32),
+    format: AtlasFormat,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let json = atlas_json(frames, image_name, size, format)?;
+    fs::write(path.as_ref(), serde_json::to_string_pretty(&json)?)
+        .with_context(|| format!("Failed to save: {:?}", path.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::texturepacker::{AtlasFormat, atlas_json};
+    use crate::spritesheet::Rect;
+
+    #[test]
+    fn test_atlas_json() {
+        let frames = vec![(
+            "hero.png".to_string(),
+            Rect {
+                x: 2,
+                y: 4,
+                width: 16,
+                height: 8,
+            },
+        )];
+        let hash = atlas_json(&frames, "sheet.png", (32, 16), AtlasFormat::Hash).unwrap();
+        assert_eq!(hash["frames"]["hero.png"]["frame"]["x"], 2);
+        assert_eq!(hash["frames"]["hero.png"]["sourceSize"]["w"], 16);
+        assert_eq!(hash["meta"]["image"], "sheet.png");
+        assert_eq!(hash["meta"]["size"]["h"], 16);
+
+        let array = atlas_json(&frames, "sheet.png", (32, 16), AtlasFormat::Array).unwrap();
+        assert_eq!(array["frames"][0]["filename"], "hero.png");
+        assert_eq!(array["frames"][0]["frame"]["h"], 8);
+        assert_eq!("Array".parse::<AtlasFormat>().unwrap(), AtlasFormat::Array);
+
+        let duplicates = vec![frames[0].clone(), frames[0].clone()];
+        for format in [AtlasFormat::Hash, AtlasFormat::Array] {
+            assert!(atlas_json(&duplicates, "sheet.png", (32, 16), format).is_err());
+        }
+    }
+}
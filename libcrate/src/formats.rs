@@ -0,0 +1,31 @@
+pub mod bmp;
+pub mod pcx;
+pub mod png;
+
+#[cfg(feature = "io")]
+use crate::Palette;
+#[cfg(feature = "io")]
+use anyhow::{Context, Result};
+#[cfg(feature = "io")]
+use std::fs;
+#[cfg(feature = "io")]
+use std::path::Path;
+
+#[cfg(feature = "io")]
+pub fn load_embedded_palette<P>(path: P) -> Result<Option<Palette>>
+where
+    P: AsRef<Path>,
+{
+    let extension = path
+        .as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    let bytes =
+        || fs::read(path.as_ref()).with_context(|| format!("Failed to open: {:?}", path.as_ref()));
+    match extension.as_deref() {
+        Some("bmp") => Ok(bmp::decode_indexed(&bytes()?).ok().map(|img| img.palette)),
+        Some("pcx") => Ok(pcx::decode(&bytes()?)?.palette().cloned()),
+        _ => Ok(None),
+    }
+}
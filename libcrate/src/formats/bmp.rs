@@ -0,0 +1,219 @@
+use crate::indexed::IndexedImage;
+#[cfg(feature = "io")]
+use anyhow::Context;
+use anyhow::{Result, anyhow, ensure};
+use image::Rgb;
+#[cfg(feature = "io")]
+use std::fs;
+#[cfg(feature = "io")]
+use std::path::Path;
+
+const FILE_HEADER_SIZE: usize = 14;
+const CORE_HEADER_SIZE: usize = 12;
+const INFO_HEADER_SIZE: usize = 40;
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+fn row_size(width: u32, bits: u32) -> usize {
+    (width as u64 * bits as u64).div_ceil(32) as usize * 4
+}
+
+pub fn encode_indexed(img: &IndexedImage) -> Vec<u8> {
+    let stride = row_size(img.width, 8);
+    let palette_size = img.palette.len() * 4;
+    let offset = FILE_HEADER_SIZE + INFO_HEADER_SIZE + palette_size;
+    let file_size = offset + stride * img.height as usize;
+
+    let mut bytes = Vec::with_capacity(file_size);
+    bytes.extend_from_slice(b"BM");
+    bytes.extend_from_slice(&(file_size as u32).to_le_bytes());
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend_from_slice(&(offset as u32).to_le_bytes());
+    bytes.extend_from_slice(&(INFO_HEADER_SIZE as u32).to_le_bytes());
+    bytes.extend_from_slice(&(img.width as i32).to_le_bytes());
+    bytes.extend_from_slice(&(img.height as i32).to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&8u16.to_le_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(&((stride * img.height as usize) as u32).to_le_bytes());
+    bytes.extend_from_slice(&[0; 8]);
+    bytes.extend_from_slice(&(img.palette.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    img.palette
+        .iter()
+        .for_each(|c| bytes.extend_from_slice(&[c[2], c[1], c[0], 0]));
+    for row in img.indices.chunks(img.width as usize).rev() {
+        bytes.extend_from_slice(row);
+        bytes.resize(bytes.len() + stride - row.len(), 0);
+    }
+    bytes
+}
+
+pub fn decode_indexed(bytes: &[u8]) -> Result<IndexedImage> {
+    ensure!(
+        bytes.len() >= FILE_HEADER_SIZE + 4 && bytes.starts_with(b"BM"),
+        "Not a BMP file"
+    );
+    let offset = read_u32(bytes, 10) as usize;
+    let header_size = read_u32(bytes, 14) as usize;
+    ensure!(
+        header_size == CORE_HEADER_SIZE || header_size >= INFO_HEADER_SIZE,
+        "Unsupported BMP header size {}",
+        header_size
+    );
+    ensure!(
+        bytes.len() >= FILE_HEADER_SIZE + header_size,
+        "Truncated BMP header"
+    );
+    // OS/2 core headers have 16-bit sizes, no compression and 3-byte RGBTRIPLE palette entries
+    let (width, height, bits, compression, colors, entry_size) = match header_size {
+        CORE_HEADER_SIZE => (
+            read_u16(bytes, 18) as i32,
+            read_u16(bytes, 20) as i32,
+            read_u16(bytes, 24) as u32,
+            0,
+            0,
+            3,
+        ),
+        _ => (
+            read_u32(bytes, 18) as i32,
+            read_u32(bytes, 22) as i32,
+            read_u16(bytes, 28) as u32,
+            read_u32(bytes, 30),
+            read_u32(bytes, 46) as usize,
+            4,
+        ),
+    };
+    ensure!(
+        matches!(bits, 1 | 4 | 8),
+        "BMP with {} bits per pixel has no palette",
+        bits
+    );
+    ensure!(compression == 0, "Compressed paletted BMP is not supported");
+    ensure!(
+        width > 0 && height != 0,
+        "Invalid BMP size {}x{}",
+        width,
+        height
+    );
+
+    let colors = match colors {
+        0 => 1 << bits,
+        count => count,
+    };
+    ensure!(colors <= 1 << bits, "Invalid BMP palette size {}", colors);
+    let palette_start = FILE_HEADER_SIZE + header_size;
+    let palette = bytes
+        .get(palette_start..palette_start + colors * entry_size)
+        .ok_or_else(|| anyhow!("Truncated BMP palette"))?
+        .chunks(entry_size)
+        .map(|c| Rgb([c[2], c[1], c[0]]))
+        .collect();
+
+    let (width, rows) = (width as u32, height.unsigned_abs());
+    let stride = row_size(width, bits);
+    let data = stride
+        .checked_mul(rows as usize)
+        .and_then(|size| offset.checked_add(size))
+        .and_then(|end| bytes.get(offset..end))
+        .ok_or_else(|| anyhow!("Truncated BMP image data"))?;
+    let per_byte = 8 / bits;
+    let mask = ((1u16 << bits) - 1) as u8;
+    // bounded by the data length checked above
+    let mut indices = Vec::with_capacity(width as usize * rows as usize);
+    for y in 0..rows {
+        let stored = if height > 0 { rows - 1 - y } else { y };
+        let row = &data[stored as usize * stride..];
+        indices.extend((0..width).map(|x| {
+            let shift = (per_byte - 1 - x % per_byte) * bits;
+            (row[(x / per_byte) as usize] >> shift) & mask
+        }));
+    }
+    IndexedImage::new(palette, indices, width, rows)
+}
+
+#[cfg(feature = "io")]
+pub fn save_indexed_bmp<P>(path: P, img: &IndexedImage) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    fs::write(path.as_ref(), encode_indexed(img))
+        .with_context(|| format!("Failed to save: {:?}", path.as_ref()))
+}
+
+#[cfg(feature = "io")]
+pub fn load_indexed_bmp<P>(path: P) -> Result<IndexedImage>
+where
+    P: AsRef<Path>,
+{
+    let bytes =
+        fs::read(path.as_ref()).with_context(|| format!("Failed to open: {:?}", path.as_ref()))?;
+    decode_indexed(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::formats::bmp::{decode_indexed, encode_indexed};
+    use crate::indexed::IndexedImage;
+    use image::Rgb;
+
+    #[test]
+    fn test_indexed_bmp_roundtrip() {
+        let palette = vec![Rgb([255u8, 0, 0]), Rgb([0, 0, 0]), Rgb([10, 20, 30])];
+        let indices = vec![2, 0, 1, 1, 0, 2, 2, 2, 0, 1];
+        let img = IndexedImage::new(palette, indices, 5, 2).unwrap();
+        let bytes = encode_indexed(&img);
+        assert_eq!(bytes.len(), 14 + 40 + 12 + 2 * 8);
+        assert_eq!(decode_indexed(&bytes).unwrap(), img);
+        let decoded = image::load_from_memory(&bytes).unwrap().to_rgb8();
+        assert_eq!(decoded, img.to_rgb());
+        assert!(decode_indexed(&bytes[..60]).is_err());
+    }
+
+    #[test]
+    fn test_core_header_bmp() {
+        // 2x1, 1 bit per pixel, palette of two RGBTRIPLEs
+        let mut bytes = b"BM".to_vec();
+        bytes.extend_from_slice(&34u32.to_le_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&32u32.to_le_bytes());
+        bytes.extend_from_slice(&12u32.to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&[30, 20, 10, 255, 255, 255]);
+        bytes.extend_from_slice(&[0b0100_0000, 0, 0, 0]);
+        let img = decode_indexed(&bytes).unwrap();
+        assert_eq!(img.palette, vec![Rgb([10, 20, 30]), Rgb([255, 255, 255])]);
+        assert_eq!(img.indices, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_corrupt_bmp() {
+        let img = IndexedImage::new(vec![Rgb([0, 0, 0])], vec![0; 4], 2, 2).unwrap();
+        let bytes = encode_indexed(&img);
+        assert!(decode_indexed(&bytes[..20]).is_err());
+        let mut huge = bytes.clone();
+        huge[18..22].copy_from_slice(&i32::MAX.to_le_bytes());
+        huge[22..26].copy_from_slice(&i32::MAX.to_le_bytes());
+        assert!(decode_indexed(&huge).is_err());
+        let mut colors = bytes.clone();
+        colors[46..50].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(decode_indexed(&colors).is_err());
+        let mut header = bytes;
+        header[14..18].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(decode_indexed(&header).is_err());
+    }
+}
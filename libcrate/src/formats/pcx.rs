@@ -0,0 +1,198 @@
+use crate::Palette;
+use crate::indexed::IndexedImage;
+use anyhow::{Result, anyhow, ensure};
+use image::{Rgb, RgbImage};
+
+pub const HEADER_SIZE: usize = 128;
+const PALETTE_MARKER: u8 = 0x0C;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PcxImage {
+    Indexed(IndexedImage),
+    Rgb(RgbImage),
+}
+
+impl PcxImage {
+    pub fn palette(&self) -> Option<&Palette> {
+        match self {
+            PcxImage::Indexed(img) => Some(&img.palette),
+            PcxImage::Rgb(_) => None,
+        }
+    }
+
+    pub fn to_rgb(&self) -> RgbImage {
+        match self {
+            PcxImage::Indexed(img) => img.to_rgb(),
+            PcxImage::Rgb(img) => img.clone(),
+        }
+    }
+}
+
+pub fn is_pcx(bytes: &[u8]) -> bool {
+    bytes.len() >= HEADER_SIZE && bytes[0] == 0x0A && bytes[1] <= 5 && bytes[2] == 1
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u32 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]]) as u32
+}
+
+fn decode_rle(data: &[u8], length: usize) -> Result<Vec<u8>> {
+    // the header size is untrusted, each input byte expands to at most 63
+    let mut result = Vec::with_capacity(length.min(data.len().saturating_mul(63)));
+    let mut bytes = data.iter();
+    while result.len() < length {
+        let byte = *bytes
+            .next()
+            .ok_or_else(|| anyhow!("Truncated PCX image data"))?;
+        if byte & 0xC0 == 0xC0 {
+            let value = *bytes
+                .next()
+                .ok_or_else(|| anyhow!("Truncated PCX image data"))?;
+            result.extend(std::iter::repeat_n(value, (byte & 0x3F) as usize));
+        } else {
+            result.push(byte);
+        }
+    }
+    result.truncate(length);
+    Ok(result)
+}
+
+pub fn decode(bytes: &[u8]) -> Result<PcxImage> {
+    ensure!(is_pcx(bytes), "Not a PCX file");
+    let bits = bytes[3];
+    let (width, height) = read_u16(bytes, 8)
+        .checked_sub(read_u16(bytes, 4))
+        .zip(read_u16(bytes, 10).checked_sub(read_u16(bytes, 6)))
+        .map(|(width, height)| (width + 1, height + 1))
+        .ok_or_else(|| anyhow!("Invalid PCX image bounds"))?;
+    let planes = bytes[65] as usize;
+    let line = read_u16(bytes, 66) as usize;
+    ensure!(planes > 0, "Invalid PCX plane count 0");
+    ensure!(
+        line > 0 && line * 8 >= width as usize * bits as usize,
+        "Invalid PCX line length {}",
+        line
+    );
+    let length = (height as usize)
+        .checked_mul(planes * line)
+        .ok_or_else(|| anyhow!("Invalid PCX image size"))?;
+    let data = decode_rle(&bytes[HEADER_SIZE..], length)?;
+    let rows = data.chunks(planes * line);
+    let bit =
+        |row: &[u8], plane: usize, x: u32| (row[plane * line + x as usize / 8] >> (7 - x % 8)) & 1;
+
+    match (bits, planes) {
+        (8, 1) => {
+            let tail = &bytes[bytes.len().saturating_sub(769)..];
+            let palette = if tail.len() == 769 && tail[0] == PALETTE_MARKER {
+                tail[1..]
+                    .chunks(3)
+                    .map(|c| Rgb([c[0], c[1], c[2]]))
+                    .collect()
+            } else {
+                (0..=255u8).map(|i| Rgb([i, i, i])).collect()
+            };
+            let indices = rows
+                .flat_map(|row| row[..width as usize].iter().copied())
+                .collect();
+            Ok(PcxImage::Indexed(IndexedImage::new(
+                palette, indices, width, height,
+            )?))
+        }
+        (8, 3) => {
+            let mut img = RgbImage::new(width, height);
+            for (y, row) in rows.enumerate() {
+                for x in 0..width {
+                    let pixel = [0, 1, 2].map(|c| row[c * line + x as usize]);
+                    img.put_pixel(x, y as u32, Rgb(pixel));
+                }
+            }
+            Ok(PcxImage::Rgb(img))
+        }
+        (1, 1) | (1, 4) => {
+            let palette = if planes == 1 {
+                vec![Rgb([0, 0, 0]), Rgb([255, 255, 255])]
+            } else {
+                bytes[16..64]
+                    .chunks(3)
+                    .map(|c| Rgb([c[0], c[1], c[2]]))
+                    .collect()
+            };
+            let indices = rows
+                .flat_map(|row| {
+                    (0..width).map(move |x| {
+                        (0..planes).fold(0, |index, plane| index | bit(row, plane, x) << plane)
+                    })
+                })
+                .collect();
+            Ok(PcxImage::Indexed(IndexedImage::new(
+                palette, indices, width, height,
+            )?))
+        }
+        _ => Err(anyhow!(
+            "Unsupported PCX format with {} bits per pixel and {} planes",
+            bits,
+            planes
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::formats::pcx::{HEADER_SIZE, PcxImage, decode, is_pcx};
+    use image::Rgb;
+
+    fn header(bits: u8, planes: u8, width: u16, height: u16, line: u16) -> Vec<u8> {
+        let mut header = vec![0u8; HEADER_SIZE];
+        header[..4].copy_from_slice(&[0x0A, 5, 1, bits]);
+        header[8..10].copy_from_slice(&(width - 1).to_le_bytes());
+        header[10..12].copy_from_slice(&(height - 1).to_le_bytes());
+        header[65] = planes;
+        header[66..68].copy_from_slice(&line.to_le_bytes());
+        header
+    }
+
+    #[test]
+    fn test_decode_indexed() {
+        let mut bytes = header(8, 1, 3, 2, 4);
+        bytes.extend([0xC3, 1, 0, 2, 0, 0xC2, 0]);
+        bytes.push(0x0C);
+        bytes.extend((0..=255u8).flat_map(|i| [i, 0, 255 - i]));
+        assert!(is_pcx(&bytes));
+        let PcxImage::Indexed(img) = decode(&bytes).unwrap() else {
+            panic!("expected an indexed image");
+        };
+        assert_eq!(img.indices, vec![1, 1, 1, 2, 0, 0]);
+        assert_eq!(img.color(0, 1), Rgb([2, 0, 253]));
+        assert!(decode(&bytes[..HEADER_SIZE + 3]).is_err());
+    }
+
+    #[test]
+    fn test_decode_rgb() {
+        let mut bytes = header(8, 3, 2, 1, 2);
+        bytes.extend([10, 20, 0xC2, 30, 40, 50]);
+        let img = decode(&bytes).unwrap();
+        assert!(img.palette().is_none());
+        assert_eq!(img.to_rgb().get_pixel(0, 0), &Rgb([10, 30, 40]));
+        assert_eq!(img.to_rgb().get_pixel(1, 0), &Rgb([20, 30, 50]));
+    }
+
+    #[test]
+    fn test_corrupt_pcx() {
+        let mut bytes = header(8, 1, 3, 2, 4);
+        bytes.extend([0xC3, 1, 0, 2, 0, 0xC2, 0]);
+        assert!(decode(&bytes[..HEADER_SIZE - 1]).is_err());
+        let mut inverted = bytes.clone();
+        inverted[6..8].copy_from_slice(&10u16.to_le_bytes());
+        assert!(decode(&inverted).is_err());
+        let mut no_planes = bytes.clone();
+        no_planes[65] = 0;
+        assert!(decode(&no_planes).is_err());
+        let mut no_line = bytes.clone();
+        no_line[66..68].copy_from_slice(&0u16.to_le_bytes());
+        assert!(decode(&no_line).is_err());
+        let mut huge = header(8, 1, u16::MAX, u16::MAX, u16::MAX);
+        huge.extend([0xC3, 1]);
+        assert!(decode(&huge).is_err());
+    }
+}
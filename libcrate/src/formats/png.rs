@@ -0,0 +1,106 @@
+use crate::ProcessedImage;
+#[cfg(feature = "io")]
+use anyhow::Context;
+use anyhow::Result;
+#[cfg(feature = "io")]
+use std::fs;
+use std::io::Cursor;
+#[cfg(feature = "io")]
+use std::path::Path;
+
+pub const SOFTWARE_KEY: &str = "Software";
+pub const PALETTE_KEY: &str = "pixelize:palette";
+pub const SETTINGS_KEY: &str = "pixelize:settings";
+pub const OPTIONS_KEY: &str = "pixelize:options";
+
+pub fn encode_with_text(image: &ProcessedImage, text: &[(&str, String)]) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    let mut encoder = ::png::Encoder::new(&mut bytes, image.width(), image.height());
+    encoder.set_depth(::png::BitDepth::Eight);
+    let data = match image.alpha {
+        Some(_) => {
+            encoder.set_color(::png::ColorType::Rgba);
+            image.to_rgba().into_raw()
+        }
+        None => {
+            encoder.set_color(::png::ColorType::Rgb);
+            image.data.as_raw().clone()
+        }
+    };
+    for (keyword, value) in text {
+        // tEXt is Latin-1 only, anything else goes into a UTF-8 iTXt chunk
+        if value.is_ascii() {
+            encoder.add_text_chunk(keyword.to_string(), value.clone())?;
+        } else {
+            encoder.add_itxt_chunk(keyword.to_string(), value.clone())?;
+        }
+    }
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(bytes)
+}
+
+#[cfg(feature = "io")]
+pub fn save_with_text<P>(path: P, image: &ProcessedImage, text: &[(&str, String)]) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    fs::write(path.as_ref(), encode_with_text(image, text)?)
+        .with_context(|| format!("Failed to save: {:?}", path.as_ref()))
+}
+
+pub fn read_text(bytes: &[u8]) -> Result<Vec<(String, String)>> {
+    let mut reader = ::png::Decoder::new(Cursor::new(bytes)).read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size().unwrap_or_default()];
+    reader.next_frame(&mut buffer)?;
+    reader.finish()?;
+    let info = reader.info();
+    let mut text = vec![];
+    for chunk in &info.uncompressed_latin1_text {
+        text.push((chunk.keyword.clone(), chunk.text.clone()));
+    }
+    for chunk in &info.compressed_latin1_text {
+        text.push((chunk.keyword.clone(), chunk.get_text()?));
+    }
+    for chunk in &info.utf8_text {
+        text.push((chunk.keyword.clone(), chunk.get_text()?));
+    }
+    Ok(text)
+}
+
+#[cfg(feature = "io")]
+pub fn load_text<P>(path: P) -> Result<Vec<(String, String)>>
+where
+    P: AsRef<Path>,
+{
+    let bytes =
+        fs::read(path.as_ref()).with_context(|| format!("Failed to open: {:?}", path.as_ref()))?;
+    read_text(&bytes).with_context(|| format!("Failed to read PNG: {:?}", path.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ProcessedImage;
+    use crate::formats::png::{PALETTE_KEY, SETTINGS_KEY, encode_with_text, read_text};
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_png_text_roundtrip() {
+        let image = ProcessedImage::from(RgbImage::from_pixel(3, 2, Rgb([10, 20, 30])));
+        let text = [
+            (PALETTE_KEY, "#0a141e".to_string()),
+            (SETTINGS_KEY, "{\"name\":\"šedá\"}".to_string()),
+        ];
+        let bytes = encode_with_text(&image, &text).unwrap();
+        let decoded = image::load_from_memory(&bytes).unwrap().to_rgb8();
+        assert_eq!(decoded, image.data);
+        let read = read_text(&bytes).unwrap();
+        assert_eq!(
+            read,
+            text.iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use crate::Palette;
+use crate::dither::{DitherOptions, bayer_matrix, dither_with};
+use anyhow::{Result, anyhow, ensure};
+use image::{Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GradientDirection {
+    #[default]
+    Horizontal,
+    Vertical,
+    Diagonal,
+}
+
+impl GradientDirection {
+    // 0 at the first stop and 1 at the last one
+    fn position(self, x: u32, y: u32, width: u32, height: u32) -> f32 {
+        let along = |value: u32, length: u32| value as f32 / (length.max(2) - 1) as f32;
+        match self {
+            GradientDirection::Horizontal => along(x, width),
+            GradientDirection::Vertical => along(y, height),
+            GradientDirection::Diagonal => along(x + y, width + height - 1),
+        }
+    }
+}
+
+impl FromStr for GradientDirection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "horizontal" => Ok(GradientDirection::Horizontal),
+            "vertical" => Ok(GradientDirection::Vertical),
+            "diagonal" => Ok(GradientDirection::Diagonal),
+            _ => Err(anyhow!(
+                "Unknown gradient direction {}, expected horizontal, vertical or diagonal",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for GradientDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            GradientDirection::Horizontal => "horizontal",
+            GradientDirection::Vertical => "vertical",
+            GradientDirection::Diagonal => "diagonal",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+fn segment(stops: &Palette, position: f32) -> (usize, f32) {
+    let scaled = position.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+    let index = (scaled as usize).min(stops.len() - 2);
+    (index, scaled - index as f32)
+}
+
+pub fn smooth_gradient(
+    width: u32,
+    height: u32,
+    stops: &Palette,
+    direction: GradientDirection,
+) -> Result<RgbImage> {
+    ensure!(stops.len() >= 2, "A gradient needs at least two colors");
+    ensure!(
+        width > 0 && height > 0,
+        "Gradient size must be greater than 0"
+    );
+    Ok(RgbImage::from_fn(width, height, |x, y| {
+        let (index, t) = segment(stops, direction.position(x, y, width, height));
+        let (from, to) = (stops[index], stops[index + 1]);
+        Rgb([0, 1, 2].map(|c| (from[c] as f32 + (to[c] as f32 - from[c] as f32) * t).round() as u8))
+    }))
+}
+
+// ordered methods pick one of the two neighbouring stops by the threshold map, so every band
+// only mixes its own two colors; the rest dither a smooth gradient against the palette
+pub fn render_gradient(
+    width: u32,
+    height: u32,
+    stops: &Palette,
+    direction: GradientDirection,
+    palette: Option<&Palette>,
+    options: &DitherOptions,
+) -> Result<RgbImage> {
+    let smooth = smooth_gradient(width, height, stops, direction)?;
+    let size = match options.method.bayer_size() {
+        Some(size) if palette.is_none() => size,
+        _ => return Ok(dither_with(&smooth, palette.unwrap_or(stops), options)),
+    };
+    let matrix = bayer_matrix(size);
+    let levels = (size * size) as f32;
+    Ok(RgbImage::from_fn(width, height, |x, y| {
+        let (index, t) = segment(stops, direction.position(x, y, width, height));
+        let threshold = (matrix[((y % size) * size + x % size) as usize] as f32 + 0.5) / levels;
+        match t > threshold {
+            true => stops[index + 1],
+            false => stops[index],
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dither::{Dither, DitherOptions};
+    use crate::gradient::{GradientDirection, render_gradient};
+    use image::Rgb;
+
+    #[test]
+    fn test_render_gradient() {
+        let stops = vec![Rgb([0, 0, 0]), Rgb([255, 255, 255]), Rgb([255, 0, 0])];
+        let options = DitherOptions::new(Dither::Bayer4);
+        let gradient =
+            render_gradient(33, 4, &stops, GradientDirection::Horizontal, None, &options).unwrap();
+        assert_eq!(*gradient.get_pixel(0, 0), Rgb([0, 0, 0]));
+        assert_eq!(*gradient.get_pixel(16, 2), Rgb([255, 255, 255]));
+        assert_eq!(*gradient.get_pixel(32, 3), Rgb([255, 0, 0]));
+        assert!(gradient.pixels().all(|p| stops.contains(p)));
+        // halfway through the first band half of the pixels are white
+        let whites = (0..4)
+            .flat_map(|y| (6..10).map(move |x| (x, y)))
+            .filter(|&(x, y)| gradient.get_pixel(x, y)[0] == 255)
+            .count();
+        assert!((6..=10).contains(&whites));
+
+        let vertical =
+            render_gradient(2, 8, &stops, GradientDirection::Vertical, None, &options).unwrap();
+        assert_eq!(vertical.get_pixel(0, 7), vertical.get_pixel(1, 7));
+        assert!(
+            render_gradient(
+                8,
+                8,
+                &stops[..1].to_vec(),
+                GradientDirection::Vertical,
+                None,
+                &options
+            )
+            .is_err()
+        );
+        // mix pairs the nearest stops instead of walking the bands
+        let options = DitherOptions::new(Dither::Mix);
+        let mixed =
+            render_gradient(33, 4, &stops, GradientDirection::Horizontal, None, &options).unwrap();
+        assert!(mixed.pixels().all(|p| stops.contains(p)));
+    }
+}
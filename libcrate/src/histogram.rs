@@ -0,0 +1,122 @@
+use crate::RgbHistogram;
+use crate::parallel::*;
+use anyhow::{Result, ensure};
+use image::{Rgb, RgbImage};
+
+pub fn check_bucket_bits(bits: [u32; 3]) -> Result<()> {
+    ensure!(
+        bits.iter().all(|b| (1..=8).contains(b)),
+        "Invalid histogram bits {:?}, expected 1 to 8 bits per channel",
+        bits
+    );
+    Ok(())
+}
+
+pub fn bucket_color(color: &Rgb<u8>, bits: [u32; 3]) -> Rgb<u8> {
+    Rgb([0, 1, 2].map(|c| {
+        let shift = 8 - bits[c];
+        let center = if shift > 0 { 1 << (shift - 1) } else { 0 };
+        ((color[c] >> shift) << shift) | center
+    }))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HistogramBuilder {
+    bits: Option<[u32; 3]>,
+    histogram: RgbHistogram,
+    pending: Vec<u8>,
+}
+
+impl HistogramBuilder {
+    pub fn new() -> Self {
+        HistogramBuilder::default()
+    }
+
+    pub fn bucketed(bits: [u32; 3]) -> Result<Self> {
+        check_bucket_bits(bits)?;
+        Ok(HistogramBuilder {
+            bits: Some(bits),
+            ..HistogramBuilder::default()
+        })
+    }
+
+    pub fn feed_rows(&mut self, rows: &[u8]) -> &mut Self {
+        let rows = if self.pending.is_empty() {
+            rows
+        } else {
+            let missing = (3 - self.pending.len()).min(rows.len());
+            self.pending.extend_from_slice(&rows[..missing]);
+            if self.pending.len() == 3 {
+                let pixel = Rgb([self.pending[0], self.pending[1], self.pending[2]]);
+                self.add(pixel, 1);
+                self.pending.clear();
+            }
+            &rows[missing..]
+        };
+        let chunks = rows.par_chunks_exact(3);
+        self.pending.extend_from_slice(chunks.remainder());
+        let bits = self.bits;
+        let counts = count_colors(chunks.map(|pixel| {
+            let pixel = Rgb([pixel[0], pixel[1], pixel[2]]);
+            bits.map_or(pixel, |bits| bucket_color(&pixel, bits))
+        }));
+        for (pixel, count) in counts {
+            *self.histogram.entry(pixel).or_insert(0) += count;
+        }
+        self
+    }
+
+    pub fn feed_image(&mut self, data: &RgbImage) -> &mut Self {
+        self.feed_rows(data.as_raw())
+    }
+
+    pub fn finish(self) -> Result<RgbHistogram> {
+        ensure!(
+            self.pending.is_empty(),
+            "Incomplete pixel data, {} trailing bytes",
+            self.pending.len()
+        );
+        Ok(self.histogram)
+    }
+
+    fn add(&mut self, pixel: Rgb<u8>, count: u32) {
+        let pixel = self.bits.map_or(pixel, |bits| bucket_color(&pixel, bits));
+        *self.histogram.entry(pixel).or_insert(0) += count;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ProcessedImage;
+    use crate::histogram::HistogramBuilder;
+    use image::Rgb;
+
+    #[test]
+    fn test_histogram_builder() {
+        let buffer = (0..7 * 5)
+            .map(|i| Rgb([(i % 7 * 30) as u8, (i / 7 * 50) as u8, 9]))
+            .collect::<Vec<_>>();
+        let image = ProcessedImage::from_buffer(7, 5, &buffer);
+        let raw = image.data.as_raw();
+
+        let mut builder = HistogramBuilder::new();
+        raw.chunks(16).for_each(|chunk| {
+            builder.feed_rows(chunk);
+        });
+        assert_eq!(builder.finish().unwrap(), image.get_color_histogram());
+
+        let mut builder = HistogramBuilder::bucketed([3, 3, 2]).unwrap();
+        raw.chunks(7 * 3).for_each(|row| {
+            builder.feed_rows(row);
+        });
+        assert_eq!(
+            builder.finish().unwrap(),
+            image.get_bucketed_histogram([3, 3, 2]).unwrap()
+        );
+
+        let mut builder = HistogramBuilder::new();
+        builder.feed_rows(&raw[..4]);
+        assert!(builder.finish().is_err());
+        assert!(HistogramBuilder::bucketed([0, 8, 8]).is_err());
+    }
+}
@@ -0,0 +1,310 @@
+use crate::Palette;
+use crate::image_processing::{oklab_to_linear, oklab_to_rgb, rgb_to_oklab};
+use crate::palette::{hsl_to_rgb, rgb_to_hsl};
+use anyhow::{Result, anyhow, ensure};
+use image::{Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::TAU;
+use std::fmt;
+use std::str::FromStr;
+
+const HUE_ITERATIONS: usize = 16;
+const MIN_GRAY_SHARE: f64 = 0.02;
+const LIGHTNESS_CLIP: f64 = 0.02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HueSpace {
+    #[default]
+    Hsl,
+    Oklch,
+}
+
+impl HueSpace {
+    // lightness 0-1, chroma and hue in degrees
+    fn to_lch(self, color: &Rgb<u8>) -> [f64; 3] {
+        match self {
+            HueSpace::Hsl => {
+                let [hue, saturation, lightness] = rgb_to_hsl(color);
+                [lightness as f64, saturation as f64, hue as f64]
+            }
+            HueSpace::Oklch => {
+                let [l, a, b] = rgb_to_oklab(color);
+                [l, a.hypot(b), b.atan2(a).to_degrees().rem_euclid(360.0)]
+            }
+        }
+    }
+
+    fn gray_chroma(self) -> f64 {
+        match self {
+            HueSpace::Hsl => 0.12,
+            HueSpace::Oklch => 0.03,
+        }
+    }
+
+    fn to_rgb(self, [lightness, chroma, hue]: [f64; 3]) -> Rgb<u8> {
+        match self {
+            HueSpace::Hsl => hsl_to_rgb([hue as f32, chroma as f32, lightness as f32]),
+            HueSpace::Oklch => {
+                let oklab = |chroma: f64| {
+                    let (sin, cos) = hue.to_radians().sin_cos();
+                    [lightness, chroma * cos, chroma * sin]
+                };
+                let in_gamut = |chroma: f64| {
+                    oklab_to_linear(oklab(chroma))
+                        .iter()
+                        .all(|c| (-1e-4..=1.0 + 1e-4).contains(c))
+                };
+                // keeps the hue and lightness and gives up chroma until the color fits sRGB
+                let (mut low, mut high) = (0.0, chroma);
+                if !in_gamut(high) {
+                    for _ in 0..16 {
+                        let mid = (low + high) / 2.0;
+                        match in_gamut(mid) {
+                            true => low = mid,
+                            false => high = mid,
+                        }
+                    }
+                    high = low;
+                }
+                oklab_to_rgb(oklab(high))
+            }
+        }
+    }
+}
+
+impl FromStr for HueSpace {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "hsl" => Ok(HueSpace::Hsl),
+            "oklch" => Ok(HueSpace::Oklch),
+            _ => Err(anyhow!("Unknown hue space {}, expected hsl or oklch", s)),
+        }
+    }
+}
+
+impl fmt::Display for HueSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HueSpace::Hsl => "hsl",
+            HueSpace::Oklch => "oklch",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// a palette of `hues` hue families with `steps` lightness steps each, plus a gray ramp if the image has grays
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct HueLightness {
+    pub hues: usize,
+    pub steps: usize,
+    pub space: HueSpace,
+}
+
+impl HueLightness {
+    pub fn new(hues: usize, steps: usize, space: HueSpace) -> Result<Self> {
+        ensure!(
+            hues > 0 && steps > 0,
+            "Hue and lightness step counts must be greater than 0"
+        );
+        Ok(HueLightness { hues, steps, space })
+    }
+}
+
+impl FromStr for HueLightness {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            anyhow!(
+                "Invalid hue/lightness quantization {}, expected HUESxSTEPS[:SPACE], e.g. 6x4:oklch",
+                s
+            )
+        };
+        let (counts, space) = match s.split_once(':') {
+            Some((counts, space)) => (counts, space.parse()?),
+            None => (s, HueSpace::default()),
+        };
+        let (hues, steps) =
+            counts
+                .to_lowercase()
+                .split_once('x')
+                .map_or(Err(invalid()), |(h, l)| {
+                    Ok((
+                        h.trim().parse().map_err(|_| invalid())?,
+                        l.trim().parse().map_err(|_| invalid())?,
+                    ))
+                })?;
+        HueLightness::new(hues, steps, space)
+    }
+}
+
+impl fmt::Display for HueLightness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}:{}", self.hues, self.steps, self.space)
+    }
+}
+
+impl TryFrom<String> for HueLightness {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<HueLightness> for String {
+    fn from(options: HueLightness) -> Self {
+        options.to_string()
+    }
+}
+
+pub fn hue_lightness_palette(img: &RgbImage, options: &HueLightness) -> Palette {
+    let space = options.space;
+    let total = img.pixels().len();
+    let (grays, chromatic): (Vec<_>, Vec<_>) = img
+        .pixels()
+        .map(|p| space.to_lch(p))
+        .partition(|[_, chroma, _]| *chroma < space.gray_chroma());
+
+    let centers = hue_centers(&chromatic, options.hues);
+    let mut families = vec![Vec::new(); centers.len()];
+    for color in &chromatic {
+        let nearest = (0..centers.len())
+            .min_by(|&a, &b| {
+                hue_distance(centers[a], color[2]).total_cmp(&hue_distance(centers[b], color[2]))
+            })
+            .unwrap();
+        families[nearest].push(*color);
+    }
+
+    let mut palette: Palette = Vec::new();
+    let mut push = |color| {
+        if !palette.contains(&color) {
+            palette.push(color);
+        }
+    };
+    for (hue, family) in centers.iter().zip(&families) {
+        if family.is_empty() {
+            continue;
+        }
+        let chroma = family.iter().map(|c| c[1]).sum::<f64>() / family.len() as f64;
+        for lightness in lightness_steps(family, options.steps) {
+            push(space.to_rgb([lightness, chroma, *hue]));
+        }
+    }
+    if !grays.is_empty() && grays.len() as f64 >= MIN_GRAY_SHARE * total as f64 {
+        for lightness in lightness_steps(&grays, options.steps) {
+            push(space.to_rgb([lightness, 0.0, 0.0]));
+        }
+    }
+    palette
+}
+
+fn hue_distance(a: f64, b: f64) -> f64 {
+    let d = (a - b).rem_euclid(360.0);
+    d.min(360.0 - d)
+}
+
+// weighted k-means on the hue circle, more colorful pixels pull harder
+fn hue_centers(colors: &[[f64; 3]], count: usize) -> Vec<f64> {
+    if colors.is_empty() {
+        return Vec::new();
+    }
+    let mut sorted = colors.iter().map(|c| (c[2], c[1])).collect::<Vec<_>>();
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let total = sorted.iter().map(|(_, w)| w).sum::<f64>();
+    // starts at evenly spaced quantiles of the weighted hue distribution
+    let mut centers = (0..count)
+        .map(|i| {
+            let target = (i as f64 + 0.5) / count as f64 * total;
+            let mut sum = 0.0;
+            sorted
+                .iter()
+                .find(|(_, w)| {
+                    sum += w;
+                    sum >= target
+                })
+                .map_or(sorted[sorted.len() - 1].0, |(hue, _)| *hue)
+        })
+        .collect::<Vec<_>>();
+    centers.dedup();
+
+    for _ in 0..HUE_ITERATIONS {
+        let mut sums = vec![(0.0, 0.0); centers.len()];
+        for (hue, weight) in &sorted {
+            let nearest = (0..centers.len())
+                .min_by(|&a, &b| {
+                    hue_distance(centers[a], *hue).total_cmp(&hue_distance(centers[b], *hue))
+                })
+                .unwrap();
+            let (sin, cos) = hue.to_radians().sin_cos();
+            sums[nearest].0 += sin * weight;
+            sums[nearest].1 += cos * weight;
+        }
+        centers = centers
+            .iter()
+            .zip(&sums)
+            .map(|(center, (sin, cos))| match *sin != 0.0 || *cos != 0.0 {
+                true => sin.atan2(*cos).rem_euclid(TAU).to_degrees(),
+                false => *center,
+            })
+            .collect();
+    }
+    centers
+}
+
+// evenly spaced between the darkest and brightest few percent of a family
+fn lightness_steps(colors: &[[f64; 3]], steps: usize) -> Vec<f64> {
+    let mut lightness = colors.iter().map(|c| c[0]).collect::<Vec<_>>();
+    lightness.sort_by(f64::total_cmp);
+    let at = |share: f64| lightness[((lightness.len() - 1) as f64 * share).round() as usize];
+    if steps == 1 {
+        return vec![at(0.5)];
+    }
+    let (low, high) = (at(LIGHTNESS_CLIP), at(1.0 - LIGHTNESS_CLIP));
+    (0..steps)
+        .map(|i| low + (high - low) * i as f64 / (steps - 1) as f64)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hue_lightness::{HueLightness, HueSpace, hue_lightness_palette};
+    use crate::palette::rgb_to_hsl;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_hue_lightness_palette() {
+        let options = "2x3".parse::<HueLightness>().unwrap();
+        assert_eq!(options.space, HueSpace::Hsl);
+        assert_eq!(
+            options.to_string().parse::<HueLightness>().unwrap(),
+            options
+        );
+        assert!("0x3".parse::<HueLightness>().is_err());
+
+        // red and blue shades, no grays
+        let img = RgbImage::from_fn(64, 2, |x, y| {
+            let shade = 40 + x as u8 * 3;
+            match y {
+                0 => Rgb([shade, 0, 0]),
+                _ => Rgb([0, 0, shade]),
+            }
+        });
+        for space in [HueSpace::Hsl, HueSpace::Oklch] {
+            let palette = hue_lightness_palette(&img, &HueLightness::new(2, 3, space).unwrap());
+            assert_eq!(palette.len(), 6);
+            assert_eq!(palette.iter().filter(|c| c[0] > c[2]).count(), 3);
+            assert_eq!(palette.iter().filter(|c| c[2] > c[0]).count(), 3);
+        }
+        let hsl = hue_lightness_palette(&img, &options);
+        assert!(
+            hsl.iter()
+                .all(|c| [0, 240].contains(&(rgb_to_hsl(c)[0].round() as i32)))
+        );
+    }
+}
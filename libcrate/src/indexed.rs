@@ -0,0 +1,90 @@
+use crate::Palette;
+use crate::image_processing::palette_indices;
+use anyhow::{Result, ensure};
+use image::{Rgb, RgbImage};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedImage {
+    pub palette: Palette,
+    pub indices: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl IndexedImage {
+    pub fn new(palette: Palette, indices: Vec<u8>, width: u32, height: u32) -> Result<Self> {
+        ensure!(
+            indices.len() as u64 == width as u64 * height as u64,
+            "Expected {} indices for a {}x{} image, got {}",
+            width as u64 * height as u64,
+            width,
+            height,
+            indices.len()
+        );
+        ensure!(
+            indices.iter().all(|i| (*i as usize) < palette.len()),
+            "Index out of the {} color palette",
+            palette.len()
+        );
+        Ok(IndexedImage {
+            palette,
+            indices,
+            width,
+            height,
+        })
+    }
+
+    pub fn from_rgb(img: &RgbImage, palette: &Palette) -> Result<Self> {
+        ensure!(
+            !palette.is_empty() && palette.len() <= 256,
+            "Indexed images need 1 to 256 colors, palette has {}",
+            palette.len()
+        );
+        let indices = palette_indices(img, palette)
+            .into_iter()
+            .map(|i| i as u8)
+            .collect();
+        Ok(IndexedImage {
+            palette: palette.clone(),
+            indices,
+            width: img.width(),
+            height: img.height(),
+        })
+    }
+
+    pub fn index(&self, x: u32, y: u32) -> u8 {
+        self.indices[(y * self.width + x) as usize]
+    }
+
+    pub fn color(&self, x: u32, y: u32) -> Rgb<u8> {
+        self.palette[self.index(x, y) as usize]
+    }
+
+    pub fn to_rgb(&self) -> RgbImage {
+        RgbImage::from_fn(self.width, self.height, |x, y| self.color(x, y))
+    }
+}
+
+impl From<&IndexedImage> for RgbImage {
+    fn from(img: &IndexedImage) -> Self {
+        img.to_rgb()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::indexed::IndexedImage;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_indexed_roundtrip() {
+        let palette = vec![Rgb([0u8, 0, 0]), Rgb([255, 0, 0]), Rgb([0, 0, 255])];
+        let img = RgbImage::from_fn(3, 2, |x, y| palette[((x + y) % 3) as usize]);
+        let indexed = IndexedImage::from_rgb(&img, &palette).unwrap();
+        assert_eq!(indexed.indices, vec![0, 1, 2, 1, 2, 0]);
+        assert_eq!(indexed.index(2, 1), 0);
+        assert_eq!(RgbImage::from(&indexed), img);
+        assert!(IndexedImage::new(palette.clone(), vec![0, 3], 2, 1).is_err());
+        assert!(IndexedImage::new(palette, vec![0, 1, 2], 2, 1).is_err());
+    }
+}
@@ -1,30 +1,366 @@
+use crate::adjust::{AutoBalance, Curve, Levels, apply_curve, apply_levels, auto_balance};
+use crate::bitdepth::{BitDepth, reduce_bit_depth};
+use crate::dither::{Dither, DitherOptions, dither, dither_with};
+use crate::hue_lightness::{HueLightness, hue_lightness_palette};
 use crate::image_processing::{
-    apply_palette, generate_image_palette, get_color_histogram, save_image, scale,
+    apply_palette, apply_palette_indexed, apply_palette_masked, auto_color_count, chroma_key,
+    colors_within, content_bounds, decode_image, flatten, generate_contrast_palette,
+    generate_image_palette, generate_masked_palette, generate_region_palette,
+    generate_weighted_palette, get_bucketed_histogram, get_color_histogram, merge_alpha,
+    proxy_size, scale, scale_linear, stabilize_frame,
 };
-use anyhow::{Context, Result};
+#[cfg(feature = "io")]
+use crate::image_processing::{ensure_encoder, open_image, save_image};
+use crate::indexed::IndexedImage;
+use crate::noise::{NoiseOptions, add_noise};
+use crate::outline::add_outline;
+use crate::palette::{
+    HueRange, PaletteUsage, PruneThreshold, RemapStrategy, constrain_image_hues, prune_palette,
+    reduce_palette, remap_palette,
+};
+use crate::parallel::*;
+use crate::threshold::{Threshold, threshold_image};
+use crate::tiles::{Tileset, extract_tiles};
+use crate::transfer::{ColorReference, ColorTransfer};
+use anyhow::{Context, Result, anyhow, ensure};
 use image::imageops::FilterType;
-use image::{ImageReader, Rgb, RgbImage};
+use image::{
+    DynamicImage, GenericImageView, GrayImage, ImageFormat, Luma, Rgb, RgbImage, RgbaImage,
+    SubImage,
+};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::io::Cursor;
+#[cfg(feature = "io")]
 use std::path::Path;
+use std::str::FromStr;
+
+pub mod adjust;
+#[cfg(feature = "async")]
+pub mod async_io;
+pub mod bitdepth;
+pub mod cvd;
+pub mod dither;
+pub mod export;
+pub mod font;
+pub mod formats;
+pub mod gradient;
+pub mod histogram;
+pub mod hue_lightness;
+pub mod indexed;
+pub mod names;
+pub mod noise;
+pub mod outline;
+pub mod palette;
+mod parallel;
+pub mod pipeline;
+pub mod preset;
+pub mod preview;
+pub mod sequence;
+pub mod spritesheet;
+pub mod threshold;
+pub mod tiles;
+pub mod transfer;
+pub mod traversal;
+#[cfg(feature = "ffmpeg")]
+pub mod video;
 
 pub type RgbHistogram = HashMap<Rgb<u8>, u32>;
 pub type Palette = Vec<Rgb<u8>>;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorWeight {
+    pub color: Rgb<u8>,
+    pub tolerance: u8,
+    pub weight: f32,
+}
+
+impl ColorWeight {
+    pub fn exclude(color: Rgb<u8>, tolerance: u8) -> Self {
+        ColorWeight {
+            color,
+            tolerance,
+            weight: 0.0,
+        }
+    }
+
+    pub fn matches(&self, color: &Rgb<u8>) -> bool {
+        colors_within(&self.color, color, self.tolerance)
+    }
+
+    fn keeps(&self, nth: usize) -> bool {
+        let weight = self.weight.clamp(0.0, 1.0) as f64;
+        (nth as f64 * weight).floor() != ((nth + 1) as f64 * weight).floor()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScaleFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    #[default]
+    Lanczos3,
+}
+
+impl ScaleFilter {
+    pub const ALL: [ScaleFilter; 5] = [
+        ScaleFilter::Nearest,
+        ScaleFilter::Triangle,
+        ScaleFilter::CatmullRom,
+        ScaleFilter::Gaussian,
+        ScaleFilter::Lanczos3,
+    ];
+}
+
+impl FromStr for ScaleFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "nearest" => Ok(ScaleFilter::Nearest),
+            "triangle" | "bilinear" => Ok(ScaleFilter::Triangle),
+            "catmull-rom" | "catmullrom" | "bicubic" => Ok(ScaleFilter::CatmullRom),
+            "gaussian" => Ok(ScaleFilter::Gaussian),
+            "lanczos3" | "lanczos" => Ok(ScaleFilter::Lanczos3),
+            _ => Err(anyhow!(
+                "Unknown scale filter {}, expected nearest, triangle, catmull-rom, gaussian or lanczos3",
+                s
+            )),
+        }
+    }
+}
+
+impl From<ScaleFilter> for FilterType {
+    fn from(filter: ScaleFilter) -> Self {
+        match filter {
+            ScaleFilter::Nearest => FilterType::Nearest,
+            ScaleFilter::Triangle => FilterType::Triangle,
+            ScaleFilter::CatmullRom => FilterType::CatmullRom,
+            ScaleFilter::Gaussian => FilterType::Gaussian,
+            ScaleFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScaleMode {
+    #[default]
+    Stretch,
+    Fit,
+    Fill,
+}
+
+impl ScaleMode {
+    pub fn scaled_size(
+        &self,
+        width: u32,
+        height: u32,
+        target_width: u32,
+        target_height: u32,
+    ) -> (u32, u32) {
+        let ratio_x = target_width as f64 / width as f64;
+        let ratio_y = target_height as f64 / height as f64;
+        let scaled = |ratio: f64| {
+            (
+                (width as f64 * ratio).round() as u32,
+                (height as f64 * ratio).round() as u32,
+            )
+        };
+        match self {
+            ScaleMode::Stretch => (target_width, target_height),
+            ScaleMode::Fit => {
+                let (w, h) = scaled(ratio_x.min(ratio_y));
+                (w.clamp(1, target_width), h.clamp(1, target_height))
+            }
+            ScaleMode::Fill => {
+                let (w, h) = scaled(ratio_x.max(ratio_y));
+                (w.max(target_width), h.max(target_height))
+            }
+        }
+    }
+}
+
+impl FromStr for ScaleMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "stretch" => Ok(ScaleMode::Stretch),
+            "fit" | "contain" | "letterbox" => Ok(ScaleMode::Fit),
+            "fill" | "cover" => Ok(ScaleMode::Fill),
+            _ => Err(anyhow!(
+                "Unknown scale mode {}, expected stretch, fit or fill",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    #[default]
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    pub fn offset(&self, free_width: u32, free_height: u32) -> (u32, u32) {
+        let (horizontal, vertical) = match self {
+            Anchor::TopLeft => (0, 0),
+            Anchor::Top => (1, 0),
+            Anchor::TopRight => (2, 0),
+            Anchor::Left => (0, 1),
+            Anchor::Center => (1, 1),
+            Anchor::Right => (2, 1),
+            Anchor::BottomLeft => (0, 2),
+            Anchor::Bottom => (1, 2),
+            Anchor::BottomRight => (2, 2),
+        };
+        (free_width * horizontal / 2, free_height * vertical / 2)
+    }
+}
+
+impl FromStr for Anchor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "top-left" | "nw" => Ok(Anchor::TopLeft),
+            "top" | "n" => Ok(Anchor::Top),
+            "top-right" | "ne" => Ok(Anchor::TopRight),
+            "left" | "w" => Ok(Anchor::Left),
+            "center" | "centre" => Ok(Anchor::Center),
+            "right" | "e" => Ok(Anchor::Right),
+            "bottom-left" | "sw" => Ok(Anchor::BottomLeft),
+            "bottom" | "s" => Ok(Anchor::Bottom),
+            "bottom-right" | "se" => Ok(Anchor::BottomRight),
+            _ => Err(anyhow!(
+                "Unknown anchor {}, expected center, top, bottom, left, right, top-left, top-right, bottom-left or bottom-right",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerOfTwo {
+    Pad,
+    Scale,
+}
+
+impl FromStr for PowerOfTwo {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "pad" => Ok(PowerOfTwo::Pad),
+            "scale" => Ok(PowerOfTwo::Scale),
+            _ => Err(anyhow!(
+                "Unknown power of two mode {}, expected pad or scale",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PixelizeOptions {
+    pub width: u32,
+    pub height: Option<u32>,
+    pub number_of_colors: usize,
+    pub sample_factor: i32,
+    pub palette: Option<Palette>,
+    pub dither: DitherOptions,
+    pub filter: ScaleFilter,
+    pub linear: bool,
+    pub upscale: bool,
+}
+
+impl PixelizeOptions {
+    pub fn new(width: u32, number_of_colors: usize) -> Self {
+        PixelizeOptions {
+            width,
+            height: None,
+            number_of_colors,
+            sample_factor: 10,
+            palette: None,
+            dither: DitherOptions::default(),
+            filter: ScaleFilter::Lanczos3,
+            linear: false,
+            upscale: true,
+        }
+    }
+}
+
+pub const DEFAULT_PROXY_PIXELS: u32 = 1_000_000;
+
+#[cfg(feature = "parallel")]
+pub fn set_thread_count(threads: usize) -> Result<()> {
+    ensure!(threads > 0, "Thread count must be positive");
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+        .with_context(|| "Failed to configure the thread pool")
+}
+
+#[cfg(feature = "parallel")]
+pub fn with_thread_count<F, R>(threads: usize, op: F) -> Result<R>
+where
+    F: FnOnce() -> R + Send,
+    R: Send,
+{
+    ensure!(threads > 0, "Thread count must be positive");
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .with_context(|| "Failed to create the thread pool")?;
+    Ok(pool.install(op))
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct ProcessedImage {
     pub data: RgbImage,
+    pub alpha: Option<GrayImage>,
 }
 
 impl ProcessedImage {
+    #[cfg(feature = "io")]
     pub fn new<P>(path: P) -> Result<Self>
     where
         P: AsRef<Path>,
     {
-        let data = ImageReader::open(path.as_ref())
-            .with_context(|| format!("Failed to open: {:?}", path.as_ref()))?
-            .decode()
-            .with_context(|| "Failed to decode the file")?
-            .to_rgb8();
-        Ok(ProcessedImage { data })
+        let data = open_image(path)?.to_rgb8();
+        Ok(ProcessedImage { data, alpha: None })
+    }
+
+    // keeps semi-transparent pixels as they look over the background instead of dropping the alpha
+    #[cfg(feature = "io")]
+    pub fn open_flattened<P>(path: P, background: Rgb<u8>) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let mut image = ProcessedImage::from(open_image(path)?);
+        image.flatten(background);
+        Ok(image)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let data = decode_image(bytes)?.to_rgb8();
+        Ok(ProcessedImage { data, alpha: None })
     }
 
     pub fn from_buffer(width: u32, height: u32, buffer: &[Rgb<u8>]) -> Self {
@@ -34,140 +370,1125 @@ impl ProcessedImage {
             *px = buffer[idx];
             idx += 1;
         });
-        ProcessedImage { data }
+        ProcessedImage { data, alpha: None }
+    }
+
+    pub fn into_inner(self) -> RgbImage {
+        self.data
+    }
+
+    pub fn flatten(&mut self, background: Rgb<u8>) {
+        if let Some(alpha) = self.alpha.take() {
+            self.data = flatten(&self.data, &alpha, background);
+        }
     }
 
     pub fn get_color_histogram(&self) -> RgbHistogram {
         get_color_histogram(&self.data)
     }
 
+    pub fn get_bucketed_histogram(&self, bits: [u32; 3]) -> Result<RgbHistogram> {
+        get_bucketed_histogram(&self.data, bits)
+    }
+
+    // images already within max_pixels, usually the scaled result, are used as they are
+    pub fn palette_proxy(&self, max_pixels: u32, filter: ScaleFilter) -> Cow<'_, ProcessedImage> {
+        match proxy_size(self.width(), self.height(), max_pixels) {
+            Some((width, height)) => {
+                let mut proxy = self.clone();
+                proxy.scale(width, height, filter);
+                Cow::Owned(proxy)
+            }
+            None => Cow::Borrowed(self),
+        }
+    }
+
     pub fn generate_image_palette(&self, sample_factor: i32, number_of_colors: usize) -> Palette {
         generate_image_palette(&self.data, sample_factor, number_of_colors)
     }
 
+    pub fn generate_hue_lightness_palette(&self, options: &HueLightness) -> Palette {
+        hue_lightness_palette(&self.data, options)
+    }
+
+    pub fn generate_contrast_palette(
+        &self,
+        sample_factor: i32,
+        number_of_colors: usize,
+        strength: f32,
+    ) -> Palette {
+        generate_contrast_palette(&self.data, sample_factor, number_of_colors, strength)
+    }
+
+    pub fn auto_color_count(
+        &self,
+        sample_factor: i32,
+        max_colors: usize,
+        max_delta_e: f64,
+    ) -> usize {
+        auto_color_count(&self.data, sample_factor, max_colors, max_delta_e)
+    }
+
+    pub fn generate_weighted_palette(
+        &self,
+        sample_factor: i32,
+        number_of_colors: usize,
+        weights: &[ColorWeight],
+    ) -> Result<Palette> {
+        generate_weighted_palette(&self.data, sample_factor, number_of_colors, weights)
+    }
+
+    pub fn generate_region_palette(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        sample_factor: i32,
+        number_of_colors: usize,
+    ) -> Result<Palette> {
+        generate_region_palette(
+            &self.data,
+            (x, y, width, height),
+            sample_factor,
+            number_of_colors,
+        )
+    }
+
+    pub fn generate_masked_palette(
+        &self,
+        mask: &GrayImage,
+        sample_factor: i32,
+        number_of_colors: usize,
+    ) -> Result<Palette> {
+        generate_masked_palette(&self.data, mask, sample_factor, number_of_colors)
+    }
+
     pub fn apply_palette(&mut self, palette: &Palette) {
         self.data = apply_palette(&self.data, palette);
     }
 
-    pub fn scale(&mut self, new_width: u32, new_height: u32, smooth: bool) {
-        self.data = scale(
-            &self.data,
-            new_width,
-            new_height,
-            if smooth {
-                FilterType::Lanczos3
-            } else {
-                FilterType::Nearest
-            },
+    pub fn dither_stable(
+        &mut self,
+        palette: &Palette,
+        options: &DitherOptions,
+        previous: &RgbImage,
+        hysteresis: f32,
+    ) {
+        let mapped = dither_with(&self.data, palette, options);
+        self.data = stabilize_frame(&self.data, &mapped, previous, hysteresis);
+    }
+
+    pub fn remap_palette(
+        &mut self,
+        from: &Palette,
+        to: &Palette,
+        strategy: RemapStrategy,
+    ) -> Result<()> {
+        self.data = remap_palette(&self.data, from, to, strategy)?;
+        Ok(())
+    }
+
+    pub fn add_noise(&mut self, options: &NoiseOptions) {
+        self.data = add_noise(&self.data, options);
+    }
+
+    pub fn auto_balance(&mut self, method: AutoBalance) {
+        self.data = auto_balance(&self.data, method);
+    }
+
+    pub fn constrain_hues(&mut self, ranges: &[HueRange]) {
+        self.data = constrain_image_hues(&self.data, ranges);
+    }
+
+    pub fn levels(&mut self, levels: &Levels) {
+        self.data = apply_levels(&self.data, levels);
+    }
+
+    pub fn curve(&mut self, curve: &Curve) {
+        self.data = apply_curve(&self.data, curve);
+    }
+
+    pub fn transfer_colors(&mut self, reference: &ColorReference, transfer: ColorTransfer) {
+        self.data = reference.apply(&self.data, transfer);
+    }
+
+    pub fn dither(&mut self, palette: &Palette, method: Dither) {
+        self.data = dither(&self.data, palette, method);
+    }
+
+    pub fn dither_with(&mut self, palette: &Palette, options: &DitherOptions) {
+        self.data = dither_with(&self.data, palette, options);
+    }
+
+    pub fn reduce_bit_depth(&mut self, depth: BitDepth, options: &DitherOptions) -> Result<()> {
+        self.data = reduce_bit_depth(&self.data, depth, options)?;
+        Ok(())
+    }
+
+    pub fn threshold(&mut self, threshold: Threshold, options: &DitherOptions) -> Result<()> {
+        self.data = threshold_image(&self.data, threshold, options)?;
+        Ok(())
+    }
+
+    pub fn pixelize(&mut self, options: &PixelizeOptions) -> Palette {
+        let (width, height) = self.data.dimensions();
+        let (new_width, new_height) = match options.height {
+            Some(new_height) => (options.width, new_height),
+            None => self.uniform_size_for_width(options.width),
+        };
+        if options.linear {
+            self.scale_linear(new_width, new_height, options.filter);
+        } else {
+            self.scale(new_width, new_height, options.filter);
+        }
+        let palette = options.palette.clone().unwrap_or_else(|| {
+            self.generate_image_palette(options.sample_factor, options.number_of_colors)
+        });
+        self.dither_with(&palette, &options.dither);
+        if options.upscale {
+            self.scale(width, height, ScaleFilter::Nearest);
+        }
+        palette
+    }
+
+    pub fn palette_usage(&self, palette: &Palette) -> PaletteUsage {
+        PaletteUsage::new(&self.data, palette)
+    }
+
+    pub fn prune_palette(&mut self, palette: &Palette, threshold: PruneThreshold) -> Palette {
+        let pruned = prune_palette(&self.data, palette, threshold);
+        if pruned.len() < palette.len() {
+            self.apply_palette(&pruned);
+        }
+        pruned
+    }
+
+    pub fn reduce_palette(&self, palette: &Palette, target: usize) -> Result<Palette> {
+        reduce_palette(palette, &self.palette_usage(palette).counts, target)
+    }
+
+    pub fn to_indexed(&self, palette: &Palette) -> Result<IndexedImage> {
+        IndexedImage::from_rgb(&self.data, palette)
+    }
+
+    pub fn apply_palette_indexed(&mut self, palette: &Palette) -> Result<Vec<u16>> {
+        let (data, indices) = apply_palette_indexed(&self.data, palette)?;
+        self.data = data;
+        Ok(indices)
+    }
+
+    pub fn apply_palette_masked(&mut self, palette: &Palette, mask: &GrayImage) -> Result<()> {
+        self.data = apply_palette_masked(&self.data, palette, mask)?;
+        Ok(())
+    }
+
+    pub fn chroma_key(&mut self, key: Rgb<u8>, tolerance: u8) {
+        let keyed = chroma_key(&self.data, key, tolerance);
+        self.alpha = Some(match self.alpha.take() {
+            Some(mut alpha) => {
+                alpha
+                    .pixels_mut()
+                    .zip(keyed.pixels())
+                    .for_each(|(a, k)| a[0] = a[0].min(k[0]));
+                alpha
+            }
+            None => keyed,
+        });
+    }
+
+    pub fn map_pixels<F>(&mut self, mut f: F)
+    where
+        F: FnMut(u32, u32, Rgb<u8>) -> Rgb<u8>,
+    {
+        self.data
+            .enumerate_pixels_mut()
+            .for_each(|(x, y, p)| *p = f(x, y, *p));
+    }
+
+    pub fn par_map_pixels<F>(&mut self, f: F)
+    where
+        F: Fn(u32, u32, Rgb<u8>) -> Rgb<u8> + Sync + Send,
+    {
+        self.data
+            .par_enumerate_pixels_mut()
+            .for_each(|(x, y, p)| *p = f(x, y, *p));
+    }
+
+    pub fn extract_tiles(&self, tile_size: u32, allow_flips: bool) -> Result<Tileset> {
+        extract_tiles(&self.data, tile_size, allow_flips)
+    }
+
+    pub fn rows(&self) -> impl ExactSizeIterator<Item = &[u8]> {
+        self.data
+            .as_raw()
+            .chunks_exact(self.width().max(1) as usize * 3)
+    }
+
+    pub fn view(&self, x: u32, y: u32, width: u32, height: u32) -> Result<SubImage<&RgbImage>> {
+        ensure!(
+            x as u64 + width as u64 <= self.width() as u64
+                && y as u64 + height as u64 <= self.height() as u64,
+            "Region {}x{} at {},{} is outside of the {}x{} image",
+            width,
+            height,
+            x,
+            y,
+            self.width(),
+            self.height()
         );
+        Ok(self.data.view(x, y, width, height))
+    }
+
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> ProcessedImage {
+        ProcessedImage {
+            data: image::imageops::crop_imm(&self.data, x, y, width, height).to_image(),
+            alpha: self
+                .alpha
+                .as_ref()
+                .map(|alpha| image::imageops::crop_imm(alpha, x, y, width, height).to_image()),
+        }
     }
 
-    pub fn uniform_scale_width(&mut self, new_width: u32, smooth: bool) {
+    pub fn trim(&mut self, background: Option<Rgb<u8>>, tolerance: u8, padding: u32) {
+        let Some((x, y, width, height)) =
+            content_bounds(&self.data, self.alpha.as_ref(), background, tolerance)
+        else {
+            return;
+        };
+        let (x0, y0) = (x.saturating_sub(padding), y.saturating_sub(padding));
+        let x1 = (x + width).saturating_add(padding).min(self.width());
+        let y1 = (y + height).saturating_add(padding).min(self.height());
+        *self = self.crop(x0, y0, x1 - x0, y1 - y0);
+    }
+
+    pub fn pad_to(
+        &mut self,
+        width: u32,
+        height: u32,
+        anchor: Anchor,
+        fill: Option<Rgb<u8>>,
+    ) -> Result<()> {
+        ensure!(
+            width >= self.width() && height >= self.height(),
+            "Cannot pad a {}x{} image to {}x{}",
+            self.width(),
+            self.height(),
+            width,
+            height
+        );
+        let (x, y) = anchor.offset(width - self.width(), height - self.height());
+        self.place(width, height, x, y, fill);
+        Ok(())
+    }
+
+    fn place(&mut self, width: u32, height: u32, x: u32, y: u32, fill: Option<Rgb<u8>>) {
+        let mut data = RgbImage::from_pixel(width, height, fill.unwrap_or(Rgb([0, 0, 0])));
+        image::imageops::replace(&mut data, &self.data, x as i64, y as i64);
+        if fill.is_none() || self.alpha.is_some() {
+            let background = if fill.is_some() { 255 } else { 0 };
+            let mut alpha = GrayImage::from_pixel(width, height, Luma([background]));
+            let source = self
+                .alpha
+                .take()
+                .unwrap_or_else(|| GrayImage::from_pixel(self.width(), self.height(), Luma([255])));
+            image::imageops::replace(&mut alpha, &source, x as i64, y as i64);
+            self.alpha = Some(alpha);
+        }
+        self.data = data;
+    }
+
+    pub fn outline(&mut self, color: Rgb<u8>) -> Result<()> {
+        let alpha = self
+            .alpha
+            .as_mut()
+            .ok_or_else(|| anyhow!("Outlines need a transparent background"))?;
+        add_outline(&mut self.data, alpha, color)
+    }
+
+    pub fn to_rgba(&self) -> RgbaImage {
+        match &self.alpha {
+            Some(alpha) => merge_alpha(&self.data, alpha),
+            None => image::DynamicImage::ImageRgb8(self.data.clone()).to_rgba8(),
+        }
+    }
+
+    pub fn scale(&mut self, new_width: u32, new_height: u32, filter: ScaleFilter) {
+        self.resize(new_width, new_height, filter, false);
+    }
+
+    pub fn scale_linear(&mut self, new_width: u32, new_height: u32, filter: ScaleFilter) {
+        self.resize(new_width, new_height, filter, true);
+    }
+
+    fn resize(&mut self, new_width: u32, new_height: u32, filter: ScaleFilter, linear: bool) {
+        let filter = filter.into();
+        self.data = if linear {
+            scale_linear(&self.data, new_width, new_height, filter)
+        } else {
+            scale(&self.data, new_width, new_height, filter)
+        };
+        if let Some(alpha) = &self.alpha {
+            self.alpha = Some(image::imageops::resize(
+                alpha, new_width, new_height, filter,
+            ));
+        }
+    }
+
+    pub fn power_of_two(&mut self, mode: PowerOfTwo, anchor: Anchor, fill: Option<Rgb<u8>>) {
+        let (width, height) = (
+            self.width().next_power_of_two(),
+            self.height().next_power_of_two(),
+        );
+        if (width, height) == self.data.dimensions() {
+            return;
+        }
+        match mode {
+            PowerOfTwo::Pad => {
+                let (x, y) = anchor.offset(width - self.width(), height - self.height());
+                self.place(width, height, x, y, fill);
+            }
+            PowerOfTwo::Scale => self.scale(width, height, ScaleFilter::Nearest),
+        }
+    }
+
+    pub fn scale_to(
+        &mut self,
+        width: u32,
+        height: u32,
+        mode: ScaleMode,
+        filter: ScaleFilter,
+        linear: bool,
+        background: Option<Rgb<u8>>,
+    ) -> Result<()> {
+        ensure!(
+            width > 0 && height > 0,
+            "Invalid target size {}x{}",
+            width,
+            height
+        );
+        let (scaled_width, scaled_height) =
+            mode.scaled_size(self.width(), self.height(), width, height);
+        self.resize(scaled_width, scaled_height, filter, linear);
+        match mode {
+            ScaleMode::Stretch => {}
+            ScaleMode::Fit => {
+                let (x, y) = Anchor::Center.offset(width - scaled_width, height - scaled_height);
+                self.place(width, height, x, y, background);
+            }
+            ScaleMode::Fill => {
+                let (x, y) = Anchor::Center.offset(scaled_width - width, scaled_height - height);
+                *self = self.crop(x, y, width, height);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn uniform_size_for_width(&self, new_width: u32) -> (u32, u32) {
         let (width, height) = self.data.dimensions();
         let ratio = new_width as f64 / width as f64;
-        let new_height = (height as f64 * ratio) as u32;
-        self.scale(new_width, new_height, smooth);
+        (new_width, (height as f64 * ratio) as u32)
     }
 
-    pub fn uniform_scale_height(&mut self, new_height: u32, smooth: bool) {
+    pub fn uniform_size_for_height(&self, new_height: u32) -> (u32, u32) {
         let (width, height) = self.data.dimensions();
         let ratio = new_height as f64 / height as f64;
-        let new_width = (width as f64 * ratio) as u32;
-        self.scale(new_width, new_height, smooth);
+        ((width as f64 * ratio) as u32, new_height)
+    }
+
+    pub fn uniform_size_for_max_dimension(&self, max_dimension: u32) -> (u32, u32) {
+        if self.width() >= self.height() {
+            self.uniform_size_for_width(max_dimension)
+        } else {
+            self.uniform_size_for_height(max_dimension)
+        }
+    }
+
+    pub fn size_for_percent(&self, percent: f64) -> (u32, u32) {
+        let (width, height) = self.data.dimensions();
+        let ratio = percent / 100.0;
+        (
+            ((width as f64 * ratio).round() as u32).max(1),
+            ((height as f64 * ratio).round() as u32).max(1),
+        )
     }
 
+    pub fn uniform_scale_max_dimension(&mut self, max_dimension: u32, filter: ScaleFilter) {
+        let (new_width, new_height) = self.uniform_size_for_max_dimension(max_dimension);
+        self.scale(new_width, new_height, filter);
+    }
+
+    pub fn scale_percent(&mut self, percent: f64, filter: ScaleFilter) {
+        let (new_width, new_height) = self.size_for_percent(percent);
+        self.scale(new_width, new_height, filter);
+    }
+
+    pub fn uniform_scale_width(&mut self, new_width: u32, filter: ScaleFilter) {
+        let (new_width, new_height) = self.uniform_size_for_width(new_width);
+        self.scale(new_width, new_height, filter);
+    }
+
+    pub fn uniform_scale_height(&mut self, new_height: u32, filter: ScaleFilter) {
+        let (new_width, new_height) = self.uniform_size_for_height(new_height);
+        self.scale(new_width, new_height, filter);
+    }
+
+    #[cfg(feature = "io")]
     pub fn save<P>(&self, path: P) -> Result<()>
     where
         P: AsRef<Path>,
     {
-        save_image(path.as_ref(), &self.data)
+        match &self.alpha {
+            Some(_) => {
+                ensure_encoder(path.as_ref())?;
+                self.to_rgba()
+                    .save(path.as_ref())
+                    .with_context(|| "Failed to save image")
+            }
+            None => save_image(path.as_ref(), &self.data),
+        }
+    }
+
+    pub fn encode(&self, format: ImageFormat) -> Result<Vec<u8>> {
+        let mut bytes = Cursor::new(vec![]);
+        match &self.alpha {
+            Some(_) => self.to_rgba().write_to(&mut bytes, format),
+            None => self.data.write_to(&mut bytes, format),
+        }
+        .with_context(|| format!("Failed to encode {:?}", format))?;
+        Ok(bytes.into_inner())
+    }
+
+    pub fn width(&self) -> u32 {
+        self.data.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.data.height()
+    }
+}
+
+impl From<RgbImage> for ProcessedImage {
+    fn from(data: RgbImage) -> Self {
+        ProcessedImage { data, alpha: None }
+    }
+}
+
+impl From<DynamicImage> for ProcessedImage {
+    fn from(image: DynamicImage) -> Self {
+        if !image.color().has_alpha() {
+            return ProcessedImage::from(image.to_rgb8());
+        }
+        let rgba = image.to_rgba8();
+        let alpha = GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+            Luma([rgba.get_pixel(x, y)[3]])
+        });
+        ProcessedImage {
+            data: DynamicImage::ImageRgba8(rgba).to_rgb8(),
+            alpha: Some(alpha),
+        }
+    }
+}
+
+impl TryFrom<&[u8]> for ProcessedImage {
+    type Error = anyhow::Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        Ok(ProcessedImage::from(decode_image(bytes)?))
+    }
+}
+
+impl From<ProcessedImage> for DynamicImage {
+    fn from(image: ProcessedImage) -> Self {
+        match image.alpha {
+            Some(_) => DynamicImage::ImageRgba8(image.to_rgba()),
+            None => DynamicImage::ImageRgb8(image.data),
+        }
+    }
+}
+
+pub mod image_processing {
+    use crate::formats::pcx;
+    use crate::histogram::{bucket_color, check_bucket_bits};
+    use crate::parallel::*;
+    use crate::traversal::{Traversal, map_image};
+    use crate::{ColorWeight, Palette, RgbHistogram};
+    use anyhow::{Context, Result, ensure};
+    use color_quant::NeuQuant;
+    use image::imageops::FilterType;
+    use image::{
+        DynamicImage, GenericImageView, GrayImage, ImageFormat, ImageReader, Luma, Rgb,
+        Rgb32FImage, RgbImage, Rgba, RgbaImage,
+    };
+    #[cfg(feature = "kdtree")]
+    use kiddo::{KdTree, SquaredEuclidean};
+    #[cfg(feature = "io")]
+    use std::fs;
+    use std::io::Cursor;
+    use std::path::Path;
+
+    pub fn get_color_histogram(data: &RgbImage) -> RgbHistogram {
+        count_colors(data.pixels().par_bridge().copied())
+    }
+
+    pub fn get_bucketed_histogram(data: &RgbImage, bits: [u32; 3]) -> Result<RgbHistogram> {
+        check_bucket_bits(bits)?;
+        Ok(count_colors(
+            data.pixels()
+                .par_bridge()
+                .map(|pixel| bucket_color(pixel, bits)),
+        ))
+    }
+
+    pub fn generate_image_palette(
+        data: &RgbImage,
+        sample_factor: i32,
+        number_of_colors: usize,
+    ) -> Palette {
+        let pixels: Vec<u8> = data.pixels().flat_map(|p| p.0.to_vec()).collect();
+        quantize_pixels(&pixels, sample_factor, number_of_colors)
+    }
+
+    pub fn generate_shared_palette(
+        images: &[&RgbImage],
+        sample_factor: i32,
+        number_of_colors: usize,
+    ) -> Palette {
+        let pixels: Vec<u8> = images
+            .iter()
+            .flat_map(|data| data.pixels().flat_map(|p| p.0))
+            .collect();
+        quantize_pixels(&pixels, sample_factor, number_of_colors)
+    }
+
+    pub fn generate_weighted_palette(
+        data: &RgbImage,
+        sample_factor: i32,
+        number_of_colors: usize,
+        weights: &[ColorWeight],
+    ) -> Result<Palette> {
+        generate_shared_weighted_palette(&[data], sample_factor, number_of_colors, weights)
+    }
+
+    // the weights count matching pixels across all images, as if they were one
+    pub fn generate_shared_weighted_palette(
+        images: &[&RgbImage],
+        sample_factor: i32,
+        number_of_colors: usize,
+        weights: &[ColorWeight],
+    ) -> Result<Palette> {
+        let mut counters = vec![0; weights.len()];
+        let pixels: Vec<u8> = images
+            .iter()
+            .flat_map(|data| data.pixels())
+            .filter(|p| match weights.iter().position(|w| w.matches(p)) {
+                Some(i) => {
+                    counters[i] += 1;
+                    weights[i].keeps(counters[i] - 1)
+                }
+                None => true,
+            })
+            .flat_map(|p| p.0.to_vec())
+            .collect();
+        ensure!(
+            !pixels.is_empty(),
+            "All pixels were excluded by the weights"
+        );
+        Ok(quantize_pixels(&pixels, sample_factor, number_of_colors))
+    }
+
+    pub fn generate_region_palette(
+        data: &RgbImage,
+        region: (u32, u32, u32, u32),
+        sample_factor: i32,
+        number_of_colors: usize,
+    ) -> Result<Palette> {
+        let (x, y, width, height) = region;
+        ensure!(
+            width > 0 && height > 0,
+            "Region {}x{} is empty",
+            width,
+            height
+        );
+        ensure!(
+            x.saturating_add(width) <= data.width() && y.saturating_add(height) <= data.height(),
+            "Region {}x{}+{}+{} is out of the image bounds {}x{}",
+            width,
+            height,
+            x,
+            y,
+            data.width(),
+            data.height()
+        );
+        let pixels: Vec<u8> = data
+            .view(x, y, width, height)
+            .pixels()
+            .flat_map(|(_, _, p)| p.0.to_vec())
+            .collect();
+        Ok(quantize_pixels(&pixels, sample_factor, number_of_colors))
+    }
+
+    pub fn generate_masked_palette(
+        data: &RgbImage,
+        mask: &GrayImage,
+        sample_factor: i32,
+        number_of_colors: usize,
+    ) -> Result<Palette> {
+        ensure!(
+            data.dimensions() == mask.dimensions(),
+            "Mask dimensions {:?} do not match image dimensions {:?}",
+            mask.dimensions(),
+            data.dimensions()
+        );
+        let pixels: Vec<u8> = data
+            .pixels()
+            .zip(mask.pixels())
+            .filter(|(_, m)| m[0] > 0)
+            .flat_map(|(p, _)| p.0.to_vec())
+            .collect();
+        ensure!(!pixels.is_empty(), "Mask does not select any pixels");
+        Ok(quantize_pixels(&pixels, sample_factor, number_of_colors))
+    }
+
+    pub fn proxy_size(width: u32, height: u32, max_pixels: u32) -> Option<(u32, u32)> {
+        let pixels = width as u64 * height as u64;
+        if max_pixels == 0 || pixels <= max_pixels as u64 {
+            return None;
+        }
+        let ratio = (max_pixels as f64 / pixels as f64).sqrt();
+        Some((
+            ((width as f64 * ratio) as u32).max(1),
+            ((height as f64 * ratio) as u32).max(1),
+        ))
+    }
+
+    pub fn snap_size(width: u32, height: u32, multiple: u32) -> (u32, u32) {
+        let snap = |value: u32| {
+            if multiple <= 1 {
+                value
+            } else {
+                ((value + multiple / 2) / multiple).max(1) * multiple
+            }
+        };
+        (snap(width), snap(height))
+    }
+
+    pub fn local_contrast(data: &RgbImage) -> GrayImage {
+        let (width, height) = data.dimensions();
+        GrayImage::from_fn(width, height, |x, y| {
+            let center = luminance(data.get_pixel(x, y));
+            let neighbours = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            let contrast = neighbours
+                .iter()
+                .filter(|(nx, ny)| *nx < width && *ny < height)
+                .map(|(nx, ny)| (luminance(data.get_pixel(*nx, *ny)) - center).abs())
+                .fold(0.0, f64::max);
+            Luma([contrast.round() as u8])
+        })
+    }
+
+    pub fn generate_contrast_palette(
+        data: &RgbImage,
+        sample_factor: i32,
+        number_of_colors: usize,
+        strength: f32,
+    ) -> Palette {
+        let contrast = local_contrast(data);
+        let pixels: Vec<u8> = data
+            .pixels()
+            .zip(contrast.pixels())
+            .flat_map(|(p, c)| {
+                let extra =
+                    (strength.max(0.0) * MAX_CONTRAST_REPEATS * c[0] as f32 / 255.0).round();
+                std::iter::repeat_n(p.0, 1 + extra as usize).flatten()
+            })
+            .collect();
+        quantize_pixels(&pixels, sample_factor, number_of_colors)
+    }
+
+    pub fn auto_color_count(
+        data: &RgbImage,
+        sample_factor: i32,
+        max_colors: usize,
+        max_delta_e: f64,
+    ) -> usize {
+        let meets = |n: usize| {
+            let palette = generate_image_palette(data, sample_factor, n);
+            mean_delta_e(data, &palette) <= max_delta_e
+        };
+        let max_colors = max_colors.max(2);
+        let mut low = 1;
+        let mut high = 2;
+        while high < max_colors && !meets(high) {
+            low = high;
+            high = (high * 2).min(max_colors);
+        }
+        while high - low > 1 {
+            let mid = (low + high) / 2;
+            if meets(mid) {
+                high = mid;
+            } else {
+                low = mid;
+            }
+        }
+        high
+    }
+
+    const MAX_CONTRAST_REPEATS: f32 = 8.0;
+
+    fn quantize_pixels(pixels: &[u8], sample_factor: i32, number_of_colors: usize) -> Palette {
+        let quantizer = NeuQuant::new(sample_factor, number_of_colors, pixels);
+        let color_map = quantizer.color_map_rgb();
+        color_map
+            .chunks(3)
+            .map(|c| Rgb([c[0], c[1], c[2]]))
+            .collect()
+    }
+
+    pub fn colors_within(a: &Rgb<u8>, b: &Rgb<u8>, tolerance: u8) -> bool {
+        a.0.iter()
+            .zip(b.0.iter())
+            .all(|(a, b)| a.abs_diff(*b) <= tolerance)
+    }
+
+    pub fn chroma_key(data: &RgbImage, key: Rgb<u8>, tolerance: u8) -> GrayImage {
+        GrayImage::from_fn(data.width(), data.height(), |x, y| {
+            if colors_within(data.get_pixel(x, y), &key, tolerance) {
+                Luma([0])
+            } else {
+                Luma([255])
+            }
+        })
+    }
+
+    pub fn content_bounds(
+        data: &RgbImage,
+        alpha: Option<&GrayImage>,
+        background: Option<Rgb<u8>>,
+        tolerance: u8,
+    ) -> Option<(u32, u32, u32, u32)> {
+        // an empty image has no corner pixel to take the background from, and no content
+        if data.width() == 0 || data.height() == 0 {
+            return None;
+        }
+        let background = match (background, alpha) {
+            (None, None) => Some(*data.get_pixel(0, 0)),
+            _ => background,
+        };
+        let mut bounds: Option<(u32, u32, u32, u32)> = None;
+        for (x, y, pixel) in data.enumerate_pixels() {
+            let transparent = alpha.is_some_and(|alpha| alpha.get_pixel(x, y)[0] == 0);
+            let matches = background.is_some_and(|bg| colors_within(pixel, &bg, tolerance));
+            if transparent || matches {
+                continue;
+            }
+            bounds = Some(match bounds {
+                Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+                None => (x, y, x, y),
+            });
+        }
+        bounds.map(|(x0, y0, x1, y1)| (x0, y0, x1 + 1 - x0, y1 + 1 - y0))
+    }
+
+    pub fn merge_alpha(data: &RgbImage, alpha: &GrayImage) -> RgbaImage {
+        RgbaImage::from_fn(data.width(), data.height(), |x, y| {
+            let [r, g, b] = data.get_pixel(x, y).0;
+            Rgba([r, g, b, alpha.get_pixel(x, y)[0]])
+        })
+    }
+
+    pub fn blend(foreground: &Rgb<u8>, background: &Rgb<u8>, alpha: u8) -> Rgb<u8> {
+        let a = alpha as u32;
+        Rgb([0, 1, 2].map(|c| {
+            ((foreground[c] as u32 * a + background[c] as u32 * (255 - a) + 127) / 255) as u8
+        }))
+    }
+
+    pub fn flatten(data: &RgbImage, alpha: &GrayImage, background: Rgb<u8>) -> RgbImage {
+        let mut result = data.clone();
+        result.par_enumerate_pixels_mut().for_each(|(x, y, p)| {
+            *p = blend(p, &background, alpha.get_pixel(x, y)[0]);
+        });
+        result
+    }
+
+    pub fn apply_palette(img: &RgbImage, palette: &Palette) -> RgbImage {
+        apply_palette_with(img, palette, Traversal::default())
+    }
+
+    pub fn apply_palette_with(img: &RgbImage, palette: &Palette, traversal: Traversal) -> RgbImage {
+        map_to_palette(img, palette, traversal, |_, _| true)
+    }
+
+    pub fn stabilize_frame(
+        source: &RgbImage,
+        mapped: &RgbImage,
+        previous: &RgbImage,
+        hysteresis: f32,
+    ) -> RgbImage {
+        if mapped.dimensions() != previous.dimensions() {
+            return mapped.clone();
+        }
+        let distance = |a: &Rgb<u8>, b: &Rgb<u8>| {
+            (0..3)
+                .map(|c| (a[c] as f32 - b[c] as f32).powi(2))
+                .sum::<f32>()
+                .sqrt()
+        };
+        let mut result = mapped.clone();
+        result.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+            let (wanted, kept) = (source.get_pixel(x, y), previous.get_pixel(x, y));
+            if distance(wanted, kept) <= distance(wanted, pixel) + hysteresis {
+                *pixel = *kept;
+            }
+        });
+        result
+    }
+
+    pub fn apply_palette_indexed(
+        img: &RgbImage,
+        palette: &Palette,
+    ) -> Result<(RgbImage, Vec<u16>)> {
+        // bit depth reduction alone can leave more colors than u16 indices can address
+        ensure!(
+            !palette.is_empty() && palette.len() <= u16::MAX as usize + 1,
+            "Indexed results need 1 to 65536 colors, palette has {}",
+            palette.len()
+        );
+        let indices = palette_indices(img, palette);
+        let (width, height) = img.dimensions();
+        let mut result = RgbImage::new(width, height);
+        result
+            .pixels_mut()
+            .zip(indices.iter())
+            .for_each(|(p, i)| *p = palette[*i]);
+        Ok((result, indices.into_iter().map(|i| i as u16).collect()))
+    }
+
+    pub fn apply_palette_masked(
+        img: &RgbImage,
+        palette: &Palette,
+        mask: &GrayImage,
+    ) -> Result<RgbImage> {
+        ensure!(
+            img.dimensions() == mask.dimensions(),
+            "Mask dimensions {:?} do not match image dimensions {:?}",
+            mask.dimensions(),
+            img.dimensions()
+        );
+        Ok(map_to_palette(
+            img,
+            palette,
+            Traversal::default(),
+            |x, y| mask.get_pixel(x, y)[0] > 0,
+        ))
+    }
+
+    // palettes up to this size are faster to scan than to query a tree for, see `pixelize bench`
+    pub const LINEAR_SCAN_MAX_COLORS: usize = 16;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum MatchStrategy {
+        Linear,
+        Tree,
+    }
+
+    impl MatchStrategy {
+        pub fn for_palette(palette: &Palette) -> Self {
+            if !cfg!(feature = "kdtree") || palette.len() <= LINEAR_SCAN_MAX_COLORS {
+                MatchStrategy::Linear
+            } else {
+                MatchStrategy::Tree
+            }
+        }
+    }
+
+    fn squared_distance(a: &Rgb<u8>, b: &Rgb<u8>) -> u32 {
+        let dr = a[0] as i32 - b[0] as i32;
+        let dg = a[1] as i32 - b[1] as i32;
+        let db = a[2] as i32 - b[2] as i32;
+        (dr * dr + dg * dg + db * db) as u32
+    }
+
+    // without the kdtree feature there is no tree and every lookup is a linear scan
+    #[cfg(feature = "kdtree")]
+    type Tree = KdTree<f64, 3>;
+    #[cfg(not(feature = "kdtree"))]
+    type Tree = std::convert::Infallible;
+
+    #[cfg(feature = "kdtree")]
+    fn rgb_to_point(rgb: &Rgb<u8>) -> [f64; 3] {
+        [rgb[0] as f64, rgb[1] as f64, rgb[2] as f64]
+    }
+
+    #[cfg(feature = "kdtree")]
+    fn build_tree(palette: &Palette) -> Option<Tree> {
+        let mut tree: Tree = KdTree::new();
+        for (i, color) in palette.iter().enumerate() {
+            tree.add(&rgb_to_point(color), i as u64);
+        }
+        Some(tree)
+    }
+
+    #[cfg(not(feature = "kdtree"))]
+    fn build_tree(_: &Palette) -> Option<Tree> {
+        None
+    }
+
+    #[cfg(feature = "kdtree")]
+    fn tree_nearest_two(tree: &Tree, color: &Rgb<u8>) -> (usize, usize) {
+        let mut nearest = tree.nearest_n::<SquaredEuclidean>(&rgb_to_point(color), 2);
+        nearest.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        let first = nearest[0].item as usize;
+        let second = nearest.get(1).map_or(first, |n| n.item as usize);
+        (first, second)
+    }
+
+    #[cfg(feature = "kdtree")]
+    fn tree_nearest(tree: &Tree, color: &Rgb<u8>) -> usize {
+        tree.nearest_one::<SquaredEuclidean>(&rgb_to_point(color))
+            .item as usize
     }
 
-    pub fn width(&self) -> u32 {
-        self.data.width()
+    #[cfg(not(feature = "kdtree"))]
+    fn tree_nearest_two(tree: &Tree, _: &Rgb<u8>) -> (usize, usize) {
+        match *tree {}
     }
 
-    pub fn height(&self) -> u32 {
-        self.data.height()
+    #[cfg(not(feature = "kdtree"))]
+    fn tree_nearest(tree: &Tree, _: &Rgb<u8>) -> usize {
+        match *tree {}
     }
-}
 
-pub mod image_processing {
-    use crate::{Palette, RgbHistogram};
-    use anyhow::{Context, Result};
-    use color_quant::NeuQuant;
-    use image::imageops::FilterType;
-    use image::{Rgb, RgbImage};
-    use kiddo::{KdTree, SquaredEuclidean};
-    use rayon::prelude::*;
-    use std::collections::HashMap;
-    use std::path::Path;
+    pub struct PaletteMatcher {
+        tree: Option<Tree>,
+        palette: Palette,
+    }
 
-    type Point = [f64; 3];
+    impl PaletteMatcher {
+        pub fn new(palette: &Palette) -> Self {
+            Self::with_strategy(palette, MatchStrategy::for_palette(palette))
+        }
 
-    pub fn get_color_histogram(data: &RgbImage) -> RgbHistogram {
-        data.pixels()
-            .par_bridge()
-            .fold(HashMap::new, |mut local_map, pixel| {
-                *local_map.entry(*pixel).or_insert(0) += 1;
-                local_map
-            })
-            .reduce(HashMap::new, |mut map1, map2| {
-                for (k, v) in map2 {
-                    *map1.entry(k).or_insert(0) += v;
+        pub fn with_strategy(palette: &Palette, strategy: MatchStrategy) -> Self {
+            let tree = match strategy {
+                MatchStrategy::Tree => build_tree(palette),
+                MatchStrategy::Linear => None,
+            };
+            PaletteMatcher {
+                tree,
+                palette: palette.clone(),
+            }
+        }
+
+        pub fn strategy(&self) -> MatchStrategy {
+            match self.tree {
+                Some(_) => MatchStrategy::Tree,
+                None => MatchStrategy::Linear,
+            }
+        }
+
+        pub fn nearest_index(&self, color: &Rgb<u8>) -> usize {
+            match &self.tree {
+                Some(tree) => tree_nearest(tree, color),
+                None => {
+                    let mut best = (0, u32::MAX);
+                    for (i, entry) in self.palette.iter().enumerate() {
+                        let distance = squared_distance(color, entry);
+                        if distance < best.1 {
+                            best = (i, distance);
+                        }
+                    }
+                    best.0
                 }
-                map1
-            })
+            }
+        }
+
+        pub fn nearest(&self, color: &Rgb<u8>) -> Rgb<u8> {
+            self.palette[self.nearest_index(color)]
+        }
+
+        pub fn palette(&self) -> &Palette {
+            &self.palette
+        }
+
+        pub fn nearest_two(&self, color: &Rgb<u8>) -> (usize, usize) {
+            let Some(tree) = &self.tree else {
+                let (mut first, mut second) = ((0, u32::MAX), (0, u32::MAX));
+                for (i, entry) in self.palette.iter().enumerate() {
+                    let distance = squared_distance(color, entry);
+                    if distance < first.1 {
+                        second = first;
+                        first = (i, distance);
+                    } else if distance < second.1 {
+                        second = (i, distance);
+                    }
+                }
+                return (first.0, second.0);
+            };
+            tree_nearest_two(tree, color)
+        }
     }
 
-    pub fn generate_image_palette(
-        data: &RgbImage,
-        sample_factor: i32,
-        number_of_colors: usize,
-    ) -> Palette {
-        let pixels: Vec<u8> = data.pixels().flat_map(|p| p.0.to_vec()).collect();
-        let quantizer = NeuQuant::new(sample_factor, number_of_colors, &pixels);
-        let color_map = quantizer.color_map_rgb();
-        color_map
-            .chunks(3)
-            .map(|c| Rgb([c[0], c[1], c[2]]))
+    pub fn palette_indices(img: &RgbImage, palette: &Palette) -> Vec<usize> {
+        palette_indices_with(img, &PaletteMatcher::new(palette))
+    }
+
+    pub fn palette_indices_with(img: &RgbImage, matcher: &PaletteMatcher) -> Vec<usize> {
+        img.par_pixels()
+            .map(|pixel| matcher.nearest_index(pixel))
             .collect()
     }
 
-    fn rgb_to_point(rgb: &Rgb<u8>) -> Point {
-        [rgb[0] as f64, rgb[1] as f64, rgb[2] as f64]
+    pub fn palette_usage(img: &RgbImage, palette: &Palette) -> Vec<u32> {
+        let mut usage = vec![0; palette.len()];
+        palette_indices(img, palette)
+            .into_iter()
+            .for_each(|i| usage[i] += 1);
+        usage
     }
 
-    pub fn apply_palette(img: &RgbImage, palette: &Palette) -> RgbImage {
-        let mut tree: KdTree<f64, 3> = KdTree::new();
-        let mut color_map = HashMap::new();
-        for (i, color) in palette.iter().enumerate() {
-            let item = i as u64;
-            tree.add(&rgb_to_point(color), item);
-            color_map.insert(item, *color);
-        }
-        let (width, height) = img.dimensions();
-        let processed_pixels: Vec<(u32, u32, Rgb<u8>)> = img
-            .enumerate_pixels()
-            .par_bridge()
-            .map(|(x, y, pixel)| {
-                let point = rgb_to_point(pixel);
-                let nearest = tree.nearest_one::<SquaredEuclidean>(&point);
-                let nearest_color = color_map[&nearest.item];
-                (x, y, nearest_color)
-            })
-            .collect();
-        let mut new_img = RgbImage::new(width, height);
-        for (x, y, color) in processed_pixels {
-            new_img.put_pixel(x, y, color);
-        }
-        new_img
+    fn map_to_palette<F>(
+        img: &RgbImage,
+        palette: &Palette,
+        traversal: Traversal,
+        selected: F,
+    ) -> RgbImage
+    where
+        F: Fn(u32, u32) -> bool + Sync,
+    {
+        let matcher = PaletteMatcher::new(palette);
+        map_image(img, traversal, |x, y, pixel| match selected(x, y) {
+            true => matcher.nearest(&pixel),
+            false => pixel,
+        })
     }
 
     pub fn scale(img: &RgbImage, new_width: u32, new_height: u32, filter: FilterType) -> RgbImage {
         image::imageops::resize(img, new_width, new_height, filter)
     }
 
+    pub fn scale_linear(
+        img: &RgbImage,
+        new_width: u32,
+        new_height: u32,
+        filter: FilterType,
+    ) -> RgbImage {
+        let linear = Rgb32FImage::from_fn(img.width(), img.height(), |x, y| {
+            Rgb(img.get_pixel(x, y).0.map(srgb_to_linear))
+        });
+        let resized = image::imageops::resize(&linear, new_width, new_height, filter);
+        RgbImage::from_fn(new_width, new_height, |x, y| {
+            Rgb(resized.get_pixel(x, y).0.map(linear_to_srgb))
+        })
+    }
+
+    #[cfg(feature = "io")]
     pub fn save_palette<P>(path: P, palette: &Palette) -> Result<()>
     where
         P: AsRef<Path>,
@@ -185,32 +1506,236 @@ pub mod image_processing {
         Ok(())
     }
 
+    #[cfg(feature = "io")]
+    pub fn open_image<P>(path: P) -> Result<DynamicImage>
+    where
+        P: AsRef<Path>,
+    {
+        let reader = ImageReader::open(path.as_ref())
+            .with_context(|| format!("Failed to open: {:?}", path.as_ref()))?
+            .with_guessed_format()
+            .with_context(|| format!("Failed to open: {:?}", path.as_ref()))?;
+        let detected = reader.format();
+        if detected.is_none() {
+            let bytes = fs::read(path.as_ref())
+                .with_context(|| format!("Failed to open: {:?}", path.as_ref()))?;
+            if pcx::is_pcx(&bytes) {
+                return Ok(DynamicImage::ImageRgb8(pcx::decode(&bytes)?.to_rgb()));
+            }
+        }
+        let expected = ImageFormat::from_path(path.as_ref()).ok();
+        reader
+            .decode()
+            .with_context(|| decode_error(detected, expected))
+    }
+
+    pub fn decode_image(bytes: &[u8]) -> Result<DynamicImage> {
+        let reader = ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .with_context(|| "Failed to read the image data")?;
+        let detected = reader.format();
+        if detected.is_none() && pcx::is_pcx(bytes) {
+            return Ok(DynamicImage::ImageRgb8(pcx::decode(bytes)?.to_rgb()));
+        }
+        reader
+            .decode()
+            .with_context(|| decode_error(detected, None))
+    }
+
+    fn decode_error(detected: Option<ImageFormat>, expected: Option<ImageFormat>) -> String {
+        match (detected, expected) {
+            (None, _) => {
+                "Failed to decode the file, the image format was not recognized".to_string()
+            }
+            (Some(ImageFormat::Avif), _) if !cfg!(feature = "avif-decode") => {
+                "Failed to decode the file, reading AVIF requires building with the avif-decode feature"
+                    .to_string()
+            }
+            (Some(detected), Some(expected)) if detected != expected => format!(
+                "Failed to decode the file, detected {:?} format but the extension suggests {:?}",
+                detected, expected
+            ),
+            (Some(detected), _) => format!("Failed to decode the file as {:?}", detected),
+        }
+    }
+
+    #[cfg(feature = "io")]
+    pub fn load_palette<P>(path: P) -> Result<Palette>
+    where
+        P: AsRef<Path>,
+    {
+        let img = open_image(path)?.to_rgb8();
+        let mut palette = Palette::new();
+        img.pixels().for_each(|p| {
+            if !palette.contains(p) {
+                palette.push(*p);
+            }
+        });
+        Ok(palette)
+    }
+
+    #[cfg(feature = "io")]
     pub fn save_image<P>(path: P, data: &RgbImage) -> Result<()>
     where
         P: AsRef<Path>,
     {
+        ensure_encoder(path.as_ref())?;
         data.save(path)?;
         Ok(())
     }
 
+    pub fn ensure_encoder(path: &Path) -> Result<()> {
+        if let Ok(ImageFormat::Avif) = ImageFormat::from_path(path) {
+            ensure!(
+                cfg!(feature = "avif"),
+                "Saving AVIF requires building with the avif feature: {:?}",
+                path
+            );
+        }
+        Ok(())
+    }
+
     pub fn palette_from_tuples(tuples: &[(u8, u8, u8)]) -> Palette {
-        tuples
-            .iter()
-            .copied()
-            .map(|(r, g, b)| Rgb([r, g, b]))
-            .collect()
+        tuples.iter().copied().map(rgb_from_tuple).collect()
+    }
+
+    pub fn luminance(color: &Rgb<u8>) -> f64 {
+        0.299 * color[0] as f64 + 0.587 * color[1] as f64 + 0.114 * color[2] as f64
+    }
+
+    pub fn srgb_to_linear(value: u8) -> f32 {
+        let v = value as f32 / 255.0;
+        if v <= 0.04045 {
+            v / 12.92
+        } else {
+            ((v + 0.055) / 1.055).powf(2.4)
+        }
+    }
+
+    pub fn linear_to_srgb(value: f32) -> u8 {
+        let v = value.clamp(0.0, 1.0);
+        let v = if v <= 0.0031308 {
+            v * 12.92
+        } else {
+            1.055 * v.powf(1.0 / 2.4) - 0.055
+        };
+        (v * 255.0).round() as u8
+    }
+
+    pub fn rgb_to_lab(color: &Rgb<u8>) -> [f64; 3] {
+        let [r, g, b] = color.0.map(|c| srgb_to_linear(c) as f64);
+        let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+        let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+        let f = |t: f64| {
+            if t > 0.008856 {
+                t.cbrt()
+            } else {
+                7.787 * t + 16.0 / 116.0
+            }
+        };
+        let (fx, fy, fz) = (f(x), f(y), f(z));
+        [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+    }
+
+    pub fn lab_to_rgb([l, a, b]: [f64; 3]) -> Rgb<u8> {
+        let fy = (l + 16.0) / 116.0;
+        let (fx, fz) = (fy + a / 500.0, fy - b / 200.0);
+        let f_inv = |t: f64| {
+            if t.powi(3) > 0.008856 {
+                t.powi(3)
+            } else {
+                (t - 16.0 / 116.0) / 7.787
+            }
+        };
+        let (x, y, z) = (f_inv(fx) * 0.95047, f_inv(fy), f_inv(fz) * 1.08883);
+        let r = 3.2406 * x - 1.5372 * y - 0.4986 * z;
+        let g = -0.9689 * x + 1.8758 * y + 0.0415 * z;
+        let b = 0.0557 * x - 0.2040 * y + 1.0570 * z;
+        Rgb([r, g, b].map(|c| linear_to_srgb(c as f32)))
+    }
+
+    pub fn rgb_to_oklab(color: &Rgb<u8>) -> [f64; 3] {
+        let [r, g, b] = color.0.map(|c| srgb_to_linear(c) as f64);
+        let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+        let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+        let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+        [
+            0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+            1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+            0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+        ]
+    }
+
+    // linear sRGB without clamping, so callers can tell whether a color is in gamut
+    pub fn oklab_to_linear([l, a, b]: [f64; 3]) -> [f64; 3] {
+        let l_ = (l + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+        let m_ = (l - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+        let s_ = (l - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+        [
+            4.0767416621 * l_ - 3.3077115913 * m_ + 0.2309699292 * s_,
+            -1.2684380046 * l_ + 2.6097574011 * m_ - 0.3413193965 * s_,
+            -0.0041960863 * l_ - 0.7034186147 * m_ + 1.7076147010 * s_,
+        ]
+    }
+
+    pub fn oklab_to_rgb(oklab: [f64; 3]) -> Rgb<u8> {
+        Rgb(oklab_to_linear(oklab).map(|c| linear_to_srgb(c as f32)))
+    }
+
+    pub fn delta_e(a: &Rgb<u8>, b: &Rgb<u8>) -> f64 {
+        let (a, b) = (rgb_to_lab(a), rgb_to_lab(b));
+        a.iter()
+            .zip(b.iter())
+            .map(|(x, y)| (x - y).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    pub fn mean_delta_e(img: &RgbImage, palette: &Palette) -> f64 {
+        let indices = palette_indices(img, palette);
+        let total = img
+            .par_pixels()
+            .zip(indices.par_iter())
+            .map(|(pixel, &i)| delta_e(pixel, &palette[i]))
+            .sum::<f64>();
+        total / indices.len().max(1) as f64
+    }
+
+    pub fn rgb_to_hex(color: &Rgb<u8>) -> String {
+        format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+    }
+
+    pub fn rgb_from_tuple((r, g, b): (u8, u8, u8)) -> Rgb<u8> {
+        Rgb([r, g, b])
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::ProcessedImage;
-    use crate::image_processing::save_palette;
-    use image::Rgb;
+    use crate::dither::Dither;
+    use crate::image_processing::{
+        MatchStrategy, PaletteMatcher, content_bounds, delta_e, generate_shared_palette,
+        generate_shared_weighted_palette, mean_delta_e, proxy_size, snap_size, stabilize_frame,
+    };
+    #[cfg(feature = "io")]
+    use crate::image_processing::{load_palette, save_palette};
+    #[cfg(feature = "parallel")]
+    use crate::with_thread_count;
+    use crate::{
+        Anchor, ColorWeight, Palette, PixelizeOptions, PowerOfTwo, ProcessedImage, ScaleFilter,
+        ScaleMode,
+    };
+    #[cfg(feature = "io")]
+    use image::ImageFormat;
+    use image::{GenericImageView, GrayImage, Luma, Rgb, RgbImage, Rgba};
+    use std::borrow::Cow;
     use std::collections::HashMap;
     use std::fs;
+    #[cfg(feature = "io")]
     use std::path::Path;
 
+    #[cfg(feature = "io")]
     #[allow(dead_code)]
     fn generate_img_code<P>(path: P, output: P)
     where
@@ -350,18 +1875,187 @@ mod tests {
         assert_eq!(histogram, expected);
     }
 
+    #[test]
+    fn test_bucketed_histogram() {
+        let image = ProcessedImage::from_buffer(
+            4,
+            1,
+            &[
+                Rgb([0, 0, 0]),
+                Rgb([7, 7, 7]),
+                Rgb([8, 0, 255]),
+                Rgb([255, 255, 255]),
+            ],
+        );
+        let histogram = image.get_bucketed_histogram([5, 5, 5]).unwrap();
+        let expected = HashMap::from([
+            (Rgb([4, 4, 4]), 2),
+            (Rgb([12, 4, 252]), 1),
+            (Rgb([252, 252, 252]), 1),
+        ]);
+        assert_eq!(histogram, expected);
+        assert_eq!(image.get_bucketed_histogram([8, 8, 8]).unwrap().len(), 4);
+        assert!(image.get_bucketed_histogram([0, 5, 9]).is_err());
+    }
+
+    #[test]
+    fn test_map_pixels() {
+        let original = get_test_image();
+        let mut image = original.clone();
+        let mut parallel = original.clone();
+        let invert = |x: u32, y: u32, p: Rgb<u8>| {
+            if (x + y).is_multiple_of(2) {
+                Rgb(p.0.map(|c| 255 - c))
+            } else {
+                p
+            }
+        };
+        image.map_pixels(invert);
+        parallel.par_map_pixels(invert);
+        assert_eq!(image.data, parallel.data);
+        let [r, g, b] = original.data.get_pixel(0, 0).0;
+        assert_eq!(
+            *image.data.get_pixel(0, 0),
+            Rgb([255 - r, 255 - g, 255 - b])
+        );
+        assert_eq!(image.data.get_pixel(1, 0), original.data.get_pixel(1, 0));
+    }
+
+    #[test]
+    fn test_rows_and_view() {
+        let image = get_test_image();
+        assert_eq!(image.rows().len(), 10);
+        let row = image.rows().nth(3).unwrap();
+        assert_eq!(row.len(), 30);
+        assert_eq!(&row[6..9], &image.data.get_pixel(2, 3).0);
+
+        let view = image.view(2, 3, 4, 5).unwrap();
+        assert_eq!(view.dimensions(), (4, 5));
+        assert_eq!(view.get_pixel(1, 1), *image.data.get_pixel(3, 4));
+        assert!(image.view(8, 0, 3, 1).is_err());
+    }
+
+    #[test]
+    fn test_conversions() {
+        let data = RgbImage::from_pixel(3, 2, Rgb([10, 20, 30]));
+        let image = ProcessedImage::from(data.clone());
+        assert!(image.alpha.is_none());
+        assert_eq!(image.clone(), image);
+        assert_eq!(image.into_inner(), data);
+
+        let mut rgba = image::RgbaImage::from_pixel(3, 2, Rgba([10, 20, 30, 255]));
+        rgba.put_pixel(1, 1, Rgba([0, 0, 0, 0]));
+        let image = ProcessedImage::from(image::DynamicImage::ImageRgba8(rgba.clone()));
+        assert_eq!(image.alpha.as_ref().unwrap().get_pixel(1, 1)[0], 0);
+        assert_eq!(image::DynamicImage::from(image.clone()).to_rgba8(), rgba);
+
+        rgba.put_pixel(2, 1, Rgba([200, 100, 0, 128]));
+        let mut flat = ProcessedImage::from(image::DynamicImage::ImageRgba8(rgba));
+        flat.flatten(Rgb([255, 255, 255]));
+        assert!(flat.alpha.is_none());
+        assert_eq!(*flat.data.get_pixel(0, 0), Rgb([10, 20, 30]));
+        assert_eq!(*flat.data.get_pixel(1, 1), Rgb([255, 255, 255]));
+        assert_eq!(*flat.data.get_pixel(2, 1), Rgb([227, 177, 127]));
+
+        let bytes = fs::read("assets/converted4.png").unwrap();
+        let image = ProcessedImage::try_from(bytes.as_slice()).unwrap();
+        assert_eq!((image.width(), image.height()), (306, 204));
+        assert!(ProcessedImage::try_from(&bytes[..16]).is_err());
+    }
+
     #[test]
     fn test_scaling() {
         let mut image = get_test_image();
-        image.scale(100, 100, true);
+        image.scale(100, 100, ScaleFilter::Lanczos3);
         assert_eq!(image.width(), 100);
         assert_eq!(image.height(), 100);
-        image.uniform_scale_width(50, true);
+        image.uniform_scale_width(50, ScaleFilter::Lanczos3);
         assert_eq!(image.width(), 50);
         assert_eq!(image.height(), 50);
-        image.uniform_scale_height(70, true);
+        image.uniform_scale_height(70, ScaleFilter::Lanczos3);
         assert_eq!(image.width(), 70);
         assert_eq!(image.height(), 70);
+        image.scale(35, 35, ScaleFilter::CatmullRom);
+        assert_eq!(image.width(), 35);
+        assert_eq!(
+            "catmull-rom".parse::<ScaleFilter>().unwrap(),
+            ScaleFilter::CatmullRom
+        );
+        assert!("cubic-spline".parse::<ScaleFilter>().is_err());
+    }
+
+    #[test]
+    fn test_contrast_palette() {
+        let buffer = (0..400)
+            .map(|i| match i {
+                210 | 230 => Rgb([250u8, 250, 250]),
+                i if i % 2 == 0 => Rgb([90, 90, 90]),
+                _ => Rgb([110, 110, 110]),
+            })
+            .collect::<Vec<_>>();
+        let image = ProcessedImage::from_buffer(20, 20, &buffer);
+        let brightest = |palette: &Palette| palette.iter().map(|c| c[0]).max().unwrap();
+        let plain = image.generate_image_palette(1, 4);
+        let weighted = image.generate_contrast_palette(1, 4, 1.0);
+        assert!(brightest(&weighted) > brightest(&plain));
+        assert_eq!(
+            image.generate_contrast_palette(1, 4, 0.0),
+            image.generate_image_palette(1, 4)
+        );
+    }
+
+    #[test]
+    fn test_auto_color_count() {
+        let colors = [
+            Rgb([20u8, 20, 20]),
+            Rgb([120, 120, 120]),
+            Rgb([220, 220, 220]),
+        ];
+        let buffer = (0..300).map(|i| colors[i % 3]).collect::<Vec<_>>();
+        let image = ProcessedImage::from_buffer(30, 10, &buffer);
+        let count = image.auto_color_count(1, 64, 5.0);
+        assert!(count < 64);
+        let palette = image.generate_image_palette(1, count);
+        assert!(mean_delta_e(&image.data, &palette) <= 5.0);
+        assert_eq!(image.auto_color_count(1, 16, 0.0), 16);
+        assert_eq!(image.auto_color_count(1, 2, 1.0), 2);
+        assert!(delta_e(&colors[0], &colors[0]) < 1e-9);
+        assert!(delta_e(&Rgb([0, 0, 0]), &Rgb([255, 255, 255])) > 99.0);
+    }
+
+    #[test]
+    fn test_pixelize() {
+        let mut image = get_test_image();
+        let (width, height) = (image.width(), image.height());
+        let options = PixelizeOptions {
+            dither: Dither::FloydSteinberg.into(),
+            ..PixelizeOptions::new(width / 4, 4)
+        };
+        let palette = image.pixelize(&options);
+        assert_eq!(palette.len(), 4);
+        assert_eq!((image.width(), image.height()), (width, height));
+        assert!(image.data.pixels().all(|p| palette.contains(p)));
+        assert_eq!(image.data.get_pixel(0, 0), image.data.get_pixel(3, 3));
+    }
+
+    #[test]
+    fn test_linear_scaling() {
+        let stripes = (0..16)
+            .map(|x| {
+                if x % 2 == 0 {
+                    Rgb([0u8; 3])
+                } else {
+                    Rgb([255u8; 3])
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut srgb = ProcessedImage::from_buffer(16, 1, &stripes);
+        let mut linear = ProcessedImage::from_buffer(16, 1, &stripes);
+        srgb.scale(8, 1, ScaleFilter::Lanczos3);
+        linear.scale_linear(8, 1, ScaleFilter::Lanczos3);
+        let center = |image: &ProcessedImage| image.data.get_pixel(4, 0)[0];
+        assert!(center(&srgb).abs_diff(128) < 16);
+        assert!(center(&linear) > 170);
     }
 
     #[test]
@@ -397,26 +2091,421 @@ mod tests {
         assert_eq!(data, &expected);
     }
 
+    #[test]
+    fn test_apply_palette_indexed() {
+        let mut image = get_test_image();
+        let mut expected = get_test_image();
+        let palette = vec![Rgb([0u8, 0, 0]), Rgb([130, 10, 20]), Rgb([255, 255, 255])];
+        let indices = image.apply_palette_indexed(&palette).unwrap();
+        expected.apply_palette(&palette);
+        assert_eq!(image.data, expected.data);
+        assert_eq!(indices.len(), (image.width() * image.height()) as usize);
+        assert!(
+            image
+                .data
+                .pixels()
+                .zip(indices.iter())
+                .all(|(p, i)| *p == palette[*i as usize])
+        );
+        let huge = (0..=u16::MAX as u32 + 1)
+            .map(|i| Rgb([(i >> 16) as u8, (i >> 8) as u8, i as u8]))
+            .collect::<Vec<_>>();
+        assert!(image.apply_palette_indexed(&huge).is_err());
+    }
+
+    #[test]
+    fn test_match_strategies() {
+        let palette = get_test_image().generate_image_palette(1, 8);
+        let linear = PaletteMatcher::new(&palette);
+        let tree = PaletteMatcher::with_strategy(&palette, MatchStrategy::Tree);
+        assert_eq!(linear.strategy(), MatchStrategy::Linear);
+        let large = (0..64u8)
+            .map(|i| Rgb([i * 4, 255 - i * 4, i]))
+            .collect::<Palette>();
+        let expected = match cfg!(feature = "kdtree") {
+            true => MatchStrategy::Tree,
+            false => MatchStrategy::Linear,
+        };
+        assert_eq!(PaletteMatcher::new(&large).strategy(), expected);
+        let distance = |c: &Rgb<u8>, i: usize| {
+            (0..3)
+                .map(|k| (c[k] as i32 - palette[i][k] as i32).pow(2))
+                .sum::<i32>()
+        };
+        for r in (0..=255u8).step_by(15) {
+            for g in (0..=255u8).step_by(51) {
+                let color = Rgb([r, g, 255 - r]);
+                let (first, second) = linear.nearest_two(&color);
+                let (tree_first, tree_second) = tree.nearest_two(&color);
+                assert_eq!(first, linear.nearest_index(&color));
+                assert_eq!(distance(&color, first), distance(&color, tree_first));
+                assert_eq!(distance(&color, second), distance(&color, tree_second));
+            }
+        }
+    }
+
+    #[test]
+    fn test_snap_size() {
+        assert_eq!(snap_size(100, 67, 8), (104, 64));
+        assert_eq!(snap_size(3, 12, 8), (8, 16));
+        assert_eq!(snap_size(33, 17, 16), (32, 16));
+        assert_eq!(snap_size(33, 17, 0), (33, 17));
+    }
+
+    #[test]
+    fn test_palette_proxy() {
+        assert_eq!(proxy_size(8000, 5000, 1_000_000), Some((1264, 790)));
+        assert_eq!(proxy_size(1000, 1000, 1_000_000), None);
+        assert_eq!(proxy_size(8000, 5000, 0), None);
+        let image = get_test_image();
+        let max_pixels = image.width() * image.height() / 4;
+        let proxy = image.palette_proxy(max_pixels, ScaleFilter::Triangle);
+        assert!(proxy.width() * proxy.height() <= max_pixels);
+        assert!(matches!(
+            image.palette_proxy(u32::MAX, ScaleFilter::Triangle),
+            Cow::Borrowed(_)
+        ));
+        // nearest keeps exact colors, e.g. a background color to weight
+        let colors = [Rgb([255, 0, 255]), Rgb([0, 0, 0])];
+        let checker = ProcessedImage::from_buffer(8, 8, &[colors; 32].concat());
+        let proxy = checker.palette_proxy(16, ScaleFilter::Nearest);
+        assert!(proxy.data.pixels().all(|p| colors.contains(p)));
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn test_with_thread_count() {
+        assert_eq!(with_thread_count(2, rayon::current_num_threads).unwrap(), 2);
+        assert!(with_thread_count(0, || ()).is_err());
+    }
+
+    #[test]
+    fn test_stabilize_frame() {
+        let palette = [Rgb([0u8, 0, 0]), Rgb([100, 100, 100])];
+        let previous = RgbImage::from_pixel(2, 1, palette[0]);
+        let source = RgbImage::from_fn(2, 1, |x, _| Rgb([52 + 40 * x as u8; 3]));
+        let mapped = RgbImage::from_pixel(2, 1, palette[1]);
+        let stable = stabilize_frame(&source, &mapped, &previous, 10.0);
+        assert_eq!(stable.get_pixel(0, 0), &palette[0]);
+        assert_eq!(stable.get_pixel(1, 0), &palette[1]);
+        assert_eq!(stabilize_frame(&source, &mapped, &previous, 0.0), mapped);
+    }
+
+    #[test]
+    fn test_apply_palette_masked() {
+        let buffer = [
+            Rgb([0xFFu8, 0xFF, 0xFF]),
+            Rgb([0x88, 0x88, 0x88]),
+            Rgb([0x22, 0x22, 0x22]),
+            Rgb([0x10, 0x10, 0x10]),
+        ];
+        let mut image = ProcessedImage::from_buffer(2, 2, &buffer);
+        let palette = vec![Rgb([0u8, 0, 0]), Rgb([0x90, 0x90, 0x90])];
+        let mask = GrayImage::from_raw(2, 2, vec![255, 0, 0, 255]).unwrap();
+        image.apply_palette_masked(&palette, &mask).unwrap();
+        let expected = [
+            0x90u8, 0x90, 0x90, 0x88, 0x88, 0x88, 0x22, 0x22, 0x22, 0, 0, 0,
+        ]
+        .into_iter()
+        .collect::<Vec<_>>();
+        assert_eq!(image.data.as_raw(), &expected);
+        assert!(
+            image
+                .apply_palette_masked(&palette, &GrayImage::new(3, 2))
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "io")]
+    #[test]
+    fn test_load_palette() {
+        let palette = vec![Rgb([1u8, 2, 3]), Rgb([200, 100, 50]), Rgb([1, 2, 3])];
+        let path = std::env::temp_dir().join("pixelize_test_load_palette.png");
+        save_palette(&path, &palette).unwrap();
+        let loaded = load_palette(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, palette[..2]);
+    }
+
+    #[cfg(feature = "io")]
+    #[test]
+    fn test_format_detection() {
+        let image = get_test_image();
+        let path = std::env::temp_dir().join("pixelize_test_format_detection.jpg");
+        image
+            .data
+            .save_with_format(&path, ImageFormat::Png)
+            .unwrap();
+        let loaded = ProcessedImage::new(&path);
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap().data, image.data);
+        assert_eq!(ProcessedImage::from_bytes(&bytes).unwrap().data, image.data);
+
+        let mut broken = bytes[..64].to_vec();
+        broken.resize(128, 0);
+        let error = format!("{:#}", ProcessedImage::from_bytes(&broken).err().unwrap());
+        assert!(error.contains("Png"), "{}", error);
+        assert!(ProcessedImage::from_bytes(b"not an image").is_err());
+    }
+
+    #[cfg(feature = "io")]
+    #[test]
+    fn test_webp_lossless() {
+        let image = get_test_image();
+        let path = std::env::temp_dir().join("pixelize_test_webp_lossless.webp");
+        image.save(&path).unwrap();
+        let loaded = ProcessedImage::new(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap().data, image.data);
+    }
+
+    #[test]
+    fn test_shared_palette() {
+        let image = get_test_image();
+        let twice = image
+            .data
+            .pixels()
+            .chain(image.data.pixels())
+            .copied()
+            .collect::<Vec<_>>();
+        let expected = ProcessedImage::from_buffer(10, 20, &twice).generate_image_palette(10, 4);
+        let palette = generate_shared_palette(&[&image.data, &image.data], 10, 4);
+        assert_eq!(palette, expected);
+    }
+
+    #[test]
+    fn test_weighted_palette() {
+        let image = get_test_image();
+        let weights = [ColorWeight::exclude(Rgb([0, 0, 0]), 10)];
+        let palette = image.generate_weighted_palette(10, 4, &weights).unwrap();
+        let expected = image.generate_masked_palette(
+            &GrayImage::from_fn(10, 10, |x, y| {
+                Luma([if image.data.get_pixel(x, y) == &Rgb([0, 0, 0]) {
+                    0
+                } else {
+                    255
+                }])
+            }),
+            10,
+            4,
+        );
+        assert_eq!(palette, expected.unwrap());
+
+        let half = ColorWeight {
+            color: Rgb([0, 0, 0]),
+            tolerance: 0,
+            weight: 0.5,
+        };
+        assert_eq!((0..14).filter(|n| half.keeps(*n)).count(), 7);
+        assert!(half.matches(&Rgb([0, 0, 0])));
+        assert!(!half.matches(&Rgb([1, 0, 0])));
+
+        let everything = [ColorWeight::exclude(Rgb([128, 128, 128]), 255)];
+        assert!(image.generate_weighted_palette(10, 4, &everything).is_err());
+
+        let shared =
+            generate_shared_weighted_palette(&[&image.data, &image.data], 10, 4, &weights).unwrap();
+        assert!(!shared.contains(&Rgb([0, 0, 0])));
+    }
+
+    #[test]
+    fn test_chroma_key() {
+        let mut image = get_test_image();
+        image.chroma_key(Rgb([5, 0, 3]), 5);
+        let alpha = image.alpha.as_ref().unwrap();
+        assert_eq!(alpha.pixels().filter(|a| a[0] == 0).count(), 14);
+        image.chroma_key(Rgb([136, 0, 21]), 0);
+        let rgba = image.to_rgba();
+        assert_eq!(rgba.pixels().filter(|p| p[3] == 0).count(), 44);
+        assert_eq!(rgba.get_pixel(9, 9), &Rgba([63, 72, 204, 255]));
+        image.scale(20, 20, ScaleFilter::Nearest);
+        assert_eq!(image.alpha.as_ref().unwrap().dimensions(), (20, 20));
+    }
+
+    #[test]
+    fn test_trim() {
+        let sprite = || ProcessedImage {
+            data: RgbImage::from_fn(12, 10, |x, y| {
+                if (5..9).contains(&x) && (2..5).contains(&y) {
+                    Rgb([200, 0, 0])
+                } else {
+                    Rgb([10, 10, 10])
+                }
+            }),
+            alpha: None,
+        };
+        let mut image = sprite();
+        image.trim(None, 0, 0);
+        assert_eq!((image.width(), image.height()), (4, 3));
+        assert_eq!(image.data.get_pixel(0, 0), &Rgb([200, 0, 0]));
+
+        let mut padded = sprite();
+        padded.trim(Some(Rgb([10, 10, 10])), 5, 3);
+        assert_eq!((padded.width(), padded.height()), (10, 8));
+
+        let mut keyed = sprite();
+        keyed.chroma_key(Rgb([10, 10, 10]), 0);
+        keyed.trim(None, 0, 1);
+        assert_eq!((keyed.width(), keyed.height()), (6, 5));
+        assert_eq!(keyed.alpha.as_ref().unwrap().dimensions(), (6, 5));
+
+        let mut empty = ProcessedImage::from_buffer(3, 3, &[Rgb([1, 2, 3]); 9]);
+        empty.trim(None, 0, 0);
+        assert_eq!((empty.width(), empty.height()), (3, 3));
+
+        let mut blank = ProcessedImage::from_buffer(0, 0, &[]);
+        blank.trim(None, 0, 2);
+        assert_eq!((blank.width(), blank.height()), (0, 0));
+        assert_eq!(content_bounds(&RgbImage::new(0, 4), None, None, 0), None);
+    }
+
+    #[test]
+    fn test_pad_to() {
+        let mut image = ProcessedImage::from_buffer(2, 3, &[Rgb([9, 9, 9]); 6]);
+        image
+            .pad_to(6, 5, Anchor::BottomRight, Some(Rgb([1, 2, 3])))
+            .unwrap();
+        assert_eq!((image.width(), image.height()), (6, 5));
+        assert!(image.alpha.is_none());
+        assert_eq!(image.data.get_pixel(3, 1), &Rgb([1, 2, 3]));
+        assert_eq!(image.data.get_pixel(4, 2), &Rgb([9, 9, 9]));
+        assert_eq!(image.data.get_pixel(5, 4), &Rgb([9, 9, 9]));
+
+        let mut centered = ProcessedImage::from_buffer(2, 2, &[Rgb([9, 9, 9]); 4]);
+        centered.pad_to(6, 4, Anchor::Center, None).unwrap();
+        let alpha = centered.alpha.as_ref().unwrap();
+        assert_eq!(alpha.pixels().filter(|a| a[0] == 255).count(), 4);
+        assert_eq!(alpha.get_pixel(2, 1)[0], 255);
+        assert!(centered.pad_to(4, 4, Anchor::Center, None).is_err());
+        assert_eq!("se".parse::<Anchor>().unwrap(), Anchor::BottomRight);
+    }
+
+    #[test]
+    fn test_scale_modes() {
+        let source = ProcessedImage::from_buffer(40, 20, &[Rgb([200, 10, 10]); 800]);
+        let mut fit = source.clone();
+        fit.scale_to(
+            30,
+            30,
+            ScaleMode::Fit,
+            ScaleFilter::Nearest,
+            false,
+            Some(Rgb([0, 0, 255])),
+        )
+        .unwrap();
+        assert_eq!(fit.data.dimensions(), (30, 30));
+        assert_eq!(fit.data.get_pixel(15, 0), &Rgb([0, 0, 255]));
+        assert_eq!(fit.data.get_pixel(15, 15), &Rgb([200, 10, 10]));
+        assert!(fit.alpha.is_none());
+
+        let mut fill = source.clone();
+        fill.scale_to(30, 30, ScaleMode::Fill, ScaleFilter::Nearest, false, None)
+            .unwrap();
+        assert_eq!(fill.data.dimensions(), (30, 30));
+        for (width, height) in [(0, 30), (30, 0)] {
+            let mut zero = source.clone();
+            assert!(
+                zero.scale_to(
+                    width,
+                    height,
+                    ScaleMode::Fit,
+                    ScaleFilter::Nearest,
+                    false,
+                    None
+                )
+                .is_err()
+            );
+        }
+        assert!(fill.data.pixels().all(|p| *p == Rgb([200, 10, 10])));
+
+        assert_eq!(ScaleMode::Fit.scaled_size(40, 20, 30, 30), (30, 15));
+        assert_eq!(ScaleMode::Fill.scaled_size(40, 20, 30, 30), (60, 30));
+        assert_eq!(ScaleMode::Stretch.scaled_size(40, 20, 30, 30), (30, 30));
+    }
+
+    #[test]
+    fn test_scale_max_dimension_and_percent() {
+        let mut image = ProcessedImage::from_buffer(40, 20, &[Rgb([1, 2, 3]); 800]);
+        assert_eq!(image.uniform_size_for_max_dimension(10), (10, 5));
+        image.scale_percent(25.0, ScaleFilter::Nearest);
+        assert_eq!(image.data.dimensions(), (10, 5));
+        let mut tall = ProcessedImage::from_buffer(20, 40, &[Rgb([1, 2, 3]); 800]);
+        tall.uniform_scale_max_dimension(8, ScaleFilter::Nearest);
+        assert_eq!(tall.data.dimensions(), (4, 8));
+        assert_eq!(tall.size_for_percent(1.0), (1, 1));
+    }
+
+    #[test]
+    fn test_power_of_two() {
+        let source = ProcessedImage::from_buffer(5, 3, &[Rgb([7, 7, 7]); 15]);
+        let mut padded = source.clone();
+        padded.power_of_two(PowerOfTwo::Pad, Anchor::TopLeft, None);
+        assert_eq!(padded.data.dimensions(), (8, 4));
+        let alpha = padded.alpha.as_ref().unwrap();
+        assert_eq!(
+            (alpha.get_pixel(4, 2)[0], alpha.get_pixel(5, 2)[0]),
+            (255, 0)
+        );
+        let mut scaled = source.clone();
+        scaled.power_of_two(PowerOfTwo::Scale, Anchor::Center, None);
+        assert_eq!(scaled.data.dimensions(), (8, 4));
+        assert!(scaled.alpha.is_none());
+        let mut exact = ProcessedImage::from_buffer(4, 2, &[Rgb([7, 7, 7]); 8]);
+        exact.power_of_two(PowerOfTwo::Pad, Anchor::Center, None);
+        assert_eq!(exact.data.dimensions(), (4, 2));
+        assert!(exact.alpha.is_none());
+    }
+
+    #[test]
+    fn test_region_palette() {
+        let image = get_test_image();
+        let cropped = image::imageops::crop_imm(&image.data, 3, 2, 7, 5).to_image();
+        let buffer = cropped.pixels().copied().collect::<Vec<_>>();
+        let expected = ProcessedImage::from_buffer(7, 5, &buffer).generate_image_palette(10, 4);
+        let palette = image.generate_region_palette(3, 2, 7, 5, 10, 4).unwrap();
+        assert_eq!(palette, expected);
+        assert!(image.generate_region_palette(5, 5, 6, 1, 10, 4).is_err());
+        assert!(image.generate_region_palette(0, 0, 0, 1, 10, 4).is_err());
+    }
+
+    #[test]
+    fn test_masked_palette() {
+        let image = get_test_image();
+        let mask = GrayImage::from_fn(10, 10, |x, _| Luma([if x < 3 { 255 } else { 0 }]));
+        let expected = ProcessedImage::from_buffer(30, 1, &[Rgb([136, 0, 21]); 30])
+            .generate_image_palette(1, 4);
+        let palette = image.generate_masked_palette(&mask, 1, 4).unwrap();
+        assert_eq!(palette, expected);
+        assert!(
+            image
+                .generate_masked_palette(&GrayImage::new(10, 10), 1, 4)
+                .is_err()
+        );
+        assert!(
+            image
+                .generate_masked_palette(&GrayImage::new(5, 5), 1, 4)
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "io")]
     #[test]
     #[ignore]
     fn end_to_end() {
         {
             let mut image = ProcessedImage::new("./assets/test_img_1.jpg").unwrap();
             println!("Dimensions: {}x{}", image.width(), image.height());
-            let orig_width = image.width();
-            image.uniform_scale_width(orig_width / 5, true);
-            let palette = image.generate_image_palette(10, 16);
+            let palette = image.pixelize(&PixelizeOptions::new(image.width() / 5, 16));
             println!("Palette: {:?}", palette);
             save_palette("./assets/palette1.png", &palette).unwrap();
-            image.apply_palette(&palette);
-            image.uniform_scale_width(orig_width, false);
             image.save("./assets/converted1.png").unwrap();
         }
         {
             let mut image = ProcessedImage::new("./assets/test_img_1.jpg").unwrap();
             println!("Dimensions: {}x{}", image.width(), image.height());
             let orig_width = image.width();
-            image.uniform_scale_width(orig_width / 2, true);
+            image.uniform_scale_width(orig_width / 2, ScaleFilter::Lanczos3);
             let palette = image.generate_image_palette(10, 8);
             image.apply_palette(&palette);
             image.save("./assets/converted3.png").unwrap();
@@ -424,20 +2513,16 @@ mod tests {
         {
             let mut image = ProcessedImage::new("./assets/test_img_2.jpg").unwrap();
             println!("Dimensions: {}x{}", image.width(), image.height());
-            let orig_width = image.width();
-            image.uniform_scale_width(orig_width / 5, true);
-            let palette = image.generate_image_palette(10, 16);
+            let palette = image.pixelize(&PixelizeOptions::new(image.width() / 5, 16));
             println!("Palette: {:?}", palette);
             save_palette("./assets/palette2.png", &palette).unwrap();
-            image.apply_palette(&palette);
-            image.uniform_scale_width(orig_width, false);
             image.save("./assets/converted2.png").unwrap();
         }
         {
             let mut image = ProcessedImage::new("./assets/test_img_2.jpg").unwrap();
             println!("Dimensions: {}x{}", image.width(), image.height());
             let orig_width = image.width();
-            image.uniform_scale_width(orig_width / 2, true);
+            image.uniform_scale_width(orig_width / 2, ScaleFilter::Lanczos3);
             let palette = image.generate_image_palette(10, 8);
             image.apply_palette(&palette);
             image.save("./assets/converted4.png").unwrap();
@@ -0,0 +1,99 @@
+use crate::parallel::*;
+use anyhow::{Result, anyhow};
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoiseKind {
+    Uniform,
+    Gaussian,
+}
+
+impl FromStr for NoiseKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "uniform" => Ok(NoiseKind::Uniform),
+            "gaussian" | "gauss" => Ok(NoiseKind::Gaussian),
+            _ => Err(anyhow!("Unknown noise {}, expected uniform or gaussian", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoiseOptions {
+    pub kind: NoiseKind,
+    pub amplitude: f32,
+    pub seed: u64,
+}
+
+impl NoiseOptions {
+    pub fn new(kind: NoiseKind, amplitude: f32) -> Self {
+        NoiseOptions {
+            kind,
+            amplitude,
+            seed: 0,
+        }
+    }
+
+    fn sample(&self, x: u32, y: u32) -> f32 {
+        let key = self.seed ^ ((y as u64) << 32 | x as u64);
+        match self.kind {
+            NoiseKind::Uniform => (unit(hash(key)) * 2.0 - 1.0) * self.amplitude,
+            NoiseKind::Gaussian => {
+                let first = unit(hash(key)).max(f32::MIN_POSITIVE);
+                let second = unit(hash(key ^ 0x9E37_79B9_7F4A_7C15));
+                let normal =
+                    (-2.0 * first.ln()).sqrt() * (2.0 * std::f32::consts::PI * second).cos();
+                normal * self.amplitude
+            }
+        }
+    }
+}
+
+fn hash(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+fn unit(value: u64) -> f32 {
+    (value >> 40) as f32 / (1u64 << 24) as f32
+}
+
+pub fn add_noise(img: &RgbImage, options: &NoiseOptions) -> RgbImage {
+    let mut result = img.clone();
+    result.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let offset = options.sample(x, y);
+        pixel.0 = pixel
+            .0
+            .map(|c| (c as f32 + offset).round().clamp(0.0, 255.0) as u8);
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::noise::{NoiseKind, NoiseOptions, add_noise};
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_noise() {
+        let img = RgbImage::from_pixel(32, 32, Rgb([128, 128, 128]));
+        for kind in [NoiseKind::Uniform, NoiseKind::Gaussian] {
+            let options = NoiseOptions::new(kind, 10.0);
+            let noisy = add_noise(&img, &options);
+            assert_eq!(noisy, add_noise(&img, &options));
+            assert_ne!(noisy, add_noise(&img, &NoiseOptions { seed: 7, ..options }));
+            let mean = noisy.pixels().map(|p| p[0] as f64).sum::<f64>() / 1024.0;
+            assert!((mean - 128.0).abs() < 2.0, "{:?}: {}", kind, mean);
+            assert!(noisy.pixels().any(|p| p[0] != 128));
+        }
+        let uniform = add_noise(&img, &NoiseOptions::new(NoiseKind::Uniform, 10.0));
+        assert!(uniform.pixels().all(|p| p[0].abs_diff(128) <= 10));
+    }
+}
@@ -0,0 +1,192 @@
+use crate::image_processing::{luminance, rgb_to_hex};
+use crate::names::parse_hex;
+use crate::palette::detect_ramps;
+use crate::{Palette, ProcessedImage};
+use anyhow::{Result, anyhow, ensure};
+use image::{GrayImage, Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+const OPAQUE: u8 = 128;
+const OUTLINE_HUE_BAND: f32 = 30.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum OutlineColor {
+    Darkest,
+    Ramp,
+    Fixed(Rgb<u8>),
+}
+
+impl OutlineColor {
+    pub fn resolve(&self, image: &ProcessedImage, palette: &Palette) -> Result<Rgb<u8>> {
+        let darkest = || {
+            palette
+                .iter()
+                .min_by(|a, b| luminance(a).total_cmp(&luminance(b)))
+                .copied()
+                .ok_or_else(|| anyhow!("Cannot pick an outline color from an empty palette"))
+        };
+        match self {
+            OutlineColor::Fixed(color) => Ok(*color),
+            OutlineColor::Darkest => darkest(),
+            OutlineColor::Ramp => {
+                let mut counts = HashMap::new();
+                for (x, y, pixel) in image.data.enumerate_pixels() {
+                    if is_opaque(image.alpha.as_ref(), x, y) {
+                        *counts.entry(*pixel).or_insert(0u32) += 1;
+                    }
+                }
+                let ramps = detect_ramps(palette, OUTLINE_HUE_BAND);
+                let dominant = ramps
+                    .iter()
+                    .filter(|ramp| ramp.hue.is_some())
+                    .max_by_key(|ramp| {
+                        ramp.colors
+                            .iter()
+                            .map(|c| counts.get(c).copied().unwrap_or(0))
+                            .sum::<u32>()
+                    });
+                match dominant {
+                    Some(ramp) => Ok(ramp.colors[0]),
+                    None => darkest(),
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for OutlineColor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "darkest" => Ok(OutlineColor::Darkest),
+            "ramp" | "hue" => Ok(OutlineColor::Ramp),
+            hex if hex.starts_with('#') => Ok(OutlineColor::Fixed(parse_hex(&hex[1..])?)),
+            _ => Err(anyhow!(
+                "Unknown outline color {}, expected darkest, ramp or #rrggbb",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OutlineColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutlineColor::Darkest => write!(f, "darkest"),
+            OutlineColor::Ramp => write!(f, "ramp"),
+            OutlineColor::Fixed(color) => write!(f, "{}", rgb_to_hex(color)),
+        }
+    }
+}
+
+impl TryFrom<String> for OutlineColor {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<OutlineColor> for String {
+    fn from(color: OutlineColor) -> Self {
+        color.to_string()
+    }
+}
+
+fn is_opaque(alpha: Option<&GrayImage>, x: u32, y: u32) -> bool {
+    alpha.is_none_or(|alpha| alpha.get_pixel(x, y)[0] >= OPAQUE)
+}
+
+pub fn add_outline(data: &mut RgbImage, alpha: &mut GrayImage, color: Rgb<u8>) -> Result<()> {
+    ensure!(
+        data.dimensions() == alpha.dimensions(),
+        "Alpha mask size does not match the image"
+    );
+    let (width, height) = data.dimensions();
+    let source = alpha.clone();
+    for y in 0..height {
+        for x in 0..width {
+            if is_opaque(Some(&source), x, y) {
+                continue;
+            }
+            let neighbors = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            if neighbors
+                .iter()
+                .any(|&(nx, ny)| nx < width && ny < height && is_opaque(Some(&source), nx, ny))
+            {
+                data.put_pixel(x, y, color);
+                alpha.put_pixel(x, y, image::Luma([255]));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ProcessedImage;
+    use crate::outline::OutlineColor;
+    use image::{GrayImage, Luma, Rgb, RgbImage};
+
+    fn sprite() -> ProcessedImage {
+        let red = Rgb([200u8, 40, 40]);
+        let mut image = ProcessedImage::from(RgbImage::from_fn(5, 5, |x, y| {
+            if (1..4).contains(&x) && (1..4).contains(&y) {
+                red
+            } else {
+                Rgb([255, 255, 255])
+            }
+        }));
+        image.alpha = Some(GrayImage::from_fn(5, 5, |x, y| {
+            Luma([((1..4).contains(&x) && (1..4).contains(&y)) as u8 * 255])
+        }));
+        image
+    }
+
+    #[test]
+    fn test_outline_color() {
+        let image = sprite();
+        let palette = vec![
+            Rgb([200u8, 40, 40]),
+            Rgb([90, 15, 20]),
+            Rgb([10, 10, 40]),
+            Rgb([255, 255, 255]),
+        ];
+        let darkest = OutlineColor::Darkest.resolve(&image, &palette).unwrap();
+        assert_eq!(darkest, Rgb([10, 10, 40]));
+        let ramp = OutlineColor::Ramp.resolve(&image, &palette).unwrap();
+        assert_eq!(ramp, Rgb([90, 15, 20]));
+        assert_eq!(
+            "#102030".parse::<OutlineColor>().unwrap(),
+            OutlineColor::Fixed(Rgb([16, 32, 48]))
+        );
+        assert!("lightest".parse::<OutlineColor>().is_err());
+    }
+
+    #[test]
+    fn test_outline() {
+        let mut image = sprite();
+        let black = Rgb([0u8, 0, 0]);
+        image.outline(black).unwrap();
+        let alpha = image.alpha.as_ref().unwrap();
+        assert_eq!(alpha.get_pixel(0, 2)[0], 255);
+        assert_eq!(*image.data.get_pixel(2, 0), black);
+        assert_eq!(alpha.get_pixel(0, 0)[0], 0);
+        assert_eq!(*image.data.get_pixel(2, 2), Rgb([200, 40, 40]));
+        assert!(
+            ProcessedImage::from(RgbImage::new(2, 2))
+                .outline(black)
+                .is_err()
+        );
+    }
+}
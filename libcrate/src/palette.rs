@@ -0,0 +1,1105 @@
+#[cfg(feature = "io")]
+use crate::image_processing::load_palette;
+use crate::image_processing::{
+    PaletteMatcher, delta_e, luminance, palette_indices, palette_usage, rgb_to_hex, rgb_to_lab,
+};
+use crate::names::parse_hex;
+use crate::parallel::*;
+use crate::{Palette, RgbHistogram};
+#[cfg(feature = "io")]
+use anyhow::Context;
+use anyhow::{Result, anyhow, ensure};
+use image::{Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+#[cfg(feature = "io")]
+use std::fs;
+use std::ops::Deref;
+#[cfg(feature = "io")]
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum RemapStrategy {
+    Index,
+    #[default]
+    Nearest,
+    Luminance,
+}
+
+impl FromStr for RemapStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "index" => Ok(RemapStrategy::Index),
+            "nearest" => Ok(RemapStrategy::Nearest),
+            "luminance" | "luma" => Ok(RemapStrategy::Luminance),
+            _ => Err(anyhow!(
+                "Unknown remap strategy {}, expected index, nearest or luminance",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RemapStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemapStrategy::Index => write!(f, "index"),
+            RemapStrategy::Nearest => write!(f, "nearest"),
+            RemapStrategy::Luminance => write!(f, "luminance"),
+        }
+    }
+}
+
+impl TryFrom<String> for RemapStrategy {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<RemapStrategy> for String {
+    fn from(strategy: RemapStrategy) -> Self {
+        strategy.to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaletteUsage {
+    pub counts: Vec<u32>,
+}
+
+impl PaletteUsage {
+    pub fn new(img: &RgbImage, palette: &Palette) -> Self {
+        PaletteUsage {
+            counts: palette_usage(img, palette),
+        }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.counts.iter().sum()
+    }
+
+    pub fn percentage(&self, index: usize) -> f64 {
+        100.0 * self.counts[index] as f64 / self.total().max(1) as f64
+    }
+
+    pub fn unused(&self) -> Vec<usize> {
+        self.below(1)
+    }
+
+    pub fn below(&self, min_count: u32) -> Vec<usize> {
+        (0..self.counts.len())
+            .filter(|i| self.counts[*i] < min_count)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PruneThreshold {
+    Pixels(u32),
+    Percent(f64),
+}
+
+impl PruneThreshold {
+    fn min_count(&self, total: u32) -> u32 {
+        match self {
+            PruneThreshold::Pixels(count) => *count,
+            PruneThreshold::Percent(percent) => (total as f64 * percent / 100.0).ceil() as u32,
+        }
+    }
+}
+
+impl FromStr for PruneThreshold {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let error = || {
+            anyhow!(
+                "Invalid prune threshold {}, expected pixel count or percentage",
+                s
+            )
+        };
+        match s.trim().strip_suffix('%') {
+            Some(percent) => Ok(PruneThreshold::Percent(
+                percent.trim().parse().map_err(|_| error())?,
+            )),
+            None => Ok(PruneThreshold::Pixels(
+                s.trim().parse().map_err(|_| error())?,
+            )),
+        }
+    }
+}
+
+pub fn prune_palette(img: &RgbImage, palette: &Palette, threshold: PruneThreshold) -> Palette {
+    let usage = PaletteUsage::new(img, palette);
+    let min_count = threshold.min_count(usage.total());
+    let most_used = (0..palette.len()).max_by_key(|i| usage.counts[*i]);
+    palette
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| usage.counts[*i] >= min_count || Some(*i) == most_used)
+        .map(|(_, color)| *color)
+        .collect()
+}
+
+// mean color, weight and Lab of the rounded mean, merged entries become None
+type Cluster = Option<([f64; 3], f64, [f64; 3])>;
+
+pub fn reduce_palette(palette: &Palette, weights: &[u32], target: usize) -> Result<Palette> {
+    ensure!(target > 0, "Cannot reduce a palette to zero colors");
+    ensure!(
+        weights.len() == palette.len(),
+        "Expected {} palette weights, got {}",
+        palette.len(),
+        weights.len()
+    );
+    let to_rgb = |color: &[f64; 3]| Rgb(color.map(|c| c.round().clamp(0.0, 255.0) as u8));
+    let mut entries = palette
+        .iter()
+        .zip(weights)
+        .map(|(color, weight)| {
+            Some((
+                color.0.map(|c| c as f64),
+                *weight as f64 + 1.0,
+                rgb_to_lab(color),
+            ))
+        })
+        .collect::<Vec<_>>();
+    let cost = |entries: &[Cluster], a: usize, b: usize| {
+        let (Some((_, wa, la)), Some((_, wb, lb))) = (&entries[a], &entries[b]) else {
+            return f64::INFINITY;
+        };
+        let distance = la.iter().zip(lb).map(|(x, y)| (x - y).powi(2)).sum::<f64>();
+        wa * wb / (wa + wb) * distance
+    };
+    // the cheapest merge partner of each entry, only rows touched by a merge are rescanned
+    let nearest = |entries: &[Cluster], a: usize| {
+        (0..entries.len())
+            .filter(|&b| b != a && entries[b].is_some())
+            .map(|b| (cost(entries, a, b), b))
+            .min_by(|x, y| x.0.total_cmp(&y.0))
+    };
+    let mut partners = (0..entries.len())
+        .map(|a| nearest(&entries, a))
+        .collect::<Vec<_>>();
+    let mut remaining = entries.len();
+    while remaining > target {
+        let (a, b) = partners
+            .iter()
+            .enumerate()
+            .filter(|(a, _)| entries[*a].is_some())
+            .filter_map(|(a, partner)| partner.map(|(cost, b)| (cost, a.min(b), a.max(b))))
+            .min_by(|x, y| x.0.total_cmp(&y.0).then((x.1, x.2).cmp(&(y.1, y.2))))
+            .map(|(_, a, b)| (a, b))
+            .expect("at least two entries");
+        let (cb, wb, _) = entries[b].take().expect("active entry");
+        let (ca, wa, _) = entries[a].expect("active entry");
+        let color = [0, 1, 2].map(|c| (ca[c] * wa + cb[c] * wb) / (wa + wb));
+        entries[a] = Some((color, wa + wb, rgb_to_lab(&to_rgb(&color))));
+        remaining -= 1;
+        partners[b] = None;
+        partners[a] = nearest(&entries, a);
+        for k in (0..entries.len()).filter(|&k| k != a && entries[k].is_some()) {
+            partners[k] = match partners[k] {
+                Some((_, partner)) if partner == a || partner == b => nearest(&entries, k),
+                Some((current, _)) if cost(&entries, k, a) < current => {
+                    Some((cost(&entries, k, a), a))
+                }
+                partner => partner,
+            };
+        }
+    }
+    Ok(entries
+        .iter()
+        .flatten()
+        .map(|(color, _, _)| to_rgb(color))
+        .collect())
+}
+
+pub fn reduce_histogram(histogram: &RgbHistogram, target: usize) -> Result<Palette> {
+    let mut entries = histogram.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|(color, _)| color.0);
+    let palette = entries.iter().map(|(color, _)| **color).collect();
+    let weights = entries.iter().map(|(_, count)| **count).collect::<Vec<_>>();
+    reduce_palette(&palette, &weights, target)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PaletteScore {
+    pub mean: f64,
+    pub median: f64,
+    pub p95: f64,
+    pub max: f64,
+}
+
+pub fn score_palette(img: &RgbImage, palette: &Palette) -> Result<PaletteScore> {
+    ensure!(!palette.is_empty(), "Cannot score an empty palette");
+    ensure!(
+        img.width() > 0 && img.height() > 0,
+        "Cannot score an empty image"
+    );
+    let indices = palette_indices(img, palette);
+    let mut distances = img
+        .par_pixels()
+        .zip(indices.par_iter())
+        .map(|(pixel, i)| delta_e(pixel, &palette[*i]))
+        .collect::<Vec<_>>();
+    distances.sort_by(|a, b| a.total_cmp(b));
+    let percentile = |p: f64| distances[((distances.len() - 1) as f64 * p).round() as usize];
+    Ok(PaletteScore {
+        mean: distances.iter().sum::<f64>() / distances.len() as f64,
+        median: percentile(0.5),
+        p95: percentile(0.95),
+        max: distances[distances.len() - 1],
+    })
+}
+
+pub fn parse_gpl(text: &str) -> Result<Palette> {
+    let mut lines = text.lines();
+    ensure!(
+        lines.next().map(str::trim) == Some("GIMP Palette"),
+        "Missing GIMP Palette header"
+    );
+    lines
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.contains(':'))
+        .map(|line| {
+            let channels = line
+                .split_whitespace()
+                .take(3)
+                .map(|c| c.parse::<u8>())
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(|_| anyhow!("Invalid palette line {}", line))?;
+            ensure!(channels.len() == 3, "Invalid palette line {}", line);
+            Ok(Rgb([channels[0], channels[1], channels[2]]))
+        })
+        .collect()
+}
+
+pub fn parse_hex_palette(text: &str) -> Result<Palette> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(';'))
+        .map(|line| parse_hex(line.trim_start_matches('#')))
+        .collect()
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<PaletteEntry>", into = "Vec<String>")]
+pub struct HexPalette(pub Palette);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PaletteEntry {
+    Hex(String),
+    Rgb([u8; 3]),
+}
+
+impl PaletteEntry {
+    fn color(self) -> Result<Rgb<u8>> {
+        match self {
+            PaletteEntry::Hex(hex) => parse_hex(hex.trim().trim_start_matches('#')),
+            PaletteEntry::Rgb(rgb) => Ok(Rgb(rgb)),
+        }
+    }
+}
+
+impl TryFrom<Vec<PaletteEntry>> for HexPalette {
+    type Error = anyhow::Error;
+
+    fn try_from(entries: Vec<PaletteEntry>) -> Result<Self> {
+        entries
+            .into_iter()
+            .map(PaletteEntry::color)
+            .collect::<Result<Palette>>()
+            .map(HexPalette)
+    }
+}
+
+impl From<HexPalette> for Vec<String> {
+    fn from(palette: HexPalette) -> Self {
+        palette.0.iter().map(rgb_to_hex).collect()
+    }
+}
+
+impl From<Palette> for HexPalette {
+    fn from(palette: Palette) -> Self {
+        HexPalette(palette)
+    }
+}
+
+impl From<HexPalette> for Palette {
+    fn from(palette: HexPalette) -> Self {
+        palette.0
+    }
+}
+
+impl Deref for HexPalette {
+    type Target = Palette;
+
+    fn deref(&self) -> &Palette {
+        &self.0
+    }
+}
+
+impl FromStr for HexPalette {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        s.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|color| !color.is_empty())
+            .map(|color| parse_hex(color.trim_start_matches('#')))
+            .collect::<Result<Palette>>()
+            .map(HexPalette)
+    }
+}
+
+impl fmt::Display for HexPalette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let colors = self.0.iter().map(rgb_to_hex).collect::<Vec<_>>();
+        write!(f, "{}", colors.join(", "))
+    }
+}
+
+// a single color written like a HexPalette entry, "#rrggbb" or [r, g, b]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "PaletteEntry", into = "String")]
+pub struct HexColor(pub Rgb<u8>);
+
+impl TryFrom<PaletteEntry> for HexColor {
+    type Error = anyhow::Error;
+
+    fn try_from(entry: PaletteEntry) -> Result<Self> {
+        entry.color().map(HexColor)
+    }
+}
+
+impl From<HexColor> for String {
+    fn from(color: HexColor) -> Self {
+        rgb_to_hex(&color.0)
+    }
+}
+
+impl From<Rgb<u8>> for HexColor {
+    fn from(color: Rgb<u8>) -> Self {
+        HexColor(color)
+    }
+}
+
+impl FromStr for HexColor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_hex(s.trim().trim_start_matches('#')).map(HexColor)
+    }
+}
+
+impl fmt::Display for HexColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", rgb_to_hex(&self.0))
+    }
+}
+
+#[cfg(feature = "io")]
+pub fn load_palette_file<P>(path: P) -> Result<Palette>
+where
+    P: AsRef<Path>,
+{
+    let extension = path
+        .as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    let read = || {
+        fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to load: {:?}", path.as_ref()))
+    };
+    let palette = match extension.as_deref() {
+        Some("gpl") => parse_gpl(&read()?)?,
+        Some("hex") | Some("txt") => parse_hex_palette(&read()?)?,
+        _ => load_palette(path.as_ref())?,
+    };
+    ensure!(!palette.is_empty(), "Palette {:?} is empty", path.as_ref());
+    Ok(palette)
+}
+
+fn luminance_ranks(palette: &Palette) -> Vec<usize> {
+    let mut order = (0..palette.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| luminance(&palette[*a]).total_cmp(&luminance(&palette[*b])));
+    let mut ranks = vec![0; palette.len()];
+    order
+        .iter()
+        .enumerate()
+        .for_each(|(rank, i)| ranks[*i] = rank);
+    ranks
+}
+
+pub fn remap_table(from: &Palette, to: &Palette, strategy: RemapStrategy) -> Result<Vec<usize>> {
+    ensure!(
+        !from.is_empty() && !to.is_empty(),
+        "Cannot remap between empty palettes"
+    );
+    let table = match strategy {
+        RemapStrategy::Index => (0..from.len()).map(|i| i % to.len()).collect(),
+        RemapStrategy::Nearest => {
+            let matcher = PaletteMatcher::new(to);
+            from.iter().map(|c| matcher.nearest_index(c)).collect()
+        }
+        RemapStrategy::Luminance => {
+            let from_ranks = luminance_ranks(from);
+            let mut to_order = (0..to.len()).collect::<Vec<_>>();
+            to_order.sort_by(|a, b| luminance(&to[*a]).total_cmp(&luminance(&to[*b])));
+            from_ranks
+                .iter()
+                .map(|rank| to_order[rank * to.len() / from.len()])
+                .collect()
+        }
+    };
+    Ok(table)
+}
+
+pub fn remap_palette(
+    img: &RgbImage,
+    from: &Palette,
+    to: &Palette,
+    strategy: RemapStrategy,
+) -> Result<RgbImage> {
+    let table = remap_table(from, to, strategy)?;
+    let matcher = PaletteMatcher::new(from);
+    let mut result = img.clone();
+    result
+        .par_pixels_mut()
+        .for_each(|p| *p = to[table[matcher.nearest_index(p)]]);
+    Ok(result)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChannel {
+    Red,
+    Green,
+    Blue,
+    Hue,
+    Saturation,
+    Lightness,
+}
+
+impl ColorChannel {
+    pub const RGB: [ColorChannel; 3] = [ColorChannel::Red, ColorChannel::Green, ColorChannel::Blue];
+    pub const HSL: [ColorChannel; 3] = [
+        ColorChannel::Hue,
+        ColorChannel::Saturation,
+        ColorChannel::Lightness,
+    ];
+
+    // RGB in 0-255, hue in degrees, saturation and lightness in percent
+    pub fn value(&self, color: &Rgb<u8>) -> f32 {
+        let hsl = rgb_to_hsl(color);
+        match self {
+            ColorChannel::Red => color[0] as f32,
+            ColorChannel::Green => color[1] as f32,
+            ColorChannel::Blue => color[2] as f32,
+            ColorChannel::Hue => hsl[0],
+            ColorChannel::Saturation => hsl[1] * 100.0,
+            ColorChannel::Lightness => hsl[2] * 100.0,
+        }
+    }
+}
+
+pub fn rgb_to_hsl(color: &Rgb<u8>) -> [f32; 3] {
+    let [r, g, b] = color.0.map(|c| c as f32 / 255.0);
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let lightness = (max + min) / 2.0;
+    let chroma = max - min;
+    if chroma == 0.0 {
+        return [0.0, 0.0, lightness];
+    }
+    let saturation = chroma / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == r {
+        ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    [hue * 60.0, saturation, lightness]
+}
+
+pub fn hsl_to_rgb([hue, saturation, lightness]: [f32; 3]) -> Rgb<u8> {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    Rgb([r, g, b].map(|c| ((c + m) * 255.0).round().clamp(0.0, 255.0) as u8))
+}
+
+pub fn nudge_color(color: &Rgb<u8>, channel: ColorChannel, delta: f32) -> Rgb<u8> {
+    let mut rgb = *color;
+    let mut hsl = rgb_to_hsl(color);
+    match channel {
+        ColorChannel::Red => rgb[0] = (rgb[0] as f32 + delta).round().clamp(0.0, 255.0) as u8,
+        ColorChannel::Green => rgb[1] = (rgb[1] as f32 + delta).round().clamp(0.0, 255.0) as u8,
+        ColorChannel::Blue => rgb[2] = (rgb[2] as f32 + delta).round().clamp(0.0, 255.0) as u8,
+        ColorChannel::Hue => hsl[0] = (hsl[0] + delta).rem_euclid(360.0),
+        ColorChannel::Saturation => hsl[1] = (hsl[1] + delta / 100.0).clamp(0.0, 1.0),
+        ColorChannel::Lightness => hsl[2] = (hsl[2] + delta / 100.0).clamp(0.0, 1.0),
+    }
+    if ColorChannel::RGB.contains(&channel) {
+        rgb
+    } else {
+        hsl_to_rgb(hsl)
+    }
+}
+
+pub fn adjust_palette(palette: &Palette, channel: ColorChannel, delta: f32) -> Palette {
+    palette
+        .iter()
+        .map(|color| nudge_color(color, channel, delta))
+        .collect()
+}
+
+pub fn lighten_palette(palette: &Palette, percent: f32) -> Palette {
+    adjust_palette(palette, ColorChannel::Lightness, percent)
+}
+
+pub fn shift_palette_hue(palette: &Palette, degrees: f32) -> Palette {
+    adjust_palette(palette, ColorChannel::Hue, degrees)
+}
+
+pub fn saturate_palette(palette: &Palette, percent: f32) -> Palette {
+    adjust_palette(palette, ColorChannel::Saturation, percent)
+}
+
+const NAMED_HUES: [(&str, f32, f32); 8] = [
+    ("red", 345.0, 15.0),
+    ("orange", 15.0, 45.0),
+    ("yellow", 45.0, 70.0),
+    ("green", 70.0, 165.0),
+    ("cyan", 165.0, 195.0),
+    ("blue", 195.0, 255.0),
+    ("purple", 255.0, 290.0),
+    ("magenta", 290.0, 345.0),
+];
+
+// a band of hues in degrees going up from start, wraps past 360 when start > end
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct HueRange {
+    pub start: f32,
+    pub end: f32,
+}
+
+impl HueRange {
+    pub fn contains(&self, hue: f32) -> bool {
+        let hue = hue.rem_euclid(360.0);
+        match self.start <= self.end {
+            true => (self.start..=self.end).contains(&hue),
+            false => hue >= self.start || hue <= self.end,
+        }
+    }
+
+    fn nearest(&self, hue: f32) -> (f32, f32) {
+        let distance = |target: f32| (target - hue + 540.0).rem_euclid(360.0) - 180.0;
+        [self.start, self.end]
+            .map(|bound| (distance(bound).abs(), bound))
+            .into_iter()
+            .fold((f32::MAX, hue), |best, next| match next.0 < best.0 {
+                true => next,
+                false => best,
+            })
+    }
+}
+
+impl FromStr for HueRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let lower = s.trim().to_lowercase();
+        if let Some(&(_, start, end)) = NAMED_HUES.iter().find(|(name, _, _)| *name == lower) {
+            return Ok(HueRange { start, end });
+        }
+        let invalid = || {
+            anyhow!(
+                "Invalid hue range {}, expected START-END in degrees (e.g. 200-280) or one of {}",
+                s,
+                NAMED_HUES.map(|(name, _, _)| name).join(", ")
+            )
+        };
+        let (start, end) = lower.split_once('-').ok_or_else(invalid)?;
+        let (start, end): (f32, f32) = (
+            start.trim().parse().map_err(|_| invalid())?,
+            end.trim().parse().map_err(|_| invalid())?,
+        );
+        ensure!(
+            (0.0..=360.0).contains(&start) && (0.0..=360.0).contains(&end),
+            "Invalid hue range {}, hues are between 0 and 360",
+            s
+        );
+        Ok(HueRange { start, end })
+    }
+}
+
+impl fmt::Display for HueRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = NAMED_HUES
+            .iter()
+            .find(|(_, start, end)| *start == self.start && *end == self.end);
+        match name {
+            Some((name, _, _)) => write!(f, "{}", name),
+            None => write!(f, "{}-{}", self.start, self.end),
+        }
+    }
+}
+
+impl TryFrom<String> for HueRange {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<HueRange> for String {
+    fn from(range: HueRange) -> Self {
+        range.to_string()
+    }
+}
+
+// rotates a color outside every range to the closest range edge, grays have no hue and stay
+pub fn constrain_hue(color: &Rgb<u8>, ranges: &[HueRange]) -> Rgb<u8> {
+    let [hue, saturation, lightness] = rgb_to_hsl(color);
+    if ranges.is_empty() || saturation == 0.0 || ranges.iter().any(|r| r.contains(hue)) {
+        return *color;
+    }
+    let (_, hue) = ranges.iter().map(|range| range.nearest(hue)).fold(
+        (f32::MAX, hue),
+        |best, next| match next.0 < best.0 {
+            true => next,
+            false => best,
+        },
+    );
+    hsl_to_rgb([hue, saturation, lightness])
+}
+
+pub fn constrain_palette_hues(palette: &Palette, ranges: &[HueRange]) -> Palette {
+    let mut result: Palette = Vec::with_capacity(palette.len());
+    for color in palette {
+        let color = constrain_hue(color, ranges);
+        if !result.contains(&color) {
+            result.push(color);
+        }
+    }
+    result
+}
+
+pub fn constrain_image_hues(img: &RgbImage, ranges: &[HueRange]) -> RgbImage {
+    let mut result = img.clone();
+    result
+        .par_pixels_mut()
+        .for_each(|p| *p = constrain_hue(p, ranges));
+    result
+}
+
+const RAMP_SHADOW_HUE: f32 = 240.0;
+const RAMP_HIGHLIGHT_HUE: f32 = 60.0;
+const RAMP_LIGHTNESS: (f32, f32) = (0.1, 0.92);
+
+fn rotate_hue_toward(hue: f32, target: f32, degrees: f32) -> f32 {
+    let distance = (target - hue + 540.0).rem_euclid(360.0) - 180.0;
+    (hue + distance.signum() * degrees.min(distance.abs())).rem_euclid(360.0)
+}
+
+pub fn generate_ramps(keys: &Palette, steps: usize, hue_shift: f32) -> Result<Palette> {
+    ensure!(!keys.is_empty(), "At least one key color is required");
+    ensure!(steps > 0, "Ramp steps must be greater than 0");
+    let (darkest, lightest) = RAMP_LIGHTNESS;
+    Ok(keys
+        .iter()
+        .flat_map(|key| {
+            let [hue, saturation, lightness] = rgb_to_hsl(key);
+            (0..steps).map(move |i| {
+                // -1 is the deepest shadow, 0 the key color and 1 the brightest highlight
+                let t = if steps == 1 {
+                    0.0
+                } else {
+                    i as f32 / (steps - 1) as f32 * 2.0 - 1.0
+                };
+                let (l, h) = if t < 0.0 {
+                    (
+                        lightness + t * (lightness - darkest.min(lightness)),
+                        rotate_hue_toward(hue, RAMP_SHADOW_HUE, -t * hue_shift),
+                    )
+                } else {
+                    (
+                        lightness + t * (lightest.max(lightness) - lightness),
+                        rotate_hue_toward(hue, RAMP_HIGHLIGHT_HUE, t * hue_shift),
+                    )
+                };
+                hsl_to_rgb([h, saturation, l])
+            })
+        })
+        .collect())
+}
+
+const RAMP_GRAY_SATURATION: f32 = 0.12;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColorRamp {
+    pub hue: Option<f32>,
+    pub colors: HexPalette,
+}
+
+fn is_gray([_, saturation, lightness]: [f32; 3]) -> bool {
+    saturation < RAMP_GRAY_SATURATION || !(0.04..=0.96).contains(&lightness)
+}
+
+pub fn detect_ramps(palette: &Palette, hue_band: f32) -> Vec<ColorRamp> {
+    let (grays, mut chromatic): (Vec<_>, Vec<_>) = palette
+        .iter()
+        .map(|color| (rgb_to_hsl(color), *color))
+        .partition(|(hsl, _)| is_gray(*hsl));
+    chromatic.sort_by(|a, b| a.0[0].total_cmp(&b.0[0]));
+
+    let gap = |i: usize| {
+        let next = chromatic[(i + 1) % chromatic.len()].0[0];
+        (next - chromatic[i].0[0]).rem_euclid(360.0)
+    };
+    let start = (0..chromatic.len())
+        .max_by(|a, b| gap(*a).total_cmp(&gap(*b)))
+        .map_or(0, |i| (i + 1) % chromatic.len());
+    let mut bands: Vec<Vec<([f32; 3], Rgb<u8>)>> = vec![];
+    for i in 0..chromatic.len() {
+        let index = (start + i) % chromatic.len();
+        let previous = (index + chromatic.len() - 1) % chromatic.len();
+        match bands.last_mut() {
+            Some(band) if gap(previous) <= hue_band => band.push(chromatic[index]),
+            _ => bands.push(vec![chromatic[index]]),
+        }
+    }
+    if !grays.is_empty() {
+        bands.push(grays);
+    }
+
+    bands
+        .into_iter()
+        .map(|mut band| {
+            band.sort_by(|a, b| luminance(&a.1).total_cmp(&luminance(&b.1)));
+            let key = band[band.len() / 2].0;
+            ColorRamp {
+                hue: (!is_gray(key)).then_some(key[0]),
+                colors: HexPalette(band.into_iter().map(|(_, color)| color).collect()),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::image_processing::delta_e;
+    use crate::palette::{
+        ColorChannel, HexColor, HexPalette, HueRange, PaletteUsage, PruneThreshold, RemapStrategy,
+        constrain_palette_hues, detect_ramps, generate_ramps, hsl_to_rgb, lighten_palette,
+        nudge_color, parse_gpl, parse_hex_palette, prune_palette, reduce_histogram, reduce_palette,
+        remap_palette, remap_table, rgb_to_hsl, saturate_palette, score_palette, shift_palette_hue,
+    };
+    use image::{Rgb, RgbImage};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_hex_palette() {
+        let palette = HexPalette(vec![Rgb([255, 0, 16]), Rgb([0, 0, 0])]);
+        assert_eq!(palette.to_string(), "#ff0010, #000000");
+        assert_eq!("#ff0010, #000000".parse::<HexPalette>().unwrap(), palette);
+        assert_eq!("ff0010 000000".parse::<HexPalette>().unwrap(), palette);
+        assert_eq!(palette.len(), 2);
+        assert!("#ff00".parse::<HexPalette>().is_err());
+    }
+
+    #[test]
+    fn test_hex_palette_serde() {
+        let palette = HexPalette(vec![Rgb([170, 187, 204]), Rgb([0, 0, 0])]);
+        let json = serde_json::to_string(&palette).unwrap();
+        assert_eq!(json, r##"["#aabbcc","#000000"]"##);
+        assert_eq!(serde_json::from_str::<HexPalette>(&json).unwrap(), palette);
+        let mixed = serde_json::from_str::<HexPalette>(r#"["AABBCC", [0, 0, 0]]"#).unwrap();
+        assert_eq!(mixed, palette);
+        assert!(serde_json::from_str::<HexPalette>(r##"["#zzzzzz"]"##).is_err());
+
+        let color = HexColor(Rgb([170, 187, 204]));
+        assert_eq!(serde_json::to_string(&color).unwrap(), r##""#aabbcc""##);
+        assert_eq!(
+            serde_json::from_str::<HexColor>(r##""#AABBCC""##).unwrap(),
+            color
+        );
+        assert_eq!(
+            serde_json::from_str::<HexColor>("[170, 187, 204]").unwrap(),
+            color
+        );
+        assert_eq!("aabbcc".parse::<HexColor>().unwrap(), color);
+        assert!(serde_json::from_str::<HexColor>(r#"["aabbcc"]"#).is_err());
+    }
+
+    #[test]
+    fn test_remap_table() {
+        let from = vec![Rgb([255u8, 255, 255]), Rgb([0, 0, 0]), Rgb([128, 128, 128])];
+        let to = vec![Rgb([10u8, 10, 40]), Rgb([250, 240, 200])];
+        assert_eq!(
+            remap_table(&from, &to, RemapStrategy::Index).unwrap(),
+            vec![0, 1, 0]
+        );
+        assert_eq!(
+            remap_table(&from, &to, RemapStrategy::Nearest).unwrap(),
+            vec![1, 0, 1]
+        );
+        assert_eq!(
+            remap_table(&from, &to, RemapStrategy::Luminance).unwrap(),
+            vec![1, 0, 0]
+        );
+        assert!(remap_table(&from, &vec![], RemapStrategy::Index).is_err());
+    }
+
+    #[test]
+    fn test_remap_palette() {
+        let from = vec![Rgb([200u8, 0, 0]), Rgb([0, 0, 200])];
+        let to = vec![Rgb([0u8, 200, 0]), Rgb([200, 200, 0])];
+        let img = RgbImage::from_fn(4, 1, |x, _| from[(x % 2) as usize]);
+        let remapped = remap_palette(&img, &from, &to, RemapStrategy::Index).unwrap();
+        assert_eq!(remapped.get_pixel(0, 0), &to[0]);
+        assert_eq!(remapped.get_pixel(1, 0), &to[1]);
+    }
+
+    #[test]
+    fn test_palette_usage() {
+        let palette = vec![Rgb([0u8, 0, 0]), Rgb([255, 255, 255]), Rgb([255, 0, 0])];
+        let img = RgbImage::from_fn(4, 1, |x, _| palette[(x / 3) as usize]);
+        let usage = PaletteUsage::new(&img, &palette);
+        assert_eq!(usage.counts, vec![3, 1, 0]);
+        assert_eq!(usage.total(), 4);
+        assert_eq!(usage.percentage(0), 75.0);
+        assert_eq!(usage.unused(), vec![2]);
+        assert_eq!(usage.below(2), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_prune_palette() {
+        let palette = vec![Rgb([0u8, 0, 0]), Rgb([250, 250, 250]), Rgb([255, 255, 255])];
+        let img = RgbImage::from_fn(10, 1, |x, _| {
+            palette[if x < 7 { 0 } else { x as usize - 7 }]
+        });
+        assert_eq!(
+            prune_palette(&img, &palette, PruneThreshold::Pixels(2)),
+            palette[..1]
+        );
+        assert_eq!(
+            prune_palette(&img, &palette, "10%".parse().unwrap()),
+            palette
+        );
+        assert_eq!(
+            prune_palette(&img, &palette, PruneThreshold::Percent(100.0)),
+            palette[..1]
+        );
+        assert!("many".parse::<PruneThreshold>().is_err());
+    }
+
+    #[test]
+    fn test_reduce_palette() {
+        let palette = vec![
+            Rgb([0u8, 0, 0]),
+            Rgb([10, 10, 10]),
+            Rgb([250, 250, 250]),
+            Rgb([255, 0, 0]),
+        ];
+        let reduced = reduce_palette(&palette, &[0, 9, 5, 5], 3).unwrap();
+        assert_eq!(reduced, vec![Rgb([9, 9, 9]), palette[2], palette[3]]);
+        assert_eq!(reduce_palette(&palette, &[1; 4], 1).unwrap().len(), 1);
+        assert_eq!(reduce_palette(&palette, &[1; 4], 8).unwrap(), palette);
+        assert!(reduce_palette(&palette, &[1; 3], 2).is_err());
+        assert!(reduce_palette(&palette, &[1; 4], 0).is_err());
+
+        let histogram = HashMap::from([(Rgb([0u8, 0, 0]), 3), (Rgb([4, 4, 4]), 1)]);
+        assert_eq!(
+            reduce_histogram(&histogram, 1).unwrap(),
+            vec![Rgb([1, 1, 1])]
+        );
+    }
+
+    #[test]
+    fn test_reduce_palette_matches_exhaustive_search() {
+        let palette = (0..48u32)
+            .map(|i| {
+                Rgb([
+                    (i * 37 % 256) as u8,
+                    (i * 91 % 256) as u8,
+                    (i * 53 % 256) as u8,
+                ])
+            })
+            .collect::<Vec<_>>();
+        let weights = (0..48u32).map(|i| i * 7 % 13).collect::<Vec<_>>();
+        // the straightforward version rescanning every pair after each merge
+        let to_rgb = |c: &[f64; 3]| Rgb(c.map(|c| c.round().clamp(0.0, 255.0) as u8));
+        let mut entries = palette
+            .iter()
+            .zip(&weights)
+            .map(|(c, w)| (c.0.map(|c| c as f64), *w as f64 + 1.0))
+            .collect::<Vec<_>>();
+        while entries.len() > 5 {
+            let cost = |a: usize, b: usize| {
+                let ((ca, wa), (cb, wb)) = (&entries[a], &entries[b]);
+                wa * wb / (wa + wb) * delta_e(&to_rgb(ca), &to_rgb(cb)).powi(2)
+            };
+            let (a, b) = (0..entries.len())
+                .flat_map(|a| (a + 1..entries.len()).map(move |b| (a, b)))
+                .min_by(|x, y| cost(x.0, x.1).total_cmp(&cost(y.0, y.1)))
+                .unwrap();
+            let (cb, wb) = entries.remove(b);
+            let (ca, wa) = entries[a];
+            entries[a] = (
+                [0, 1, 2].map(|c| (ca[c] * wa + cb[c] * wb) / (wa + wb)),
+                wa + wb,
+            );
+        }
+        let expected = entries.iter().map(|(c, _)| to_rgb(c)).collect::<Vec<_>>();
+        assert_eq!(reduce_palette(&palette, &weights, 5).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_score_palette() {
+        let img = RgbImage::from_fn(10, 1, |x, _| Rgb([x as u8 * 20, 0, 0]));
+        let exact = img.pixels().copied().collect::<Vec<_>>();
+        let score = score_palette(&img, &exact).unwrap();
+        assert_eq!((score.mean, score.max), (0.0, 0.0));
+        let coarse = score_palette(&img, &vec![Rgb([0u8, 0, 0])]).unwrap();
+        let better = score_palette(&img, &vec![Rgb([0u8, 0, 0]), Rgb([160, 0, 0])]).unwrap();
+        assert!(coarse.mean > better.mean);
+        assert!(better.median <= better.p95 && better.p95 <= better.max);
+        assert!(score_palette(&img, &vec![]).is_err());
+    }
+
+    #[test]
+    fn test_parse_palette_files() {
+        let gpl = "GIMP Palette\nName: Test\nColumns: 2\n#\n  0   0   0\tBlack\n255 128 1 Orange\n";
+        assert_eq!(
+            parse_gpl(gpl).unwrap(),
+            vec![Rgb([0u8, 0, 0]), Rgb([255, 128, 1])]
+        );
+        assert!(parse_gpl("0 0 0").is_err());
+        assert_eq!(
+            parse_hex_palette("ff0000\n#00ff00\n\n").unwrap(),
+            vec![Rgb([255u8, 0, 0]), Rgb([0, 255, 0])]
+        );
+        assert!(parse_hex_palette("nothex").is_err());
+    }
+
+    #[test]
+    fn test_nudge_color() {
+        for color in [Rgb([255u8, 0, 0]), Rgb([12, 200, 99]), Rgb([90, 90, 90])] {
+            assert_eq!(hsl_to_rgb(rgb_to_hsl(&color)), color);
+        }
+        let red = Rgb([255u8, 0, 0]);
+        assert_eq!(
+            nudge_color(&red, ColorChannel::Green, 300.0),
+            Rgb([255, 255, 0])
+        );
+        assert_eq!(
+            nudge_color(&red, ColorChannel::Hue, 120.0),
+            Rgb([0, 255, 0])
+        );
+        assert_eq!(
+            nudge_color(&red, ColorChannel::Hue, -120.0),
+            Rgb([0, 0, 255])
+        );
+        let lighter = nudge_color(&red, ColorChannel::Lightness, 25.0);
+        assert_eq!(lighter, Rgb([255, 128, 128]));
+        assert_eq!(ColorChannel::Lightness.value(&lighter).round(), 75.0);
+    }
+
+    #[test]
+    fn test_generate_ramps() {
+        let keys = vec![Rgb([200u8, 60, 60]), Rgb([60, 160, 60])];
+        let ramps = generate_ramps(&keys, 5, 20.0).unwrap();
+        assert_eq!(ramps.len(), 10);
+        assert_eq!(ramps[2], keys[0]);
+        assert_eq!(ramps[7], keys[1]);
+        for ramp in ramps.chunks(5) {
+            let lightness = ramp
+                .iter()
+                .map(|c| ColorChannel::Lightness.value(c))
+                .collect::<Vec<_>>();
+            assert!(lightness.windows(2).all(|w| w[0] < w[1]));
+        }
+        // red shadows turn toward blue (purple), highlights toward yellow (orange)
+        assert!(ColorChannel::Hue.value(&ramps[0]) > 300.0);
+        assert!(ColorChannel::Hue.value(&ramps[4]) > 5.0);
+        assert!(generate_ramps(&keys, 0, 20.0).is_err());
+    }
+
+    #[test]
+    fn test_detect_ramps() {
+        let reds = generate_ramps(&vec![Rgb([200u8, 40, 50])], 4, 10.0).unwrap();
+        let greens = generate_ramps(&vec![Rgb([60u8, 170, 60])], 3, 10.0).unwrap();
+        let grays = vec![Rgb([255u8, 255, 255]), Rgb([0, 0, 0]), Rgb([128, 128, 128])];
+        let mut palette = [reds.clone(), grays, greens.clone()].concat();
+        palette.reverse();
+
+        let ramps = detect_ramps(&palette, 30.0);
+        assert_eq!(ramps.len(), 3);
+        assert!(ramps[0].hue.is_some());
+        assert_eq!(ramps[0].colors.0, reds);
+        assert_eq!(ramps[1].colors.0, greens);
+        assert_eq!(ramps[2].hue, None);
+        assert_eq!(
+            ramps[2].colors.0,
+            vec![Rgb([0, 0, 0]), Rgb([128, 128, 128]), Rgb([255, 255, 255])]
+        );
+    }
+
+    #[test]
+    fn test_palette_adjustments() {
+        let palette = vec![Rgb([255u8, 0, 0]), Rgb([0, 0, 255]), Rgb([128, 128, 128])];
+        assert_eq!(
+            shift_palette_hue(&palette, 120.0),
+            vec![Rgb([0, 255, 0]), Rgb([255, 0, 0]), Rgb([128, 128, 128])]
+        );
+        assert_eq!(
+            lighten_palette(&palette, -25.0),
+            vec![Rgb([128, 0, 0]), Rgb([0, 0, 128]), Rgb([64, 64, 64])]
+        );
+        let gray = saturate_palette(&palette, -100.0);
+        assert!(gray.iter().all(|c| c[0] == c[1] && c[1] == c[2]));
+    }
+
+    #[test]
+    fn test_hue_ranges() {
+        let blue = "blue".parse::<HueRange>().unwrap();
+        let wrapping = "330-30".parse::<HueRange>().unwrap();
+        assert!(blue.contains(220.0) && !blue.contains(100.0));
+        assert!(wrapping.contains(350.0) && wrapping.contains(10.0) && !wrapping.contains(180.0));
+        assert_eq!(wrapping.to_string(), "330-30");
+        assert!("10-400".parse::<HueRange>().is_err());
+
+        let palette = vec![
+            Rgb([0, 0, 255]),
+            Rgb([255, 0, 0]),
+            Rgb([0, 255, 0]),
+            Rgb([128, 128, 128]),
+        ];
+        let constrained = constrain_palette_hues(&palette, &[blue, "purple".parse().unwrap()]);
+        assert_eq!(constrained[0], Rgb([0, 0, 255]));
+        // red is closest to the purple edge, green to the blue one
+        assert!((rgb_to_hsl(&constrained[1])[0] - 290.0).abs() < 1.0);
+        assert!((rgb_to_hsl(&constrained[2])[0] - 195.0).abs() < 1.0);
+        assert_eq!(constrained[3], Rgb([128, 128, 128]));
+    }
+}
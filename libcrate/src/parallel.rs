@@ -0,0 +1,105 @@
+// rayon with the parallel feature, otherwise plain iterators behind the same method names
+use crate::RgbHistogram;
+use image::Rgb;
+use std::collections::HashMap;
+
+#[cfg(feature = "parallel")]
+pub use rayon::prelude::*;
+
+#[cfg(not(feature = "parallel"))]
+pub use serial::*;
+
+#[cfg(feature = "parallel")]
+pub fn count_colors<I>(colors: I) -> RgbHistogram
+where
+    I: ParallelIterator<Item = Rgb<u8>>,
+{
+    colors
+        .fold(HashMap::new, |mut local_map, color| {
+            *local_map.entry(color).or_insert(0) += 1;
+            local_map
+        })
+        .reduce(HashMap::new, |mut map1, map2| {
+            for (k, v) in map2 {
+                *map1.entry(k).or_insert(0) += v;
+            }
+            map1
+        })
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn count_colors<I>(colors: I) -> RgbHistogram
+where
+    I: Iterator<Item = Rgb<u8>>,
+{
+    let mut map = HashMap::new();
+    for color in colors {
+        *map.entry(color).or_insert(0) += 1;
+    }
+    map
+}
+
+#[cfg(not(feature = "parallel"))]
+mod serial {
+    use image::buffer::{EnumeratePixelsMut, Pixels, PixelsMut};
+    use image::{ImageBuffer, Pixel};
+    use std::slice::{Chunks, ChunksExact, ChunksMut, Iter};
+
+    pub trait ParallelBridge: Iterator + Sized {
+        fn par_bridge(self) -> Self {
+            self
+        }
+    }
+
+    impl<I: Iterator> ParallelBridge for I {}
+
+    pub trait ParallelSlice<T> {
+        fn par_iter(&self) -> Iter<'_, T>;
+        fn par_chunks(&self, size: usize) -> Chunks<'_, T>;
+        fn par_chunks_exact(&self, size: usize) -> ChunksExact<'_, T>;
+    }
+
+    impl<T> ParallelSlice<T> for [T] {
+        fn par_iter(&self) -> Iter<'_, T> {
+            self.iter()
+        }
+
+        fn par_chunks(&self, size: usize) -> Chunks<'_, T> {
+            self.chunks(size)
+        }
+
+        fn par_chunks_exact(&self, size: usize) -> ChunksExact<'_, T> {
+            self.chunks_exact(size)
+        }
+    }
+
+    pub trait ParallelSliceMut<T> {
+        fn par_chunks_mut(&mut self, size: usize) -> ChunksMut<'_, T>;
+    }
+
+    impl<T> ParallelSliceMut<T> for [T] {
+        fn par_chunks_mut(&mut self, size: usize) -> ChunksMut<'_, T> {
+            self.chunks_mut(size)
+        }
+    }
+
+    pub trait ParallelPixels<P: Pixel> {
+        fn par_pixels(&self) -> Pixels<'_, P>;
+        fn par_pixels_mut(&mut self) -> PixelsMut<'_, P>;
+        fn par_enumerate_pixels_mut(&mut self) -> EnumeratePixelsMut<'_, P>;
+    }
+
+    impl<P: Pixel> ParallelPixels<P> for ImageBuffer<P, Vec<P::Subpixel>> {
+        fn par_pixels(&self) -> Pixels<'_, P> {
+            self.pixels()
+        }
+
+        fn par_pixels_mut(&mut self) -> PixelsMut<'_, P> {
+            self.pixels_mut()
+        }
+
+        fn par_enumerate_pixels_mut(&mut self) -> EnumeratePixelsMut<'_, P> {
+            self.enumerate_pixels_mut()
+        }
+    }
+}
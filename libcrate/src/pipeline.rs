@@ -0,0 +1,567 @@
+use crate::adjust::{AutoBalance, Curve, CurveChannel, Levels};
+use crate::bitdepth::BitDepth;
+use crate::dither::{Dither, DitherOptions, check_quantizer_dither};
+use crate::hue_lightness::{HueLightness, HueSpace};
+use crate::noise::{NoiseKind, NoiseOptions};
+use crate::outline::OutlineColor;
+use crate::palette::{HexPalette, HueRange, RemapStrategy, constrain_palette_hues};
+use crate::threshold::Threshold;
+use crate::traversal::Traversal;
+use crate::{Palette, ProcessedImage, ScaleFilter};
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+pub struct PipelineState {
+    pub image: ProcessedImage,
+    pub palette: Option<Palette>,
+}
+
+impl PipelineState {
+    pub fn new(image: ProcessedImage) -> Self {
+        PipelineState {
+            image,
+            palette: None,
+        }
+    }
+}
+
+pub trait PipelineStage: Send + Sync {
+    fn name(&self) -> &str;
+    fn apply(&self, state: &mut PipelineState, params: &Value) -> Result<()>;
+
+    // called for every stage before the first image is processed, so bad parameters fail early
+    fn check(&self, _params: &Value) -> Result<()> {
+        Ok(())
+    }
+}
+
+// a stage reference as written on the command line, `name` or `name:{"key": value}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StageSpec {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub params: Value,
+}
+
+impl StageSpec {
+    pub fn new(name: &str, params: Value) -> Self {
+        StageSpec {
+            name: name.to_string(),
+            params,
+        }
+    }
+}
+
+impl FromStr for StageSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, params) = match s.split_once(':') {
+            Some((name, params)) => (
+                name,
+                serde_json::from_str(params)
+                    .with_context(|| format!("Invalid parameters of stage {}", name))?,
+            ),
+            None => (s, Value::Null),
+        };
+        ensure!(!name.trim().is_empty(), "Missing stage name in {}", s);
+        Ok(StageSpec {
+            name: name.trim().to_string(),
+            params,
+        })
+    }
+}
+
+impl fmt::Display for StageSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.params {
+            Value::Null => write!(f, "{}", self.name),
+            params => write!(f, "{}:{}", self.name, params),
+        }
+    }
+}
+
+pub struct StageRegistry {
+    stages: BTreeMap<String, Box<dyn PipelineStage>>,
+}
+
+impl StageRegistry {
+    pub fn new() -> Self {
+        StageRegistry {
+            stages: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_builtins() -> Self {
+        let mut registry = StageRegistry::new();
+        for stage in builtin_stages() {
+            registry.stages.insert(stage.name().to_string(), stage);
+        }
+        registry
+    }
+
+    pub fn register<S>(&mut self, stage: S) -> Result<()>
+    where
+        S: PipelineStage + 'static,
+    {
+        let name = stage.name().to_string();
+        ensure!(
+            !self.stages.contains_key(&name),
+            "Stage {} is already registered",
+            name
+        );
+        self.stages.insert(name, Box::new(stage));
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn PipelineStage> {
+        self.stages.get(name).map(|stage| stage.as_ref())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.stages.keys().map(|name| name.as_str())
+    }
+
+    fn find(&self, name: &str) -> Result<&dyn PipelineStage> {
+        self.get(name).ok_or_else(|| {
+            anyhow!(
+                "Unknown stage {}, expected one of {}",
+                name,
+                self.names().collect::<Vec<_>>().join(", ")
+            )
+        })
+    }
+
+    pub fn check(&self, specs: &[StageSpec]) -> Result<()> {
+        specs.iter().try_for_each(|spec| {
+            self.find(&spec.name)?
+                .check(&spec.params)
+                .with_context(|| format!("Invalid stage {}", spec))
+        })
+    }
+
+    pub fn apply(&self, spec: &StageSpec, state: &mut PipelineState) -> Result<()> {
+        self.find(&spec.name)?
+            .apply(state, &spec.params)
+            .with_context(|| format!("Stage {} failed", spec.name))
+    }
+
+    pub fn run(&self, specs: &[StageSpec], state: &mut PipelineState) -> Result<()> {
+        specs.iter().try_for_each(|spec| self.apply(spec, state))
+    }
+}
+
+impl Default for StageRegistry {
+    fn default() -> Self {
+        StageRegistry::with_builtins()
+    }
+}
+
+// missing parameters are an empty object, so stages with all-default parameters need none
+pub fn stage_params<T>(params: &Value) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let params = match params {
+        Value::Null => Value::Object(Map::new()),
+        params => params.clone(),
+    };
+    serde_json::from_value(params).with_context(|| "Invalid stage parameters")
+}
+
+struct FnStage<P, F> {
+    name: &'static str,
+    apply: F,
+    params: PhantomData<fn() -> P>,
+}
+
+impl<P, F> PipelineStage for FnStage<P, F>
+where
+    P: DeserializeOwned,
+    F: Fn(&mut PipelineState, P) -> Result<()> + Send + Sync,
+{
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn apply(&self, state: &mut PipelineState, params: &Value) -> Result<()> {
+        (self.apply)(state, stage_params(params)?)
+    }
+
+    fn check(&self, params: &Value) -> Result<()> {
+        stage_params::<P>(params).map(|_| ())
+    }
+}
+
+fn stage<P, F>(name: &'static str, apply: F) -> Box<dyn PipelineStage>
+where
+    P: DeserializeOwned + 'static,
+    F: Fn(&mut PipelineState, P) -> Result<()> + Send + Sync + 'static,
+{
+    Box::new(FnStage {
+        name,
+        apply,
+        params: PhantomData,
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScaleParams {
+    width: Option<u32>,
+    height: Option<u32>,
+    percent: Option<f64>,
+    #[serde(default)]
+    filter: ScaleFilter,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoiseParams {
+    kind: NoiseKind,
+    amplitude: f32,
+    #[serde(default)]
+    seed: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AutoBalanceParams {
+    method: AutoBalance,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LevelsParams {
+    #[serde(default)]
+    black: u8,
+    #[serde(default = "default_white")]
+    white: u8,
+    #[serde(default = "default_gamma")]
+    gamma: f32,
+}
+
+fn default_white() -> u8 {
+    255
+}
+
+fn default_gamma() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CurveParams {
+    #[serde(default)]
+    channel: CurveChannel,
+    points: Vec<(u8, u8)>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TrimParams {
+    #[serde(default)]
+    tolerance: u8,
+    #[serde(default)]
+    padding: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuantizeParams {
+    colors: usize,
+    #[serde(default = "default_sample_factor")]
+    sample_factor: i32,
+    #[serde(default)]
+    hues: Vec<HueRange>,
+}
+
+fn default_sample_factor() -> i32 {
+    10
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HueLightnessParams {
+    hues: usize,
+    steps: usize,
+    #[serde(default)]
+    space: HueSpace,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DitherParams {
+    method: Dither,
+    #[serde(default = "default_strength")]
+    strength: f32,
+    error_clamp: Option<f32>,
+    #[serde(default)]
+    serpentine: bool,
+    #[serde(default)]
+    traversal: Traversal,
+}
+
+fn default_strength() -> f32 {
+    1.0
+}
+
+impl DitherParams {
+    fn options(params: Option<&DitherParams>) -> DitherOptions {
+        match params {
+            Some(params) => DitherOptions {
+                strength: params.strength,
+                error_clamp: params.error_clamp,
+                serpentine: params.serpentine,
+                traversal: params.traversal,
+                ..DitherOptions::new(params.method)
+            },
+            None => DitherOptions::default(),
+        }
+    }
+}
+
+// bit depth and threshold quantize without a palette, reject mix before any input is read
+fn quantizer_dither<'de, D>(deserializer: D) -> Result<Option<DitherParams>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let params = Option::<DitherParams>::deserialize(deserializer)?;
+    if let Some(params) = &params {
+        check_quantizer_dither(params.method).map_err(serde::de::Error::custom)?;
+    }
+    Ok(params)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BitDepthParams {
+    depth: BitDepth,
+    #[serde(default, deserialize_with = "quantizer_dither")]
+    dither: Option<DitherParams>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThresholdParams {
+    threshold: Threshold,
+    #[serde(default, deserialize_with = "quantizer_dither")]
+    dither: Option<DitherParams>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RemapParams {
+    colors: HexPalette,
+    #[serde(default)]
+    strategy: RemapStrategy,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OutlineParams {
+    color: OutlineColor,
+}
+
+// the colors left after reducing them directly, most used first
+fn image_colors(image: &ProcessedImage) -> Palette {
+    let mut colors = image.get_color_histogram().into_iter().collect::<Vec<_>>();
+    colors.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.0.cmp(&b.0.0)));
+    colors.into_iter().map(|(color, _)| color).collect()
+}
+
+fn builtin_stages() -> Vec<Box<dyn PipelineStage>> {
+    vec![
+        stage("scale", |state, params: ScaleParams| {
+            let image = &mut state.image;
+            match (params.width, params.height, params.percent) {
+                (Some(width), Some(height), None) => image.scale(width, height, params.filter),
+                (Some(width), None, None) => image.uniform_scale_width(width, params.filter),
+                (None, Some(height), None) => image.uniform_scale_height(height, params.filter),
+                (None, None, Some(percent)) => image.scale_percent(percent, params.filter),
+                _ => bail!("Expected width and/or height, or percent"),
+            }
+            Ok(())
+        }),
+        stage("noise", |state, params: NoiseParams| {
+            state.image.add_noise(&NoiseOptions {
+                seed: params.seed,
+                ..NoiseOptions::new(params.kind, params.amplitude)
+            });
+            Ok(())
+        }),
+        stage("auto-balance", |state, params: AutoBalanceParams| {
+            state.image.auto_balance(params.method);
+            Ok(())
+        }),
+        stage("levels", |state, params: LevelsParams| {
+            let levels = Levels::new(params.black, params.white, params.gamma)?;
+            state.image.levels(&levels);
+            Ok(())
+        }),
+        stage("curve", |state, params: CurveParams| {
+            state
+                .image
+                .curve(&Curve::new(params.channel, params.points)?);
+            Ok(())
+        }),
+        stage("trim", |state, params: TrimParams| {
+            state.image.trim(None, params.tolerance, params.padding);
+            Ok(())
+        }),
+        stage("quantize", |state, params: QuantizeParams| {
+            let mut source = state.image.clone();
+            source.constrain_hues(&params.hues);
+            let palette = constrain_palette_hues(
+                &source.generate_image_palette(params.sample_factor, params.colors),
+                &params.hues,
+            );
+            state.image.apply_palette(&palette);
+            state.palette = Some(palette);
+            Ok(())
+        }),
+        stage("hue-lightness", |state, params: HueLightnessParams| {
+            let options = HueLightness::new(params.hues, params.steps, params.space)?;
+            let palette = state.image.generate_hue_lightness_palette(&options);
+            state.image.apply_palette(&palette);
+            state.palette = Some(palette);
+            Ok(())
+        }),
+        stage("dither", |state, params: DitherParams| {
+            let palette = state
+                .palette
+                .as_ref()
+                .ok_or_else(|| anyhow!("Dithering needs a palette, add a quantize stage first"))?;
+            state
+                .image
+                .dither_with(palette, &DitherParams::options(Some(&params)));
+            Ok(())
+        }),
+        stage("bit-depth", |state, params: BitDepthParams| {
+            let options = DitherParams::options(params.dither.as_ref());
+            state.image.reduce_bit_depth(params.depth, &options)?;
+            state.palette = Some(image_colors(&state.image));
+            Ok(())
+        }),
+        stage("threshold", |state, params: ThresholdParams| {
+            let options = DitherParams::options(params.dither.as_ref());
+            state.image.threshold(params.threshold, &options)?;
+            state.palette = Some(image_colors(&state.image));
+            Ok(())
+        }),
+        stage("remap", |state, params: RemapParams| {
+            let palette = state
+                .palette
+                .as_ref()
+                .ok_or_else(|| anyhow!("Remapping needs a palette, add a quantize stage first"))?;
+            state
+                .image
+                .remap_palette(palette, &params.colors, params.strategy)?;
+            state.palette = Some(params.colors.0);
+            Ok(())
+        }),
+        stage("outline", |state, params: OutlineParams| {
+            let color = match (params.color, &state.palette) {
+                (OutlineColor::Fixed(color), _) => color,
+                (color, Some(palette)) => color.resolve(&state.image, palette)?,
+                (color, None) => bail!(
+                    "Outline color {} needs a palette, add a quantize stage first",
+                    color
+                ),
+            };
+            state.image.outline(color)
+        }),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ProcessedImage;
+    use crate::pipeline::{PipelineStage, PipelineState, StageRegistry, StageSpec};
+    use anyhow::Result;
+    use image::Rgb;
+    use serde_json::Value;
+
+    struct Invert;
+
+    impl PipelineStage for Invert {
+        fn name(&self) -> &str {
+            "invert"
+        }
+
+        fn apply(&self, state: &mut PipelineState, _: &Value) -> Result<()> {
+            state.image.map_pixels(|_, _, p| Rgb(p.0.map(|c| 255 - c)));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = StageRegistry::with_builtins();
+        registry.register(Invert).unwrap();
+        assert!(registry.register(Invert).is_err());
+
+        let image = ProcessedImage::from_buffer(4, 2, &[Rgb([10, 20, 30]); 8]);
+        let mut state = PipelineState::new(image);
+        let specs = [
+            "invert".parse::<StageSpec>().unwrap(),
+            r#"scale:{"width": 2}"#.parse().unwrap(),
+            r#"quantize:{"colors": 2}"#.parse().unwrap(),
+        ];
+        registry.run(&specs[..2], &mut state).unwrap();
+        assert_eq!(state.image.width(), 2);
+        assert_eq!(state.image.height(), 1);
+        assert_eq!(*state.image.data.get_pixel(0, 0), Rgb([245, 235, 225]));
+        registry.apply(&specs[2], &mut state).unwrap();
+        assert!(state.palette.is_some());
+
+        let unknown = "stylize".parse::<StageSpec>().unwrap();
+        assert!(registry.apply(&unknown, &mut state).is_err());
+        let invalid = r#"scale:{"size": 2}"#.parse::<StageSpec>().unwrap();
+        assert!(registry.apply(&invalid, &mut state).is_err());
+        assert_eq!(specs[1].to_string(), r#"scale:{"width":2}"#);
+
+        assert!(registry.check(&specs).is_ok());
+        assert!(registry.check(&[unknown]).is_err());
+        assert!(registry.check(&[invalid]).is_err());
+        let missing = "quantize".parse::<StageSpec>().unwrap();
+        assert!(registry.check(&[missing]).is_err());
+    }
+
+    #[test]
+    fn test_reduce_and_remap_stages() {
+        let registry = StageRegistry::with_builtins();
+        let colors = [Rgb([250, 10, 10]), Rgb([20, 30, 240]), Rgb([250, 10, 10])];
+        let image = ProcessedImage::from_buffer(3, 1, &colors);
+        let mut state = PipelineState::new(image);
+        let specs = [
+            r#"bit-depth:{"depth": "rgb332", "dither": {"method": "none"}}"#
+                .parse::<StageSpec>()
+                .unwrap(),
+            r##"remap:{"colors": ["#ff0000", "#0000ff"], "strategy": "index"}"##
+                .parse()
+                .unwrap(),
+        ];
+        registry.check(&specs).unwrap();
+        let mix = r#"threshold:{"threshold": "otsu", "dither": {"method": "mix"}}"#;
+        assert!(registry.check(&[mix.parse().unwrap()]).is_err());
+        registry.apply(&specs[0], &mut state).unwrap();
+        let reduced = state.palette.clone().unwrap();
+        assert_eq!(reduced.len(), 2);
+        assert_eq!(*state.image.data.get_pixel(0, 0), reduced[0]);
+
+        registry.apply(&specs[1], &mut state).unwrap();
+        assert_eq!(*state.image.data.get_pixel(0, 0), Rgb([255, 0, 0]));
+        assert_eq!(*state.image.data.get_pixel(1, 0), Rgb([0, 0, 255]));
+        assert_eq!(
+            state.palette.unwrap(),
+            vec![Rgb([255, 0, 0]), Rgb([0, 0, 255])]
+        );
+    }
+}
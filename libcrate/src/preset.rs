@@ -0,0 +1,144 @@
+use crate::dither::Dither;
+use crate::export::gameboy::dmg_palette;
+use crate::export::pico8::pico8_palette;
+use crate::image_processing::palette_from_tuples;
+use crate::{Palette, ProcessedImage, ScaleFilter};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+pub const C64_PALETTE: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0xFF, 0xFF, 0xFF),
+    (0x68, 0x37, 0x2B),
+    (0x70, 0xA4, 0xB2),
+    (0x6F, 0x3D, 0x86),
+    (0x58, 0x8D, 0x43),
+    (0x35, 0x28, 0x79),
+    (0xB8, 0xC7, 0x6F),
+    (0x6F, 0x4F, 0x25),
+    (0x43, 0x39, 0x00),
+    (0x9A, 0x67, 0x59),
+    (0x44, 0x44, 0x44),
+    (0x6C, 0x6C, 0x6C),
+    (0x9A, 0xD2, 0x84),
+    (0x6C, 0x5E, 0xB5),
+    (0x95, 0x95, 0x95),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    GameBoy,
+    Pico8,
+    C64,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 3] = [Preset::GameBoy, Preset::Pico8, Preset::C64];
+
+    pub fn palette(&self) -> Palette {
+        match self {
+            Preset::GameBoy => dmg_palette(),
+            Preset::Pico8 => pico8_palette(),
+            Preset::C64 => palette_from_tuples(&C64_PALETTE),
+        }
+    }
+
+    pub fn resolution(&self) -> (u32, u32) {
+        match self {
+            Preset::GameBoy => (160, 144),
+            Preset::Pico8 => (128, 128),
+            Preset::C64 => (160, 200),
+        }
+    }
+
+    pub fn pixel_aspect(&self) -> u32 {
+        match self {
+            Preset::C64 => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn dither(&self) -> Dither {
+        match self {
+            Preset::GameBoy => Dither::Bayer4,
+            Preset::Pico8 => Dither::FloydSteinberg,
+            Preset::C64 => Dither::Bayer2,
+        }
+    }
+
+    pub fn prepare(&self, image: &mut ProcessedImage) {
+        let (width, height) = self.resolution();
+        let aspect = self.pixel_aspect();
+        let (display_width, display_height) = image.uniform_size_for_width(width * aspect);
+        let (display_width, display_height) = if display_height > height {
+            image.uniform_size_for_height(height)
+        } else {
+            (display_width, display_height)
+        };
+        image.scale(
+            (display_width / aspect).max(1),
+            display_height.max(1),
+            ScaleFilter::Lanczos3,
+        );
+    }
+
+    pub fn finish(&self, image: &mut ProcessedImage) {
+        let aspect = self.pixel_aspect();
+        if aspect > 1 {
+            image.scale(image.width() * aspect, image.height(), ScaleFilter::Nearest);
+        }
+    }
+}
+
+impl FromStr for Preset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "gameboy" | "gb" => Ok(Preset::GameBoy),
+            "pico8" | "pico-8" => Ok(Preset::Pico8),
+            "c64" => Ok(Preset::C64),
+            _ => Err(anyhow!(
+                "Unknown preset {}, expected gameboy, pico8 or c64",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Preset::GameBoy => "Game Boy",
+            Preset::Pico8 => "PICO-8",
+            Preset::C64 => "C64",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ProcessedImage;
+    use crate::preset::Preset;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_preset_sizes() {
+        let data = RgbImage::from_pixel(640, 400, Rgb([10, 20, 30]));
+        let mut image = ProcessedImage { data, alpha: None };
+        Preset::GameBoy.prepare(&mut image);
+        assert_eq!((image.width(), image.height()), (160, 100));
+
+        let data = RgbImage::from_pixel(640, 400, Rgb([10, 20, 30]));
+        let mut image = ProcessedImage { data, alpha: None };
+        Preset::C64.prepare(&mut image);
+        assert_eq!((image.width(), image.height()), (160, 200));
+        Preset::C64.finish(&mut image);
+        assert_eq!((image.width(), image.height()), (320, 200));
+        assert_eq!(Preset::C64.palette().len(), 16);
+    }
+}
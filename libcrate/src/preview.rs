@@ -0,0 +1,203 @@
+use crate::ProcessedImage;
+use crate::font::{draw_text, text_height, text_width};
+use crate::image_processing::blend;
+use anyhow::{Result, anyhow, ensure};
+use image::imageops::FilterType;
+use image::{Rgb, RgbImage};
+use std::str::FromStr;
+
+const DIVIDER: Rgb<u8> = Rgb([255, 255, 255]);
+const DIVIDER_WIDTH: u32 = 2;
+const MONTAGE_BACKGROUND: Rgb<u8> = Rgb([32, 32, 32]);
+const MONTAGE_TEXT: Rgb<u8> = Rgb([230, 230, 230]);
+const MONTAGE_GAP: u32 = 4;
+const LABEL_SCALE: u32 = 2;
+const CHECKER_LIGHT: Rgb<u8> = Rgb([204, 204, 204]);
+const CHECKER_DARK: Rgb<u8> = Rgb([153, 153, 153]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareLayout {
+    SideBySide,
+    Split,
+}
+
+impl FromStr for CompareLayout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "side" | "side-by-side" => Ok(CompareLayout::SideBySide),
+            "split" => Ok(CompareLayout::Split),
+            _ => Err(anyhow!(
+                "Unknown compare layout {}, expected side or split",
+                s
+            )),
+        }
+    }
+}
+
+pub fn compare(before: &RgbImage, after: &RgbImage, layout: CompareLayout) -> RgbImage {
+    let (width, height) = before.dimensions();
+    let after = if after.dimensions() == (width, height) {
+        after.clone()
+    } else {
+        image::imageops::resize(after, width, height, FilterType::Nearest)
+    };
+    match layout {
+        CompareLayout::SideBySide => {
+            let mut result = RgbImage::from_pixel(2 * width + DIVIDER_WIDTH, height, DIVIDER);
+            image::imageops::replace(&mut result, before, 0, 0);
+            image::imageops::replace(&mut result, &after, (width + DIVIDER_WIDTH) as i64, 0);
+            result
+        }
+        CompareLayout::Split => {
+            let middle = width / 2;
+            RgbImage::from_fn(width, height, |x, y| {
+                if x.abs_diff(middle) < DIVIDER_WIDTH / 2 {
+                    DIVIDER
+                } else if x < middle {
+                    *before.get_pixel(x, y)
+                } else {
+                    *after.get_pixel(x, y)
+                }
+            })
+        }
+    }
+}
+
+pub fn compare_images(
+    before: &ProcessedImage,
+    after: &ProcessedImage,
+    layout: CompareLayout,
+) -> ProcessedImage {
+    ProcessedImage {
+        data: compare(&before.data, &after.data, layout),
+        alpha: None,
+    }
+}
+
+pub fn checkerboard(width: u32, height: u32, cell: u32) -> RgbImage {
+    let cell = cell.max(1);
+    RgbImage::from_fn(width, height, |x, y| match (x / cell + y / cell) % 2 {
+        0 => CHECKER_LIGHT,
+        _ => CHECKER_DARK,
+    })
+}
+
+// the image over the usual editor checkerboard, so transparency shows in any viewer
+pub fn checkerboard_preview(image: &ProcessedImage, cell: u32) -> ProcessedImage {
+    let mut data = checkerboard(image.width(), image.height(), cell);
+    data.enumerate_pixels_mut().for_each(|(x, y, p)| {
+        let alpha = image.alpha.as_ref().map_or(255, |a| a.get_pixel(x, y)[0]);
+        *p = blend(image.data.get_pixel(x, y), p, alpha);
+    });
+    ProcessedImage { data, alpha: None }
+}
+
+pub fn montage(images: &[(String, &RgbImage)], columns: u32, labels: bool) -> Result<RgbImage> {
+    ensure!(!images.is_empty(), "No images for the montage");
+    ensure!(columns > 0, "Montage needs at least one column");
+    let mut cell_width = images.iter().map(|(_, i)| i.width()).max().unwrap_or(0);
+    if labels {
+        let label_width = images
+            .iter()
+            .map(|(label, _)| text_width(label, LABEL_SCALE))
+            .max()
+            .unwrap_or(0);
+        cell_width = cell_width.max(label_width);
+    }
+    let image_height = images.iter().map(|(_, i)| i.height()).max().unwrap_or(0);
+    let label_height = if labels {
+        text_height(LABEL_SCALE) + MONTAGE_GAP
+    } else {
+        0
+    };
+    let cell_height = image_height + label_height;
+    let columns = columns.min(images.len() as u32);
+    let rows = (images.len() as u32).div_ceil(columns);
+    let mut result = RgbImage::from_pixel(
+        columns * (cell_width + MONTAGE_GAP) + MONTAGE_GAP,
+        rows * (cell_height + MONTAGE_GAP) + MONTAGE_GAP,
+        MONTAGE_BACKGROUND,
+    );
+    for (idx, (label, img)) in images.iter().enumerate() {
+        let idx = idx as u32;
+        let x = MONTAGE_GAP + (idx % columns) * (cell_width + MONTAGE_GAP);
+        let y = MONTAGE_GAP + (idx / columns) * (cell_height + MONTAGE_GAP);
+        image::imageops::replace(&mut result, *img, x as i64, y as i64);
+        if labels {
+            draw_text(
+                &mut result,
+                x,
+                y + image_height + MONTAGE_GAP,
+                label,
+                MONTAGE_TEXT,
+                LABEL_SCALE,
+            );
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ProcessedImage;
+    use crate::preview::{
+        CHECKER_DARK, CHECKER_LIGHT, CompareLayout, DIVIDER, MONTAGE_BACKGROUND, MONTAGE_TEXT,
+        checkerboard_preview, compare, montage,
+    };
+    use image::{GrayImage, Luma, Rgb, RgbImage};
+
+    #[test]
+    fn test_compare() {
+        let before = RgbImage::from_pixel(8, 4, Rgb([10, 10, 10]));
+        let after = RgbImage::from_pixel(4, 2, Rgb([200, 0, 0]));
+        let side = compare(&before, &after, CompareLayout::SideBySide);
+        assert_eq!(side.dimensions(), (18, 4));
+        assert_eq!(side.get_pixel(0, 0), &Rgb([10, 10, 10]));
+        assert_eq!(side.get_pixel(8, 0), &DIVIDER);
+        assert_eq!(side.get_pixel(17, 3), &Rgb([200, 0, 0]));
+
+        let split = compare(&before, &after, CompareLayout::Split);
+        assert_eq!(split.dimensions(), (8, 4));
+        assert_eq!(split.get_pixel(0, 0), &Rgb([10, 10, 10]));
+        assert_eq!(split.get_pixel(4, 0), &DIVIDER);
+        assert_eq!(split.get_pixel(7, 0), &Rgb([200, 0, 0]));
+    }
+
+    #[test]
+    fn test_checkerboard_preview() {
+        let mut image = ProcessedImage::from(RgbImage::from_pixel(8, 4, Rgb([255, 0, 0])));
+        let mut alpha = GrayImage::from_pixel(8, 4, Luma([0]));
+        alpha.put_pixel(0, 0, Luma([255]));
+        image.alpha = Some(alpha);
+        let preview = checkerboard_preview(&image, 4);
+        assert!(preview.alpha.is_none());
+        assert_eq!(*preview.data.get_pixel(0, 0), Rgb([255, 0, 0]));
+        assert_eq!(*preview.data.get_pixel(1, 0), CHECKER_LIGHT);
+        assert_eq!(*preview.data.get_pixel(4, 0), CHECKER_DARK);
+        assert_eq!(*preview.data.get_pixel(4, 3), CHECKER_DARK);
+    }
+
+    #[test]
+    fn test_montage() {
+        let red = RgbImage::from_pixel(20, 10, Rgb([255, 0, 0]));
+        let blue = RgbImage::from_pixel(10, 20, Rgb([0, 0, 255]));
+        let images = vec![
+            ("red".to_string(), &red),
+            ("blue".to_string(), &blue),
+            ("red".to_string(), &red),
+        ];
+        let result = montage(&images, 2, false).unwrap();
+        assert_eq!(result.dimensions(), (4 + 2 * 24, 4 + 2 * 24));
+        assert_eq!(result.get_pixel(4, 4), &Rgb([255, 0, 0]));
+        assert_eq!(result.get_pixel(28, 23), &Rgb([0, 0, 255]));
+        assert_eq!(result.get_pixel(4, 28), &Rgb([255, 0, 0]));
+        assert_eq!(result.get_pixel(0, 0), &MONTAGE_BACKGROUND);
+
+        let labeled = montage(&images, 3, true).unwrap();
+        assert_eq!(labeled.dimensions(), (4 + 3 * 34, 4 + 20 + 14 + 4));
+        assert!(labeled.pixels().any(|p| *p == MONTAGE_TEXT));
+        assert!(montage(&[], 3, true).is_err());
+    }
+}
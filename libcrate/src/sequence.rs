@@ -0,0 +1,116 @@
+use anyhow::{Result, anyhow, ensure};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+pub fn is_pattern(pattern: &str) -> bool {
+    parse_pattern(pattern).is_ok_and(|(_, width, _)| width.is_some())
+}
+
+fn parse_pattern(pattern: &str) -> Result<(String, Option<usize>, String)> {
+    let mut prefix = String::new();
+    let mut chars = pattern.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        if c != '%' {
+            prefix.push(c);
+            continue;
+        }
+        match chars.peek() {
+            Some((_, '%')) => {
+                chars.next();
+                prefix.push('%');
+            }
+            _ => {
+                let rest = &pattern[idx + 1..];
+                let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+                ensure!(
+                    rest[digits..].starts_with('d'),
+                    "Unsupported sequence pattern {}, expected %d or %0Nd",
+                    pattern
+                );
+                let width = if digits == 0 {
+                    0
+                } else {
+                    rest[..digits].parse()?
+                };
+                let suffix = &rest[digits + 1..];
+                ensure!(
+                    !suffix.replace("%%", "").contains('%'),
+                    "Sequence pattern {} can contain only one frame number",
+                    pattern
+                );
+                return Ok((prefix, Some(width), suffix.replace("%%", "%")));
+            }
+        }
+    }
+    Ok((prefix, None, String::new()))
+}
+
+pub fn format_pattern(pattern: &str, frame: u32) -> Result<String> {
+    match parse_pattern(pattern)? {
+        (prefix, Some(width), suffix) => Ok(format!(
+            "{}{:0width$}{}",
+            prefix,
+            frame,
+            suffix,
+            width = width
+        )),
+        _ => Err(anyhow!("{} is not a sequence pattern", pattern)),
+    }
+}
+
+pub fn sequence_paths(pattern: &str, frames: RangeInclusive<u32>) -> Result<Vec<PathBuf>> {
+    frames
+        .map(|frame| format_pattern(pattern, frame).map(PathBuf::from))
+        .collect()
+}
+
+pub fn detect_frames(pattern: &str) -> Result<RangeInclusive<u32>> {
+    let exists = |frame| format_pattern(pattern, frame).map(|p| Path::new(&p).exists());
+    let start = match (exists(0)?, exists(1)?) {
+        (true, _) => 0,
+        (false, true) => 1,
+        _ => return Err(anyhow!("No frames found for {}", pattern)),
+    };
+    let mut end = start;
+    while exists(end + 1)? {
+        end += 1;
+    }
+    Ok(start..=end)
+}
+
+pub fn parse_range(s: &str) -> Result<RangeInclusive<u32>> {
+    let (start, end) = s
+        .split_once('-')
+        .ok_or_else(|| anyhow!("Expected frame range as START-END, got {}", s))?;
+    let (start, end) = (start.trim().parse()?, end.trim().parse()?);
+    ensure!(start <= end, "Empty frame range {}", s);
+    Ok(start..=end)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sequence::{format_pattern, is_pattern, parse_range, sequence_paths};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_patterns() {
+        assert!(is_pattern("frame_%04d.png"));
+        assert!(is_pattern("frame_%d.png"));
+        assert!(!is_pattern("frame.png"));
+        assert!(!is_pattern("100%%.png"));
+        assert!(!is_pattern("frame_%s.png"));
+        assert_eq!(
+            format_pattern("frame_%04d.png", 7).unwrap(),
+            "frame_0007.png"
+        );
+        assert_eq!(format_pattern("f%d_%%.png", 12).unwrap(), "f12_%.png");
+        assert!(format_pattern("f%d_%d.png", 1).is_err());
+        assert_eq!(
+            sequence_paths("out/%02d.png", 9..=10).unwrap(),
+            vec![PathBuf::from("out/09.png"), PathBuf::from("out/10.png")]
+        );
+        assert_eq!(parse_range("3-5").unwrap(), 3..=5);
+        assert!(parse_range("5-3").is_err());
+        assert!(parse_range("5").is_err());
+    }
+}
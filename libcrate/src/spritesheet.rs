@@ -0,0 +1,307 @@
+use crate::ProcessedImage;
+#[cfg(feature = "io")]
+use anyhow::Context;
+use anyhow::{Result, ensure};
+use image::{GrayImage, Luma, RgbImage};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+#[cfg(feature = "io")]
+use std::fs;
+#[cfg(feature = "io")]
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct PackedSheet {
+    pub image: ProcessedImage,
+    pub frames: Vec<(String, Rect)>,
+}
+
+impl PackedSheet {
+    pub fn frame_map(&self) -> BTreeMap<&str, Rect> {
+        self.frames
+            .iter()
+            .map(|(name, rect)| (name.as_str(), *rect))
+            .collect()
+    }
+
+    #[cfg(feature = "io")]
+    pub fn save_frame_map<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        fs::write(
+            path.as_ref(),
+            serde_json::to_string_pretty(&self.frame_map())?,
+        )
+        .with_context(|| format!("Failed to save: {:?}", path.as_ref()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SheetLayout {
+    pub cell_width: u32,
+    pub cell_height: u32,
+    pub margin: u32,
+    pub spacing: u32,
+}
+
+impl SheetLayout {
+    pub fn new(cell_width: u32, cell_height: u32) -> Self {
+        SheetLayout {
+            cell_width,
+            cell_height,
+            margin: 0,
+            spacing: 0,
+        }
+    }
+
+    pub fn grid(&self, width: u32, height: u32) -> (u32, u32) {
+        let count = |size: u32, cell: u32| {
+            (size.saturating_sub(2 * self.margin) + self.spacing) / (cell + self.spacing)
+        };
+        (
+            count(width, self.cell_width),
+            count(height, self.cell_height),
+        )
+    }
+
+    pub fn cell_origin(&self, column: u32, row: u32) -> (u32, u32) {
+        (
+            self.margin + column * (self.cell_width + self.spacing),
+            self.margin + row * (self.cell_height + self.spacing),
+        )
+    }
+
+    pub fn cells(&self, width: u32, height: u32) -> Result<Vec<Rect>> {
+        ensure!(
+            self.cell_width > 0 && self.cell_height > 0,
+            "Cell size {}x{} is empty",
+            self.cell_width,
+            self.cell_height
+        );
+        let (columns, rows) = self.grid(width, height);
+        ensure!(
+            columns > 0 && rows > 0,
+            "Image {}x{} is smaller than a single cell",
+            width,
+            height
+        );
+        Ok((0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                let (x, y) = self.cell_origin(column, row);
+                Rect {
+                    x,
+                    y,
+                    width: self.cell_width,
+                    height: self.cell_height,
+                }
+            })
+            .collect())
+    }
+}
+
+pub fn slice(image: &ProcessedImage, layout: &SheetLayout) -> Result<Vec<ProcessedImage>> {
+    slice_and_process(image, layout, |_| {})
+}
+
+pub fn slice_and_process<F>(
+    image: &ProcessedImage,
+    layout: &SheetLayout,
+    mut process: F,
+) -> Result<Vec<ProcessedImage>>
+where
+    F: FnMut(&mut ProcessedImage),
+{
+    let cells = layout
+        .cells(image.width(), image.height())?
+        .into_iter()
+        .map(|rect| {
+            let mut cell = image.crop(rect.x, rect.y, rect.width, rect.height);
+            process(&mut cell);
+            cell
+        })
+        .collect();
+    Ok(cells)
+}
+
+#[cfg(feature = "io")]
+pub fn save_cells<P>(
+    cells: &[ProcessedImage],
+    directory: P,
+    prefix: &str,
+    extension: &str,
+) -> Result<Vec<PathBuf>>
+where
+    P: AsRef<Path>,
+{
+    fs::create_dir_all(directory.as_ref())?;
+    cells
+        .iter()
+        .enumerate()
+        .map(|(idx, cell)| {
+            let path = directory
+                .as_ref()
+                .join(format!("{}_{:03}.{}", prefix, idx, extension));
+            cell.save(&path)?;
+            Ok(path)
+        })
+        .collect()
+}
+
+pub fn pack(
+    sprites: &[(String, ProcessedImage)],
+    max_width: u32,
+    padding: u32,
+) -> Result<PackedSheet> {
+    ensure!(!sprites.is_empty(), "Nothing to pack");
+    // the frame map is keyed by name, a duplicate would hide one of the frames
+    let mut names = HashSet::new();
+    for (name, _) in sprites {
+        ensure!(names.insert(name), "Duplicate frame name {}", name);
+    }
+    let widest = sprites.iter().map(|(_, s)| s.width()).max().unwrap_or(0);
+    ensure!(
+        widest <= max_width,
+        "Sprite width {} exceeds the maximum sheet width {}",
+        widest,
+        max_width
+    );
+    let mut order = (0..sprites.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| std::cmp::Reverse(sprites[i].1.height()));
+
+    let mut rects = vec![None; sprites.len()];
+    let (mut x, mut y, mut shelf_height, mut sheet_width) = (0, 0, 0, 0);
+    for i in order {
+        let sprite = &sprites[i].1;
+        if x > 0 && x + sprite.width() > max_width {
+            x = 0;
+            y += shelf_height + padding;
+            shelf_height = 0;
+        }
+        rects[i] = Some(Rect {
+            x,
+            y,
+            width: sprite.width(),
+            height: sprite.height(),
+        });
+        sheet_width = sheet_width.max(x + sprite.width());
+        shelf_height = shelf_height.max(sprite.height());
+        x += sprite.width() + padding;
+    }
+    let sheet_height = y + shelf_height;
+
+    let has_alpha = sprites.iter().any(|(_, s)| s.alpha.is_some());
+    let mut data = RgbImage::new(sheet_width, sheet_height);
+    let mut alpha = has_alpha.then(|| GrayImage::new(sheet_width, sheet_height));
+    let mut frames = Vec::with_capacity(sprites.len());
+    for ((name, sprite), rect) in sprites.iter().zip(rects) {
+        let rect = rect.expect("every sprite is placed");
+        image::imageops::replace(&mut data, &sprite.data, rect.x as i64, rect.y as i64);
+        if let Some(alpha) = alpha.as_mut() {
+            let sprite_alpha = sprite.alpha.clone().unwrap_or_else(|| {
+                GrayImage::from_pixel(sprite.width(), sprite.height(), Luma([255]))
+            });
+            image::imageops::replace(alpha, &sprite_alpha, rect.x as i64, rect.y as i64);
+        }
+        frames.push((name.clone(), rect));
+    }
+    Ok(PackedSheet {
+        image: ProcessedImage { data, alpha },
+        frames,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::spritesheet::{Rect, SheetLayout, pack, slice, slice_and_process};
+    use crate::{ProcessedImage, ScaleFilter};
+    use image::Rgb;
+
+    fn get_sheet() -> ProcessedImage {
+        let buffer = (0..100u8).map(|i| Rgb([i, i, i])).collect::<Vec<_>>();
+        ProcessedImage::from_buffer(10, 10, &buffer)
+    }
+
+    #[test]
+    fn test_grid() {
+        let layout = SheetLayout {
+            cell_width: 3,
+            cell_height: 4,
+            margin: 1,
+            spacing: 1,
+        };
+        assert_eq!(layout.grid(10, 10), (2, 1));
+        assert_eq!(layout.grid(12, 10), (2, 1));
+        assert_eq!(layout.grid(13, 10), (3, 1));
+        assert_eq!(layout.cell_origin(2, 1), (9, 6));
+        assert_eq!(SheetLayout::new(5, 5).grid(10, 10), (2, 2));
+    }
+
+    #[test]
+    fn test_slice() {
+        let sheet = get_sheet();
+        let layout = SheetLayout {
+            margin: 1,
+            ..SheetLayout::new(4, 4)
+        };
+        let cells = slice(&sheet, &layout).unwrap();
+        assert_eq!(cells.len(), 4);
+        assert!(cells.iter().all(|c| c.width() == 4 && c.height() == 4));
+        assert_eq!(cells[0].data.get_pixel(0, 0), &Rgb([11, 11, 11]));
+        assert_eq!(cells[1].data.get_pixel(0, 0), &Rgb([15, 15, 15]));
+        assert_eq!(cells[3].data.get_pixel(3, 3), &Rgb([88, 88, 88]));
+
+        let mut processed = 0;
+        let cells = slice_and_process(&sheet, &layout, |cell| {
+            cell.scale(2, 2, ScaleFilter::Nearest);
+            processed += 1;
+        })
+        .unwrap();
+        assert_eq!(processed, 4);
+        assert!(cells.iter().all(|c| c.width() == 2 && c.height() == 2));
+        assert!(slice(&sheet, &SheetLayout::new(11, 1)).is_err());
+        assert!(slice(&sheet, &SheetLayout::new(0, 1)).is_err());
+    }
+
+    #[test]
+    fn test_pack() {
+        let sprite = |w, h, v| {
+            (
+                format!("s{}", v),
+                ProcessedImage::from_buffer(w, h, &vec![Rgb([v, v, v]); (w * h) as usize]),
+            )
+        };
+        let sprites = [sprite(4, 2, 1), sprite(3, 5, 2), sprite(5, 3, 3)];
+        let packed = pack(&sprites, 9, 1).unwrap();
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert_eq!(
+            packed.frames,
+            vec![
+                ("s1".to_string(), rect(0, 6, 4, 2)),
+                ("s2".to_string(), rect(0, 0, 3, 5)),
+                ("s3".to_string(), rect(4, 0, 5, 3)),
+            ]
+        );
+        assert_eq!(packed.image.width(), 9);
+        assert_eq!(packed.image.height(), 8);
+        assert_eq!(packed.image.data.get_pixel(3, 7), &Rgb([1, 1, 1]));
+        assert_eq!(packed.image.data.get_pixel(8, 2), &Rgb([3, 3, 3]));
+        assert!(packed.image.alpha.is_none());
+        assert!(pack(&sprites, 4, 0).is_err());
+        assert!(pack(&[], 4, 0).is_err());
+        assert!(pack(&[sprite(4, 2, 1), sprite(3, 5, 1)], 9, 0).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+use crate::dither::{DitherOptions, Quantizer, quantize_with};
+use crate::image_processing::luminance;
+use anyhow::{Result, anyhow};
+use image::{GrayImage, Luma, Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+const BLACK: Rgb<u8> = Rgb([0, 0, 0]);
+const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+const DEFAULT_WINDOW: u32 = 15;
+const DEFAULT_OFFSET: i16 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Threshold {
+    Fixed(u8),
+    Otsu,
+    Mean { window: u32, offset: i16 },
+    Gaussian { window: u32, offset: i16 },
+}
+
+fn parse_adaptive(s: &str) -> Option<Threshold> {
+    let mut parts = s.split(':');
+    let kind = parts.next()?;
+    let window = match parts.next() {
+        Some(window) => window.parse().ok().filter(|w| *w > 0)?,
+        None => DEFAULT_WINDOW,
+    };
+    let offset = match parts.next() {
+        Some(offset) => offset.parse().ok()?,
+        None => DEFAULT_OFFSET,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    match kind {
+        "mean" => Some(Threshold::Mean { window, offset }),
+        "gaussian" | "gauss" => Some(Threshold::Gaussian { window, offset }),
+        _ => None,
+    }
+}
+
+impl FromStr for Threshold {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "otsu" | "auto" => Ok(Threshold::Otsu),
+            level => level
+                .parse::<u8>()
+                .ok()
+                .map(Threshold::Fixed)
+                .or_else(|| parse_adaptive(level))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Unknown threshold {}, expected otsu, mean[:WINDOW[:OFFSET]], gaussian[:WINDOW[:OFFSET]] or a level from 0 to 255",
+                        s
+                    )
+                }),
+        }
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Threshold::Fixed(level) => write!(f, "{}", level),
+            Threshold::Otsu => write!(f, "otsu"),
+            Threshold::Mean { window, offset } => write!(f, "mean:{}:{}", window, offset),
+            Threshold::Gaussian { window, offset } => {
+                write!(f, "gaussian:{}:{}", window, offset)
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for Threshold {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<Threshold> for String {
+    fn from(threshold: Threshold) -> Self {
+        threshold.to_string()
+    }
+}
+
+struct Level(u8);
+
+impl Quantizer for Level {
+    fn quantize(&self, color: &Rgb<u8>) -> Rgb<u8> {
+        if color[0] > self.0 { WHITE } else { BLACK }
+    }
+
+    fn spread(&self) -> [f32; 3] {
+        [255.0; 3]
+    }
+}
+
+fn to_gray(img: &RgbImage) -> RgbImage {
+    let mut gray = img.clone();
+    gray.pixels_mut()
+        .for_each(|p| *p = Rgb([luminance(p).round().clamp(0.0, 255.0) as u8; 3]));
+    gray
+}
+
+pub fn otsu_threshold(img: &RgbImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    img.pixels()
+        .for_each(|p| histogram[luminance(p).round().clamp(0.0, 255.0) as usize] += 1);
+    let total = histogram.iter().sum::<u64>() as f64;
+    let sum = histogram
+        .iter()
+        .enumerate()
+        .map(|(i, n)| i as f64 * *n as f64)
+        .sum::<f64>();
+    let (mut background, mut background_sum) = (0.0, 0.0);
+    let (mut best, mut best_variance) = (0, -1.0);
+    for (level, count) in histogram.iter().enumerate() {
+        background += *count as f64;
+        background_sum += level as f64 * *count as f64;
+        let foreground = total - background;
+        if background == 0.0 || foreground == 0.0 {
+            continue;
+        }
+        let mean_background = background_sum / background;
+        let mean_foreground = (sum - background_sum) / foreground;
+        let variance = background * foreground * (mean_background - mean_foreground).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best = level as u8;
+        }
+    }
+    best
+}
+
+fn box_mean(gray: &RgbImage, window: u32) -> GrayImage {
+    let (width, height) = gray.dimensions();
+    let stride = width as usize + 1;
+    let mut integral = vec![0u64; stride * (height as usize + 1)];
+    for y in 0..height as usize {
+        let mut row = 0u64;
+        for x in 0..width as usize {
+            row += gray.get_pixel(x as u32, y as u32)[0] as u64;
+            integral[(y + 1) * stride + x + 1] = integral[y * stride + x + 1] + row;
+        }
+    }
+    let radius = window / 2;
+    GrayImage::from_fn(width, height, |x, y| {
+        let (x0, y0) = (
+            x.saturating_sub(radius) as usize,
+            y.saturating_sub(radius) as usize,
+        );
+        let (x1, y1) = (
+            (x + radius + 1).min(width) as usize,
+            (y + radius + 1).min(height) as usize,
+        );
+        let sum = integral[y1 * stride + x1] + integral[y0 * stride + x0]
+            - integral[y0 * stride + x1]
+            - integral[y1 * stride + x0];
+        Luma([(sum as f64 / ((x1 - x0) * (y1 - y0)) as f64).round() as u8])
+    })
+}
+
+fn gaussian_mean(gray: &RgbImage, window: u32) -> GrayImage {
+    let luma = GrayImage::from_fn(gray.width(), gray.height(), |x, y| {
+        Luma([gray.get_pixel(x, y)[0]])
+    });
+    image::imageops::blur(&luma, window as f32 / 6.0)
+}
+
+// shifts every pixel so its local threshold lands in the middle of the range
+fn normalize_local(gray: &mut RgbImage, local: &GrayImage, offset: i16) {
+    gray.enumerate_pixels_mut().for_each(|(x, y, p)| {
+        let threshold = local.get_pixel(x, y)[0] as i16 - offset;
+        p.0 = [(p[0] as i16 - threshold + 127).clamp(0, 255) as u8; 3];
+    });
+}
+
+pub fn threshold_image(
+    img: &RgbImage,
+    threshold: Threshold,
+    options: &DitherOptions,
+) -> Result<RgbImage> {
+    let mut gray = to_gray(img);
+    let level = match threshold {
+        Threshold::Fixed(level) => level,
+        Threshold::Otsu => otsu_threshold(img),
+        Threshold::Mean { window, offset } => {
+            let local = box_mean(&gray, window);
+            normalize_local(&mut gray, &local, offset);
+            127
+        }
+        Threshold::Gaussian { window, offset } => {
+            let local = gaussian_mean(&gray, window);
+            normalize_local(&mut gray, &local, offset);
+            127
+        }
+    };
+    quantize_with(&gray, &Level(level), options)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dither::{Dither, DitherOptions};
+    use crate::threshold::{Threshold, otsu_threshold, threshold_image};
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_otsu_threshold() {
+        let img = RgbImage::from_fn(10, 10, |x, _| {
+            if x < 6 {
+                Rgb([40 + x as u8, 40, 40])
+            } else {
+                Rgb([200, 190 + x as u8, 200])
+            }
+        });
+        let level = otsu_threshold(&img);
+        assert!((41..190).contains(&level));
+        let result = threshold_image(&img, Threshold::Otsu, &DitherOptions::default()).unwrap();
+        assert_eq!(*result.get_pixel(5, 0), Rgb([0, 0, 0]));
+        assert_eq!(*result.get_pixel(6, 0), Rgb([255, 255, 255]));
+        assert_eq!("128".parse::<Threshold>().unwrap(), Threshold::Fixed(128));
+        assert!("256".parse::<Threshold>().is_err());
+    }
+
+    #[test]
+    fn test_dithered_threshold() {
+        let img = RgbImage::from_pixel(8, 8, Rgb([64, 64, 64]));
+        let result = threshold_image(
+            &img,
+            Threshold::Fixed(127),
+            &DitherOptions::new(Dither::Bayer4),
+        )
+        .unwrap();
+        let white = result.pixels().filter(|p| p[0] == 255).count();
+        assert!(result.pixels().all(|p| p[0] == 0 || p[0] == 255));
+        assert!((8..=24).contains(&white));
+        let flat = threshold_image(&img, Threshold::Fixed(127), &DitherOptions::default()).unwrap();
+        assert!(flat.pixels().all(|p| p[0] == 0));
+    }
+
+    #[test]
+    fn test_adaptive_threshold() {
+        // dark lines on a background fading from bright to dark
+        let img = RgbImage::from_fn(32, 8, |x, y| {
+            let background = 250 - x as u8 * 6;
+            if y == 4 {
+                Rgb([background - 60; 3])
+            } else {
+                Rgb([background; 3])
+            }
+        });
+        let global = threshold_image(&img, Threshold::Otsu, &DitherOptions::default()).unwrap();
+        assert!(global.pixels().any(|p| p[0] == 0) && global.get_pixel(31, 0)[0] == 0);
+        for threshold in ["mean:7:15", "gaussian:9:15"] {
+            let threshold = threshold.parse::<Threshold>().unwrap();
+            let result = threshold_image(&img, threshold, &DitherOptions::default()).unwrap();
+            for x in 0..32 {
+                assert_eq!(result.get_pixel(x, 4)[0], 0);
+                assert_eq!(result.get_pixel(x, 0)[0], 255);
+            }
+        }
+        assert_eq!(
+            "mean:21:-3".parse::<Threshold>().unwrap(),
+            Threshold::Mean {
+                window: 21,
+                offset: -3
+            }
+        );
+        assert!("mean:0".parse::<Threshold>().is_err());
+    }
+}
@@ -0,0 +1,176 @@
+#[cfg(feature = "io")]
+use anyhow::Context;
+use anyhow::{Result, ensure};
+use image::RgbImage;
+use image::imageops::{flip_horizontal, flip_vertical};
+use serde::Serialize;
+use std::collections::HashMap;
+#[cfg(feature = "io")]
+use std::fs;
+#[cfg(feature = "io")]
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TileEntry {
+    pub tile: usize,
+    pub flip_x: bool,
+    pub flip_y: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Tileset {
+    pub tile_size: u32,
+    pub columns: u32,
+    pub rows: u32,
+    #[serde(skip)]
+    pub tiles: Vec<RgbImage>,
+    pub map: Vec<TileEntry>,
+}
+
+impl Tileset {
+    pub fn tileset_image(&self, columns: u32) -> RgbImage {
+        let columns = columns.clamp(1, (self.tiles.len() as u32).max(1));
+        let rows = (self.tiles.len() as u32).div_ceil(columns);
+        let mut result = RgbImage::new(columns * self.tile_size, rows * self.tile_size);
+        for (idx, tile) in self.tiles.iter().enumerate() {
+            let idx = idx as u32;
+            image::imageops::replace(
+                &mut result,
+                tile,
+                ((idx % columns) * self.tile_size) as i64,
+                ((idx / columns) * self.tile_size) as i64,
+            );
+        }
+        result
+    }
+
+    #[cfg(feature = "io")]
+    pub fn save_tileset<P>(&self, path: P, columns: u32) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        self.tileset_image(columns)
+            .save(path.as_ref())
+            .with_context(|| "Failed to save image")
+    }
+
+    #[cfg(feature = "io")]
+    pub fn save_tilemap<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        fs::write(path.as_ref(), serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to save: {:?}", path.as_ref()))
+    }
+}
+
+pub fn extract_tiles(img: &RgbImage, tile_size: u32, allow_flips: bool) -> Result<Tileset> {
+    ensure!(tile_size > 0, "Tile size must be positive");
+    let (width, height) = img.dimensions();
+    ensure!(
+        width % tile_size == 0 && height % tile_size == 0,
+        "Image dimensions {}x{} are not a multiple of the tile size {}",
+        width,
+        height,
+        tile_size
+    );
+    let (columns, rows) = (width / tile_size, height / tile_size);
+    let mut known: HashMap<Vec<u8>, TileEntry> = HashMap::new();
+    let mut tiles = vec![];
+    let mut map = Vec::with_capacity((columns * rows) as usize);
+    for row in 0..rows {
+        for column in 0..columns {
+            let tile = image::imageops::crop_imm(
+                img,
+                column * tile_size,
+                row * tile_size,
+                tile_size,
+                tile_size,
+            )
+            .to_image();
+            if let Some(entry) = known.get(tile.as_raw()) {
+                map.push(*entry);
+                continue;
+            }
+            let entry = TileEntry {
+                tile: tiles.len(),
+                flip_x: false,
+                flip_y: false,
+            };
+            if allow_flips {
+                let flipped_x = flip_horizontal(&tile);
+                let flipped_y = flip_vertical(&tile);
+                let flipped_xy = flip_vertical(&flipped_x);
+                for (variant, flip_x, flip_y) in [
+                    (flipped_xy, true, true),
+                    (flipped_y, false, true),
+                    (flipped_x, true, false),
+                ] {
+                    known.insert(
+                        variant.into_raw(),
+                        TileEntry {
+                            flip_x,
+                            flip_y,
+                            ..entry
+                        },
+                    );
+                }
+            }
+            known.insert(tile.as_raw().clone(), entry);
+            tiles.push(tile);
+            map.push(entry);
+        }
+    }
+    Ok(Tileset {
+        tile_size,
+        columns,
+        rows,
+        tiles,
+        map,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tiles::{TileEntry, extract_tiles};
+    use image::{Rgb, RgbImage};
+
+    fn get_tiled_image() -> RgbImage {
+        // 4x2 tiles of 2x2 pixels: A, B, A, flip_x(B) / flip_y(A), B, C, A
+        let a = [[1, 2], [3, 4]];
+        let b = [[5, 6], [7, 8]];
+        let c = [[9, 9], [9, 9]];
+        let flip_x = |t: [[u8; 2]; 2]| [[t[0][1], t[0][0]], [t[1][1], t[1][0]]];
+        let flip_y = |t: [[u8; 2]; 2]| [t[1], t[0]];
+        let layout = [[a, b, a, flip_x(b)], [flip_y(a), b, c, a]];
+        RgbImage::from_fn(8, 4, |x, y| {
+            let v = layout[(y / 2) as usize][(x / 2) as usize][(y % 2) as usize][(x % 2) as usize];
+            Rgb([v, v, v])
+        })
+    }
+
+    #[test]
+    fn test_extract_tiles() {
+        let img = get_tiled_image();
+        let tileset = extract_tiles(&img, 2, false).unwrap();
+        assert_eq!((tileset.columns, tileset.rows), (4, 2));
+        assert_eq!(tileset.tiles.len(), 5);
+        assert_eq!(
+            tileset.map.iter().map(|e| e.tile).collect::<Vec<_>>(),
+            vec![0, 1, 0, 2, 3, 1, 4, 0]
+        );
+
+        let tileset = extract_tiles(&img, 2, true).unwrap();
+        assert_eq!(tileset.tiles.len(), 3);
+        let entry = |tile, flip_x, flip_y| TileEntry {
+            tile,
+            flip_x,
+            flip_y,
+        };
+        assert_eq!(tileset.map[3], entry(1, true, false));
+        assert_eq!(tileset.map[4], entry(0, false, true));
+        assert_eq!(tileset.map[6], entry(2, false, false));
+        assert_eq!(tileset.tileset_image(2).dimensions(), (4, 4));
+        assert!(extract_tiles(&img, 3, false).is_err());
+    }
+}
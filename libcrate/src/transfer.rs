@@ -0,0 +1,165 @@
+use crate::adjust::apply_lookup;
+use crate::image_processing::{lab_to_rgb, rgb_to_lab};
+use crate::parallel::*;
+use anyhow::{Result, anyhow};
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorTransfer {
+    #[default]
+    Histogram,
+    Reinhard,
+}
+
+impl FromStr for ColorTransfer {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "histogram" => Ok(ColorTransfer::Histogram),
+            "reinhard" => Ok(ColorTransfer::Reinhard),
+            _ => Err(anyhow!(
+                "Unknown color transfer {}, expected histogram or reinhard",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ColorTransfer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColorTransfer::Histogram => "histogram",
+            ColorTransfer::Reinhard => "reinhard",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// the statistics of a reference image the transfers need, so a reference is measured once per batch
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorReference {
+    cdf: [[f64; 256]; 3],
+    lab_mean: [f64; 3],
+    lab_deviation: [f64; 3],
+}
+
+impl ColorReference {
+    pub fn new(img: &RgbImage) -> Self {
+        let (lab_mean, lab_deviation) = lab_statistics(img);
+        ColorReference {
+            cdf: channel_cdfs(img),
+            lab_mean,
+            lab_deviation,
+        }
+    }
+
+    pub fn apply(&self, img: &RgbImage, transfer: ColorTransfer) -> RgbImage {
+        match transfer {
+            ColorTransfer::Histogram => self.match_histogram(img),
+            ColorTransfer::Reinhard => self.reinhard(img),
+        }
+    }
+
+    fn match_histogram(&self, img: &RgbImage) -> RgbImage {
+        let source = channel_cdfs(img);
+        // every source level maps to the first reference level reaching the same cumulative share
+        let lookup: [[u8; 256]; 3] = std::array::from_fn(|channel| {
+            let target = &self.cdf[channel];
+            std::array::from_fn(|level| {
+                let share = source[channel][level];
+                target.partition_point(|&t| t < share).min(255) as u8
+            })
+        });
+        apply_lookup(img, &lookup)
+    }
+
+    fn reinhard(&self, img: &RgbImage) -> RgbImage {
+        let (mean, deviation) = lab_statistics(img);
+        let mut result = img.clone();
+        result.par_pixels_mut().for_each(|p| {
+            let lab = rgb_to_lab(p);
+            *p = lab_to_rgb(std::array::from_fn(|c| {
+                let scale = match deviation[c] > f64::EPSILON {
+                    true => self.lab_deviation[c] / deviation[c],
+                    false => 1.0,
+                };
+                (lab[c] - mean[c]) * scale + self.lab_mean[c]
+            }));
+        });
+        result
+    }
+}
+
+pub fn transfer_colors(img: &RgbImage, reference: &RgbImage, transfer: ColorTransfer) -> RgbImage {
+    ColorReference::new(reference).apply(img, transfer)
+}
+
+fn channel_cdfs(img: &RgbImage) -> [[f64; 256]; 3] {
+    let mut counts = [[0u64; 256]; 3];
+    for p in img.pixels() {
+        for c in 0..3 {
+            counts[c][p[c] as usize] += 1;
+        }
+    }
+    let total = (img.width() as u64 * img.height() as u64).max(1) as f64;
+    counts.map(|channel| {
+        let mut sum = 0;
+        channel.map(|count| {
+            sum += count;
+            sum as f64 / total
+        })
+    })
+}
+
+fn lab_statistics(img: &RgbImage) -> ([f64; 3], [f64; 3]) {
+    let total = (img.width() as u64 * img.height() as u64).max(1) as f64;
+    let labs = img.pixels().map(rgb_to_lab).collect::<Vec<_>>();
+    let mean: [f64; 3] = std::array::from_fn(|c| labs.iter().map(|l| l[c]).sum::<f64>() / total);
+    let deviation = std::array::from_fn(|c| {
+        (labs.iter().map(|l| (l[c] - mean[c]).powi(2)).sum::<f64>() / total).sqrt()
+    });
+    (mean, deviation)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::transfer::{ColorReference, ColorTransfer, transfer_colors};
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_histogram_matching() {
+        let img = RgbImage::from_fn(64, 4, |x, _| Rgb([x as u8, x as u8, x as u8]));
+        let reference = RgbImage::from_fn(64, 4, |x, _| Rgb([128 + x as u8, 0, 255 - x as u8]));
+        let matched = transfer_colors(&img, &reference, ColorTransfer::Histogram);
+        assert_eq!(*matched.get_pixel(0, 0), Rgb([128, 0, 192]));
+        assert_eq!(*matched.get_pixel(63, 0), Rgb([191, 0, 255]));
+        assert_eq!(
+            ColorReference::new(&reference).apply(&reference, ColorTransfer::Histogram),
+            reference
+        );
+    }
+
+    #[test]
+    fn test_reinhard_transfer() {
+        let img = RgbImage::from_fn(8, 8, |x, y| Rgb([(x * 10) as u8, (y * 10) as u8, 40]));
+        let reference = RgbImage::from_pixel(8, 8, Rgb([200, 60, 60]));
+        let result = transfer_colors(&img, &reference, ColorTransfer::Reinhard);
+        // a flat reference has no spread, every pixel moves to its color
+        assert!(
+            result
+                .pixels()
+                .all(|p| p[0].abs_diff(200) <= 1 && p[1].abs_diff(60) <= 1)
+        );
+        let same = transfer_colors(&img, &img, ColorTransfer::Reinhard);
+        assert!(
+            img.pixels()
+                .zip(same.pixels())
+                .all(|(a, b)| (0..3).all(|c| a[c].abs_diff(b[c]) <= 1))
+        );
+    }
+}
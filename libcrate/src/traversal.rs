@@ -0,0 +1,156 @@
+use crate::dither::hilbert_curve;
+use crate::parallel::*;
+use anyhow::{Result, anyhow};
+use image::{Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+pub const TILE_SIZE: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Traversal {
+    #[default]
+    Rows,
+    Tiles,
+    ZOrder,
+    Hilbert,
+}
+
+impl FromStr for Traversal {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "rows" => Ok(Traversal::Rows),
+            "tiles" => Ok(Traversal::Tiles),
+            "zorder" | "z-order" | "morton" => Ok(Traversal::ZOrder),
+            "hilbert" => Ok(Traversal::Hilbert),
+            _ => Err(anyhow!(
+                "Unknown traversal {}, expected rows, tiles, zorder or hilbert",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Traversal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Traversal::Rows => "rows",
+            Traversal::Tiles => "tiles",
+            Traversal::ZOrder => "zorder",
+            Traversal::Hilbert => "hilbert",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Traversal {
+    // order of the pixels inside one tile, None for plain rows
+    pub fn tile_order(&self) -> Option<Vec<(u32, u32)>> {
+        match self {
+            Traversal::Rows => None,
+            Traversal::Tiles => Some(
+                (0..TILE_SIZE)
+                    .flat_map(|y| (0..TILE_SIZE).map(move |x| (x, y)))
+                    .collect(),
+            ),
+            Traversal::ZOrder => Some((0..TILE_SIZE * TILE_SIZE).map(morton_point).collect()),
+            Traversal::Hilbert => Some(hilbert_curve(TILE_SIZE, TILE_SIZE)),
+        }
+    }
+}
+
+fn morton_point(d: u32) -> (u32, u32) {
+    let compact = |mut v: u32| {
+        v &= 0x5555_5555;
+        v = (v | (v >> 1)) & 0x3333_3333;
+        v = (v | (v >> 2)) & 0x0f0f_0f0f;
+        v = (v | (v >> 4)) & 0x00ff_00ff;
+        (v | (v >> 8)) & 0x0000_ffff
+    };
+    (compact(d), compact(d >> 1))
+}
+
+pub fn map_image<F>(img: &RgbImage, traversal: Traversal, map: F) -> RgbImage
+where
+    F: Fn(u32, u32, Rgb<u8>) -> Rgb<u8> + Sync,
+{
+    let (width, height) = img.dimensions();
+    let mut result = RgbImage::new(width, height);
+    if width == 0 || height == 0 {
+        return result;
+    }
+    let row_len = width as usize * 3;
+    let order = traversal.tile_order();
+    // bands of whole rows are disjoint chunks of the output, written in place by one task
+    let band_height = match order {
+        Some(_) => TILE_SIZE as usize,
+        None => 1,
+    };
+    result
+        .par_chunks_mut(row_len * band_height)
+        .zip(img.par_chunks(row_len * band_height))
+        .enumerate()
+        .for_each(|(band, (target, source))| {
+            let top = (band * band_height) as u32;
+            let rows = (source.len() / row_len) as u32;
+            let mut visit = |x: u32, y: u32| {
+                let offset = (y as usize * width as usize + x as usize) * 3;
+                let pixel = Rgb([source[offset], source[offset + 1], source[offset + 2]]);
+                let color = map(x, top + y, pixel);
+                target[offset..offset + 3].copy_from_slice(&color.0);
+            };
+            match &order {
+                Some(order) => {
+                    for left in (0..width).step_by(TILE_SIZE as usize) {
+                        for &(x, y) in order {
+                            if left + x < width && y < rows {
+                                visit(left + x, y);
+                            }
+                        }
+                    }
+                }
+                None => (0..width).for_each(|x| visit(x, 0)),
+            }
+        });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::traversal::{TILE_SIZE, Traversal, map_image, morton_point};
+    use image::{Rgb, RgbImage};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_tile_orders() {
+        assert_eq!(morton_point(0b1011), (0b01, 0b11));
+        for traversal in [Traversal::Tiles, Traversal::ZOrder, Traversal::Hilbert] {
+            let order = traversal.tile_order().unwrap();
+            assert_eq!(order.len(), (TILE_SIZE * TILE_SIZE) as usize);
+            assert_eq!(order.iter().collect::<HashSet<_>>().len(), order.len());
+        }
+        assert_eq!("z-order".parse::<Traversal>().unwrap(), Traversal::ZOrder);
+    }
+
+    #[test]
+    fn test_map_image() {
+        let img = RgbImage::from_fn(150, 70, |x, y| Rgb([x as u8, y as u8, 7]));
+        let expected = RgbImage::from_fn(150, 70, |x, y| Rgb([y as u8, x as u8, 7]));
+        for traversal in [
+            Traversal::Rows,
+            Traversal::Tiles,
+            Traversal::ZOrder,
+            Traversal::Hilbert,
+        ] {
+            let mapped = map_image(&img, traversal, |x, y, p| {
+                assert_eq!(p, Rgb([x as u8, y as u8, 7]));
+                Rgb([p[1], p[0], p[2]])
+            });
+            assert_eq!(mapped, expected, "{}", traversal);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::ProcessedImage;
+use anyhow::{Context, Result, ensure};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const FRAME_PATTERN: &str = "frame_%05d.png";
+
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(suffix: &str) -> Result<Self> {
+        let name = format!("pixelize-{}-{}", std::process::id(), suffix);
+        let path = std::env::temp_dir().join(name);
+        // a crashed run with the same pid may have left its frames behind
+        if path.exists() {
+            fs::remove_dir_all(&path)
+                .with_context(|| format!("Failed to clear directory: {:?}", path))?;
+        }
+        fs::create_dir_all(&path)
+            .with_context(|| format!("Failed to create directory: {:?}", path))?;
+        Ok(TempDir(path))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn ffmpeg(args: &[&str]) -> Result<()> {
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .args(args)
+        .output()
+        .with_context(|| "Failed to run ffmpeg, is it installed and on the PATH?")?;
+    ensure!(
+        output.status.success(),
+        "ffmpeg failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(())
+}
+
+pub fn extract_frames<P>(input: P, directory: &Path, fps: f64) -> Result<Vec<PathBuf>>
+where
+    P: AsRef<Path>,
+{
+    let pattern = directory.join(FRAME_PATTERN);
+    ffmpeg(&[
+        "-i",
+        &input.as_ref().to_string_lossy(),
+        "-vf",
+        &format!("fps={}", fps),
+        &pattern.to_string_lossy(),
+    ])?;
+    let mut frames = fs::read_dir(directory)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    frames.sort();
+    ensure!(
+        !frames.is_empty(),
+        "No frames extracted from {:?}",
+        input.as_ref()
+    );
+    Ok(frames)
+}
+
+pub fn encode_frames<P>(directory: &Path, output: P, fps: f64) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let pattern = directory.join(FRAME_PATTERN);
+    let fps = fps.to_string();
+    let is_gif = output
+        .as_ref()
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("gif"));
+    let output = output.as_ref().to_string_lossy();
+    let pattern = pattern.to_string_lossy();
+    let mut args = vec!["-framerate", &fps, "-i", &pattern];
+    if !is_gif {
+        args.extend([
+            "-pix_fmt",
+            "yuv420p",
+            "-vf",
+            "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+        ]);
+    }
+    args.push(&output);
+    ffmpeg(&args)
+}
+
+pub fn process_video<P, Q, F>(input: P, output: Q, fps: f64, process: F) -> Result<usize>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    F: FnOnce(&mut [ProcessedImage]) -> Result<()>,
+{
+    let source = TempDir::new("in")?;
+    let frame_paths = extract_frames(input, &source.0, fps)?;
+    let mut frames = frame_paths
+        .iter()
+        .map(ProcessedImage::new)
+        .collect::<Result<Vec<_>>>()?;
+    process(&mut frames)?;
+
+    let target = TempDir::new("out")?;
+    for (idx, frame) in frames.iter().enumerate() {
+        frame.save(target.0.join(format!("frame_{:05}.png", idx + 1)))?;
+    }
+    encode_frames(&target.0, output, fps)?;
+    Ok(frames.len())
+}